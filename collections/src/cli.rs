@@ -0,0 +1,587 @@
+// The subcommands behind the `collections` binary. Each one reads its input from
+// the files named on the command line, or stdin when there are none, and writes
+// plain text, JSON or CSV to stdout.
+
+use collections::csv::{self, CsvOptions, CsvTable};
+use collections::pig_latin::{self, Dialect};
+use collections::sheet::{CellRef, Formula, Sheet, Value};
+use collections::sketch::QuantileSketch;
+use collections::stats::{ExactStats, Stats};
+use collections::vectors::SpreadsheetCell;
+use collections::word_counter::{WordCounter, ENGLISH_STOP_WORDS};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+
+pub const USAGE: &str = "\
+usage: collections <command> [options] [FILE...]
+
+Reads FILE, or stdin when no file is given.
+
+commands:
+  stats       summarise whitespace or comma separated numbers
+                --exact        add quantiles and modes (keeps every number)
+                --approx EPS   estimate quantiles with a sketch of rank error EPS
+  piglatin    translate text to Pig Latin
+                --decode       translate back from Pig Latin
+                --dialect D    'default' (reversible) or 'book'
+  wordcount   count words
+                --top N        how many words to show, 0 for all (default 10)
+                --ngram N      count runs of N words (default 1)
+                --stop-words   ignore common English words
+                --strip-accents
+  sheet       evaluate a CSV file as a spreadsheet, '=' fields are formulas
+                --delimiter C  field delimiter (default ',')
+  demo        run the chapter examples
+
+options:
+  --format F  plain (default), json or csv
+  --help      show this message";
+
+// Usage errors exit with 2, bad input or I/O failures with 1
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    Input(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            CliError::Input(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Usage(_) => 2,
+            CliError::Input(_) => 1,
+        }
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::Input(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Plain,
+    Json,
+    Csv,
+}
+
+// Flags that are followed by a value; every other flag is a switch
+const VALUE_FLAGS: &[&str] = &[
+    "--format",
+    "--approx",
+    "--dialect",
+    "--top",
+    "--ngram",
+    "--delimiter",
+];
+const SWITCHES: &[&str] = &[
+    "--exact",
+    "--decode",
+    "--stop-words",
+    "--strip-accents",
+    "--help",
+];
+
+pub struct Args {
+    pub command: String,
+    flags: HashMap<String, String>,
+    switches: Vec<String>,
+    files: Vec<String>,
+}
+
+impl Args {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, CliError> {
+        let mut args = args.into_iter();
+        let mut command = None;
+        let mut flags = HashMap::new();
+        let mut switches = Vec::new();
+        let mut files = Vec::new();
+        while let Some(arg) = args.next() {
+            if VALUE_FLAGS.contains(&arg.as_str()) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| CliError::Usage(format!("{} needs a value", arg)))?;
+                flags.insert(arg, value);
+            } else if SWITCHES.contains(&arg.as_str()) {
+                switches.push(arg);
+            } else if arg.starts_with("--") {
+                return Err(CliError::Usage(format!("unknown option {}", arg)));
+            } else if command.is_none() {
+                command = Some(arg);
+            } else {
+                files.push(arg);
+            }
+        }
+        let command = match command {
+            Some(command) => command,
+            None if switches.iter().any(|s| s == "--help") => String::from("help"),
+            None => return Err(CliError::Usage(String::from("no command given"))),
+        };
+        Ok(Args {
+            command,
+            flags,
+            switches,
+            files,
+        })
+    }
+
+    pub fn has(&self, switch: &str) -> bool {
+        self.switches.iter().any(|s| s == switch)
+    }
+
+    pub fn format(&self) -> Result<Format, CliError> {
+        match self.flags.get("--format").map(String::as_str) {
+            None | Some("plain") => Ok(Format::Plain),
+            Some("json") => Ok(Format::Json),
+            Some("csv") => Ok(Format::Csv),
+            Some(other) => Err(CliError::Usage(format!("unknown format '{}'", other))),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&self, flag: &str, default: T) -> Result<T, CliError> {
+        match self.flags.get(flag) {
+            None => Ok(default),
+            Some(value) => value.parse().map_err(|_| {
+                CliError::Usage(format!("{} expects a number, got '{}'", flag, value))
+            }),
+        }
+    }
+
+    // The contents of every input file concatenated, or all of stdin
+    fn read_input(&self) -> Result<String, CliError> {
+        if self.files.is_empty() {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            return Ok(input);
+        }
+        let mut input = String::new();
+        for file in &self.files {
+            let text = fs::read_to_string(file)
+                .map_err(|e| CliError::Input(format!("{}: {}", file, e)))?;
+            input.push_str(&text);
+        }
+        Ok(input)
+    }
+
+    // Each line of every input file, or of stdin, one at a time so the input
+    // never has to fit in memory
+    fn for_each_line(
+        &self,
+        mut f: impl FnMut(&str) -> Result<(), CliError>,
+    ) -> Result<(), CliError> {
+        fn each<R: BufRead>(
+            reader: R,
+            f: &mut impl FnMut(&str) -> Result<(), CliError>,
+        ) -> Result<(), CliError> {
+            for line in reader.lines() {
+                f(&line?)?;
+            }
+            Ok(())
+        }
+        if self.files.is_empty() {
+            return each(io::stdin().lock(), &mut f);
+        }
+        for file in &self.files {
+            let opened =
+                fs::File::open(file).map_err(|e| CliError::Input(format!("{}: {}", file, e)))?;
+            each(BufReader::new(opened), &mut f)?;
+        }
+        Ok(())
+    }
+}
+
+// Whitespace or comma separated numbers, handed over as they're read
+fn for_each_number(args: &Args, mut f: impl FnMut(f64)) -> Result<(), CliError> {
+    let mut line_number = 0;
+    args.for_each_line(|line| {
+        line_number += 1;
+        for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+            if token.is_empty() {
+                continue;
+            }
+            let n: f64 = token.parse().map_err(|_| {
+                CliError::Input(format!("line {}: '{}' is not a number", line_number, token))
+            })?;
+            f(n);
+        }
+        Ok(())
+    })
+}
+
+pub fn stats(args: &Args) -> Result<String, CliError> {
+    let format = args.format()?;
+    let error = |e: collections::stats::StatsError| CliError::Input(e.to_string());
+
+    let fields: Vec<(&str, Field)> = if let Some(epsilon) = args.flags.get("--approx") {
+        let epsilon: f64 = epsilon.parse().map_err(|_| {
+            CliError::Usage(format!("--approx expects a number, got '{}'", epsilon))
+        })?;
+        let mut sketch = QuantileSketch::new(epsilon).map_err(error)?;
+        for_each_number(args, |n| sketch.push(n))?;
+        vec![
+            ("count", Field::Int(sketch.len() as i64)),
+            ("min", Field::Number(sketch.min().map_err(error)?)),
+            ("q1", Field::Number(sketch.quantile(0.25).map_err(error)?)),
+            ("median", Field::Number(sketch.median().map_err(error)?)),
+            ("q3", Field::Number(sketch.quantile(0.75).map_err(error)?)),
+            ("max", Field::Number(sketch.max().map_err(error)?)),
+        ]
+    } else if args.has("--exact") {
+        let mut stats = ExactStats::new();
+        for_each_number(args, |n| stats.push(n))?;
+        let summary = stats.summary().map_err(error)?;
+        vec![
+            ("count", Field::Int(summary.count as i64)),
+            ("mean", Field::Number(summary.mean)),
+            ("stddev", Field::Number(summary.stddev)),
+            ("min", Field::Number(summary.min)),
+            ("q1", Field::Number(summary.q1)),
+            ("median", Field::Number(summary.median)),
+            ("q3", Field::Number(summary.q3)),
+            ("max", Field::Number(summary.max)),
+            ("modes", Field::List(summary.modes)),
+        ]
+    } else {
+        // Only a running summary is kept, so any amount of input fits
+        let mut stats = Stats::new();
+        for_each_number(args, |n| stats.push(n))?;
+        vec![
+            ("count", Field::Int(stats.len() as i64)),
+            ("mean", Field::Number(stats.mean().map_err(error)?)),
+            ("stddev", Field::Number(stats.stddev().map_err(error)?)),
+            ("min", Field::Number(stats.min().map_err(error)?)),
+            ("max", Field::Number(stats.max().map_err(error)?)),
+        ]
+    };
+    Ok(render_record(&fields, format))
+}
+
+pub fn piglatin(args: &Args) -> Result<String, CliError> {
+    let format = args.format()?;
+    let dialect = match args.flags.get("--dialect").map(String::as_str) {
+        None | Some("default") => Dialect::default(),
+        Some("book") => Dialect::book(),
+        Some(other) => return Err(CliError::Usage(format!("unknown dialect '{}'", other))),
+    };
+    let input = args.read_input()?;
+    let output = if args.has("--decode") {
+        pig_latin::from_pig_latin(&input, &dialect)
+    } else {
+        pig_latin::to_pig_latin(&input, &dialect)
+    };
+    match format {
+        Format::Plain => Ok(output),
+        Format::Json => Ok(format!("{{\"text\":{}}}\n", json_string(&output))),
+        Format::Csv => Ok(format!("text\r\n{}\r\n", csv_field(&output))),
+    }
+}
+
+pub fn wordcount(args: &Args) -> Result<String, CliError> {
+    let format = args.format()?;
+    let top: usize = args.number("--top", 10)?;
+    let ngram: usize = args.number("--ngram", 1)?;
+    let mut counter = WordCounter::new().ngrams(ngram);
+    if args.has("--strip-accents") {
+        counter = counter.strip_accents();
+    }
+    if args.has("--stop-words") {
+        counter = counter.stop_words(ENGLISH_STOP_WORDS);
+    }
+    if args.files.is_empty() {
+        counter.ingest_reader(io::stdin().lock())?;
+    }
+    for file in &args.files {
+        counter
+            .ingest_file(file)
+            .map_err(|e| CliError::Input(format!("{}: {}", file, e)))?;
+    }
+    let k = if top == 0 { counter.unique() } else { top };
+    let rows = counter.top_k(k);
+    let mut out = String::new();
+    match format {
+        Format::Plain => {
+            for (word, count) in rows {
+                out.push_str(&format!("{}\t{}\n", word, count));
+            }
+        }
+        Format::Csv => {
+            out.push_str("word,count\r\n");
+            for (word, count) in rows {
+                out.push_str(&format!("{},{}\r\n", csv_field(word), count));
+            }
+        }
+        Format::Json => {
+            let items: Vec<String> = rows
+                .iter()
+                .map(|(word, count)| {
+                    format!("{{\"word\":{},\"count\":{}}}", json_string(word), count)
+                })
+                .collect();
+            out.push_str(&format!("[{}]\n", items.join(",")));
+        }
+    }
+    Ok(out)
+}
+
+pub fn sheet(args: &Args) -> Result<String, CliError> {
+    let format = args.format()?;
+    let delimiter = match args.flags.get("--delimiter") {
+        None => ',',
+        Some(d) if d == "\\t" => '\t',
+        Some(d) if d.chars().count() == 1 => d.chars().next().unwrap_or(','),
+        Some(d) => {
+            return Err(CliError::Usage(format!(
+                "delimiter must be one character, got '{}'",
+                d
+            )))
+        }
+    };
+    let options = CsvOptions {
+        delimiter,
+        ..CsvOptions::default()
+    };
+    let input = args.read_input()?;
+    let records =
+        csv::read_records(&input, &options).map_err(|e| CliError::Input(e.to_string()))?;
+
+    let mut sheet = Sheet::new();
+    for (row, record) in records.iter().enumerate() {
+        for (col, field) in record.iter().enumerate() {
+            let at = CellRef::new(row, col);
+            let cell = if field.starts_with('=') {
+                let formula =
+                    Formula::parse(field).map_err(|e| CliError::Input(format!("{}: {}", at, e)))?;
+                SpreadsheetCell::Formula(formula)
+            } else if field.is_empty() {
+                continue;
+            } else {
+                csv::infer(field.clone())
+            };
+            sheet
+                .set_at(at, cell)
+                .map_err(|e| CliError::Input(format!("{}: {}", at, e)))?;
+        }
+    }
+
+    let (rows, _) = sheet.dimensions();
+    let values: Vec<Vec<Value>> = (0..rows).map(|row| sheet.row(row)).collect();
+    let out = match format {
+        Format::Plain => values
+            .iter()
+            .map(|row| {
+                let cells: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+                cells.join("\t") + "\n"
+            })
+            .collect(),
+        Format::Csv => {
+            let table = CsvTable {
+                header: None,
+                rows: values
+                    .iter()
+                    .map(|row| row.iter().map(value_cell).collect())
+                    .collect(),
+            };
+            csv::to_csv_string(&table, &options).map_err(|e| CliError::Input(e.to_string()))?
+        }
+        Format::Json => {
+            let rows: Vec<String> = values
+                .iter()
+                .map(|row| {
+                    let cells: Vec<String> = row.iter().map(json_value).collect();
+                    format!("[{}]", cells.join(","))
+                })
+                .collect();
+            format!("[{}]\n", rows.join(","))
+        }
+    };
+    Ok(out)
+}
+
+enum Field {
+    Int(i64),
+    Number(f64),
+    List(Vec<f64>),
+}
+
+impl Field {
+    fn json(&self) -> String {
+        match self {
+            Field::Int(n) => n.to_string(),
+            Field::Number(n) => json_number(*n),
+            Field::List(items) => {
+                let items: Vec<String> = items.iter().map(|n| json_number(*n)).collect();
+                format!("[{}]", items.join(","))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Field::Int(n) => write!(f, "{}", n),
+            Field::Number(n) => write!(f, "{}", n),
+            Field::List(items) => {
+                let items: Vec<String> = items.iter().map(|n| n.to_string()).collect();
+                write!(f, "{}", items.join(" "))
+            }
+        }
+    }
+}
+
+fn render_record(fields: &[(&str, Field)], format: Format) -> String {
+    match format {
+        Format::Plain => fields
+            .iter()
+            .map(|(name, value)| format!("{}: {}\n", name, value))
+            .collect(),
+        Format::Json => {
+            let items: Vec<String> = fields
+                .iter()
+                .map(|(name, value)| format!("{}:{}", json_string(name), value.json()))
+                .collect();
+            format!("{{{}}}\n", items.join(","))
+        }
+        Format::Csv => {
+            let names: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+            // Numbers, or lists of them split by spaces, never need quoting
+            let values: Vec<String> = fields.iter().map(|(_, v)| v.to_string()).collect();
+            format!("{}\r\n{}\r\n", names.join(","), values.join(","))
+        }
+    }
+}
+
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Empty => String::from("null"),
+        Value::Number(n) => json_number(*n),
+        Value::Text(s) => json_string(s),
+        Value::Error(e) => json_string(&e.to_string()),
+    }
+}
+
+// JSON has no NaN or infinity
+fn json_number(n: f64) -> String {
+    if n.is_finite() {
+        n.to_string()
+    } else {
+        String::from("null")
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn csv_field(s: &str) -> String {
+    let table = CsvTable {
+        header: None,
+        rows: vec![vec![SpreadsheetCell::Text(s.to_string())]],
+    };
+    let line = csv::to_csv_string(&table, &CsvOptions::default()).unwrap_or_default(); // <- ',' is fine
+    line.trim_end_matches("\r\n").to_string()
+}
+
+// Numbers go out as numbers, so only text that looks like one gets quoted
+fn value_cell(value: &Value) -> SpreadsheetCell {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 && n.abs() <= i32::MAX as f64 => {
+            SpreadsheetCell::Int(*n as i32)
+        }
+        Value::Number(n) => SpreadsheetCell::Float(*n),
+        other => SpreadsheetCell::Text(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn args(list: &[&str]) -> Result<Args, CliError> {
+        Args::parse(list.iter().map(|s| s.to_string()))
+    }
+
+    // Write `contents` to a temporary file and return its path
+    fn input(name: &str, contents: &str) -> String {
+        let path = env::temp_dir().join(format!("cli_{}_{}.txt", name, process::id()));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn flags_switches_and_files_are_told_apart() {
+        let parsed = args(&["wordcount", "--top", "3", "--stop-words", "a.txt"]).unwrap();
+        assert_eq!(parsed.command, "wordcount");
+        assert!(parsed.has("--stop-words"));
+        assert_eq!(parsed.files, ["a.txt"]);
+        assert!(matches!(
+            args(&["stats", "--bogus"]),
+            Err(CliError::Usage(_))
+        ));
+        assert!(matches!(args(&["stats", "--top"]), Err(CliError::Usage(_))));
+    }
+
+    #[test]
+    fn stats_reads_numbers_across_files() {
+        let a = input("stats_a", "1 2\n3,4");
+        let b = input("stats_b", "10\n");
+        let out = stats(&args(&["stats", "--format", "json", &a, &b]).unwrap()).unwrap();
+        assert!(out.starts_with("{\"count\":5,\"mean\":4,"), "{}", out);
+        assert!(out.ends_with(",\"min\":1,\"max\":10}\n"), "{}", out);
+        let exact = stats(&args(&["stats", "--exact", "--format", "json", &a, &b]).unwrap());
+        assert!(exact.unwrap().contains("\"median\":3,"));
+        let approx =
+            stats(&args(&["stats", "--approx", "0.1", "--format", "csv", &a, &b]).unwrap());
+        assert!(approx
+            .unwrap()
+            .starts_with("count,min,q1,median,q3,max\r\n5,1,"));
+        fs::remove_file(a).unwrap();
+        fs::remove_file(b).unwrap();
+    }
+
+    #[test]
+    fn stats_names_the_line_with_a_bad_number() {
+        let bad = input("stats_bad", "1\n2 x\n");
+        match stats(&args(&["stats", &bad]).unwrap()) {
+            Err(CliError::Input(message)) => assert_eq!(message, "line 2: 'x' is not a number"),
+            other => panic!("expected an input error, got {:?}", other),
+        }
+        fs::remove_file(bad).unwrap();
+    }
+
+    #[test]
+    fn sheet_evaluates_formulas_from_csv() {
+        let file = input("sheet", "1,2,=A1+B1\n=SUM(A1:C1),,text\n");
+        let out = sheet(&args(&["sheet", &file]).unwrap()).unwrap();
+        assert_eq!(out, "1\t2\t3\n6\t\ttext\n");
+        fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,467 @@
+// Reading and writing CSV (RFC 4180) as rows of `SpreadsheetCell`s.
+// Fields may be quoted, in which case they can contain the delimiter, doubled
+// quotes ("") and line breaks. Each unquoted field becomes an Int, Float or Text
+// cell by trying each in turn, unless a schema says what a column should hold;
+// a quoted one is always Text. Writing quotes any Text that would read back as
+// a number, and gives every Float a decimal point, so cells round-trip.
+
+use crate::sheet::Formula;
+use crate::vectors::SpreadsheetCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub has_header: bool, // <- keep the first record as column names
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            has_header: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Int,
+    Float,
+    Text,
+    Formula,
+}
+
+// Columns not listed in the schema fall back to type inference
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    columns: HashMap<usize, ColumnType>,
+}
+
+impl Schema {
+    pub fn new() -> Schema {
+        Schema::default()
+    }
+
+    // Columns are zero based, to match `CellRef`
+    pub fn column(mut self, index: usize, column_type: ColumnType) -> Schema {
+        self.columns.insert(index, column_type);
+        self
+    }
+
+    pub fn get(&self, index: usize) -> Option<ColumnType> {
+        self.columns.get(&index).copied()
+    }
+}
+
+#[derive(Debug)]
+pub enum CsvError {
+    // `line` and `column` are one based so they match what an editor shows;
+    // `column` counts fields, not characters
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Delimiter(char), // <- one that can't be told apart from quoting or line breaks
+    Io(io::Error),
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CsvError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            CsvError::Delimiter(c) => write!(f, "{:?} can't be used as a delimiter", c),
+            CsvError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+impl From<io::Error> for CsvError {
+    fn from(e: io::Error) -> Self {
+        CsvError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvTable {
+    pub header: Option<Vec<String>>,
+    pub rows: Vec<Vec<SpreadsheetCell>>,
+}
+
+// A field as read, with the line it starts on and whether it was quoted
+struct Field {
+    text: String,
+    line: usize,
+    quoted: bool,
+}
+
+// Split the input into records of raw field strings
+pub fn read_records(input: &str, options: &CsvOptions) -> Result<Vec<Vec<String>>, CsvError> {
+    Ok(read_fields(input, options)?
+        .into_iter()
+        .map(|record| record.into_iter().map(|f| f.text).collect())
+        .collect())
+}
+
+fn read_fields(input: &str, options: &CsvOptions) -> Result<Vec<Vec<Field>>, CsvError> {
+    let delimiter = check_delimiter(options.delimiter)?;
+    let mut records = Vec::new();
+    let mut record: Vec<Field> = Vec::new();
+    let mut field = String::new();
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+    let mut field_line = 1; // <- where the current field started
+    let mut in_quotes = false;
+    let mut was_quoted = false; // <- the current field had a closing quote
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                    was_quoted = true;
+                }
+            } else {
+                if c == '\n' || (c == '\r' && chars.peek() != Some(&'\n')) {
+                    line += 1;
+                }
+                field.push(c);
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() && !was_quoted => in_quotes = true,
+            '"' => {
+                return Err(parse_error(
+                    line,
+                    record.len() + 1,
+                    "unexpected quote in unquoted field",
+                ));
+            }
+            c if c == delimiter => {
+                record.push(Field {
+                    text: std::mem::take(&mut field),
+                    line: field_line,
+                    quoted: was_quoted,
+                });
+                field_line = line;
+                was_quoted = false;
+            }
+            '\r' | '\n' => {
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                record.push(Field {
+                    text: std::mem::take(&mut field),
+                    line: field_line,
+                    quoted: was_quoted,
+                });
+                records.push(std::mem::take(&mut record));
+                line += 1;
+                field_line = line;
+                was_quoted = false;
+            }
+            _ if was_quoted => {
+                return Err(parse_error(
+                    line,
+                    record.len() + 1,
+                    "characters after closing quote",
+                ));
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(parse_error(
+            field_line,
+            record.len() + 1,
+            "quoted field is never closed",
+        ));
+    }
+    // The final line break is optional
+    if !field.is_empty() || !record.is_empty() || was_quoted {
+        record.push(Field {
+            text: field,
+            line: field_line,
+            quoted: was_quoted,
+        });
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// The quote and line breaks already mean something, so none can be the delimiter
+fn check_delimiter(delimiter: char) -> Result<char, CsvError> {
+    match delimiter {
+        '"' | '\r' | '\n' => Err(CsvError::Delimiter(delimiter)),
+        _ => Ok(delimiter),
+    }
+}
+
+fn parse_error(line: usize, column: usize, message: &str) -> CsvError {
+    CsvError::Parse {
+        line,
+        column,
+        message: message.to_string(),
+    }
+}
+
+pub fn read_cells(
+    input: &str,
+    options: &CsvOptions,
+    schema: &Schema,
+) -> Result<CsvTable, CsvError> {
+    let mut records = read_fields(input, options)?.into_iter();
+    let header = if options.has_header {
+        records
+            .next()
+            .map(|record| record.into_iter().map(|f| f.text).collect())
+    } else {
+        None
+    };
+    let mut rows = Vec::new();
+    for record in records {
+        let mut row = Vec::with_capacity(record.len());
+        for (col, field) in record.into_iter().enumerate() {
+            let line = field.line;
+            let cell = to_cell(field, schema.get(col))
+                .map_err(|message| parse_error(line, col + 1, &message))?;
+            row.push(cell);
+        }
+        rows.push(row);
+    }
+    Ok(CsvTable { header, rows })
+}
+
+pub fn read_from<R: Read>(
+    mut reader: R,
+    options: &CsvOptions,
+    schema: &Schema,
+) -> Result<CsvTable, CsvError> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    read_cells(&input, options, schema)
+}
+
+fn to_cell(field: Field, column_type: Option<ColumnType>) -> Result<SpreadsheetCell, String> {
+    let Field {
+        text: field,
+        quoted,
+        ..
+    } = field;
+    match column_type {
+        None if quoted => Ok(SpreadsheetCell::Text(field)),
+        None => Ok(infer(field)),
+        Some(ColumnType::Text) => Ok(SpreadsheetCell::Text(field)),
+        Some(ColumnType::Int) => field
+            .trim()
+            .parse()
+            .map(SpreadsheetCell::Int)
+            .map_err(|_| format!("expected an integer, found '{}'", field)),
+        Some(ColumnType::Float) => field
+            .trim()
+            .parse()
+            .map(SpreadsheetCell::Float)
+            .map_err(|_| format!("expected a number, found '{}'", field)),
+        Some(ColumnType::Formula) => Formula::parse(&field)
+            .map(SpreadsheetCell::Formula)
+            .map_err(|e| e.to_string()),
+    }
+}
+
+// Try the narrowest type first so "3" becomes an Int, "3.5" a Float
+pub fn infer(field: String) -> SpreadsheetCell {
+    let trimmed = field.trim();
+    if let Ok(n) = trimmed.parse::<i32>() {
+        return SpreadsheetCell::Int(n);
+    }
+    // f64 also parses "inf" and "NaN", which are more likely to be words
+    if trimmed.chars().any(|c| c.is_ascii_digit()) {
+        if let Ok(n) = trimmed.parse::<f64>() {
+            return SpreadsheetCell::Float(n);
+        }
+    }
+    SpreadsheetCell::Text(field)
+}
+
+pub fn write_cells<W: Write>(
+    mut writer: W,
+    table: &CsvTable,
+    options: &CsvOptions,
+) -> Result<(), CsvError> {
+    let delimiter = check_delimiter(options.delimiter)?;
+    if let Some(header) = &table.header {
+        let fields: Vec<String> = header.iter().map(|h| escape(h, delimiter, false)).collect();
+        write_line(&mut writer, &fields, delimiter)?;
+    }
+    for row in &table.rows {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|cell| {
+                let text = cell_text(cell);
+                // Text that looks like a number is quoted so it reads back as Text
+                let quote = matches!(cell, SpreadsheetCell::Text(_))
+                    && !matches!(infer(text.clone()), SpreadsheetCell::Text(_));
+                escape(&text, delimiter, quote)
+            })
+            .collect();
+        write_line(&mut writer, &fields, delimiter)?;
+    }
+    Ok(())
+}
+
+// Only fails for a delimiter `check_delimiter` turns down; writing to a Vec can't
+pub fn to_csv_string(table: &CsvTable, options: &CsvOptions) -> Result<String, CsvError> {
+    let mut buffer = Vec::new();
+    write_cells(&mut buffer, table, options)?;
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+fn cell_text(cell: &SpreadsheetCell) -> String {
+    match cell {
+        SpreadsheetCell::Int(n) => n.to_string(),
+        SpreadsheetCell::Float(n) => format!("{:?}", n), // <- "2.0", not "2", so it reads back as a Float
+        SpreadsheetCell::Text(s) => s.clone(),
+        SpreadsheetCell::Formula(f) => f.to_string(),
+    }
+}
+
+// RFC 4180 lines end in CRLF
+fn write_line<W: Write>(writer: &mut W, fields: &[String], delimiter: char) -> io::Result<()> {
+    let line = fields.join(&delimiter.to_string());
+    write!(writer, "{}\r\n", line)
+}
+
+fn escape(field: &str, delimiter: char, always: bool) -> String {
+    let needs_quotes = always
+        || field.contains(delimiter)
+        || field.contains('"')
+        || field.contains('\n')
+        || field.contains('\r');
+    if needs_quotes {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_fields_keep_delimiters_quotes_and_newlines() {
+        let input = "a,\"b, c\",\"say \"\"hi\"\"\nthere\"\r\n1,2,3\n";
+        let records = read_records(input, &CsvOptions::default()).unwrap();
+        assert_eq!(
+            records,
+            vec![vec!["a", "b, c", "say \"hi\"\nthere"], vec!["1", "2", "3"],]
+        );
+    }
+
+    #[test]
+    fn fields_are_inferred_unless_the_schema_says_otherwise() {
+        let options = CsvOptions {
+            has_header: true,
+            ..CsvOptions::default()
+        };
+        let schema = Schema::new().column(1, ColumnType::Text);
+        let table = read_cells("n,code,x\n3,007,2.5\n", &options, &schema).unwrap();
+        assert_eq!(
+            table.header,
+            Some(vec!["n".into(), "code".into(), "x".into()])
+        );
+        assert_eq!(
+            table.rows,
+            vec![vec![
+                SpreadsheetCell::Int(3),
+                SpreadsheetCell::Text("007".into()),
+                SpreadsheetCell::Float(2.5),
+            ]]
+        );
+        assert_eq!(infer("NaN".into()), SpreadsheetCell::Text("NaN".into()));
+    }
+
+    #[test]
+    fn writing_then_reading_round_trips() {
+        let table = CsvTable {
+            header: None,
+            rows: vec![vec![
+                SpreadsheetCell::Text("comma, and \"quotes\"".into()),
+                SpreadsheetCell::Int(-4),
+            ]],
+        };
+        let options = CsvOptions::default();
+        let text = to_csv_string(&table, &options).unwrap();
+        assert_eq!(text, "\"comma, and \"\"quotes\"\"\",-4\r\n");
+        assert_eq!(read_cells(&text, &options, &Schema::new()).unwrap(), table);
+    }
+
+    #[test]
+    fn unterminated_quotes_report_where_they_started() {
+        match read_records("a,b\nc,\"d", &CsvOptions::default()) {
+            Err(CsvError::Parse { line, column, .. }) => assert_eq!((line, column), (2, 2)),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn numbers_and_text_that_looks_like_one_keep_their_type() {
+        let table = CsvTable {
+            header: None,
+            rows: vec![vec![
+                SpreadsheetCell::Float(2.0),
+                SpreadsheetCell::Text("3".into()),
+                SpreadsheetCell::Text("2.5".into()),
+                SpreadsheetCell::Int(3),
+            ]],
+        };
+        let options = CsvOptions::default();
+        let text = to_csv_string(&table, &options).unwrap();
+        assert_eq!(text, "2.0,\"3\",\"2.5\",3\r\n");
+        assert_eq!(read_cells(&text, &options, &Schema::new()).unwrap(), table);
+    }
+
+    #[test]
+    fn errors_give_the_line_the_field_is_on() {
+        let input = "\"two\nlines\",1\nx,oops\n";
+        let schema = Schema::new().column(1, ColumnType::Int);
+        match read_cells(input, &CsvOptions::default(), &schema) {
+            Err(CsvError::Parse { line, column, .. }) => assert_eq!((line, column), (3, 2)),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quotes_and_line_breaks_cant_be_delimiters() {
+        for delimiter in ['"', '\n', '\r'] {
+            let options = CsvOptions {
+                delimiter,
+                ..CsvOptions::default()
+            };
+            assert!(matches!(
+                read_records("a", &options),
+                Err(CsvError::Delimiter(_))
+            ));
+            let table = CsvTable {
+                header: None,
+                rows: Vec::new(),
+            };
+            assert!(matches!(
+                to_csv_string(&table, &options),
+                Err(CsvError::Delimiter(_))
+            ));
+        }
+    }
+}
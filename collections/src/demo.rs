@@ -0,0 +1,175 @@
+use collections::csv::{self, ColumnType, CsvOptions, Schema};
+use collections::pig_latin::{self, Dialect};
+use collections::sheet::{Formula, Sheet};
+use collections::store::DiskMap;
+use collections::vectors::SpreadsheetCell;
+use collections::word_counter::{WordCounter, ENGLISH_STOP_WORDS};
+use collections::{maps, problems, stats, strings, vectors};
+
+use std::env;
+
+// The examples from working through the chapter, run with `collections demo`
+pub fn run() {
+    let v = vectors::get_vector();
+    let val = vectors::read_vector(1);
+    vectors::bad_read();
+    vectors::vector_iterator(true);
+    println!(
+        "Hello, world!, I have a vector: {:?} and a value: {:?}",
+        v, val
+    );
+    vectors::spreadsheet();
+    if let Err(e) = sheet_demo() {
+        println!("Sheet error: {}", e);
+    }
+    if let Err(e) = csv_demo() {
+        println!("CSV error: {}", e);
+    }
+
+    let s = strings::new_string();
+    println!("String: {}", s);
+
+    let s = strings::string_from_literal();
+    println!("String: {}", s);
+
+    strings::string_concat();
+    strings::string_format();
+    strings::string_rope();
+
+    let m = maps::create_map();
+    println!("Map: {:?}", m);
+    let val = maps::access_map(&m, "a");
+    println!("Value: {}", val);
+    maps::interate_map(&m);
+
+    maps::update_map();
+
+    let mut tally: maps::CountingMap<&str> = "a b c a b a".split_whitespace().collect();
+    tally.subtract("c", 1);
+    let other: maps::CountingMap<&str> = "a d d".split_whitespace().collect();
+    println!("Most common: {:?}", tally.most_common(2));
+    println!(
+        "Union: {:?}, Intersection: {:?}, Difference: {:?}",
+        tally.union(&other).with_order(maps::Order::Sorted),
+        tally.intersection(&other),
+        tally.difference(&other)
+    );
+    let mut groups: maps::DefaultMap<usize, Vec<&str>> = maps::DefaultMap::new(Vec::new);
+    for word in ["fig", "kiwi", "pear", "plum", "apple"] {
+        groups.entry(word.len()).push(word);
+    }
+    println!(
+        "Grouped by length: {:?}",
+        groups.with_order(maps::Order::Sorted)
+    );
+    if let Err(e) = store_demo() {
+        println!("Store error: {}", e);
+    }
+
+    let texts = [
+        "The cat sat on the mat. The cat was happy!",
+        "A café, a Cafe and another cafe\u{301}: the cat's favourite",
+    ];
+    let mut counter = WordCounter::new()
+        .strip_accents()
+        .stop_words(ENGLISH_STOP_WORDS);
+    counter.ingest_parallel(&texts, 2);
+    println!("Top words: {:?}", counter.top_k(3));
+    let mut bigrams = WordCounter::new().ngrams(2);
+    texts.iter().for_each(|t| bigrams.ingest(t));
+    println!("Top bigrams: {:?}", bigrams.top_k(2));
+
+    let numbers = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 4, 4, 6, 6, 6, 6];
+    match problems::mean_median_mode(numbers.clone()) {
+        Ok((mean, median, modes)) => {
+            println!("Mean: {}, Median: {}, Modes: {:?}", mean, median, modes)
+        }
+        Err(e) => println!("Error: {}", e),
+    }
+    match problems::approximate_median(1..=1_000_000, 0.01) {
+        Ok(median) => println!("Approximate median of 1..=1000000: {}", median),
+        Err(e) => println!("Error: {}", e),
+    }
+    let mut summary: stats::ExactStats<i32> = numbers.into_iter().collect();
+    match summary.summary() {
+        Ok(s) => println!("Summary: {}", s),
+        Err(e) => println!("Error: {}", e),
+    }
+
+    let s = "Hello from a string literal!";
+    let pig_latin = problems::to_pig_latin(s);
+    println!("Pig Latin: {}", pig_latin);
+
+    let s = "Strings, queens & rhythm: don't split \"Crème brûlée\" or NASA!";
+    let encoded = pig_latin::to_pig_latin(s, &Dialect::default());
+    let decoded = pig_latin::from_pig_latin(&encoded, &Dialect::default());
+    println!("Pig Latin: {}", encoded);
+    println!("Decoded: {}", decoded);
+}
+
+fn sheet_demo() -> Result<(), Box<dyn std::error::Error>> {
+    let mut sheet = Sheet::new();
+    sheet.set("A1", SpreadsheetCell::Int(3))?;
+    sheet.set("A2", SpreadsheetCell::Float(10.12))?;
+    sheet.set(
+        "A3",
+        SpreadsheetCell::Formula(Formula::parse("=SUM(A1:A2)")?),
+    )?;
+    sheet.set(
+        "B1",
+        SpreadsheetCell::Formula(Formula::parse("=A3*2-(A1-1)")?),
+    )?;
+    println!("A3: {}, B1: {}", sheet.value("A3")?, sheet.value("B1")?);
+    let updated = sheet.set("A1", SpreadsheetCell::Int(5))?;
+    println!(
+        "Recalculated {} cells, B1: {}",
+        updated.len(),
+        sheet.value("B1")?
+    );
+    if let Err(e) = sheet.set("A1", SpreadsheetCell::Formula(Formula::parse("=B1+1")?)) {
+        println!("Rejected: {}", e);
+    }
+    sheet.insert_row(0);
+    sheet.set("B1", SpreadsheetCell::Text(String::from("inserted")))?;
+    if let Some(SpreadsheetCell::Formula(f)) = sheet.get("B2")? {
+        println!("B2 after inserting a row: {} = {}", f, sheet.value("B2")?);
+    }
+    sheet.delete_row(2);
+    if let Some(SpreadsheetCell::Formula(f)) = sheet.get("A3")? {
+        println!("A3 after deleting row 3: {} = {}", f, sheet.value("A3")?);
+    }
+    println!("B2 after deleting row 3: {}", sheet.value("B2")?);
+    Ok(())
+}
+
+fn csv_demo() -> Result<(), Box<dyn std::error::Error>> {
+    let input = "name,qty,price,note\r\nwidget,3,10.12,\"blue, large\"\r\n\"gadget\",007,2,\"says \"\"hi\"\"\non two lines\"\r\n";
+    let options = CsvOptions {
+        has_header: true,
+        ..CsvOptions::default()
+    };
+    // Keep the quantity column as text so "007" isn't turned into 7
+    let schema = Schema::new().column(1, ColumnType::Text);
+    let table = csv::read_cells(input, &options, &schema)?;
+    println!("CSV rows: {:?}", table.rows);
+    print!("{}", csv::to_csv_string(&table, &options)?);
+    if let Err(e) = csv::read_cells("a,b\nc,\"d", &options, &Schema::new()) {
+        println!("CSV error: {}", e);
+    }
+    Ok(())
+}
+
+// The counts in the store survive between runs of the program
+fn store_demo() -> std::io::Result<()> {
+    let path = env::temp_dir().join("collections_counts.log");
+    let mut store = DiskMap::open(&path)?;
+    for key in "a b c d".split_whitespace() {
+        store
+            .entry(key)
+            .and_modify(|count| *count += 1)?
+            .or_insert(1)?;
+    }
+    store.sync()?;
+    println!("Stored counts: {:?}", store.iter().collect::<Vec<_>>());
+    Ok(())
+}
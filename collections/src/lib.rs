@@ -0,0 +1,11 @@
+pub mod csv;
+pub mod maps;
+pub mod pig_latin;
+pub mod problems;
+pub mod sheet;
+pub mod sketch;
+pub mod stats;
+pub mod store;
+pub mod strings;
+pub mod vectors;
+pub mod word_counter;
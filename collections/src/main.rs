@@ -1,44 +1,40 @@
-mod maps;
-mod problems;
-mod strings;
-mod vectors;
+mod cli;
+mod demo;
 
 use std::env;
-
-fn main() {
-    env::set_var("RUST_BACKTRACE", "1"); // <- enable backtraces
-    let v = vectors::get_vector();
-    let val = vectors::read_vector(1);
-    vectors::bad_read();
-    vectors::vector_iterator(true);
-    println!(
-        "Hello, world!, I have a vector: {:?} and a value: {:?}",
-        v, val
-    );
-    vectors::spreadsheet();
-
-    let s = strings::new_string();
-    println!("String: {}", s);
-
-    let s = strings::string_from_literal();
-    println!("String: {}", s);
-
-    strings::string_concat();
-    strings::string_format();
-
-    let m = maps::create_map();
-    println!("Map: {:?}", m);
-    let val = maps::access_map(&m, "a");
-    println!("Value: {}", val);
-    maps::interate_map(&m);
-
-    maps::update_map();
-
-    let numbers = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 4, 4, 6, 6, 6, 6];
-    let (mean, median, mode) = problems::mean_median_mode(numbers);
-    println!("Mean: {}, Median: {}, Mode: {}", mean, median, mode);
-
-    let s = "Hello from a string literal!";
-    let pig_latin = problems::to_pig_latin(s);
-    println!("Pig Latin: {}", pig_latin);
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args = match cli::Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("collections: {}", e);
+            return ExitCode::from(e.exit_code());
+        }
+    };
+    let result = match args.command.as_str() {
+        _ if args.has("--help") => Ok(format!("{}\n", cli::USAGE)),
+        "help" => Ok(format!("{}\n", cli::USAGE)),
+        "stats" => cli::stats(&args),
+        "piglatin" => cli::piglatin(&args),
+        "wordcount" => cli::wordcount(&args),
+        "sheet" => cli::sheet(&args),
+        "demo" => {
+            demo::run();
+            Ok(String::new())
+        }
+        other => Err(cli::CliError::Usage(format!("unknown command '{}'", other))),
+    };
+    match result {
+        Ok(output) => {
+            // A closed pipe (e.g. `| head`) isn't worth reporting
+            let _ = io::stdout().write_all(output.as_bytes());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("collections: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
 }
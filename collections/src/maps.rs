@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+use std::ops::{Add, Sub};
 
 pub fn create_map() -> HashMap<String, i32> {
     let mut map = HashMap::new();
@@ -14,8 +17,11 @@ pub fn access_map(map: &HashMap<String, i32>, key: &str) -> i32 {
 }
 
 pub fn interate_map(map: &HashMap<String, i32>) {
-    for (key, value) in map {
-        println!("Key: {}, Value: {}", key, value);
+    // Sort the keys first, otherwise the order changes from one run to the next
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort();
+    for key in keys {
+        println!("Key: {}, Value: {}", key, map[key]);
     }
 }
 
@@ -33,3 +39,381 @@ pub fn update_map() {
     }
     println!("Map: {:?}", map);
 }
+
+// The order `DefaultMap` and `CountingMap` hand back their entries in. A HashMap
+// iterates in an arbitrary order that changes from run to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Insertion,
+    Sorted,
+}
+
+// A map that produces a value for missing keys from a user supplied factory,
+// like Python's `defaultdict`, instead of silently returning 0 as `access_map` does.
+pub struct DefaultMap<K, V> {
+    map: HashMap<K, (u64, V)>, // <- each value with its place in `keys`
+    keys: BTreeMap<u64, K>,    // <- insertion order
+    inserted: u64,             // <- keys ever inserted, numbering the next one
+    default: Box<dyn Fn() -> V>,
+    order: Order,
+}
+
+impl<K: Eq + Hash + Ord + Clone, V> DefaultMap<K, V> {
+    pub fn new(default: impl Fn() -> V + 'static) -> DefaultMap<K, V> {
+        DefaultMap {
+            map: HashMap::new(),
+            keys: BTreeMap::new(),
+            inserted: 0,
+            default: Box::new(default),
+            order: Order::Insertion,
+        }
+    }
+
+    pub fn with_order(mut self, order: Order) -> DefaultMap<K, V> {
+        self.order = order;
+        self
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.map.get(key).map(|(_, v)| v)
+    }
+
+    // Like `entry().or_insert_with()`, using the map's own default
+    pub fn entry(&mut self, key: K) -> &mut V {
+        if !self.map.contains_key(&key) {
+            let value = (self.default)();
+            self.insert(key.clone(), value);
+        }
+        &mut self.map.get_mut(&key).expect("key was just inserted").1
+    }
+
+    // The stored value, or a fresh default without inserting it
+    pub fn value(&self, key: &K) -> V
+    where
+        V: Clone,
+    {
+        match self.get(key) {
+            Some(v) => v.clone(),
+            None => (self.default)(),
+        }
+    }
+
+    // Replacing a value keeps the key where it was in insertion order
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some((_, old)) = self.map.get_mut(&key) {
+            return Some(std::mem::replace(old, value));
+        }
+        self.keys.insert(self.inserted, key.clone());
+        self.map.insert(key, (self.inserted, value));
+        self.inserted += 1;
+        None
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (place, old) = self.map.remove(key)?;
+        self.keys.remove(&place);
+        Some(old)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut keys: Vec<&K> = self.keys.values().collect();
+        if self.order == Order::Sorted {
+            keys.sort();
+        }
+        keys.into_iter().map(move |k| (k, &self.map[k].1))
+    }
+}
+
+impl<K: fmt::Debug + Eq + Hash + Ord + Clone, V: fmt::Debug> fmt::Debug for DefaultMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// Any integer type can be used as a count
+pub trait Count: Copy + Default + Ord + Add<Output = Self> + Sub<Output = Self> {
+    fn one() -> Self;
+    fn saturating_add(self, other: Self) -> Self; // <- stops at the type's maximum instead of overflowing
+    fn saturating_sub(self, other: Self) -> Self; // <- stops at the type's minimum instead of overflowing
+}
+
+macro_rules! impl_count {
+    ($($t:ty),*) => {
+        $(
+            impl Count for $t {
+                fn one() -> Self {
+                    1
+                }
+
+                fn saturating_add(self, other: Self) -> Self {
+                    <$t>::saturating_add(self, other)
+                }
+
+                fn saturating_sub(self, other: Self) -> Self {
+                    <$t>::saturating_sub(self, other)
+                }
+            }
+        )*
+    };
+}
+
+impl_count!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+// A multiset: counts per key with the arithmetic of Python's `Counter`.
+pub struct CountingMap<K, V = i64> {
+    counts: DefaultMap<K, V>,
+}
+
+impl<K: Eq + Hash + Ord + Clone, V: Count + 'static> Default for CountingMap<K, V> {
+    fn default() -> Self {
+        CountingMap::new()
+    }
+}
+
+impl<K: Eq + Hash + Ord + Clone, V: Count + 'static> CountingMap<K, V> {
+    pub fn new() -> CountingMap<K, V> {
+        CountingMap {
+            counts: DefaultMap::new(V::default),
+        }
+    }
+
+    pub fn with_order(mut self, order: Order) -> CountingMap<K, V> {
+        self.counts = self.counts.with_order(order);
+        self
+    }
+
+    pub fn increment(&mut self, key: K) {
+        self.add(key, V::one());
+    }
+
+    // Counts stay between zero and the type's maximum, and a key whose count
+    // reaches zero is dropped. A negative amount takes away.
+    pub fn add(&mut self, key: K, amount: V) {
+        let count = self.count(&key).saturating_add(amount);
+        self.set(key, count);
+    }
+
+    pub fn subtract(&mut self, key: K, amount: V) {
+        let count = self.count(&key).saturating_sub(amount);
+        self.set(key, count);
+    }
+
+    pub fn count(&self, key: &K) -> V {
+        self.counts.value(key)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.counts.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    // Stops at the type's maximum
+    pub fn total(&self) -> V {
+        self.counts
+            .iter()
+            .fold(V::default(), |sum, (_, &v)| sum.saturating_add(v))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, V)> {
+        self.counts.iter().map(|(k, &v)| (k, v))
+    }
+
+    // The n largest counts, ties in key order so the result is repeatable
+    pub fn most_common(&self, n: usize) -> Vec<(&K, V)> {
+        let mut entries: Vec<(&K, V)> = self.iter().collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        entries.truncate(n);
+        entries
+    }
+
+    // Multiset sum: counts are added together
+    pub fn sum(&self, other: &CountingMap<K, V>) -> CountingMap<K, V> {
+        self.combine(other, |a, b| a.saturating_add(b))
+    }
+
+    // Multiset difference: counts are subtracted and anything not positive is dropped
+    pub fn difference(&self, other: &CountingMap<K, V>) -> CountingMap<K, V> {
+        let mut result = self.like();
+        for (key, count) in self.iter() {
+            let other_count = other.count(key);
+            if count > other_count {
+                result.add(key.clone(), count - other_count);
+            }
+        }
+        result
+    }
+
+    // Multiset union: the larger of the two counts
+    pub fn union(&self, other: &CountingMap<K, V>) -> CountingMap<K, V> {
+        self.combine(other, |a, b| a.max(b))
+    }
+
+    // Multiset intersection: the smaller of the two counts, keys in both only
+    pub fn intersection(&self, other: &CountingMap<K, V>) -> CountingMap<K, V> {
+        let mut result = self.like();
+        for (key, count) in self.iter() {
+            if other.counts.contains_key(key) {
+                let smaller = count.min(other.count(key));
+                if smaller > V::default() {
+                    result.add(key.clone(), smaller);
+                }
+            }
+        }
+        result
+    }
+
+    fn set(&mut self, key: K, count: V) {
+        if count > V::default() {
+            self.counts.insert(key, count);
+        } else {
+            self.counts.remove(&key);
+        }
+    }
+
+    fn like(&self) -> CountingMap<K, V> {
+        CountingMap::new().with_order(self.counts.order)
+    }
+
+    fn combine(&self, other: &CountingMap<K, V>, op: impl Fn(V, V) -> V) -> CountingMap<K, V> {
+        let mut result = self.like();
+        for (key, count) in self.iter() {
+            result.add(key.clone(), op(count, other.count(key)));
+        }
+        for (key, count) in other.iter() {
+            if !self.counts.contains_key(key) {
+                result.add(key.clone(), op(V::default(), count));
+            }
+        }
+        result.drop_non_positive();
+        result
+    }
+
+    fn drop_non_positive(&mut self) {
+        let empty: Vec<K> = self
+            .iter()
+            .filter(|(_, v)| *v <= V::default())
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in empty {
+            self.counts.remove(&key);
+        }
+    }
+}
+
+impl<K: Eq + Hash + Ord + Clone, V: Count + 'static> FromIterator<K> for CountingMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut counts = CountingMap::new();
+        for key in iter {
+            counts.increment(key);
+        }
+        counts
+    }
+}
+
+impl<K: fmt::Debug + Eq + Hash + Ord + Clone, V: fmt::Debug> fmt::Debug for CountingMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.counts.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_map_fills_missing_keys_and_keeps_insertion_order() {
+        let mut map: DefaultMap<&str, Vec<u32>> = DefaultMap::new(Vec::new);
+        map.entry("b").push(1);
+        map.entry("a").push(2);
+        map.entry("b").push(3);
+        assert_eq!(map.value(&"missing"), Vec::<u32>::new());
+        assert!(!map.contains_key(&"missing"));
+        let keys: Vec<&&str> = map.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, [&"b", &"a"]);
+        let map = map.with_order(Order::Sorted);
+        let keys: Vec<&&str> = map.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, [&"a", &"b"]);
+    }
+
+    #[test]
+    fn subtract_stops_at_zero_and_drops_the_key() {
+        let mut counts: CountingMap<&str, u32> = CountingMap::new();
+        counts.add("a", 1);
+        counts.subtract("a", 2);
+        counts.subtract("never", 1);
+        assert_eq!(counts.count(&"a"), 0);
+        assert!(counts.is_empty());
+        counts.add("b", 5);
+        counts.subtract("b", 2);
+        assert_eq!(counts.count(&"b"), 3);
+    }
+
+    #[test]
+    fn multiset_arithmetic_matches_counter() {
+        let a: CountingMap<char> = "aaabbc".chars().collect();
+        let b: CountingMap<char> = "abbbd".chars().collect();
+        let sorted = |m: CountingMap<char>| m.iter().map(|(k, v)| (*k, v)).collect::<Vec<_>>();
+        assert_eq!(
+            sorted(a.sum(&b).with_order(Order::Sorted)),
+            [('a', 4), ('b', 5), ('c', 1), ('d', 1)]
+        );
+        assert_eq!(sorted(a.difference(&b)), [('a', 2), ('c', 1)]);
+        assert_eq!(
+            sorted(a.union(&b).with_order(Order::Sorted)),
+            [('a', 3), ('b', 3), ('c', 1), ('d', 1)]
+        );
+        assert_eq!(sorted(a.intersection(&b)), [('a', 1), ('b', 2)]);
+        assert_eq!(a.most_common(2), [(&'a', 3), (&'b', 2)]);
+        assert_eq!(a.total(), 6);
+    }
+
+    #[test]
+    fn counts_stay_between_zero_and_the_maximum() {
+        let mut counts: CountingMap<&str, i8> = CountingMap::new();
+        counts.add("a", 3);
+        counts.add("a", -5);
+        counts.add("never", -1);
+        assert!(counts.is_empty());
+        counts.add("b", 100);
+        counts.add("b", 100);
+        counts.add("c", i8::MAX);
+        assert_eq!(counts.count(&"b"), i8::MAX);
+        assert_eq!(counts.total(), i8::MAX);
+        assert_eq!(counts.sum(&counts).count(&"c"), i8::MAX);
+        counts.subtract("b", -1);
+        assert_eq!(counts.count(&"b"), i8::MAX);
+    }
+
+    #[test]
+    fn removed_keys_leave_the_rest_in_order() {
+        let mut map: DefaultMap<u32, u32> = DefaultMap::new(|| 0);
+        for key in [3, 1, 2] {
+            map.insert(key, key * 10);
+        }
+        assert_eq!(map.remove(&1), Some(10));
+        assert_eq!(map.remove(&1), None);
+        map.insert(3, 33);
+        map.insert(1, 11);
+        let entries: Vec<(u32, u32)> = map.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(entries, [(3, 33), (2, 20), (1, 11)]);
+    }
+}
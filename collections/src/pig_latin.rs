@@ -0,0 +1,277 @@
+// A Pig Latin translator that leaves everything that isn't a word alone.
+// Text is split into word and non-word tokens so punctuation, whitespace and
+// casing survive the trip, and words are handled as grapheme clusters so accented
+// letters are never split apart. `from_pig_latin` reverses `to_pig_latin`.
+
+use crate::strings::{graphemes, is_grapheme_extend};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dialect {
+    pub separator: char,          // <- goes between the word and the moved letters
+    pub consonant_suffix: String, // <- added after the moved consonant cluster
+    pub vowel_suffix: String,     // <- added to words that start with a vowel
+    pub vowels: String,
+    pub move_clusters: bool, // <- "string" -> "ing-stray" rather than "tring-say"
+    pub qu_cluster: bool,    // <- keep the u with the q, "queen" -> "een-quay"
+    pub y_as_vowel: bool,    // <- y after a consonant is a vowel, "rhythm" -> "ythm-rhay"
+}
+
+impl Default for Dialect {
+    // The default dialect can always be decoded: a vowel word ends in just the
+    // suffix, which a moved consonant cluster can never produce.
+    fn default() -> Self {
+        Dialect {
+            separator: '-',
+            consonant_suffix: String::from("ay"),
+            vowel_suffix: String::from("ay"),
+            vowels: String::from(
+                "aeiouáàâäãåāăąéèêëēėęěíìîïīįıóòôöõøōőúùûüūůűųæœαεηιουωаеёиоуыэюяіїє",
+            ),
+            move_clusters: true,
+            qu_cluster: true,
+            y_as_vowel: true,
+        }
+    }
+}
+
+impl Dialect {
+    // The rules from the book: only the first letter moves and vowel words get
+    // "hay". Note "at-hay" could be "hat" or "at", so decoding favours "at".
+    pub fn book() -> Dialect {
+        Dialect {
+            vowel_suffix: String::from("hay"),
+            move_clusters: false,
+            qu_cluster: false,
+            y_as_vowel: false,
+            ..Dialect::default()
+        }
+    }
+
+    fn is_vowel(&self, grapheme: &str) -> bool {
+        match grapheme.chars().next() {
+            Some(c) => c.to_lowercase().any(|l| self.vowels.contains(l)),
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Case {
+    Lower,
+    Capitalized,
+    Upper,
+    Mixed,
+}
+
+pub fn to_pig_latin(text: &str, dialect: &Dialect) -> String {
+    let mut result = String::with_capacity(text.len() * 2);
+    for (token, is_word) in tokenize(text, None) {
+        if is_word {
+            result.push_str(&encode_word(token, dialect));
+        } else {
+            result.push_str(token);
+        }
+    }
+    result
+}
+
+pub fn from_pig_latin(text: &str, dialect: &Dialect) -> String {
+    let mut result = String::with_capacity(text.len());
+    for (token, is_word) in tokenize(text, Some(dialect.separator)) {
+        if is_word {
+            result.push_str(&decode_token(token, dialect));
+        } else {
+            result.push_str(token);
+        }
+    }
+    result
+}
+
+fn encode_word(word: &str, dialect: &Dialect) -> String {
+    let case = case_of(word);
+    let lowered;
+    let word = match case {
+        Case::Capitalized | Case::Upper => {
+            lowered = word.to_lowercase();
+            lowered.as_str()
+        }
+        Case::Lower | Case::Mixed => word,
+    };
+
+    let clusters = graphemes(word);
+    let split = cluster_length(&clusters, dialect);
+    let mut encoded = String::with_capacity(word.len() + 4);
+    // No vowel at all ("psst") is treated like a vowel word so it can be decoded
+    if split == 0 || split == clusters.len() {
+        encoded.push_str(word);
+        encoded.push(dialect.separator);
+        encoded.push_str(&dialect.vowel_suffix);
+    } else {
+        encoded.extend(clusters[split..].iter().copied());
+        encoded.push(dialect.separator);
+        encoded.extend(clusters[..split].iter().copied());
+        encoded.push_str(&dialect.consonant_suffix);
+    }
+    restore_case(encoded, case)
+}
+
+// How many leading graphemes make up the consonant cluster that gets moved
+fn cluster_length(clusters: &[&str], dialect: &Dialect) -> usize {
+    let mut length = 0;
+    while length < clusters.len() {
+        let grapheme = clusters[length];
+        let is_y = grapheme.eq_ignore_ascii_case("y");
+        if dialect.is_vowel(grapheme) || (is_y && length > 0 && dialect.y_as_vowel) {
+            break;
+        }
+        length += 1;
+        if dialect.qu_cluster
+            && grapheme.eq_ignore_ascii_case("q")
+            && clusters
+                .get(length)
+                .is_some_and(|next| next.eq_ignore_ascii_case("u"))
+        {
+            length += 1;
+        }
+        if !dialect.move_clusters {
+            break;
+        }
+    }
+    length
+}
+
+// A token may hold several encoded words joined by the separator, since
+// "well-known" encodes to "ell-way-own-kay". Decode them two pieces at a time.
+fn decode_token(token: &str, dialect: &Dialect) -> String {
+    let pieces: Vec<&str> = token.split(dialect.separator).collect();
+    let mut words = Vec::with_capacity(pieces.len() / 2 + 1);
+    for pair in pieces.chunks(2) {
+        match pair {
+            [body, tail] => words.push(decode_word(body, tail, dialect)),
+            [leftover] => words.push(leftover.to_string()),
+            _ => {}
+        }
+    }
+    words.join(&dialect.separator.to_string())
+}
+
+fn decode_word(body: &str, tail: &str, dialect: &Dialect) -> String {
+    let case = case_of(&format!("{}{}", body, tail));
+    let (lower_body, lower_tail) = match case {
+        Case::Capitalized | Case::Upper => (body.to_lowercase(), tail.to_lowercase()),
+        Case::Lower | Case::Mixed => (body.to_string(), tail.to_string()),
+    };
+    let suffix = dialect.consonant_suffix.as_str();
+    let decoded = if lower_tail.eq_ignore_ascii_case(&dialect.vowel_suffix) {
+        lower_body
+    } else if lower_tail.len() > suffix.len() && lower_tail.to_lowercase().ends_with(suffix) {
+        let moved = &lower_tail[..lower_tail.len() - suffix.len()];
+        format!("{}{}", moved, lower_body)
+    } else {
+        // Not something we produced, so put it back the way it was
+        return format!("{}{}{}", body, dialect.separator, tail);
+    };
+    restore_case(decoded, case)
+}
+
+fn case_of(word: &str) -> Case {
+    let mut letters = word.chars().filter(|c| c.is_alphabetic());
+    let first_upper = match letters.next() {
+        Some(c) => c.is_uppercase(),
+        None => return Case::Lower,
+    };
+    let rest: Vec<char> = letters.collect();
+    let rest_upper = rest.iter().filter(|c| c.is_uppercase()).count();
+    if !first_upper && rest_upper == 0 {
+        Case::Lower
+    } else if first_upper && rest_upper == 0 {
+        Case::Capitalized
+    } else if first_upper && rest_upper == rest.len() {
+        Case::Upper
+    } else {
+        Case::Mixed
+    }
+}
+
+fn restore_case(word: String, case: Case) -> String {
+    match case {
+        Case::Lower | Case::Mixed => word,
+        Case::Upper => word.to_uppercase(),
+        Case::Capitalized => {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => word,
+            }
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphabetic() || is_grapheme_extend(c)
+}
+
+// Split text into (token, is_word) pairs that concatenate back to the input.
+// Apostrophes inside a word ("don't") belong to it, and when decoding the dialect
+// separator is allowed between letters too.
+fn tokenize(text: &str, joiner: Option<char>) -> Vec<(&str, bool)> {
+    let mut tokens = Vec::new();
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = chars[i].0;
+        let is_word = is_word_char(chars[i].1);
+        i += 1;
+        while i < chars.len() {
+            let c = chars[i].1;
+            let inner = c == '\'' || c == '’' || Some(c) == joiner;
+            let next_is_word = chars.get(i + 1).is_some_and(|&(_, n)| is_word_char(n));
+            let continues = if is_word {
+                is_word_char(c) || (inner && next_is_word)
+            } else {
+                !is_word_char(c)
+            };
+            if !continues {
+                break;
+            }
+            i += 1;
+        }
+        let end = chars.get(i).map_or(text.len(), |&(idx, _)| idx);
+        tokens.push((&text[start..end], is_word));
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn book_rules_move_the_first_consonant() {
+        let book = Dialect::book();
+        assert_eq!(to_pig_latin("first apple", &book), "irst-fay apple-hay");
+    }
+
+    #[test]
+    fn clusters_case_and_punctuation_survive() {
+        let dialect = Dialect::default();
+        assert_eq!(
+            to_pig_latin("Strings, queens & rhythm!", &dialect),
+            "Ings-stray, eens-quay & ythm-rhay!"
+        );
+        assert_eq!(to_pig_latin("NASA", &dialect), "ASA-NAY");
+    }
+
+    #[test]
+    fn decoding_reverses_encoding() {
+        let dialect = Dialect::default();
+        for text in [
+            "Strings, queens & rhythm: don't split \"Crème brûlée\" or NASA!",
+            "well-known words",
+            "",
+        ] {
+            let encoded = to_pig_latin(text, &dialect);
+            assert_eq!(from_pig_latin(&encoded, &dialect), text);
+        }
+    }
+}
@@ -0,0 +1,1192 @@
+// A small spreadsheet built on `SpreadsheetCell`.
+// Cells are addressed in A1 notation and may hold formulas such as "=A1*2" or
+// "=SUM(A1:B3)". The sheet keeps a graph of which cells read which, so changing a
+// cell only recalculates the cells downstream of it, and a formula that would
+// depend on itself is rejected before it is stored. Ranges are kept whole in the
+// graph rather than expanded into their cells, so "=SUM(A1:A1000000)" costs no
+// more to store than "=A1".
+
+use crate::vectors::SpreadsheetCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+// Ranges bigger than this are rejected when the formula is parsed
+pub const MAX_RANGE_CELLS: usize = 1 << 24;
+
+// Formulas nested deeper than this are rejected when parsed, which keeps the
+// recursive parser and evaluator well inside the stack
+pub const MAX_FORMULA_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CellRef {
+    pub row: usize, // <- zero based, so "A1" is row 0
+    pub col: usize,
+}
+
+impl CellRef {
+    pub fn new(row: usize, col: usize) -> CellRef {
+        CellRef { row, col }
+    }
+
+    // "B12" -> CellRef { row: 11, col: 1 }
+    pub fn parse(s: &str) -> Result<CellRef, SheetError> {
+        let invalid = || SheetError::InvalidReference(s.to_string());
+        let letters = s.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+        let (col_part, row_part) = s.split_at(letters);
+        // usize's parser would also take a sign, as in "A+5"
+        if col_part.is_empty()
+            || row_part.is_empty()
+            || !row_part.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let mut col = 0usize;
+        for c in col_part.chars() {
+            let digit = (c.to_ascii_uppercase() as u8 - b'A') as usize + 1;
+            col = col
+                .checked_mul(26)
+                .and_then(|n| n.checked_add(digit))
+                .ok_or_else(invalid)?;
+        }
+        let row: usize = row_part.parse().map_err(|_| invalid())?;
+        if row == 0 {
+            return Err(invalid());
+        }
+        Ok(CellRef::new(row - 1, col - 1))
+    }
+}
+
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut letters = Vec::new();
+        let mut n = self.col + 1;
+        while n > 0 {
+            let rem = (n - 1) % 26;
+            letters.push((b'A' + rem as u8) as char);
+            n = (n - 1) / 26;
+        }
+        let col: String = letters.iter().rev().collect();
+        write!(f, "{}{}", col, self.row + 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: CellRef,
+    pub end: CellRef,
+}
+
+impl Range {
+    // The corners may be given in any order
+    pub fn new(a: CellRef, b: CellRef) -> Range {
+        Range {
+            start: CellRef::new(a.row.min(b.row), a.col.min(b.col)),
+            end: CellRef::new(a.row.max(b.row), a.col.max(b.col)),
+        }
+    }
+
+    pub fn contains(&self, cell: CellRef) -> bool {
+        (self.start.row..=self.end.row).contains(&cell.row)
+            && (self.start.col..=self.end.col).contains(&cell.col)
+    }
+
+    // Number of cells covered, or None if that doesn't fit in a usize
+    pub fn size(&self) -> Option<usize> {
+        let rows = self.end.row - self.start.row + 1;
+        let cols = self.end.col - self.start.col + 1;
+        rows.checked_mul(cols)
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = CellRef> + '_ {
+        (self.start.row..=self.end.row).flat_map(move |row| {
+            (self.start.col..=self.end.col).map(move |col| CellRef::new(row, col))
+        })
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SheetError {
+    InvalidReference(String),
+    Parse { position: usize, message: String },
+    Cycle(CellRef), // <- the cell whose formula would have depended on itself
+    RangeTooLarge(Range),
+    TooDeep, // <- the formula nests deeper than MAX_FORMULA_DEPTH
+}
+
+impl fmt::Display for SheetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SheetError::InvalidReference(s) => write!(f, "invalid cell reference '{}'", s),
+            SheetError::Parse { position, message } => {
+                write!(f, "formula error at position {}: {}", position, message)
+            }
+            SheetError::Cycle(cell) => write!(f, "formula in {} refers back to itself", cell),
+            SheetError::RangeTooLarge(range) => write!(
+                f,
+                "range {} covers more than {} cells",
+                range, MAX_RANGE_CELLS
+            ),
+            SheetError::TooDeep => write!(
+                f,
+                "formula nests more than {} levels deep",
+                MAX_FORMULA_DEPTH
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SheetError {}
+
+// Errors that end up *inside* a cell, shown the way spreadsheets usually do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellError {
+    Ref,   // <- the formula pointed at a row or column that was deleted
+    Value, // <- text where a number was needed
+    Div0,
+}
+
+impl fmt::Display for CellError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CellError::Ref => write!(f, "#REF!"),
+            CellError::Value => write!(f, "#VALUE!"),
+            CellError::Div0 => write!(f, "#DIV/0!"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Empty,
+    Number(f64),
+    Text(String),
+    Error(CellError),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Empty => Ok(()),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Text(s) => write!(f, "{}", s),
+            Value::Error(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Function> {
+        match name.to_ascii_uppercase().as_str() {
+            "SUM" => Some(Function::Sum),
+            "AVG" | "AVERAGE" => Some(Function::Avg),
+            "MIN" => Some(Function::Min),
+            "MAX" => Some(Function::Max),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Function::Sum => "SUM",
+            Function::Avg => "AVG",
+            Function::Min => "MIN",
+            Function::Max => "MAX",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn symbol(&self) -> char {
+        match self {
+            Op::Add => '+',
+            Op::Sub => '-',
+            Op::Mul => '*',
+            Op::Div => '/',
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Ref(CellRef),
+    Range(Range), // <- only meaningful as a function argument
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+    Invalid, // <- a reference that no longer exists, printed as #REF!
+}
+
+impl Expr {
+    // How many levels the tree goes down, counted without recursing
+    fn depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((expr, depth)) = stack.pop() {
+            deepest = deepest.max(depth);
+            match expr {
+                Expr::Neg(inner) => stack.push((inner, depth + 1)),
+                Expr::Binary(_, left, right) => {
+                    stack.push((left, depth + 1));
+                    stack.push((right, depth + 1));
+                }
+                Expr::Call(_, args) => stack.extend(args.iter().map(|a| (a, depth + 1))),
+                Expr::Number(_) | Expr::Ref(_) | Expr::Range(_) | Expr::Invalid => {}
+            }
+        }
+        deepest
+    }
+
+    // Every cell and range this expression reads from
+    fn references(&self, out: &mut Precedents) {
+        match self {
+            Expr::Ref(cell) => out.cells.push(*cell),
+            Expr::Range(range) => out.ranges.push(*range),
+            Expr::Neg(inner) => inner.references(out),
+            Expr::Binary(_, left, right) => {
+                left.references(out);
+                right.references(out);
+            }
+            Expr::Call(_, args) => {
+                for arg in args {
+                    arg.references(out);
+                }
+            }
+            Expr::Number(_) | Expr::Invalid => {}
+        }
+    }
+
+    // Rewrite references after a row or column is inserted or deleted
+    fn remap(&self, edit: &Edit) -> Expr {
+        match self {
+            Expr::Ref(cell) => edit.cell(*cell).map_or(Expr::Invalid, Expr::Ref),
+            Expr::Range(range) => edit.range(range).map_or(Expr::Invalid, Expr::Range),
+            Expr::Neg(inner) => Expr::Neg(Box::new(inner.remap(edit))),
+            Expr::Binary(op, left, right) => {
+                Expr::Binary(*op, Box::new(left.remap(edit)), Box::new(right.remap(edit)))
+            }
+            Expr::Call(func, args) => {
+                Expr::Call(*func, args.iter().map(|a| a.remap(edit)).collect())
+            }
+            Expr::Number(_) | Expr::Invalid => self.clone(),
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, parent: u8) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Ref(cell) => write!(f, "{}", cell),
+            Expr::Range(range) => write!(f, "{}", range),
+            Expr::Invalid => write!(f, "#REF!"),
+            Expr::Neg(inner) => {
+                write!(f, "-")?;
+                inner.write(f, 3)
+            }
+            Expr::Binary(op, left, right) => {
+                let precedence = op.precedence();
+                if precedence < parent {
+                    write!(f, "(")?;
+                }
+                left.write(f, precedence)?;
+                write!(f, "{}", op.symbol())?;
+                // The right side binds tighter so "A1-(B1-C1)" keeps its brackets
+                right.write(f, precedence + 1)?;
+                if precedence < parent {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Expr::Call(func, args) => {
+                write!(f, "{}(", func.name())?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    arg.write(f, 0)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+// A change to the shape of the sheet
+#[derive(Debug, Clone, Copy)]
+enum Edit {
+    InsertRow(usize),
+    DeleteRow(usize),
+    InsertColumn(usize),
+    DeleteColumn(usize),
+}
+
+impl Edit {
+    // Where a cell ends up, or None if it was deleted
+    fn cell(&self, c: CellRef) -> Option<CellRef> {
+        match *self {
+            Edit::InsertRow(at) | Edit::DeleteRow(at) => {
+                Some(CellRef::new(self.index(at, c.row)?, c.col))
+            }
+            Edit::InsertColumn(at) | Edit::DeleteColumn(at) => {
+                Some(CellRef::new(c.row, self.index(at, c.col)?))
+            }
+        }
+    }
+
+    // Ranges move their corners: they grow when a row or column is inserted
+    // inside them, shrink when one is deleted and only become invalid when every
+    // one of them is gone.
+    fn range(&self, range: &Range) -> Option<Range> {
+        let (start, end) = (range.start, range.end);
+        match *self {
+            Edit::InsertRow(at) | Edit::DeleteRow(at) => {
+                let (first, last) = self.span(at, start.row, end.row)?;
+                Some(Range::new(
+                    CellRef::new(first, start.col),
+                    CellRef::new(last, end.col),
+                ))
+            }
+            Edit::InsertColumn(at) | Edit::DeleteColumn(at) => {
+                let (first, last) = self.span(at, start.col, end.col)?;
+                Some(Range::new(
+                    CellRef::new(start.row, first),
+                    CellRef::new(end.row, last),
+                ))
+            }
+        }
+    }
+
+    fn index(&self, at: usize, i: usize) -> Option<usize> {
+        match self {
+            Edit::InsertRow(_) | Edit::InsertColumn(_) if i >= at => Some(i + 1),
+            Edit::DeleteRow(_) | Edit::DeleteColumn(_) if i == at => None,
+            Edit::DeleteRow(_) | Edit::DeleteColumn(_) if i > at => Some(i - 1),
+            _ => Some(i),
+        }
+    }
+
+    // The rows (or columns) first..=last after the edit
+    fn span(&self, at: usize, first: usize, last: usize) -> Option<(usize, usize)> {
+        match self {
+            Edit::InsertRow(_) | Edit::InsertColumn(_) => {
+                Some((self.index(at, first)?, self.index(at, last)?))
+            }
+            Edit::DeleteRow(_) | Edit::DeleteColumn(_) => {
+                if first == at && last == at {
+                    return None;
+                }
+                let first = if first > at { first - 1 } else { first };
+                let last = if last >= at { last - 1 } else { last };
+                Some((first, last))
+            }
+        }
+    }
+}
+
+// What a formula reads: single cells, and ranges kept whole
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Precedents {
+    cells: Vec<CellRef>,
+    ranges: Vec<Range>,
+}
+
+impl Precedents {
+    fn is_empty(&self) -> bool {
+        self.cells.is_empty() && self.ranges.is_empty()
+    }
+
+    fn reads(&self, cell: CellRef) -> bool {
+        self.cells.contains(&cell) || self.ranges.iter().any(|r| r.contains(cell))
+    }
+}
+
+// Formulas that read a range, filed under each column the range spans, or under
+// each row when it is wider than it is tall. Finding the ranges that hold a cell
+// then only looks through one column's and one row's worth of them.
+#[derive(Debug, Default)]
+struct RangeIndex {
+    by_col: RangeSlots,
+    by_row: RangeSlots,
+}
+
+type RangeSlots = HashMap<usize, Vec<(Range, CellRef)>>;
+
+impl RangeIndex {
+    fn slots(&mut self, range: &Range) -> (&mut RangeSlots, RangeInclusive<usize>) {
+        let rows = range.end.row - range.start.row;
+        let cols = range.end.col - range.start.col;
+        if cols <= rows {
+            (&mut self.by_col, range.start.col..=range.end.col)
+        } else {
+            (&mut self.by_row, range.start.row..=range.end.row)
+        }
+    }
+
+    fn insert(&mut self, range: Range, formula: CellRef) {
+        let (slots, keys) = self.slots(&range);
+        for key in keys {
+            slots.entry(key).or_default().push((range, formula));
+        }
+    }
+
+    fn remove(&mut self, range: Range, formula: CellRef) {
+        let (slots, keys) = self.slots(&range);
+        for key in keys {
+            if let Some(entries) = slots.get_mut(&key) {
+                if let Some(i) = entries.iter().position(|e| *e == (range, formula)) {
+                    entries.swap_remove(i);
+                }
+                if entries.is_empty() {
+                    slots.remove(&key);
+                }
+            }
+        }
+    }
+
+    // A formula with two ranges holding `cell` comes up twice
+    fn readers(&self, cell: CellRef) -> impl Iterator<Item = CellRef> + '_ {
+        let by_col = self.by_col.get(&cell.col).into_iter().flatten();
+        let by_row = self.by_row.get(&cell.row).into_iter().flatten();
+        by_col
+            .chain(by_row)
+            .filter(move |(range, _)| range.contains(cell))
+            .map(|(_, formula)| *formula)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Formula {
+    expr: Expr,
+}
+
+impl Formula {
+    // Accepts the formula with or without its leading '='
+    pub fn parse(source: &str) -> Result<Formula, SheetError> {
+        let source = source.strip_prefix('=').unwrap_or(source);
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+            nesting: 0,
+        };
+        let expr = parser.expression(0)?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unexpected trailing input"));
+        }
+        Ok(Formula { expr })
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    // Single cells this formula reads; ranges are listed whole by `ranges`
+    pub fn references(&self) -> Vec<CellRef> {
+        self.precedents().cells
+    }
+
+    pub fn ranges(&self) -> Vec<Range> {
+        self.precedents().ranges
+    }
+
+    fn precedents(&self) -> Precedents {
+        let mut refs = Precedents::default();
+        self.expr.references(&mut refs);
+        refs.cells.sort();
+        refs.cells.dedup();
+        refs
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "=")?;
+        self.expr.write(f, 0)
+    }
+}
+
+// A recursive descent parser with precedence climbing for the binary operators
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    nesting: usize, // <- how many `expression`/`unary` calls are on the stack
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> SheetError {
+        SheetError::Parse {
+            position: self.pos,
+            message: message.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SheetError> {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    // Guard every recursive step, so "((((..." or "----..." can't run off the stack
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Parser) -> Result<T, SheetError>,
+    ) -> Result<T, SheetError> {
+        if self.nesting >= MAX_FORMULA_DEPTH {
+            return Err(SheetError::TooDeep);
+        }
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> Result<Expr, SheetError> {
+        self.nested(|p| p.binary(min_precedence))
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, SheetError> {
+        let mut left = self.unary()?;
+        // "1+1+1+..." is parsed in a loop here but still builds a deep tree
+        let mut depth = left.depth();
+        loop {
+            let op = match self.peek() {
+                Some('+') => Op::Add,
+                Some('-') => Op::Sub,
+                Some('*') => Op::Mul,
+                Some('/') => Op::Div,
+                _ => break,
+            };
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let right = self.expression(op.precedence() + 1)?;
+            depth = depth.max(right.depth()) + 1;
+            if depth > MAX_FORMULA_DEPTH {
+                return Err(SheetError::TooDeep);
+            }
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, SheetError> {
+        self.nested(Parser::sign)
+    }
+
+    fn sign(&mut self) -> Result<Expr, SheetError> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            }
+            Some('+') => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, SheetError> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let inner = self.expression(0)?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.name(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of formula")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Expr, SheetError> {
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse()
+            .map(Expr::Number)
+            .map_err(|_| SheetError::Parse {
+                position: start,
+                message: format!("'{}' is not a number", text),
+            })
+    }
+
+    // Either a function call like SUM(...) or a cell reference / range
+    fn name(&mut self) -> Result<Expr, SheetError> {
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_alphanumeric())
+        {
+            self.pos += 1;
+        }
+        let word: String = self.chars[start..self.pos].iter().collect();
+        if self.peek() == Some('(') {
+            let func = Function::from_name(&word).ok_or(SheetError::Parse {
+                position: start,
+                message: format!("unknown function '{}'", word),
+            })?;
+            self.pos += 1;
+            let mut args = Vec::new();
+            if self.peek() != Some(')') {
+                loop {
+                    args.push(self.expression(0)?);
+                    if self.peek() == Some(',') {
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+            self.expect(')')?;
+            return Ok(Expr::Call(func, args));
+        }
+        let cell = CellRef::parse(&word)?;
+        if self.peek() == Some(':') {
+            self.pos += 1;
+            self.skip_whitespace();
+            let end_start = self.pos;
+            while self
+                .chars
+                .get(self.pos)
+                .is_some_and(|c| c.is_ascii_alphanumeric())
+            {
+                self.pos += 1;
+            }
+            let end: String = self.chars[end_start..self.pos].iter().collect();
+            let range = Range::new(cell, CellRef::parse(&end)?);
+            if range.size().filter(|len| *len <= MAX_RANGE_CELLS).is_none() {
+                return Err(SheetError::RangeTooLarge(range));
+            }
+            return Ok(Expr::Range(range));
+        }
+        Ok(Expr::Ref(cell))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Sheet {
+    cells: HashMap<CellRef, SpreadsheetCell>,
+    values: HashMap<CellRef, Value>, // <- cached results of formula cells
+    precedents: HashMap<CellRef, Precedents>, // <- cells and ranges a formula reads
+    dependents: HashMap<CellRef, HashSet<CellRef>>, // <- formulas that read a single cell
+    ranged: RangeIndex,              // <- formulas that read a range
+}
+
+impl Sheet {
+    pub fn new() -> Sheet {
+        Sheet::default()
+    }
+
+    pub fn get(&self, cell: &str) -> Result<Option<&SpreadsheetCell>, SheetError> {
+        Ok(self.cells.get(&CellRef::parse(cell)?))
+    }
+
+    pub fn cell(&self, cell: CellRef) -> Option<&SpreadsheetCell> {
+        self.cells.get(&cell)
+    }
+
+    pub fn value(&self, cell: &str) -> Result<Value, SheetError> {
+        Ok(self.value_at(CellRef::parse(cell)?))
+    }
+
+    pub fn value_at(&self, cell: CellRef) -> Value {
+        match self.cells.get(&cell) {
+            None => Value::Empty,
+            Some(SpreadsheetCell::Int(n)) => Value::Number(*n as f64),
+            Some(SpreadsheetCell::Float(n)) => Value::Number(*n),
+            Some(SpreadsheetCell::Text(s)) => Value::Text(s.clone()),
+            Some(SpreadsheetCell::Formula(_)) => {
+                self.values.get(&cell).cloned().unwrap_or(Value::Empty)
+            }
+        }
+    }
+
+    // Store a cell and return every cell whose value was recalculated
+    pub fn set(
+        &mut self,
+        cell: &str,
+        content: SpreadsheetCell,
+    ) -> Result<Vec<CellRef>, SheetError> {
+        self.set_at(CellRef::parse(cell)?, content)
+    }
+
+    pub fn set_at(
+        &mut self,
+        cell: CellRef,
+        content: SpreadsheetCell,
+    ) -> Result<Vec<CellRef>, SheetError> {
+        let refs = match &content {
+            SpreadsheetCell::Formula(formula) => formula.precedents(),
+            _ => Precedents::default(),
+        };
+        if self.reaches(&refs, cell) {
+            return Err(SheetError::Cycle(cell));
+        }
+        self.unlink(cell);
+        self.link(cell, refs);
+        self.cells.insert(cell, content);
+        Ok(self.recalculate_from(cell))
+    }
+
+    pub fn clear(&mut self, cell: &str) -> Result<Vec<CellRef>, SheetError> {
+        let cell = CellRef::parse(cell)?;
+        self.unlink(cell);
+        self.cells.remove(&cell);
+        self.values.remove(&cell);
+        Ok(self.recalculate_from(cell))
+    }
+
+    // (rows, columns) covering every non-empty cell
+    pub fn dimensions(&self) -> (usize, usize) {
+        let rows = self.cells.keys().map(|c| c.row + 1).max().unwrap_or(0);
+        let cols = self.cells.keys().map(|c| c.col + 1).max().unwrap_or(0);
+        (rows, cols)
+    }
+
+    pub fn row(&self, row: usize) -> Vec<Value> {
+        let (_, cols) = self.dimensions();
+        (0..cols)
+            .map(|col| self.value_at(CellRef::new(row, col)))
+            .collect()
+    }
+
+    pub fn column(&self, col: usize) -> Vec<Value> {
+        let (rows, _) = self.dimensions();
+        (0..rows)
+            .map(|row| self.value_at(CellRef::new(row, col)))
+            .collect()
+    }
+
+    pub fn insert_row(&mut self, at: usize) {
+        self.restructure(Edit::InsertRow(at));
+    }
+
+    pub fn delete_row(&mut self, at: usize) {
+        self.restructure(Edit::DeleteRow(at));
+    }
+
+    pub fn insert_column(&mut self, at: usize) {
+        self.restructure(Edit::InsertColumn(at));
+    }
+
+    pub fn delete_column(&mut self, at: usize) {
+        self.restructure(Edit::DeleteColumn(at));
+    }
+
+    // Move every cell and rewrite every formula, then rebuild the graph. This is
+    // the one operation that recalculates the whole sheet.
+    fn restructure(&mut self, edit: Edit) {
+        let old = std::mem::take(&mut self.cells);
+        self.values.clear();
+        self.precedents.clear();
+        self.dependents.clear();
+        self.ranged = RangeIndex::default();
+        for (cell, content) in old {
+            let Some(moved) = edit.cell(cell) else {
+                continue;
+            };
+            let content = match content {
+                SpreadsheetCell::Formula(formula) => SpreadsheetCell::Formula(Formula {
+                    expr: formula.expr.remap(&edit),
+                }),
+                other => other,
+            };
+            self.cells.insert(moved, content);
+        }
+        let formulas: Vec<(CellRef, Precedents)> = self
+            .cells
+            .iter()
+            .filter_map(|(cell, content)| match content {
+                SpreadsheetCell::Formula(f) => Some((*cell, f.precedents())),
+                _ => None,
+            })
+            .collect();
+        // Every formula, including ones like "=#REF!+1" that no longer read anything
+        let all: Vec<CellRef> = formulas.iter().map(|(cell, _)| *cell).collect();
+        for (cell, refs) in formulas {
+            self.link(cell, refs);
+        }
+        self.evaluate_in_order(all);
+    }
+
+    // Would a formula at `target` reading `refs` end up reading itself? Only if
+    // it reads `target` or something downstream of it.
+    fn reaches(&self, refs: &Precedents, target: CellRef) -> bool {
+        if refs.is_empty() {
+            return false;
+        }
+        let mut stack = vec![target];
+        let mut seen = HashSet::from([target]);
+        while let Some(cell) = stack.pop() {
+            if refs.reads(cell) {
+                return true;
+            }
+            for d in self.dependents_of(cell) {
+                if seen.insert(d) {
+                    stack.push(d);
+                }
+            }
+        }
+        false
+    }
+
+    fn link(&mut self, cell: CellRef, refs: Precedents) {
+        if refs.is_empty() {
+            return;
+        }
+        for r in &refs.cells {
+            self.dependents.entry(*r).or_default().insert(cell);
+        }
+        for range in &refs.ranges {
+            self.ranged.insert(*range, cell);
+        }
+        self.precedents.insert(cell, refs);
+    }
+
+    fn unlink(&mut self, cell: CellRef) {
+        if let Some(old) = self.precedents.remove(&cell) {
+            for r in old.cells {
+                if let Some(set) = self.dependents.get_mut(&r) {
+                    set.remove(&cell);
+                }
+            }
+            for range in old.ranges {
+                self.ranged.remove(range, cell);
+            }
+        }
+    }
+
+    // Formulas reading `cell`, directly or through one of their ranges
+    fn dependents_of(&self, cell: CellRef) -> Vec<CellRef> {
+        let direct = self.dependents.get(&cell).into_iter().flatten().copied();
+        direct.chain(self.ranged.readers(cell)).collect()
+    }
+
+    fn recalculate_from(&mut self, cell: CellRef) -> Vec<CellRef> {
+        // Collect the changed cell and everything downstream of it
+        let mut affected = vec![cell];
+        let mut seen = HashSet::from([cell]);
+        let mut i = 0;
+        while i < affected.len() {
+            for d in self.dependents_of(affected[i]) {
+                if seen.insert(d) {
+                    affected.push(d);
+                }
+            }
+            i += 1;
+        }
+        self.evaluate_in_order(affected)
+    }
+
+    // Evaluate formulas so that each one runs after the cells it reads (a
+    // topological sort restricted to `cells`)
+    fn evaluate_in_order(&mut self, cells: Vec<CellRef>) -> Vec<CellRef> {
+        let pending: HashSet<CellRef> = cells.iter().copied().collect();
+        let mut done = HashSet::new();
+        let mut order = Vec::new();
+        for cell in cells {
+            self.visit(cell, &pending, &mut done, &mut order);
+        }
+        for cell in &order {
+            if let Some(SpreadsheetCell::Formula(formula)) = self.cells.get(cell) {
+                let value = self.evaluate(&formula.expr);
+                self.values.insert(*cell, value);
+            }
+        }
+        order
+    }
+
+    // Depth first, with an explicit stack so a long chain of formulas can't
+    // overflow the real one
+    fn visit(
+        &self,
+        start: CellRef,
+        pending: &HashSet<CellRef>,
+        done: &mut HashSet<CellRef>,
+        order: &mut Vec<CellRef>,
+    ) {
+        // (cell, whether what it reads has been visited already)
+        let mut stack = vec![(start, false)];
+        while let Some((cell, finished)) = stack.pop() {
+            if finished {
+                order.push(cell);
+                continue;
+            }
+            if !done.insert(cell) {
+                continue;
+            }
+            stack.push((cell, true));
+            let Some(refs) = self.precedents.get(&cell) else {
+                continue;
+            };
+            let mut reads: Vec<CellRef> = refs
+                .cells
+                .iter()
+                .filter(|r| pending.contains(r))
+                .copied()
+                .collect();
+            for range in &refs.ranges {
+                reads.extend(within(range, pending.len(), pending.iter(), |c| {
+                    pending.contains(c)
+                }));
+            }
+            // Reversed, so they come off the stack in the order they were read
+            stack.extend(reads.into_iter().rev().map(|r| (r, false)));
+        }
+    }
+
+    fn evaluate(&self, expr: &Expr) -> Value {
+        match self.number(expr) {
+            Ok(n) => Value::Number(n),
+            Err(e) => Value::Error(e),
+        }
+    }
+
+    fn number(&self, expr: &Expr) -> Result<f64, CellError> {
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Ref(cell) => match self.value_at(*cell) {
+                Value::Empty => Ok(0.0),
+                Value::Number(n) => Ok(n),
+                Value::Text(_) => Err(CellError::Value),
+                Value::Error(e) => Err(e),
+            },
+            Expr::Range(_) => Err(CellError::Value),
+            Expr::Invalid => Err(CellError::Ref),
+            Expr::Neg(inner) => Ok(-self.number(inner)?),
+            Expr::Binary(op, left, right) => {
+                let (a, b) = (self.number(left)?, self.number(right)?);
+                match op {
+                    Op::Add => Ok(a + b),
+                    Op::Sub => Ok(a - b),
+                    Op::Mul => Ok(a * b),
+                    Op::Div if b == 0.0 => Err(CellError::Div0),
+                    Op::Div => Ok(a / b),
+                }
+            }
+            Expr::Call(func, args) => {
+                let numbers = self.collect_numbers(args)?;
+                match func {
+                    Function::Sum => Ok(numbers.iter().sum()),
+                    Function::Avg if numbers.is_empty() => Err(CellError::Div0),
+                    Function::Avg => Ok(numbers.iter().sum::<f64>() / numbers.len() as f64),
+                    Function::Min => Ok(numbers.iter().copied().reduce(f64::min).unwrap_or(0.0)),
+                    Function::Max => Ok(numbers.iter().copied().reduce(f64::max).unwrap_or(0.0)),
+                }
+            }
+        }
+    }
+
+    // Function arguments: ranges skip text and empty cells like most spreadsheets
+    fn collect_numbers(&self, args: &[Expr]) -> Result<Vec<f64>, CellError> {
+        let mut numbers = Vec::new();
+        for arg in args {
+            match arg {
+                Expr::Range(range) => {
+                    for cell in self.cells_in(range) {
+                        match self.value_at(cell) {
+                            Value::Number(n) => numbers.push(n),
+                            Value::Error(e) => return Err(e),
+                            Value::Empty | Value::Text(_) => {}
+                        }
+                    }
+                }
+                other => numbers.push(self.number(other)?),
+            }
+        }
+        Ok(numbers)
+    }
+
+    // The non-empty cells of a range, in row order
+    fn cells_in(&self, range: &Range) -> Vec<CellRef> {
+        within(range, self.cells.len(), self.cells.keys(), |c| {
+            self.cells.contains_key(c)
+        })
+    }
+}
+
+// The cells of `range` out of a set of `len` cells, in row order. Large ranges
+// are mostly empty, so walk whichever is smaller: the range or the set.
+fn within<'a>(
+    range: &Range,
+    len: usize,
+    cells: impl Iterator<Item = &'a CellRef>,
+    has: impl Fn(&CellRef) -> bool,
+) -> Vec<CellRef> {
+    if range.size().is_some_and(|size| size <= len) {
+        return range.cells().filter(|c| has(c)).collect();
+    }
+    let mut found: Vec<CellRef> = cells.filter(|c| range.contains(**c)).copied().collect();
+    found.sort();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formula(source: &str) -> SpreadsheetCell {
+        SpreadsheetCell::Formula(Formula::parse(source).unwrap())
+    }
+
+    #[test]
+    fn changing_a_cell_recalculates_only_what_reads_it() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", SpreadsheetCell::Int(2)).unwrap();
+        sheet.set("B1", formula("=A1*10")).unwrap();
+        sheet.set("C1", formula("=SUM(A1:B1)")).unwrap();
+        sheet.set("D1", formula("=7")).unwrap();
+        let changed = sheet.set("A1", SpreadsheetCell::Int(3)).unwrap();
+        assert_eq!(changed.len(), 3);
+        assert!(!changed.contains(&CellRef::parse("D1").unwrap()));
+        assert_eq!(sheet.value("C1"), Ok(Value::Number(33.0)));
+    }
+
+    #[test]
+    fn formulas_print_back_with_the_brackets_they_need() {
+        let f = Formula::parse("= (A1 - (B1 - C1)) * 2 + sum(A1:B2)").unwrap();
+        assert_eq!(f.to_string(), "=(A1-(B1-C1))*2+SUM(A1:B2)");
+    }
+
+    #[test]
+    fn cycles_are_rejected_through_ranges_too() {
+        let mut sheet = Sheet::new();
+        sheet.set("B1", formula("=A1+1")).unwrap();
+        assert_eq!(
+            sheet.set("A1", formula("=B1")),
+            Err(SheetError::Cycle(CellRef::parse("A1").unwrap()))
+        );
+        assert!(sheet.set("A5", formula("=SUM(A1:B2)")).is_ok());
+        assert!(sheet.set("A1", formula("=MAX(A3:A9)")).is_err());
+    }
+
+    #[test]
+    fn deleting_a_referenced_row_leaves_ref_errors() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", SpreadsheetCell::Int(1)).unwrap();
+        sheet.set("A2", SpreadsheetCell::Int(2)).unwrap();
+        sheet.set("A3", SpreadsheetCell::Int(4)).unwrap();
+        sheet.set("B1", formula("=A2+SUM(A1:A3)")).unwrap();
+        sheet.set("C1", formula("=SUM(A2:A2)")).unwrap();
+        sheet.delete_row(1);
+        match sheet.get("B1").unwrap() {
+            Some(SpreadsheetCell::Formula(f)) => assert_eq!(f.to_string(), "=#REF!+SUM(A1:A2)"),
+            other => panic!("expected a formula, got {:?}", other),
+        }
+        assert_eq!(sheet.value("B1"), Ok(Value::Error(CellError::Ref)));
+        assert_eq!(sheet.value("C1"), Ok(Value::Error(CellError::Ref)));
+    }
+
+    #[test]
+    fn inserting_inside_a_range_grows_it() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", SpreadsheetCell::Int(1)).unwrap();
+        sheet.set("A2", SpreadsheetCell::Int(2)).unwrap();
+        sheet.set("B1", formula("=SUM(A1:A2)")).unwrap();
+        sheet.insert_row(1);
+        sheet.set("A2", SpreadsheetCell::Int(10)).unwrap();
+        assert_eq!(sheet.value("B1"), Ok(Value::Number(13.0)));
+    }
+
+    #[test]
+    fn huge_ranges_stay_cheap_and_oversized_ones_are_rejected() {
+        let mut sheet = Sheet::new();
+        sheet.set("B1", formula("=SUM(A1:A2000000)")).unwrap();
+        sheet.set("A1999999", SpreadsheetCell::Float(1.5)).unwrap();
+        assert_eq!(sheet.value("B1"), Ok(Value::Number(1.5)));
+        assert!(matches!(
+            Formula::parse("=SUM(A1:XFD1048576)"),
+            Err(SheetError::RangeTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn long_chains_and_deep_formulas_dont_overflow_the_stack() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", SpreadsheetCell::Int(0)).unwrap();
+        for row in 1..100_000 {
+            let cell = CellRef::new(row, 0);
+            let above = CellRef::new(row - 1, 0);
+            sheet
+                .set_at(cell, formula(&format!("={}+1", above)))
+                .unwrap();
+        }
+        assert_eq!(
+            sheet.set("A1", SpreadsheetCell::Int(1)).unwrap().len(),
+            100_000
+        );
+        assert_eq!(sheet.value("A100000"), Ok(Value::Number(100_000.0)));
+
+        let nested = format!("={}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(Formula::parse(&nested), Err(SheetError::TooDeep));
+        let negated = format!("={}1", "-".repeat(100_000));
+        assert_eq!(Formula::parse(&negated), Err(SheetError::TooDeep));
+        let summed = format!("={}1", "1+".repeat(100_000));
+        assert_eq!(Formula::parse(&summed), Err(SheetError::TooDeep));
+        assert!(Formula::parse(&format!("={}1", "1+".repeat(200))).is_ok());
+    }
+
+    #[test]
+    fn references_need_letters_then_digits() {
+        assert_eq!(CellRef::parse("b12"), Ok(CellRef::new(11, 1)));
+        for bad in ["+5", "A+5", "A-5", "A 5", "A0", "5", "A"] {
+            assert!(CellRef::parse(bad).is_err(), "{}", bad);
+        }
+        assert!(Formula::parse("=A+5").is_err());
+    }
+}
@@ -0,0 +1,268 @@
+// A bounded-memory quantile sketch (KLL style) for inputs too large to sort.
+// Values land in level 0. When a level fills up it is sorted and every other value
+// is promoted to the next level with double the weight, so memory grows with the
+// log of the input size instead of linearly. Sketches built on separate shards can
+// be merged and the result is as accurate as one sketch over all of the input.
+
+use crate::stats::{Sample, StatsError};
+use std::cmp::Ordering;
+
+// Each level above is allowed roughly 2/3 of the capacity of the level below it
+const CAPACITY_DECAY: f64 = 2.0 / 3.0;
+const MIN_CAPACITY: usize = 2;
+
+#[derive(Debug, Clone)]
+pub struct QuantileSketch {
+    k: usize, // <- capacity of the top level, derived from the error bound
+    epsilon: f64,
+    levels: Vec<Vec<f64>>, // <- an item at level h stands in for 2^h inputs
+    count: u64,
+    min: Option<f64>,
+    max: Option<f64>,
+    seed: u64, // <- state for the coin flip used when compacting
+}
+
+impl QuantileSketch {
+    // `epsilon` is the target rank error, e.g. 0.01 means a query for the median
+    // returns a value whose true rank is within 1% of the middle.
+    pub fn new(epsilon: f64) -> Result<QuantileSketch, StatsError> {
+        if !(epsilon > 0.0 && epsilon < 1.0) {
+            return Err(StatsError::InvalidErrorBound(epsilon));
+        }
+        // KLL's rank error is roughly 1.65 / k with high probability
+        let k = ((1.65 / epsilon).ceil() as usize).max(MIN_CAPACITY * 4);
+        Ok(QuantileSketch {
+            k,
+            epsilon,
+            levels: vec![Vec::new()],
+            count: 0,
+            min: None,
+            max: None,
+            seed: 0x9E37_79B9_7F4A_7C15,
+        })
+    }
+
+    pub fn push<T: Sample>(&mut self, value: T) {
+        let x = value.to_f64();
+        if x.is_nan() {
+            return;
+        }
+        self.count += 1;
+        self.min = Some(self.min.map_or(x, |m| m.min(x)));
+        self.max = Some(self.max.map_or(x, |m| m.max(x)));
+        self.levels[0].push(x);
+        if self.levels[0].len() >= self.capacity(0) {
+            self.compress();
+        }
+    }
+
+    // Fold another sketch into this one. Both shards keep their weights, so the
+    // merged sketch answers queries over the combined input.
+    pub fn merge(&mut self, other: &QuantileSketch) {
+        while self.levels.len() < other.levels.len() {
+            self.levels.push(Vec::new());
+        }
+        for (h, level) in other.levels.iter().enumerate() {
+            self.levels[h].extend_from_slice(level);
+        }
+        self.count += other.count;
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        self.compress();
+    }
+
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn error_bound(&self) -> f64 {
+        self.epsilon
+    }
+
+    // Number of values actually held in memory
+    pub fn retained(&self) -> usize {
+        self.levels.iter().map(|level| level.len()).sum()
+    }
+
+    pub fn min(&self) -> Result<f64, StatsError> {
+        self.min.ok_or(StatsError::Empty)
+    }
+
+    pub fn max(&self) -> Result<f64, StatsError> {
+        self.max.ok_or(StatsError::Empty)
+    }
+
+    pub fn median(&self) -> Result<f64, StatsError> {
+        self.quantile(0.5)
+    }
+
+    pub fn quantile(&self, q: f64) -> Result<f64, StatsError> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(StatsError::InvalidQuantile(q));
+        }
+        if self.count == 0 {
+            return Err(StatsError::Empty);
+        }
+        // The extremes are tracked exactly, so don't approximate them
+        if q == 0.0 {
+            return self.min();
+        }
+        if q == 1.0 {
+            return self.max();
+        }
+        let weighted = self.weighted_items();
+        let total: u64 = weighted.iter().map(|&(_, w)| w).sum();
+        let target = q * total as f64;
+        let mut seen = 0;
+        for &(value, weight) in &weighted {
+            seen += weight;
+            if seen as f64 >= target {
+                return Ok(value);
+            }
+        }
+        self.max()
+    }
+
+    // Approximate fraction of the input that is less than or equal to `value`
+    pub fn rank<T: Sample>(&self, value: T) -> Result<f64, StatsError> {
+        if self.count == 0 {
+            return Err(StatsError::Empty);
+        }
+        let x = value.to_f64();
+        let mut below = 0;
+        let mut total = 0;
+        for (value, weight) in self.weighted_items() {
+            total += weight;
+            if value <= x {
+                below += weight;
+            }
+        }
+        Ok(below as f64 / total as f64)
+    }
+
+    fn weighted_items(&self) -> Vec<(f64, u64)> {
+        let mut items: Vec<(f64, u64)> = Vec::with_capacity(self.retained());
+        for (h, level) in self.levels.iter().enumerate() {
+            let weight = 1u64 << h;
+            items.extend(level.iter().map(|&value| (value, weight)));
+        }
+        items.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        items
+    }
+
+    fn capacity(&self, h: usize) -> usize {
+        let depth = (self.levels.len() - h - 1) as i32;
+        let capacity = (self.k as f64 * CAPACITY_DECAY.powi(depth)).ceil() as usize;
+        capacity.max(MIN_CAPACITY)
+    }
+
+    // Compact every level that is over capacity, lowest level first
+    fn compress(&mut self) {
+        let mut h = 0;
+        while h < self.levels.len() {
+            if self.levels[h].len() >= self.capacity(h) {
+                if h + 1 == self.levels.len() {
+                    self.levels.push(Vec::new());
+                }
+                let promoted = self.compact(h);
+                self.levels[h + 1].extend(promoted);
+            }
+            h += 1;
+        }
+    }
+
+    // Sort a level and keep either the odd or even positioned values, chosen by a
+    // coin flip so the error doesn't drift in one direction.
+    fn compact(&mut self, h: usize) -> Vec<f64> {
+        let offset = self.flip_coin();
+        let smallest = (self.seed >> 1) & 1 == 0; // <- an independent bit of the same draw
+        let level = &mut self.levels[h];
+        level.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        // With an odd count one value stays behind so no weight is lost. Which end
+        // it comes from is another coin flip, or the largest values would always
+        // be held back and estimates would creep upwards.
+        let leftover = match level.len() % 2 {
+            0 => None,
+            _ if smallest => Some(level.remove(0)),
+            _ => level.pop(),
+        };
+        let promoted = level.iter().skip(offset).step_by(2).copied().collect();
+        level.clear();
+        level.extend(leftover);
+        promoted
+    }
+
+    // xorshift64, good enough for a fair coin and keeps runs reproducible
+    fn flip_coin(&mut self) -> usize {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed & 1) as usize
+    }
+}
+
+impl<T: Sample> Extend<T> for QuantileSketch {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank_error(sketch: &QuantileSketch, q: f64, n: u64) -> f64 {
+        (sketch.quantile(q).unwrap() / n as f64 - q).abs()
+    }
+
+    #[test]
+    fn median_is_within_the_error_bound() {
+        let n = 100_000;
+        let mut sketch = QuantileSketch::new(0.01).unwrap();
+        sketch.extend(0..n);
+        assert_eq!(sketch.len(), n);
+        assert!(sketch.retained() < 2_000);
+        for q in [0.1, 0.25, 0.5, 0.75, 0.9] {
+            assert!(rank_error(&sketch, q, n) <= 0.02, "q = {}", q);
+        }
+        assert_eq!(sketch.min(), Ok(0.0));
+        assert_eq!(sketch.max(), Ok((n - 1) as f64));
+    }
+
+    #[test]
+    fn merged_shards_answer_for_the_whole_input() {
+        let n = 50_000;
+        let mut low = QuantileSketch::new(0.01).unwrap();
+        let mut high = QuantileSketch::new(0.01).unwrap();
+        low.extend(0..n / 2);
+        high.extend(n / 2..n);
+        low.merge(&high);
+        assert_eq!(low.len(), n);
+        assert!(rank_error(&low, 0.5, n) <= 0.02);
+        assert!((low.rank(n / 4).unwrap() - 0.25).abs() <= 0.02);
+    }
+
+    #[test]
+    fn rejects_bad_bounds_and_empty_queries() {
+        assert!(QuantileSketch::new(0.0).is_err());
+        assert!(QuantileSketch::new(1.0).is_err());
+        let sketch = QuantileSketch::new(0.1).unwrap();
+        assert_eq!(sketch.median(), Err(StatsError::Empty));
+        assert_eq!(
+            sketch.quantile(-0.5),
+            Err(StatsError::InvalidQuantile(-0.5))
+        );
+    }
+}
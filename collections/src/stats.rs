@@ -0,0 +1,384 @@
+// A streaming descriptive-statistics accumulator.
+// Values are pushed one at a time (or collected from an iterator) and the running
+// mean/variance are kept with Welford's algorithm, so the sum never overflows the
+// input type. Only a handful of numbers are kept however much is pushed; exact
+// quantiles and modes need every value, so they are opt-in through `ExactStats`.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+// Any numeric type we want to summarise needs to be comparable, copyable and
+// convertible to f64 for the floating point parts of the math.
+pub trait Sample: Copy + PartialOrd + fmt::Debug {
+    fn to_f64(self) -> f64;
+}
+
+// A macro saves us from writing the same impl block for every primitive.
+macro_rules! impl_sample {
+    ($($t:ty),*) => {
+        $(
+            impl Sample for $t {
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )*
+    };
+}
+
+impl_sample!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatsError {
+    Empty,                  // <- no values have been pushed
+    InvalidQuantile(f64),   // <- quantiles must be within 0.0..=1.0
+    InvalidErrorBound(f64), // <- sketch error bounds must be within 0.0..1.0
+}
+
+impl fmt::Display for StatsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StatsError::Empty => write!(f, "no values to summarise"),
+            StatsError::InvalidQuantile(q) => {
+                write!(f, "quantile {} is outside the range 0.0..=1.0", q)
+            }
+            StatsError::InvalidErrorBound(e) => {
+                write!(f, "error bound {} is outside the range 0.0..1.0", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StatsError {}
+
+#[derive(Debug, Clone)]
+pub struct Stats<T: Sample> {
+    count: usize,
+    mean: f64,
+    m2: f64, // <- running sum of squared distances from the mean (Welford)
+    min: Option<T>,
+    max: Option<T>,
+    skipped: usize, // <- values that could not be ordered, e.g. NaN
+}
+
+impl<T: Sample> Default for Stats<T> {
+    fn default() -> Self {
+        Stats::new()
+    }
+}
+
+impl<T: Sample> Stats<T> {
+    pub fn new() -> Stats<T> {
+        Stats {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: None,
+            max: None,
+            skipped: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        // NaN is not equal to itself, so it can't be placed in a sorted order
+        if value.partial_cmp(&value).is_none() {
+            self.skipped += 1;
+            return;
+        }
+        self.count += 1;
+
+        let x = value.to_f64();
+        let n = self.count as f64;
+        let delta = x - self.mean;
+        self.mean += delta / n;
+        self.m2 += delta * (x - self.mean);
+
+        match self.min {
+            Some(min) if min <= value => {}
+            _ => self.min = Some(value),
+        }
+        match self.max {
+            Some(max) if max >= value => {}
+            _ => self.max = Some(value),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    pub fn mean(&self) -> Result<f64, StatsError> {
+        self.check_empty()?;
+        Ok(self.mean)
+    }
+
+    // Population variance (divide by n)
+    pub fn variance(&self) -> Result<f64, StatsError> {
+        self.check_empty()?;
+        Ok(self.m2 / self.count as f64)
+    }
+
+    // Sample variance (divide by n - 1), zero when there is only one value
+    pub fn sample_variance(&self) -> Result<f64, StatsError> {
+        self.check_empty()?;
+        if self.count < 2 {
+            return Ok(0.0);
+        }
+        Ok(self.m2 / (self.count - 1) as f64)
+    }
+
+    pub fn stddev(&self) -> Result<f64, StatsError> {
+        Ok(self.variance()?.sqrt())
+    }
+
+    pub fn sample_stddev(&self) -> Result<f64, StatsError> {
+        Ok(self.sample_variance()?.sqrt())
+    }
+
+    pub fn min(&self) -> Result<T, StatsError> {
+        self.min.ok_or(StatsError::Empty)
+    }
+
+    pub fn max(&self) -> Result<T, StatsError> {
+        self.max.ok_or(StatsError::Empty)
+    }
+
+    fn check_empty(&self) -> Result<(), StatsError> {
+        if self.count == 0 {
+            return Err(StatsError::Empty);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Sample> Extend<T> for Stats<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T: Sample> FromIterator<T> for Stats<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut stats = Stats::new();
+        stats.extend(iter);
+        stats
+    }
+}
+
+// Exact medians, quantiles and modes need every value, so they live here rather
+// than in `Stats`: opt in when the input fits in memory, otherwise use
+// `QuantileSketch` for approximate quantiles.
+#[derive(Debug, Clone)]
+pub struct ExactStats<T: Sample> {
+    stats: Stats<T>,
+    values: Vec<T>,
+    sorted: bool,
+}
+
+impl<T: Sample> Default for ExactStats<T> {
+    fn default() -> Self {
+        ExactStats::new()
+    }
+}
+
+impl<T: Sample> ExactStats<T> {
+    pub fn new() -> ExactStats<T> {
+        ExactStats {
+            stats: Stats::new(),
+            values: Vec::new(),
+            sorted: true,
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        let skipped = self.stats.skipped();
+        self.stats.push(value);
+        if self.stats.skipped() > skipped {
+            return;
+        }
+        if let Some(&last) = self.values.last() {
+            if value < last {
+                self.sorted = false;
+            }
+        }
+        self.values.push(value);
+    }
+
+    // The streaming figures: count, mean, variance, min and max
+    pub fn stats(&self) -> &Stats<T> {
+        &self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn median(&mut self) -> Result<f64, StatsError> {
+        self.quantile(0.5)
+    }
+
+    // Linear interpolation between the two closest ranks, so the median of an
+    // even-length list is the average of the middle pair rather than truncated.
+    pub fn quantile(&mut self, q: f64) -> Result<f64, StatsError> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(StatsError::InvalidQuantile(q));
+        }
+        self.stats.check_empty()?;
+        self.sort();
+        let rank = q * (self.values.len() - 1) as f64;
+        let lower = rank.floor() as usize;
+        let upper = rank.ceil() as usize;
+        let low = self.values[lower].to_f64();
+        let high = self.values[upper].to_f64();
+        Ok(low + (high - low) * (rank - lower as f64))
+    }
+
+    // Every value that shares the highest count, in ascending order
+    pub fn modes(&mut self) -> Result<Vec<T>, StatsError> {
+        self.stats.check_empty()?;
+        self.sort();
+        let mut modes = Vec::new();
+        let mut best = 0;
+        let mut i = 0;
+        while i < self.values.len() {
+            let value = self.values[i];
+            let mut run = 1;
+            while i + run < self.values.len() && self.values[i + run] == value {
+                run += 1;
+            }
+            match run.cmp(&best) {
+                Ordering::Greater => {
+                    best = run;
+                    modes.clear();
+                    modes.push(value);
+                }
+                Ordering::Equal => modes.push(value),
+                Ordering::Less => {}
+            }
+            i += run;
+        }
+        Ok(modes)
+    }
+
+    pub fn summary(&mut self) -> Result<Summary<T>, StatsError> {
+        Ok(Summary {
+            count: self.len(),
+            mean: self.stats.mean()?,
+            stddev: self.stats.stddev()?,
+            min: self.stats.min()?,
+            q1: self.quantile(0.25)?,
+            median: self.median()?,
+            q3: self.quantile(0.75)?,
+            max: self.stats.max()?,
+            modes: self.modes()?,
+        })
+    }
+
+    fn sort(&mut self) {
+        if !self.sorted {
+            // NaN never makes it into `values`, so partial_cmp always succeeds
+            self.values
+                .sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+            self.sorted = true;
+        }
+    }
+}
+
+impl<T: Sample> Extend<T> for ExactStats<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T: Sample> FromIterator<T> for ExactStats<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut stats = ExactStats::new();
+        stats.extend(iter);
+        stats
+    }
+}
+
+// A snapshot of the most common figures, handy for printing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary<T: Sample> {
+    pub count: usize,
+    pub mean: f64,
+    pub stddev: f64,
+    pub min: T,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub max: T,
+    pub modes: Vec<T>,
+}
+
+impl<T: Sample> fmt::Display for Summary<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "count: {}, mean: {}, stddev: {}, min: {:?}, q1: {}, median: {}, q3: {}, max: {:?}, modes: {:?}",
+            self.count,
+            self.mean,
+            self.stddev,
+            self.min,
+            self.q1,
+            self.median,
+            self.q3,
+            self.max,
+            self.modes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streaming_figures_match_the_textbook() {
+        let stats: Stats<i32> = [2, 4, 4, 4, 5, 5, 7, 9].into_iter().collect();
+        assert_eq!(stats.len(), 8);
+        assert_eq!(stats.mean(), Ok(5.0));
+        assert_eq!(stats.variance(), Ok(4.0));
+        assert_eq!(stats.stddev(), Ok(2.0));
+        assert_eq!(stats.min(), Ok(2));
+        assert_eq!(stats.max(), Ok(9));
+    }
+
+    #[test]
+    fn nan_is_skipped_and_empty_is_an_error() {
+        let mut stats = Stats::new();
+        assert_eq!(stats.mean(), Err(StatsError::Empty));
+        stats.push(f64::NAN);
+        assert!(stats.is_empty());
+        assert_eq!(stats.skipped(), 1);
+        stats.push(1.5);
+        assert_eq!(stats.sample_variance(), Ok(0.0));
+    }
+
+    #[test]
+    fn exact_median_interpolates_and_modes_keep_ties() {
+        let mut exact: ExactStats<i32> = [3, 1, 2, 2, 4, 4].into_iter().collect();
+        assert_eq!(exact.median(), Ok(2.5));
+        assert_eq!(exact.modes(), Ok(vec![2, 4]));
+        assert_eq!(exact.quantile(0.0), Ok(1.0));
+        assert_eq!(exact.quantile(1.5), Err(StatsError::InvalidQuantile(1.5)));
+        assert_eq!(exact.stats().mean(), Ok(16.0 / 6.0));
+    }
+}
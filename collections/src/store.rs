@@ -0,0 +1,381 @@
+// A tiny on-disk key-value store for the String -> i32 maps in `maps`.
+// Every change is appended to a log file as a checksummed record, and opening the
+// store replays the log to rebuild the map. If the program crashed half way
+// through a write, the torn record at the end fails its checksum and is cut off;
+// a bad record with good ones after it is damage, and opening fails. Once the log
+// holds mostly overwritten records it is compacted by writing the live entries to
+// a new file and renaming it over the old one.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const OP_INSERT: u8 = 1;
+const OP_REMOVE: u8 = 2;
+// checksum (4) + op (1) + key length (4) + value (4), plus the key itself
+const HEADER_LEN: usize = 13;
+// Don't bother compacting small logs
+const MIN_COMPACT_RECORDS: usize = 1024;
+
+pub struct DiskMap {
+    path: PathBuf,
+    file: File,
+    map: HashMap<String, i32>,
+    records: usize, // <- records in the log, live or not
+}
+
+impl DiskMap {
+    // Open (or create) a store, replaying the log and discarding any torn tail.
+    // A damaged record anywhere else is an InvalidData error.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<DiskMap> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut map = HashMap::new();
+        let mut records = 0;
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let (op, key, value, len) = match decode(&bytes[offset..]) {
+                Ok(record) => record,
+                Err(BadRecord::Damaged(len)) if offset + len < bytes.len() => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{}: damaged record at byte {} with more records after it",
+                            path.display(),
+                            offset
+                        ),
+                    ));
+                }
+                Err(_) => break, // <- the last record, torn by an interrupted write
+            };
+            match op {
+                OP_INSERT => map.insert(key, value),
+                _ => map.remove(&key),
+            };
+            records += 1;
+            offset += len;
+        }
+        if offset < bytes.len() {
+            // Everything after the last good record is from an interrupted write
+            file.set_len(offset as u64)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        Ok(DiskMap {
+            path,
+            file,
+            map,
+            records,
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<i32> {
+        self.map.get(key).copied()
+    }
+
+    // The same behaviour as `maps::access_map`: 0 for a missing key
+    pub fn get_or_zero(&self, key: &str) -> i32 {
+        self.get(key).unwrap_or(0)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.map.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: &str, value: i32) -> io::Result<Option<i32>> {
+        self.append(OP_INSERT, key, value)?;
+        let old = self.map.insert(key.to_string(), value);
+        self.compact_if_wasteful();
+        Ok(old)
+    }
+
+    pub fn remove(&mut self, key: &str) -> io::Result<Option<i32>> {
+        if !self.map.contains_key(key) {
+            return Ok(None);
+        }
+        self.append(OP_REMOVE, key, 0)?;
+        let old = self.map.remove(key);
+        self.compact_if_wasteful();
+        Ok(old)
+    }
+
+    pub fn entry(&mut self, key: &str) -> Entry<'_> {
+        Entry {
+            store: self,
+            key: key.to_string(),
+        }
+    }
+
+    // Entries in key order, so listings are repeatable
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i32)> {
+        let mut entries: Vec<(&str, i32)> =
+            self.map.iter().map(|(k, &v)| (k.as_str(), v)).collect();
+        entries.sort();
+        entries.into_iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    // Make sure everything written so far has reached the disk
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    // Rewrite the log with one record per live key. The new log is written to a
+    // side file and renamed into place, so a crash leaves either the old or the
+    // new log, never a mix. The side file's handle becomes the store's, so a
+    // failure at any step leaves `file` pointing at whichever log is in place.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".compact");
+        let tmp_path = PathBuf::from(tmp_path);
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            let mut buffer = Vec::new();
+            for (key, &value) in &self.map {
+                buffer.extend(encode(OP_INSERT, key, value));
+            }
+            tmp.write_all(&buffer)?;
+            tmp.sync_all()?;
+        }
+        let file = OpenOptions::new().read(true).append(true).open(&tmp_path)?;
+        fs::rename(&tmp_path, &self.path)?;
+        self.file = file;
+        self.records = self.map.len();
+        // The rename itself only survives a crash once the directory is synced
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()
+    }
+
+    // Only called once the map holds the change just logged, so the compacted log
+    // is written from the new state rather than the one before it
+    fn compact_if_wasteful(&mut self) {
+        if self.records >= MIN_COMPACT_RECORDS && self.records > self.map.len() * 2 {
+            // The change is already in the log, so a failed compaction loses
+            // nothing; the old log stays in place and the next write tries again
+            let _ = self.compact();
+        }
+    }
+
+    fn append(&mut self, op: u8, key: &str, value: i32) -> io::Result<()> {
+        // One write call per record keeps a crash from interleaving two records
+        let end = self.file.stream_position()?;
+        if let Err(e) = self.file.write_all(&encode(op, key, value)) {
+            // Cut off whatever part of the record made it out, or every record
+            // after it would be lost behind the torn one on the next open
+            let _ = self.file.set_len(end);
+            return Err(e);
+        }
+        self.records += 1;
+        Ok(())
+    }
+}
+
+// Mirrors `HashMap::entry` closely enough for the counting pattern in
+// `maps::update_map`, but every call that changes a value returns io::Result.
+pub struct Entry<'a> {
+    store: &'a mut DiskMap,
+    key: String,
+}
+
+impl<'a> Entry<'a> {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn or_insert(self, default: i32) -> io::Result<i32> {
+        match self.store.get(&self.key) {
+            Some(value) => Ok(value),
+            None => {
+                self.store.insert(&self.key, default)?;
+                Ok(default)
+            }
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut i32)>(self, f: F) -> io::Result<Entry<'a>> {
+        if let Some(mut value) = self.store.get(&self.key) {
+            f(&mut value);
+            self.store.insert(&self.key, value)?;
+        }
+        Ok(self)
+    }
+}
+
+fn encode(op: u8, key: &str, value: i32) -> Vec<u8> {
+    let mut record = Vec::with_capacity(HEADER_LEN + key.len());
+    record.extend([0; 4]); // <- checksum goes here once the rest is written
+    record.push(op);
+    record.extend((key.len() as u32).to_le_bytes());
+    record.extend(value.to_le_bytes());
+    record.extend(key.as_bytes());
+    let checksum = crc32(&record[4..]);
+    record[..4].copy_from_slice(&checksum.to_le_bytes());
+    record
+}
+
+// Why `decode` couldn't read a record
+enum BadRecord {
+    Short,          // <- the log ends before the record does
+    Damaged(usize), // <- all of its bytes are there, but they're wrong
+}
+
+// Returns (op, key, value, record length)
+fn decode(bytes: &[u8]) -> Result<(u8, String, i32, usize), BadRecord> {
+    let field = |range: std::ops::Range<usize>| -> [u8; 4] {
+        bytes[range].try_into().expect("fields are 4 bytes")
+    };
+    if bytes.len() < HEADER_LEN {
+        return Err(BadRecord::Short);
+    }
+    let checksum = u32::from_le_bytes(field(0..4));
+    let op = bytes[4];
+    let key_len = u32::from_le_bytes(field(5..9)) as usize;
+    let value = i32::from_le_bytes(field(9..13));
+    let end = HEADER_LEN.saturating_add(key_len);
+    if bytes.len() < end {
+        return Err(BadRecord::Short);
+    }
+    let damaged = BadRecord::Damaged(end);
+    if crc32(&bytes[4..end]) != checksum || (op != OP_INSERT && op != OP_REMOVE) {
+        return Err(damaged);
+    }
+    let key = String::from_utf8(bytes[HEADER_LEN..end].to_vec()).map_err(|_| damaged)?;
+    Ok((op, key, value, end))
+}
+
+// CRC-32 (IEEE), computed bit by bit since the records are small
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    // A fresh log file per test, so tests running in parallel don't share one
+    fn temp_log(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("diskmap_{}_{}.log", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn values_survive_reopening() {
+        let path = temp_log("reopen");
+        let mut store = DiskMap::open(&path).unwrap();
+        store.insert("a", 1).unwrap();
+        store.insert("b", 2).unwrap();
+        store.entry("a").and_modify(|v| *v += 10).unwrap();
+        store.remove("b").unwrap();
+        drop(store);
+        let store = DiskMap::open(&path).unwrap();
+        assert_eq!(store.iter().collect::<Vec<_>>(), [("a", 11)]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compaction_keeps_the_write_that_triggered_it() {
+        let path = temp_log("compact");
+        let mut store = DiskMap::open(&path).unwrap();
+        store.insert("gone", 1).unwrap();
+        for i in 0..MIN_COMPACT_RECORDS as i32 - 2 {
+            store.insert("k", i).unwrap();
+        }
+        // This insert is the one that tips the log over into compacting
+        store.insert("k", 9999).unwrap();
+        assert!(store.records < MIN_COMPACT_RECORDS);
+        for i in 0..MIN_COMPACT_RECORDS as i32 {
+            store.insert("x", i).unwrap();
+        }
+        store.remove("gone").unwrap();
+        drop(store);
+        let store = DiskMap::open(&path).unwrap();
+        assert_eq!(store.get("k"), Some(9999));
+        assert_eq!(store.get("gone"), None);
+        assert_eq!(store.get("x"), Some(MIN_COMPACT_RECORDS as i32 - 1));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_torn_last_record_is_cut_off() {
+        let path = temp_log("torn");
+        let mut store = DiskMap::open(&path).unwrap();
+        store.insert("kept", 1).unwrap();
+        store.insert("torn", 2).unwrap();
+        drop(store);
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+        let mut store = DiskMap::open(&path).unwrap();
+        assert_eq!(store.get("torn"), None);
+        store.insert("after", 3).unwrap();
+        drop(store);
+        let store = DiskMap::open(&path).unwrap();
+        assert_eq!(
+            store.iter().collect::<Vec<_>>(),
+            [("after", 3), ("kept", 1)]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn damage_before_the_last_record_is_an_error() {
+        let path = temp_log("damaged");
+        let mut store = DiskMap::open(&path).unwrap();
+        store.insert("first", 1).unwrap();
+        store.insert("second", 2).unwrap();
+        store.insert("third", 3).unwrap();
+        drop(store);
+        let mut bytes = fs::read(&path).unwrap();
+        let second = HEADER_LEN + "first".len();
+        bytes[second + HEADER_LEN] ^= 0xFF; // <- a flipped bit in "second"
+        fs::write(&path, &bytes).unwrap();
+        let error = DiskMap::open(&path).err().expect("a damaged log");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+
+        // The same damage in the last record is a torn write
+        let last = bytes.len() - 1;
+        bytes[second + HEADER_LEN] ^= 0xFF;
+        bytes[last] ^= 0xFF;
+        fs::write(&path, &bytes).unwrap();
+        let store = DiskMap::open(&path).unwrap();
+        assert_eq!(
+            store.iter().collect::<Vec<_>>(),
+            [("first", 1), ("second", 2)]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
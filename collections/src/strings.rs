@@ -1,3 +1,7 @@
+pub mod rope;
+
+pub use rope::Rope;
+
 pub fn new_string() -> String {
     let mut s = String::new();
     s.push_str("Hello, ");
@@ -32,3 +36,72 @@ pub fn string_format() {
     let s = format!("{} {}{}", s1, s2, s3); // this is more efficient
     println!("String: {}", s);
 }
+
+pub fn string_rope() {
+    // For big buffers that get edited in the middle, a rope avoids copying the tail
+    let mut rope = Rope::from("Hello world!\nI am a wombat");
+    rope.insert(5, ",");
+    rope.remove(7..13); // <- char positions, not bytes
+    rope.push_str("\nCafe\u{301} au lait");
+    println!("Rope: {:?} has {} lines", rope, rope.len_lines());
+    println!("Line 1: {:?}", rope.line(1));
+    println!(
+        "First 4 graphemes of line 2: {}",
+        rope.slice_graphemes(22..26)
+    );
+}
+
+// Some characters don't stand on their own but modify the one before them, like a
+// combining accent or an emoji skin tone. This covers the common ranges; a full
+// implementation would use the Unicode grapheme break tables.
+pub fn is_grapheme_extend(c: char) -> bool {
+    matches!(c as u32,
+        0x0300..=0x036F // combining diacritical marks
+        | 0x0483..=0x0489 // cyrillic combining marks
+        | 0x0591..=0x05BD // hebrew points
+        | 0x064B..=0x065F // arabic harakat
+        | 0x0900..=0x0903 | 0x093A..=0x094F // devanagari signs and vowel marks
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200C..=0x200D // zero width (non-)joiner
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F // variation selectors
+        | 0xFE20..=0xFE2F
+        | 0x1F3FB..=0x1F3FF // emoji skin tones
+        | 0xE0020..=0xE007F // emoji tag sequences
+    )
+}
+
+// Split a string into user-perceived characters. Indexing a String by byte or even
+// by char can cut "é" written as 'e' + U+0301 in half, so group them first.
+pub fn graphemes(s: &str) -> Vec<&str> {
+    let mut clusters = Vec::new();
+    let mut start = 0;
+    let mut prev: Option<char> = None;
+    for (i, c) in s.char_indices() {
+        let joins = match prev {
+            None => false,
+            Some('\r') => c == '\n',
+            Some('\u{200D}') => true, // <- zero width joiner glues emoji together
+            Some(p) => is_grapheme_extend(c) || (is_regional(p) && is_regional(c)),
+        };
+        if !joins && i > 0 {
+            clusters.push(&s[start..i]);
+            start = i;
+        }
+        // A pair of regional indicators makes one flag, a third starts a new one
+        prev = if joins && is_regional(c) {
+            None
+        } else {
+            Some(c)
+        };
+    }
+    if start < s.len() {
+        clusters.push(&s[start..]);
+    }
+    clusters
+}
+
+fn is_regional(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
@@ -0,0 +1,622 @@
+// A rope: a string stored as a balanced tree of small chunks.
+// `s1 + &s2` and `String::insert_str` have to copy everything after the insertion
+// point, which gets slow for big buffers. A rope only touches the path from the
+// root to the chunk being edited, so inserting or deleting anywhere is O(log n).
+// The tree is a treap: ordered by position, balanced by random priorities. Every
+// node caches the char, byte and newline counts of its subtree so positions can
+// be found without scanning.
+
+use super::graphemes;
+use std::fmt;
+use std::ops::Range;
+
+// Chunks are kept small so splitting one is cheap
+const MAX_CHUNK_CHARS: usize = 256;
+
+type Link = Option<Box<Node>>;
+
+#[derive(Debug, Clone)]
+struct Node {
+    text: String,
+    priority: u64,
+    left: Link,
+    right: Link,
+    chars: usize, // <- these three are totals for the whole subtree
+    bytes: usize,
+    lines: usize, // <- number of '\n' characters
+}
+
+impl Node {
+    fn new(text: String, priority: u64) -> Box<Node> {
+        let mut node = Box::new(Node {
+            text,
+            priority,
+            left: None,
+            right: None,
+            chars: 0,
+            bytes: 0,
+            lines: 0,
+        });
+        node.update();
+        node
+    }
+
+    fn update(&mut self) {
+        self.chars = self.text.chars().count() + chars(&self.left) + chars(&self.right);
+        self.bytes = self.text.len() + bytes(&self.left) + bytes(&self.right);
+        self.lines = newlines(&self.text) + lines(&self.left) + lines(&self.right);
+    }
+}
+
+fn chars(link: &Link) -> usize {
+    link.as_ref().map_or(0, |n| n.chars)
+}
+
+fn bytes(link: &Link) -> usize {
+    link.as_ref().map_or(0, |n| n.bytes)
+}
+
+fn lines(link: &Link) -> usize {
+    link.as_ref().map_or(0, |n| n.lines)
+}
+
+fn newlines(text: &str) -> usize {
+    text.bytes().filter(|&b| b == b'\n').count()
+}
+
+// Byte offset of the char at `index`, or the end of the string
+fn byte_offset(text: &str, index: usize) -> usize {
+    text.char_indices()
+        .nth(index)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+// Join two trees where everything in `left` comes before everything in `right`.
+// Walks down the right spine of `left` and the left spine of `right` with a
+// stack rather than recursing, so a deep tree can't overflow the call stack.
+fn merge(left: Link, right: Link) -> Link {
+    let mut path: Vec<(Box<Node>, bool)> = Vec::new(); // <- true: its right child is pending
+    let (mut left, mut right) = (left, right);
+    let mut joined = loop {
+        match (left, right) {
+            (None, rest) | (rest, None) => break rest,
+            (Some(mut l), Some(mut r)) => {
+                if l.priority > r.priority {
+                    left = l.right.take();
+                    right = Some(r);
+                    path.push((l, true));
+                } else {
+                    left = Some(l);
+                    right = r.left.take();
+                    path.push((r, false));
+                }
+            }
+        }
+    };
+    while let Some((mut node, pending_right)) = path.pop() {
+        if pending_right {
+            node.right = joined;
+        } else {
+            node.left = joined;
+        }
+        node.update();
+        joined = Some(node);
+    }
+    joined
+}
+
+// Split a tree into the first `index` chars and the rest, again without
+// recursing. Nodes that end up on the left keep their left subtree and wait for
+// a new right child, and the other way round.
+fn split(link: Link, index: usize) -> (Link, Link) {
+    let mut lefts: Vec<Box<Node>> = Vec::new();
+    let mut rights: Vec<Box<Node>> = Vec::new();
+    let mut link = link;
+    let mut index = index;
+    while let Some(mut node) = link {
+        let left_chars = chars(&node.left);
+        let own_chars = node.chars - left_chars - chars(&node.right);
+        if index <= left_chars {
+            link = node.left.take();
+            rights.push(node);
+        } else if index >= left_chars + own_chars {
+            index -= left_chars + own_chars;
+            link = node.right.take();
+            lefts.push(node);
+        } else {
+            // The split point falls inside this node's chunk. The tail keeps the
+            // node's priority, which is already higher than anything below it.
+            let at = byte_offset(&node.text, index - left_chars);
+            let mut tail = Node::new(node.text.split_off(at), node.priority);
+            tail.right = node.right.take();
+            lefts.push(node);
+            rights.push(tail);
+            break;
+        }
+    }
+    let mut left = None;
+    while let Some(mut node) = lefts.pop() {
+        node.right = left;
+        node.update();
+        left = Some(node);
+    }
+    let mut right = None;
+    while let Some(mut node) = rights.pop() {
+        node.left = right;
+        node.update();
+        right = Some(node);
+    }
+    (left, right)
+}
+
+// Merge, but when the chunks either side of the seam would fit in one, make
+// them one, so repeated small edits don't leave a trail of tiny chunks
+fn join(left: Link, right: Link) -> Link {
+    let (Some(last), Some(first)) = (last_chunk(&left), first_chunk(&right)) else {
+        return merge(left, right);
+    };
+    let (last, first) = (last.chars().count(), first.chars().count());
+    if last + first > MAX_CHUNK_CHARS {
+        return merge(left, right);
+    }
+    let total = chars(&left);
+    let (left, tail) = split(left, total - last);
+    let (head, right) = split(right, first);
+    let (Some(mut tail), Some(head)) = (tail, head) else {
+        unreachable!("a whole chunk splits off as one node");
+    };
+    tail.text.push_str(&head.text);
+    tail.update();
+    merge(merge(left, Some(tail)), right)
+}
+
+fn first_chunk(link: &Link) -> Option<&str> {
+    let mut node = link.as_ref()?;
+    while let Some(left) = &node.left {
+        node = left;
+    }
+    Some(&node.text)
+}
+
+fn last_chunk(link: &Link) -> Option<&str> {
+    let mut node = link.as_ref()?;
+    while let Some(right) = &node.right {
+        node = right;
+    }
+    Some(&node.text)
+}
+
+// xorshift64 for treap priorities
+fn next_priority(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+#[derive(Clone)]
+pub struct Rope {
+    root: Link,
+    rng: u64,
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Rope::new()
+    }
+}
+
+impl Rope {
+    pub fn new() -> Rope {
+        Rope {
+            root: None,
+            rng: 0x2545_F491_4F6C_DD1D,
+        }
+    }
+
+    pub fn len_chars(&self) -> usize {
+        chars(&self.root)
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        bytes(&self.root)
+    }
+
+    // Like `str::lines`, except a trailing newline starts one more (empty) line,
+    // which is how editors count them
+    pub fn len_lines(&self) -> usize {
+        lines(&self.root) + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        let len = self.len_chars();
+        self.insert(len, text);
+    }
+
+    // Insert at a char index; panics if the index is past the end, like String
+    pub fn insert(&mut self, char_index: usize, text: &str) {
+        assert!(
+            char_index <= self.len_chars(),
+            "insert index {} is past the end of the rope ({} chars)",
+            char_index,
+            self.len_chars()
+        );
+        if text.is_empty() {
+            return;
+        }
+        let (left, right) = split(self.root.take(), char_index);
+        let middle = self.build(text);
+        self.root = join(join(left, middle), right);
+    }
+
+    // Remove a range of chars
+    pub fn remove(&mut self, range: Range<usize>) {
+        self.check_range(&range);
+        let (left, rest) = split(self.root.take(), range.start);
+        let (_, right) = split(rest, range.end - range.start);
+        self.root = join(left, right);
+    }
+
+    pub fn char_at(&self, char_index: usize) -> Option<char> {
+        let mut link = &self.root;
+        let mut index = char_index;
+        while let Some(node) = link {
+            let left_chars = chars(&node.left);
+            let own_chars = node.chars - left_chars - chars(&node.right);
+            if index < left_chars {
+                link = &node.left;
+            } else if index < left_chars + own_chars {
+                return node.text.chars().nth(index - left_chars);
+            } else {
+                index -= left_chars + own_chars;
+                link = &node.right;
+            }
+        }
+        None
+    }
+
+    // Copy out a range of chars
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.check_range(&range);
+        let mut out = String::new();
+        collect_range(&self.root, range.start, range.end, &mut out);
+        out
+    }
+
+    // Copy out a range of graphemes, so "e" + combining accent stays together.
+    // This walks the whole rope since grapheme boundaries aren't cached.
+    pub fn slice_graphemes(&self, range: Range<usize>) -> String {
+        let text = self.to_string();
+        graphemes(&text)
+            .get(range)
+            .map(|clusters| clusters.concat())
+            .unwrap_or_default()
+    }
+
+    pub fn len_graphemes(&self) -> usize {
+        graphemes(&self.to_string()).len()
+    }
+
+    // Char index where a (zero based) line starts
+    pub fn line_to_char(&self, line: usize) -> Option<usize> {
+        if line == 0 {
+            return Some(0);
+        }
+        if line > lines(&self.root) {
+            return None;
+        }
+        // Find the char just after the `line`th newline
+        let mut link = &self.root;
+        let mut remaining = line;
+        let mut offset = 0;
+        while let Some(node) = link {
+            let left_lines = lines(&node.left);
+            let own_lines = node.lines - left_lines - lines(&node.right);
+            if remaining <= left_lines {
+                link = &node.left;
+            } else if remaining <= left_lines + own_lines {
+                offset += chars(&node.left);
+                let mut seen = left_lines;
+                for (i, c) in node.text.chars().enumerate() {
+                    if c == '\n' {
+                        seen += 1;
+                        if seen == remaining {
+                            return Some(offset + i + 1);
+                        }
+                    }
+                }
+                return None;
+            } else {
+                remaining -= left_lines + own_lines;
+                offset += chars(&node.left) + node.text.chars().count();
+                link = &node.right;
+            }
+        }
+        None
+    }
+
+    // Which line a char index is on
+    pub fn char_to_line(&self, char_index: usize) -> usize {
+        let mut link = &self.root;
+        let mut index = char_index.min(self.len_chars());
+        let mut line = 0;
+        while let Some(node) = link {
+            let left_chars = chars(&node.left);
+            let own_chars = node.chars - left_chars - chars(&node.right);
+            if index < left_chars {
+                link = &node.left;
+            } else if index < left_chars + own_chars {
+                line += lines(&node.left);
+                line += node
+                    .text
+                    .chars()
+                    .take(index - left_chars)
+                    .filter(|&c| c == '\n')
+                    .count();
+                return line;
+            } else {
+                line += node.lines - lines(&node.right);
+                index -= left_chars + own_chars;
+                link = &node.right;
+            }
+        }
+        line
+    }
+
+    // The text of a line without its trailing newline
+    pub fn line(&self, line: usize) -> Option<String> {
+        let start = self.line_to_char(line)?;
+        let end = match self.line_to_char(line + 1) {
+            Some(next) => next - 1, // <- leave out the '\n'
+            None => self.len_chars(),
+        };
+        Some(self.slice(start..end))
+    }
+
+    // The chunks in order; concatenated they make the whole string
+    pub fn chunks(&self) -> Vec<&str> {
+        let mut out = Vec::new();
+        collect_chunks(&self.root, &mut out);
+        out
+    }
+
+    // Build a balanced subtree for new text, one node per chunk
+    fn build(&mut self, text: &str) -> Link {
+        let mut link = None;
+        let mut start = 0;
+        let mut count = 0;
+        for (i, _) in text.char_indices() {
+            if count == MAX_CHUNK_CHARS {
+                let node = Node::new(text[start..i].to_string(), next_priority(&mut self.rng));
+                link = merge(link, Some(node));
+                start = i;
+                count = 0;
+            }
+            count += 1;
+        }
+        let node = Node::new(text[start..].to_string(), next_priority(&mut self.rng));
+        merge(link, Some(node))
+    }
+
+    fn check_range(&self, range: &Range<usize>) {
+        assert!(
+            range.start <= range.end && range.end <= self.len_chars(),
+            "range {:?} is out of bounds for a rope of {} chars",
+            range,
+            self.len_chars()
+        );
+    }
+}
+
+// Copy chars [start, end) in order: find the node holding `start`, remembering
+// the ancestors still to come, then walk on in order until `end`
+fn collect_range(link: &Link, start: usize, end: usize, out: &mut String) {
+    let mut stack: Vec<(&Node, usize)> = Vec::new(); // <- each with where its own text starts
+    let mut link = link;
+    let mut offset = 0;
+    while let Some(node) = link {
+        let text_start = offset + chars(&node.left);
+        let text_end = offset + node.chars - chars(&node.right);
+        if start < text_start {
+            stack.push((node, text_start));
+            link = &node.left;
+        } else if start < text_end {
+            stack.push((node, text_start));
+            break;
+        } else {
+            offset = text_end;
+            link = &node.right;
+        }
+    }
+    while let Some((node, text_start)) = stack.pop() {
+        if text_start >= end {
+            break;
+        }
+        let own_chars = node.chars - chars(&node.left) - chars(&node.right);
+        let from = byte_offset(&node.text, start.saturating_sub(text_start));
+        let to = byte_offset(&node.text, (end - text_start).min(own_chars));
+        out.push_str(&node.text[from..to]);
+        let offset = text_start + own_chars; // <- where the right subtree starts
+        let mut link = &node.right;
+        while let Some(next) = link {
+            stack.push((next, offset + chars(&next.left)));
+            link = &next.left;
+        }
+    }
+}
+
+fn collect_chunks<'a>(link: &'a Link, out: &mut Vec<&'a str>) {
+    let mut stack: Vec<&Node> = Vec::new();
+    let mut link = link;
+    loop {
+        while let Some(node) = link {
+            stack.push(node);
+            link = &node.left;
+        }
+        let Some(node) = stack.pop() else {
+            return;
+        };
+        if !node.text.is_empty() {
+            out.push(&node.text);
+        }
+        link = &node.right;
+    }
+}
+
+// Dropping the boxes one inside the other would recurse as deep as the tree
+impl Drop for Rope {
+    fn drop(&mut self) {
+        let mut stack: Vec<Box<Node>> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        let mut rope = Rope::new();
+        rope.push_str(text);
+        rope
+    }
+}
+
+impl From<String> for Rope {
+    fn from(text: String) -> Self {
+        Rope::from(text.as_str())
+    }
+}
+
+impl From<Rope> for String {
+    fn from(rope: Rope) -> Self {
+        rope.to_string()
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Rope({:?})", self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every node's priority is at least its children's
+    fn is_heap(link: &Link) -> bool {
+        let mut stack: Vec<&Node> = link.iter().map(|n| &**n).collect();
+        while let Some(node) = stack.pop() {
+            for child in [&node.left, &node.right].into_iter().flatten() {
+                if child.priority > node.priority {
+                    return false;
+                }
+                stack.push(child);
+            }
+        }
+        true
+    }
+
+    #[test]
+    fn edits_match_the_same_edits_on_a_string() {
+        let mut rope = Rope::new();
+        let mut expected = String::new();
+        let mut seed = 7u64;
+        for step in 0..500 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let len = expected.chars().count();
+            let at = (seed >> 33) as usize % (len + 1);
+            let byte = expected
+                .char_indices()
+                .nth(at)
+                .map_or(expected.len(), |(i, _)| i);
+            if step % 3 == 2 && at < len {
+                let end = (at + 40).min(len);
+                let end_byte = expected
+                    .char_indices()
+                    .nth(end)
+                    .map_or(expected.len(), |(i, _)| i);
+                rope.remove(at..end);
+                expected.replace_range(byte..end_byte, "");
+            } else {
+                let text = format!("ünï{}\n", step);
+                rope.insert(at, &text);
+                expected.insert_str(byte, &text);
+            }
+        }
+        assert_eq!(rope.to_string(), expected);
+        assert_eq!(rope.len_chars(), expected.chars().count());
+        assert_eq!(rope.len_bytes(), expected.len());
+        assert_eq!(rope.chunks().concat(), expected);
+        assert!(rope
+            .chunks()
+            .iter()
+            .all(|c| c.chars().count() <= MAX_CHUNK_CHARS));
+        assert!(is_heap(&rope.root));
+    }
+
+    #[test]
+    fn small_edits_share_chunks() {
+        let mut rope = Rope::new();
+        for i in 0..1000 {
+            rope.insert(i / 2, "x"); // <- always inside the text, never at an end
+        }
+        rope.remove(100..600);
+        assert_eq!(rope.len_chars(), 500);
+        assert!(rope.chunks().len() < 20); // <- rather than a chunk per char
+        assert!(is_heap(&rope.root));
+    }
+
+    #[test]
+    fn a_lopsided_tree_doesnt_overflow_the_stack() {
+        let mut root: Link = None;
+        for priority in 0..200_000 {
+            let mut node = Node::new(String::from("a"), priority);
+            node.left = root;
+            node.update();
+            root = Some(node);
+        }
+        let mut rope = Rope { root, rng: 1 };
+        rope.insert(100_000, "b");
+        rope.remove(10..20);
+        assert_eq!(rope.slice(99_989..99_992), "aba");
+        assert_eq!(rope.to_string().len(), 199_991);
+    }
+
+    #[test]
+    fn lines_and_chars_convert_both_ways() {
+        let rope = Rope::from("one\ntwo\n\nfour");
+        assert_eq!(rope.len_lines(), 4);
+        assert_eq!(rope.line_to_char(1), Some(4));
+        assert_eq!(rope.line_to_char(4), None);
+        assert_eq!(rope.char_to_line(5), 1);
+        assert_eq!(rope.line(2).as_deref(), Some(""));
+        assert_eq!(rope.line(3).as_deref(), Some("four"));
+        assert_eq!(rope.char_at(4), Some('t'));
+        assert_eq!(rope.slice(4..7), "two");
+    }
+
+    #[test]
+    fn graphemes_keep_combining_marks_together() {
+        let rope = Rope::from("cafe\u{301}!");
+        assert_eq!(rope.len_chars(), 6);
+        assert_eq!(rope.len_graphemes(), 5);
+        assert_eq!(rope.slice_graphemes(3..4), "e\u{301}");
+    }
+}
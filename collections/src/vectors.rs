@@ -1,7 +1,11 @@
-enum SpreadsheetCell {
+use crate::sheet::Formula;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpreadsheetCell {
     Int(i32),
     Float(f64),
     Text(String),
+    Formula(Formula), // <- evaluated by `sheet::Sheet`
 }
 
 pub fn get_vector() -> Vec<i32> {
@@ -67,6 +71,7 @@ pub fn spreadsheet() {
             SpreadsheetCell::Int(value) => println!("Int: {}", value),
             SpreadsheetCell::Text(value) => println!("Text: {}", value),
             SpreadsheetCell::Float(value) => println!("Float: {}", value),
+            SpreadsheetCell::Formula(value) => println!("Formula: {}", value),
         }
     }
 }
@@ -0,0 +1,387 @@
+// Word frequency counting, grown out of the `entry().or_insert(0)` example in
+// `maps::update_map`. Text is split into words, normalised (lowercase, optional
+// accent folding), filtered against a stop-word list and counted either as single
+// words or as n-grams. Counters built on separate threads can be merged.
+//
+// Normalisation composes a Latin letter followed by a combining accent into the
+// single accented letter, so "café" counts the same however it was typed. That
+// covers the Latin-1 and Latin Extended-A letters; other scripts are left as
+// written, since full NFC needs the Unicode composition tables.
+
+use crate::strings::is_grapheme_extend;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::thread;
+
+pub const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "about", "after", "all", "an", "and", "are", "as", "at", "be", "been", "but", "by", "can",
+    "did", "do", "for", "from", "had", "has", "have", "he", "her", "his", "i", "if", "in", "into",
+    "is", "it", "its", "me", "my", "no", "not", "of", "on", "or", "our", "she", "so", "that",
+    "the", "their", "them", "then", "there", "they", "this", "to", "up", "was", "we", "were",
+    "what", "when", "which", "who", "will", "with", "would", "you", "your",
+];
+
+#[derive(Debug, Clone)]
+pub struct WordCounter {
+    counts: HashMap<String, usize>,
+    total: usize,
+    ngram: usize,
+    lowercase: bool,
+    strip_accents: bool,
+    stop_words: Vec<String>, // <- as given; normalised when counting, so option order doesn't matter
+}
+
+impl Default for WordCounter {
+    fn default() -> Self {
+        WordCounter::new()
+    }
+}
+
+impl WordCounter {
+    pub fn new() -> WordCounter {
+        WordCounter {
+            counts: HashMap::new(),
+            total: 0,
+            ngram: 1,
+            lowercase: true,
+            strip_accents: false,
+            stop_words: Vec::new(),
+        }
+    }
+
+    // Count runs of `n` consecutive words ("the cat", "cat sat") instead of words
+    pub fn ngrams(mut self, n: usize) -> WordCounter {
+        self.ngram = n.max(1);
+        self
+    }
+
+    pub fn case_sensitive(mut self) -> WordCounter {
+        self.lowercase = false;
+        self
+    }
+
+    // "café" and "cafe" count as the same word
+    pub fn strip_accents(mut self) -> WordCounter {
+        self.strip_accents = true;
+        self
+    }
+
+    pub fn stop_words<I, S>(mut self, words: I) -> WordCounter
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.stop_words
+            .extend(words.into_iter().map(|w| w.as_ref().to_string()));
+        self
+    }
+
+    // Each call is its own document, so n-grams don't run across two texts
+    pub fn ingest(&mut self, text: &str) {
+        let mut window = VecDeque::with_capacity(self.ngram);
+        let stop_words = self.normalised_stop_words();
+        self.ingest_into(text, &stop_words, &mut window);
+    }
+
+    // Read line by line so large files never have to fit in memory
+    pub fn ingest_reader<R: BufRead>(&mut self, reader: R) -> io::Result<()> {
+        let mut window = VecDeque::with_capacity(self.ngram);
+        let stop_words = self.normalised_stop_words();
+        for line in reader.lines() {
+            self.ingest_into(&line?, &stop_words, &mut window);
+        }
+        Ok(())
+    }
+
+    pub fn ingest_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let file = File::open(path)?;
+        self.ingest_reader(BufReader::new(file))
+    }
+
+    // Split the texts across `workers` threads, each with its own counter, and
+    // merge the results back into this one.
+    pub fn ingest_parallel(&mut self, texts: &[&str], workers: usize) {
+        let workers = workers.max(1);
+        let chunk_size = texts.len().div_ceil(workers).max(1);
+        let partials: Vec<WordCounter> = thread::scope(|scope| {
+            let handles: Vec<_> = texts
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let mut counter = self.empty_like();
+                    scope.spawn(move || {
+                        for text in chunk {
+                            counter.ingest(text);
+                        }
+                        counter
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("word counting thread panicked"))
+                .collect()
+        });
+        for partial in partials {
+            self.merge(partial);
+        }
+    }
+
+    pub fn merge(&mut self, other: WordCounter) {
+        for (word, count) in other.counts {
+            *self.counts.entry(word).or_insert(0) += count;
+        }
+        self.total += other.total;
+    }
+
+    pub fn get(&self, word: &str) -> usize {
+        self.counts.get(&self.normalise(word)).copied().unwrap_or(0)
+    }
+
+    // Total number of words (or n-grams) counted, including repeats
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn unique(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.counts
+            .iter()
+            .map(|(word, &count)| (word.as_str(), count))
+    }
+
+    // The k most frequent entries, ties broken alphabetically so output is stable.
+    // A min-heap of size k keeps this O(n log k) rather than sorting everything.
+    pub fn top_k(&self, k: usize) -> Vec<(&str, usize)> {
+        let mut heap: BinaryHeap<Reverse<Ranked>> = BinaryHeap::with_capacity(k + 1);
+        for (word, &count) in &self.counts {
+            heap.push(Reverse(Ranked { count, word }));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+        let mut top: Vec<(&str, usize)> = heap
+            .into_iter()
+            .map(|Reverse(r)| (r.word.as_str(), r.count))
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        top
+    }
+
+    fn empty_like(&self) -> WordCounter {
+        WordCounter {
+            counts: HashMap::new(),
+            total: 0,
+            ..self.clone()
+        }
+    }
+
+    fn normalised_stop_words(&self) -> HashSet<String> {
+        self.stop_words.iter().map(|w| self.normalise(w)).collect()
+    }
+
+    fn ingest_into(
+        &mut self,
+        text: &str,
+        stop_words: &HashSet<String>,
+        window: &mut VecDeque<String>,
+    ) {
+        for raw in words(text) {
+            let word = self.normalise(raw);
+            if word.is_empty() || stop_words.contains(&word) {
+                continue;
+            }
+            window.push_back(word);
+            if window.len() > self.ngram {
+                window.pop_front();
+            }
+            if window.len() == self.ngram {
+                let key = window
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let count = self.counts.entry(key).or_insert(0);
+                *count += 1;
+                self.total += 1;
+            }
+        }
+    }
+
+    fn normalise(&self, word: &str) -> String {
+        let mut out = String::with_capacity(word.len());
+        for c in compose(word) {
+            let c = if c == '’' { '\'' } else { c };
+            if self.strip_accents {
+                if is_grapheme_extend(c) {
+                    continue; // <- a combining accent written separately
+                }
+                out.push(fold_accent(c));
+            } else {
+                out.push(c);
+            }
+        }
+        if self.lowercase {
+            out = out.to_lowercase();
+        }
+        out
+    }
+}
+
+// Heap entries: more frequent is "greater", and for equal counts the
+// alphabetically earlier word is greater so it survives the cut.
+#[derive(PartialEq, Eq)]
+struct Ranked<'a> {
+    count: usize,
+    word: &'a String,
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.count
+            .cmp(&other.count)
+            .then_with(|| other.word.cmp(self.word))
+    }
+}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Words are letters and digits, with apostrophes allowed inside ("don't")
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '\'' || c == '’' || is_grapheme_extend(c)))
+        .map(|w| w.trim_matches(|c| c == '\'' || c == '’'))
+        .filter(|w| !w.is_empty())
+}
+
+// Combining accents and the letters each one composes with, position for position
+const COMPOSITIONS: &[(char, &str, &str)] = &[
+    ('\u{300}', "AEIOUaeiou", "ÀÈÌÒÙàèìòù"),
+    (
+        '\u{301}',
+        "AEIOUYaeiouyCcLlNnRrSsZz",
+        "ÁÉÍÓÚÝáéíóúýĆćĹĺŃńŔŕŚśŹź",
+    ),
+    (
+        '\u{302}',
+        "AEIOUaeiouCcGgHhJjSsWwYy",
+        "ÂÊÎÔÛâêîôûĈĉĜĝĤĥĴĵŜŝŴŵŶŷ",
+    ),
+    ('\u{303}', "ANOanoIiUu", "ÃÑÕãñõĨĩŨũ"),
+    ('\u{304}', "AaEeIiOoUu", "ĀāĒēĪīŌōŪū"),
+    ('\u{306}', "AaEeGgIiOoUu", "ĂăĔĕĞğĬĭŎŏŬŭ"),
+    ('\u{307}', "CcEeGgIZz", "ĊċĖėĠġİŻż"),
+    ('\u{308}', "AEIOUaeiouyY", "ÄËÏÖÜäëïöüÿŸ"),
+    ('\u{30A}', "AaUu", "ÅåŮů"),
+    ('\u{30B}', "OoUu", "ŐőŰű"),
+    ('\u{30C}', "CcDdEeLlNnRrSsTtZz", "ČčĎďĚěĽľŇňŘřŠšŤťŽž"),
+    ('\u{327}', "CcGgKkLlNnRrSsTt", "ÇçĢģĶķĻļŅņŖŗŞşŢţ"),
+    ('\u{328}', "AaEeIiUu", "ĄąĘęĮįŲų"),
+];
+
+// A letter followed by a combining accent becomes the precomposed letter, when
+// there is one in `COMPOSITIONS`
+fn compose(word: &str) -> Vec<char> {
+    let mut out: Vec<char> = Vec::with_capacity(word.len());
+    for c in word.chars() {
+        let composed = out.last().and_then(|&base| {
+            let (_, bases, letters) = COMPOSITIONS.iter().find(|(mark, _, _)| *mark == c)?;
+            let index = bases.chars().position(|b| b == base)?;
+            letters.chars().nth(index)
+        });
+        match composed {
+            Some(letter) => *out.last_mut().expect("composed onto a letter") = letter,
+            None => out.push(c),
+        }
+    }
+    out
+}
+
+// Precomposed Latin letters with accents mapped to their plain letter
+fn fold_accent(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => 'a',
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' | 'Ă' | 'Ą' => 'A',
+        'ç' | 'ć' | 'č' => 'c',
+        'Ç' | 'Ć' | 'Č' => 'C',
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => 'e',
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ė' | 'Ę' | 'Ě' => 'E',
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'į' => 'i',
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ī' | 'Į' => 'I',
+        'ñ' | 'ń' | 'ň' => 'n',
+        'Ñ' | 'Ń' | 'Ň' => 'N',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => 'o',
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ő' => 'O',
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' | 'ų' => 'u',
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' | 'Ů' | 'Ű' | 'Ų' => 'U',
+        'ý' | 'ÿ' => 'y',
+        'Ý' | 'Ÿ' => 'Y',
+        'š' | 'ś' => 's',
+        'Š' | 'Ś' => 'S',
+        'ž' | 'ź' | 'ż' => 'z',
+        'Ž' | 'Ź' | 'Ż' => 'Z',
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_words_follow_the_final_options_whatever_the_order() {
+        let text = "The the cat";
+        let mut before = WordCounter::new().stop_words(["The"]).case_sensitive();
+        let mut after = WordCounter::new().case_sensitive().stop_words(["The"]);
+        before.ingest(text);
+        after.ingest(text);
+        for counter in [&before, &after] {
+            assert_eq!(counter.get("The"), 0);
+            assert_eq!(counter.get("the"), 1);
+            assert_eq!(counter.total(), 2);
+        }
+    }
+
+    #[test]
+    fn composed_and_decomposed_accents_count_together() {
+        let mut counter = WordCounter::new();
+        counter.ingest("café cafe\u{301} CAFÉ cafe");
+        assert_eq!(counter.get("café"), 3);
+        assert_eq!(counter.get("cafe"), 1);
+        let mut folded = WordCounter::new().strip_accents();
+        folded.ingest("café cafe\u{301} cafe");
+        assert_eq!(folded.get("cafe"), 3);
+    }
+
+    #[test]
+    fn ngrams_and_top_k_break_ties_alphabetically() {
+        let mut counter = WordCounter::new().ngrams(2);
+        counter.ingest("b a b a c");
+        counter.ingest("c d");
+        assert_eq!(counter.top_k(3), vec![("b a", 2), ("a b", 1), ("a c", 1)]);
+        assert_eq!(counter.get("b a"), 2);
+        assert_eq!(counter.get("c c"), 0);
+    }
+
+    #[test]
+    fn parallel_counting_matches_sequential() {
+        let texts = ["one two two", "three three three", "don't stop", "two"];
+        let mut sequential = WordCounter::new();
+        for text in texts {
+            sequential.ingest(text);
+        }
+        let mut parallel = WordCounter::new();
+        parallel.ingest_parallel(&texts, 3);
+        assert_eq!(parallel.total(), sequential.total());
+        assert_eq!(parallel.top_k(10), sequential.top_k(10));
+        assert_eq!(parallel.get("don't"), 1);
+    }
+}
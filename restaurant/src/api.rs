@@ -0,0 +1,1578 @@
+// The restaurant over HTTP, for the point-of-sale terminals. Every endpoint is a
+// thin wrapper over hosting, serving or back_of_house: it checks the request,
+// calls the same function the rest of the crate uses, and turns the result (or
+// the error) into JSON. Errors always come back as
+//
+//     {"error": {"status": 404, "code": "unknown_order", "message": "there is no order #7"}}
+//
+// GET    /menu                        ?available=true for what can be ordered now
+// GET    /menu/{id}
+// GET    /tables
+// POST   /tables/{number}/clear       the party left
+// POST   /tables/{number}/clean       bussed
+// GET    /waitlist                    with estimated waits
+// POST   /waitlist                    {"name", "size", "contact"?}
+// DELETE /waitlist/{id}
+// POST   /waitlist/seat               seat whoever is next
+// POST   /waitlist/{id}/seat
+// POST   /waitlist/{id}/no-show
+// GET    /reservations                still to come, earliest first
+// POST   /reservations                {"name", "size", "time", "contact"?}, time in seconds
+// PUT    /reservations/{id}           {"time", "size"}
+// DELETE /reservations/{id}
+// POST   /reservations/{id}/arrive    seated, or put ahead of the walk-ins
+// POST   /reservations/hold           hold tables for bookings due soon, release no-shows
+// GET    /orders                      ?table= and ?status= to filter
+// POST   /orders                      {"table", "items": [{"menu_id", "choices"?: {modifier: option}, "seat"?}],
+//                                       "guests"?: [{"seat"?, "allergens"?, "diets"?}], "on_conflict"?: "reject" | "flag"}
+// GET    /orders/{id}
+// POST   /orders/{id}/cook
+// POST   /orders/{id}/serve
+// POST   /orders/{id}/void
+// POST   /orders/{id}/remake              {"menu_ids": [...], "reason"}
+// GET    /orders/{id}/receipt         ?guests= and ?tip_percent=
+// POST   /orders/{id}/payments        {"payments": [{"method", "amount_cents"}], "guests"?, "tip_percent"?}
+// GET    /tickets                     open kitchen tickets
+// POST   /tickets/{id}/start          the station has picked it up
+// POST   /tickets/{id}/bump
+// GET    /staff
+// POST   /staff/{id}/clock-in
+// POST   /staff/{id}/clock-out
+// GET    /staff/tips                  ?total_cents= to share out the tip pool
+//
+// With a journal open, every change that succeeds is also written to the event
+// log before the response goes out. A change the log can't take is undone, so
+// the terminals never see anything a restart wouldn't rebuild.
+
+use crate::back_of_house;
+use crate::back_of_house::inventory::InventoryError;
+use crate::back_of_house::kitchen::{Kitchen, KitchenError, Ticket, TicketId};
+use crate::billing::{BillingConfig, BillingError, Check, Payment, PaymentMethod, Receipt, Tip};
+use crate::clock::{Clock, Timestamp};
+use crate::dietary::{Conflict, DietChecker, DietaryError, GuestProfile, OnConflict, Substitute};
+use crate::front_of_house::floor::{FloorError, FloorPlan, Table, TableState};
+use crate::front_of_house::hosting::{self, Party, PartyId, Seating, SeatingError};
+use crate::front_of_house::reservations::{Arrival, ReservationError, ReservationId};
+use crate::front_of_house::serving;
+use crate::front_of_house::staff::{Roster, Staff, StaffError, StaffId, TipPool};
+use crate::http::{Method, Request, Response};
+use crate::journal::{Event, Journal, JournalError, Service};
+use crate::json::{Json, JsonError};
+use crate::menu::{Menu, MenuError};
+use crate::money::{Cents, Rate};
+use crate::order::{guests_json, Order, OrderError, OrderId, OrderItem, OrderStatus};
+use std::fmt;
+use std::time::Duration;
+
+// No check is split more ways than this, or paid with more than this at once
+const MAX_PAYMENTS: usize = 20;
+const MAX_PAYMENT: i64 = 10_000_000; // <- $100,000
+                                     // Bigger parties than this are a private event, not a booking or a walk-in
+const MAX_PARTY: u32 = 100;
+// How far ahead a table can be booked
+const BOOKING_HORIZON: Duration = Duration::from_secs(366 * 24 * 3600);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    pub status: u16,
+    pub code: &'static str, // <- stable, for programs; the message is for people
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: u16, code: &'static str, message: impl fmt::Display) -> ApiError {
+        ApiError {
+            status,
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn invalid(field: &str, expected: &str) -> ApiError {
+        ApiError::new(
+            422,
+            "invalid_field",
+            format!("'{}' must be {}", field, expected),
+        )
+    }
+
+    pub fn to_json(&self) -> Json {
+        let error = Json::object()
+            .with("status", self.status as u32)
+            .with("code", self.code)
+            .with("message", self.message.as_str());
+        Json::object().with("error", error)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: {}", self.status, self.code, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<JsonError> for ApiError {
+    fn from(e: JsonError) -> Self {
+        ApiError::new(400, "invalid_json", e)
+    }
+}
+
+impl From<OrderError> for ApiError {
+    fn from(e: OrderError) -> Self {
+        let (status, code) = match e {
+            OrderError::EmptyOrder => (422, "empty_order"),
+            OrderError::UnknownOrder(_) => (404, "unknown_order"),
+            OrderError::IllegalTransition { .. } => (409, "illegal_transition"),
+        };
+        ApiError::new(status, code, e)
+    }
+}
+
+impl From<MenuError> for ApiError {
+    fn from(e: MenuError) -> Self {
+        let code = match e {
+            MenuError::UnknownItem(_) => "unknown_item",
+            MenuError::UnknownModifier { .. } => "unknown_modifier",
+            MenuError::UnknownOption { .. } => "unknown_option",
+            MenuError::MissingModifier { .. } => "missing_modifier",
+            MenuError::DuplicateModifier { .. } => "duplicate_modifier",
+            MenuError::Json(_) | MenuError::Io(_) | MenuError::Invalid { .. } => {
+                return ApiError::new(500, "menu_error", e);
+            }
+        };
+        ApiError::new(422, code, e)
+    }
+}
+
+impl From<FloorError> for ApiError {
+    fn from(e: FloorError) -> Self {
+        let (status, code) = match e {
+            FloorError::UnknownTable(_) => (404, "unknown_table"),
+            FloorError::UnknownParty(_) => (404, "unknown_party"),
+            FloorError::DuplicateTable(_) | FloorError::NotCombinable(_) => (409, "floor_conflict"),
+            FloorError::TableNotFree(_)
+            | FloorError::TableNotSeated(_)
+            | FloorError::TableNotDirty(_)
+            | FloorError::TableNotReserved(_) => (409, "table_state"),
+        };
+        ApiError::new(status, code, e)
+    }
+}
+
+impl From<SeatingError> for ApiError {
+    fn from(e: SeatingError) -> Self {
+        let (status, code) = match e {
+            SeatingError::Floor(e) => return e.into(),
+            SeatingError::NoGuests => (422, "invalid_field"),
+            SeatingError::UnknownParty(_) => (404, "unknown_party"),
+            SeatingError::EmptyWaitlist => (409, "empty_waitlist"),
+            SeatingError::NoFreeTable => (409, "no_free_table"),
+            SeatingError::NoTableFits { .. } => (409, "no_table_fits"),
+        };
+        ApiError::new(status, code, e)
+    }
+}
+
+impl From<ReservationError> for ApiError {
+    fn from(e: ReservationError) -> Self {
+        let (status, code) = match &e {
+            ReservationError::Seating(e) => return e.clone().into(),
+            ReservationError::UnknownReservation(_) => (404, "unknown_reservation"),
+            ReservationError::NotBooked(_) => (409, "not_booked"),
+            ReservationError::NoGuests
+            | ReservationError::InThePast(_)
+            | ReservationError::TooLarge(_) => (422, "invalid_booking"),
+            ReservationError::Full { .. } => (409, "fully_booked"),
+        };
+        ApiError::new(status, code, e)
+    }
+}
+
+impl From<KitchenError> for ApiError {
+    fn from(e: KitchenError) -> Self {
+        let (status, code) = match e {
+            KitchenError::Order(e) => return e.into(),
+            KitchenError::Menu(e) => return e.into(),
+            KitchenError::Inventory(InventoryError::Shortage { .. }) => (409, "out_of_stock"),
+            KitchenError::Inventory(_) => (500, "inventory_error"),
+            KitchenError::UnknownTicket(_) => (404, "unknown_ticket"),
+            KitchenError::AlreadyStarted(_) | KitchenError::AlreadyDone(_) => (409, "ticket_state"),
+            KitchenError::NothingToRemake(_) => (409, "nothing_to_remake"),
+        };
+        ApiError::new(status, code, e)
+    }
+}
+
+impl From<JournalError> for ApiError {
+    fn from(e: JournalError) -> Self {
+        ApiError::new(500, "journal_error", e)
+    }
+}
+
+impl From<StaffError> for ApiError {
+    fn from(e: StaffError) -> Self {
+        let (status, code) = match e {
+            StaffError::UnknownStaff(_) => (404, "unknown_staff"),
+            StaffError::AlreadyClockedIn(_) | StaffError::NotClockedIn(_) => (409, "clock_state"),
+            StaffError::EmptyShift { .. } | StaffError::OverlappingShift(_) => (422, "bad_shift"),
+        };
+        ApiError::new(status, code, e)
+    }
+}
+
+impl From<DietaryError> for ApiError {
+    fn from(e: DietaryError) -> Self {
+        match e {
+            DietaryError::Order(e) => e.into(),
+            DietaryError::Conflicts(_) => ApiError::new(422, "dietary_conflict", e),
+        }
+    }
+}
+
+impl From<BillingError> for ApiError {
+    fn from(e: BillingError) -> Self {
+        let (status, code) = match e {
+            BillingError::Menu(e) => return e.into(),
+            BillingError::Order(e) => return e.into(),
+            BillingError::Underpaid { .. } => (422, "underpaid"),
+            BillingError::Overpaid { .. } => (422, "overpaid"),
+            BillingError::NoSeats | BillingError::SplitMismatch { .. } => (422, "bad_split"),
+            BillingError::Negative { .. } => (422, "invalid_field"),
+            BillingError::WrongOrder(_) => (409, "wrong_order"),
+            BillingError::PartialReceipt(_) => (409, "partial_receipt"),
+            BillingError::TooLarge => (422, "invalid_field"),
+        };
+        ApiError::new(status, code, e)
+    }
+}
+
+type ApiResult = Result<(u16, Json), ApiError>;
+
+// Everything the front of house and the kitchen share, behind one handler
+pub struct Restaurant<C: Clock> {
+    pub service: Service,
+    pub billing: BillingConfig,
+    journal: Option<Journal>,
+    clock: C,
+}
+
+impl<C: Clock> Restaurant<C> {
+    pub fn new(floor: FloorPlan, menu: Menu, kitchen: Kitchen, clock: C) -> Restaurant<C> {
+        Restaurant {
+            service: Service::new(floor, menu, kitchen),
+            billing: BillingConfig::default(),
+            journal: None,
+            clock,
+        }
+    }
+
+    pub fn with_roster(mut self, roster: Roster) -> Restaurant<C> {
+        self.service.staff = roster;
+        self
+    }
+
+    // Recover whatever was recorded in `dir`, and keep recording there
+    pub fn open_journal(&mut self, dir: impl AsRef<std::path::Path>) -> Result<(), JournalError> {
+        self.journal = Some(Journal::open(dir, &mut self.service)?);
+        Ok(())
+    }
+
+    // Snapshot now rather than waiting for the journal to decide it's time
+    pub fn snapshot(&mut self) -> Result<(), JournalError> {
+        match &mut self.journal {
+            Some(journal) => journal.snapshot(&self.service),
+            None => Ok(()),
+        }
+    }
+
+    fn record(&mut self, event: Event) -> Result<(), ApiError> {
+        if let Some(journal) = &mut self.journal {
+            if let Err(e) = journal.record(&event, &self.service) {
+                journal.rebuild(&mut self.service)?;
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn handle(&mut self, request: &Request) -> Response {
+        match self.route(request) {
+            Ok((status, body)) => Response::json(status, &body),
+            Err(e) => Response::json(e.status, &e.to_json()),
+        }
+    }
+
+    fn route(&mut self, request: &Request) -> ApiResult {
+        let now = self.clock.now();
+        self.service.reservations.clock().set(now);
+        let segments = request.segments();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        match (&request.method, segments.as_slice()) {
+            (Method::Get, ["menu"]) => {
+                let available = request.query("available") == Some("true");
+                let items: Vec<Json> = self
+                    .service
+                    .menu
+                    .items()
+                    .filter(|i| !available || i.is_available(now))
+                    .map(|i| i.to_json())
+                    .collect();
+                Ok((200, Json::from(items)))
+            }
+            (Method::Get, ["menu", id]) => match self.service.menu.get(id) {
+                Some(item) => Ok((200, item.to_json())),
+                None => Err(ApiError::new(
+                    404,
+                    "unknown_item",
+                    MenuError::UnknownItem(id.to_string()),
+                )),
+            },
+
+            (Method::Get, ["tables"]) => {
+                let tables: Vec<Json> = self
+                    .service
+                    .floor
+                    .tables()
+                    .iter()
+                    .map(|t| table_json(&self.service, t))
+                    .collect();
+                Ok((200, Json::from(tables)))
+            }
+            (Method::Post, ["tables", number, "clear"]) => {
+                let number = parse_id(number, "table")?;
+                let party =
+                    self.service
+                        .waitlist
+                        .clear_table(&mut self.service.floor, number, now)?;
+                self.record(Event::TableCleared {
+                    table: number,
+                    at: now,
+                })?;
+                Ok((200, party_json(&party)))
+            }
+            (Method::Post, ["tables", number, "clean"]) => {
+                let number = parse_id(number, "table")?;
+                self.service.floor.mark_clean(number)?;
+                self.record(Event::TableCleaned { table: number })?;
+                let table = self
+                    .service
+                    .floor
+                    .table(number)
+                    .ok_or(FloorError::UnknownTable(number))?;
+                Ok((200, table_json(&self.service, table)))
+            }
+
+            (Method::Get, ["waitlist"]) => {
+                let queue: Vec<Json> = self
+                    .service
+                    .waitlist
+                    .queue()
+                    .into_iter()
+                    .map(|(id, party)| {
+                        let wait =
+                            self.service
+                                .waitlist
+                                .estimate_wait(id, &self.service.floor, now);
+                        party_json(party)
+                            .with("id", id.0)
+                            .with("estimated_wait_minutes", wait.map(|w| w.as_secs() / 60))
+                    })
+                    .collect();
+                Ok((200, Json::from(queue)))
+            }
+            (Method::Post, ["waitlist"]) => {
+                let body = Json::parse(&request.body)?;
+                let name = required_str(&body, "name")?;
+                let size = party_size(&body)?;
+                let mut party = Party::walk_in(name, size, now);
+                party.contact = optional_str(&body, "contact")?.map(String::from);
+                let id = hosting::add_to_waitlist(&mut self.service.waitlist, party.clone())?;
+                self.record(Event::PartyAdded { id, party })?;
+                let party = self
+                    .service
+                    .waitlist
+                    .get(id)
+                    .ok_or(SeatingError::UnknownParty(id))?;
+                Ok((201, party_json(party).with("id", id.0)))
+            }
+            (Method::Delete, ["waitlist", id]) => {
+                let id = PartyId(parse_id(id, "party")?);
+                let party = self
+                    .service
+                    .waitlist
+                    .remove(id)
+                    .ok_or(SeatingError::UnknownParty(id))?;
+                self.record(Event::PartyLeft { id })?;
+                Ok((200, party_json(&party).with("id", id.0)))
+            }
+            (Method::Post, ["waitlist", "seat"]) => {
+                let seating = hosting::seat_at_table(
+                    &mut self.service.waitlist,
+                    &mut self.service.floor,
+                    &mut self.service.staff,
+                    now,
+                )?;
+                self.record_seating(&seating, now)?;
+                Ok((200, seating_json(&seating)))
+            }
+            (Method::Post, ["waitlist", id, "seat"]) => {
+                let id = PartyId(parse_id(id, "party")?);
+                let mut seating =
+                    self.service
+                        .waitlist
+                        .seat_party(id, &mut self.service.floor, now)?;
+                hosting::assign_server(&mut self.service.staff, &mut seating, now);
+                self.record_seating(&seating, now)?;
+                Ok((200, seating_json(&seating)))
+            }
+            (Method::Post, ["waitlist", id, "no-show"]) => {
+                let id = PartyId(parse_id(id, "party")?);
+                self.service.waitlist.mark_no_show(id)?;
+                self.record(Event::NoShow { id })?;
+                Ok((200, Json::object().with("id", id.0)))
+            }
+
+            (Method::Get, ["reservations"]) => {
+                let upcoming: Vec<Json> = self
+                    .service
+                    .reservations
+                    .upcoming()
+                    .into_iter()
+                    .map(|r| r.to_json())
+                    .collect();
+                Ok((200, Json::from(upcoming)))
+            }
+            (Method::Post, ["reservations"]) => {
+                let body = Json::parse(&request.body)?;
+                let name = required_str(&body, "name")?;
+                let size = party_size(&body)?;
+                let time = booking_time(&body, now)?;
+                let contact = optional_str(&body, "contact")?.map(String::from);
+                let book = &mut self.service.reservations;
+                let id = book.book(&self.service.floor, name, size, time)?;
+                if let Some(contact) = &contact {
+                    book.set_contact(id, contact)?;
+                }
+                self.record(Event::ReservationBooked {
+                    id,
+                    name: String::from(name),
+                    size,
+                    time,
+                    contact,
+                    at: now,
+                })?;
+                Ok((201, self.reservation_json(id)?))
+            }
+            (Method::Put, ["reservations", id]) => {
+                let id = ReservationId(parse_id(id, "reservation")?);
+                let body = Json::parse(&request.body)?;
+                let time = booking_time(&body, now)?;
+                let size = party_size(&body)?;
+                self.service
+                    .reservations
+                    .modify(&mut self.service.floor, id, time, size)?;
+                self.record(Event::ReservationChanged {
+                    id,
+                    time,
+                    size,
+                    at: now,
+                })?;
+                Ok((200, self.reservation_json(id)?))
+            }
+            (Method::Delete, ["reservations", id]) => {
+                let id = ReservationId(parse_id(id, "reservation")?);
+                self.service
+                    .reservations
+                    .cancel(&mut self.service.floor, id)?;
+                self.record(Event::ReservationCancelled { id, at: now })?;
+                Ok((200, self.reservation_json(id)?))
+            }
+            (Method::Post, ["reservations", "hold"]) => {
+                let held = self
+                    .service
+                    .reservations
+                    .hold_tables(&mut self.service.floor);
+                let missed = self
+                    .service
+                    .reservations
+                    .mark_no_shows(&mut self.service.floor);
+                if !held.is_empty() || !missed.is_empty() {
+                    self.record(Event::ReservationsChecked {
+                        held: held.clone(),
+                        missed: missed.clone(),
+                        at: now,
+                    })?;
+                }
+                let ids = |ids: Vec<ReservationId>| -> Vec<Json> {
+                    ids.into_iter().map(|id| Json::from(id.0)).collect()
+                };
+                Ok((
+                    200,
+                    Json::object()
+                        .with("held", ids(held))
+                        .with("no_shows", ids(missed)),
+                ))
+            }
+            (Method::Post, ["reservations", id, "arrive"]) => {
+                let id = ReservationId(parse_id(id, "reservation")?);
+                let arrival = self.service.reservations.arrive(
+                    &mut self.service.floor,
+                    &mut self.service.waitlist,
+                    id,
+                )?;
+                let (party, body) = match arrival {
+                    Arrival::Seated(mut seating) => {
+                        hosting::assign_server(&mut self.service.staff, &mut seating, now);
+                        (seating.party, seating_json(&seating))
+                    }
+                    Arrival::Waiting(party) => (
+                        party,
+                        Json::object().with("party", party.0).with("waiting", true),
+                    ),
+                };
+                let server = self.service.staff.server_for_party(party);
+                self.record(Event::ReservationArrived {
+                    id,
+                    party,
+                    server: server.map(|s| s.id),
+                    at: now,
+                })?;
+                Ok((200, body))
+            }
+
+            (Method::Get, ["orders"]) => {
+                let table = match request.query("table") {
+                    Some(table) => Some(parse_id(table, "table")?),
+                    None => None,
+                };
+                let status = request.query("status");
+                let orders: Vec<Json> = self
+                    .service
+                    .orders
+                    .iter()
+                    .filter(|o| table.is_none_or(|t| o.table == t))
+                    .filter(|o| status.is_none_or(|s| o.status().to_string() == s))
+                    .map(order_json)
+                    .collect();
+                Ok((200, Json::from(orders)))
+            }
+            (Method::Post, ["orders"]) => {
+                let body = Json::parse(&request.body)?;
+                let table = required_u32(&body, "table")?;
+                if self.service.floor.table(table).is_none() {
+                    return Err(FloorError::UnknownTable(table).into());
+                }
+                let items = self.order_items(&body, now)?;
+                let guests = guests(&body)?;
+                let on_conflict = match optional_str(&body, "on_conflict")? {
+                    None | Some("reject") => OnConflict::Reject,
+                    Some("flag") => OnConflict::Flag,
+                    Some(_) => return Err(ApiError::invalid("on_conflict", "reject or flag")),
+                };
+                let checker =
+                    DietChecker::new(&self.service.menu, self.service.kitchen.inventory(), now);
+                let (id, flags) = serving::take_checked_order(
+                    &mut self.service.orders,
+                    &checker,
+                    table,
+                    items.clone(),
+                    guests.clone(),
+                    on_conflict,
+                    now,
+                )?;
+                self.record(Event::OrderPlaced {
+                    id,
+                    table,
+                    items,
+                    guests,
+                    at: now,
+                })?;
+                let flags: Vec<Json> = flags.iter().map(conflict_json).collect();
+                Ok((
+                    201,
+                    order_json(self.order(id)?).with("dietary_flags", flags),
+                ))
+            }
+            (Method::Get, ["orders", id]) => {
+                let id = OrderId(parse_id(id, "order")?);
+                Ok((200, order_json(self.order(id)?)))
+            }
+            (Method::Post, ["orders", id, "cook"]) => {
+                let id = OrderId(parse_id(id, "order")?);
+                let tickets = back_of_house::cook_order(
+                    &mut self.service.orders,
+                    &mut self.service.kitchen,
+                    &mut self.service.menu,
+                    id,
+                    now,
+                )?;
+                self.record(Event::OrderCooked { id, at: now })?;
+                let tickets: Vec<Json> = tickets
+                    .iter()
+                    .filter_map(|t| self.service.kitchen.ticket(*t))
+                    .map(ticket_json)
+                    .collect();
+                Ok((200, order_json(self.order(id)?).with("tickets", tickets)))
+            }
+            (Method::Post, ["orders", id, "serve"]) => {
+                let id = OrderId(parse_id(id, "order")?);
+                serving::serve_order(&mut self.service.orders, id, now)?;
+                self.record(Event::OrderServed { id, at: now })?;
+                Ok((200, order_json(self.order(id)?)))
+            }
+            (Method::Post, ["orders", id, "void"]) => {
+                let id = OrderId(parse_id(id, "order")?);
+                self.service.orders.void(id, now)?;
+                self.record(Event::OrderVoided { id, at: now })?;
+                Ok((200, order_json(self.order(id)?)))
+            }
+            (Method::Post, ["orders", id, "remake"]) => {
+                let id = OrderId(parse_id(id, "order")?);
+                let body = Json::parse(&request.body)?;
+                let menu_ids: Vec<String> = body
+                    .get("menu_ids")
+                    .and_then(Json::as_array)
+                    .and_then(|ids| ids.iter().map(|id| id.as_str().map(String::from)).collect())
+                    .ok_or_else(|| ApiError::invalid("menu_ids", "an array of menu ids"))?;
+                let reason = required_str(&body, "reason")?;
+                let ids: Vec<&str> = menu_ids.iter().map(String::as_str).collect();
+                let tickets = back_of_house::fix_incorrect_order(
+                    &mut self.service.orders,
+                    &mut self.service.kitchen,
+                    id,
+                    &ids,
+                    reason,
+                    now,
+                )?;
+                self.record(Event::OrderRemade {
+                    id,
+                    menu_ids,
+                    reason: String::from(reason),
+                    at: now,
+                })?;
+                let tickets: Vec<Json> = tickets
+                    .iter()
+                    .filter_map(|t| self.service.kitchen.ticket(*t))
+                    .map(ticket_json)
+                    .collect();
+                Ok((200, order_json(self.order(id)?).with("tickets", tickets)))
+            }
+            (Method::Get, ["orders", id, "receipt"]) => {
+                let id = OrderId(parse_id(id, "order")?);
+                let guests = match request.query("guests") {
+                    Some(guests) => parse_id(guests, "guests")?,
+                    None => 1,
+                };
+                let tip = match request.query("tip_percent") {
+                    Some(tip) => Tip::Percent(parse_rate(tip, "tip_percent")?),
+                    None => Tip::None,
+                };
+                let receipt = self.receipt(id, guests, tip)?;
+                Ok((200, receipt_json(&receipt)))
+            }
+            (Method::Post, ["orders", id, "payments"]) => {
+                let id = OrderId(parse_id(id, "order")?);
+                let body = Json::parse(&request.body)?;
+                let guests = optional_u32(&body, "guests")?.unwrap_or(1);
+                let tip = match body.get("tip_percent") {
+                    None | Some(Json::Null) => Tip::None,
+                    Some(tip) => Tip::Percent(
+                        tip.as_f64()
+                            .and_then(|p| Rate::parse(&p.to_string()))
+                            .ok_or_else(|| ApiError::invalid("tip_percent", "a percentage"))?,
+                    ),
+                };
+                let payments = payments(&body)?;
+                let receipt = self.receipt(id, guests, tip)?;
+                let change = serving::take_payment(
+                    &mut self.service.orders,
+                    id,
+                    &receipt,
+                    payments.clone(),
+                    now,
+                )?;
+                self.record(Event::PaymentTaken {
+                    id,
+                    payments,
+                    charges: receipt.charges(id),
+                    at: now,
+                })?;
+                Ok((
+                    200,
+                    order_json(self.order(id)?)
+                        .with("total_cents", receipt.total().0)
+                        .with("change_cents", change.0),
+                ))
+            }
+
+            (Method::Get, ["tickets"]) => {
+                let tickets: Vec<Json> = self
+                    .service
+                    .kitchen
+                    .tickets()
+                    .iter()
+                    .filter(|t| !t.is_done())
+                    .map(ticket_json)
+                    .collect();
+                Ok((200, Json::from(tickets)))
+            }
+            (Method::Post, ["tickets", id, "start"]) => {
+                let id = TicketId(parse_id(id, "ticket")?);
+                self.service.kitchen.start(id, now)?;
+                self.record(Event::TicketStarted {
+                    ticket: id,
+                    at: now,
+                })?;
+                let ticket = self
+                    .service
+                    .kitchen
+                    .ticket(id)
+                    .ok_or(KitchenError::UnknownTicket(id))?;
+                Ok((200, ticket_json(ticket)))
+            }
+            (Method::Post, ["tickets", id, "bump"]) => {
+                let id = TicketId(parse_id(id, "ticket")?);
+                let ready = back_of_house::bump_ticket(
+                    &mut self.service.orders,
+                    &mut self.service.kitchen,
+                    id,
+                    now,
+                )?;
+                self.record(Event::TicketBumped {
+                    ticket: id,
+                    at: now,
+                })?;
+                let ticket = self
+                    .service
+                    .kitchen
+                    .ticket(id)
+                    .ok_or(KitchenError::UnknownTicket(id))?;
+                Ok((
+                    200,
+                    ticket_json(ticket).with("order_ready", ready.is_some()),
+                ))
+            }
+
+            (Method::Get, ["staff"]) => {
+                let staff: Vec<Json> = self
+                    .service
+                    .staff
+                    .staff()
+                    .map(|s| staff_json(&self.service.staff, s, now))
+                    .collect();
+                Ok((200, Json::from(staff)))
+            }
+            (Method::Get, ["staff", "tips"]) => {
+                let total = match request.query("total_cents") {
+                    Some(total) => Cents(parse_id(total, "total_cents")? as i64),
+                    None => return Err(ApiError::invalid("total_cents", "an amount in cents")),
+                };
+                let roster = &self.service.staff;
+                let shares: Vec<Json> = roster
+                    .pool_tips(total, &TipPool::default(), now)
+                    .iter()
+                    .map(|share| {
+                        Json::object()
+                            .with("id", share.staff.0)
+                            .with("name", roster.get(share.staff).map(|s| s.name.as_str()))
+                            .with("worked_minutes", share.worked.as_secs() / 60)
+                            .with("amount_cents", share.amount.0)
+                    })
+                    .collect();
+                Ok((200, Json::from(shares)))
+            }
+            (Method::Post, ["staff", id, action @ ("clock-in" | "clock-out")]) => {
+                let id = StaffId(parse_id(id, "staff")?);
+                if *action == "clock-in" {
+                    self.service.staff.clock_in(id, now)?;
+                    self.record(Event::ClockedIn { staff: id, at: now })?;
+                } else {
+                    self.service.staff.clock_out(id, now)?;
+                    self.record(Event::ClockedOut { staff: id, at: now })?;
+                }
+                let staff = self
+                    .service
+                    .staff
+                    .get(id)
+                    .ok_or(StaffError::UnknownStaff(id))?;
+                Ok((200, staff_json(&self.service.staff, staff, now)))
+            }
+
+            (method, _) => Err(ApiError::new(
+                404,
+                "no_route",
+                format!("there is nothing at {} {}", method, request.path),
+            )),
+        }
+    }
+
+    fn record_seating(&mut self, seating: &Seating, now: Timestamp) -> Result<(), ApiError> {
+        let server = self.service.staff.server_for_party(seating.party);
+        self.record(Event::PartySeated {
+            id: seating.party,
+            tables: seating.tables.clone(),
+            server: server.map(|s| s.id),
+            at: now,
+        })
+    }
+
+    fn reservation_json(&self, id: ReservationId) -> Result<Json, ApiError> {
+        Ok(self
+            .service
+            .reservations
+            .get(id)
+            .ok_or(ReservationError::UnknownReservation(id))?
+            .to_json())
+    }
+
+    fn order(&self, id: OrderId) -> Result<&Order, ApiError> {
+        Ok(self
+            .service
+            .orders
+            .get(id)
+            .ok_or(OrderError::UnknownOrder(id))?)
+    }
+
+    fn receipt(&self, id: OrderId, guests: u32, tip: Tip) -> Result<Receipt, ApiError> {
+        let order = self.order(id)?;
+        let check = Check::from_orders(&[order], &self.service.menu, guests)?.tip(tip);
+        Ok(check.receipt(&self.billing)?)
+    }
+
+    // Each item must be on the menu, orderable right now, and have valid choices
+    fn order_items(&self, body: &Json, now: Timestamp) -> Result<Vec<OrderItem>, ApiError> {
+        let items = body
+            .get("items")
+            .and_then(Json::as_array)
+            .ok_or_else(|| ApiError::invalid("items", "an array"))?;
+        let mut order_items = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            let field = |name: &str| format!("items[{}].{}", i, name);
+            let menu_id = item
+                .get("menu_id")
+                .and_then(Json::as_str)
+                .ok_or_else(|| ApiError::invalid(&field("menu_id"), "a string"))?;
+            let mut order_item = OrderItem::new(menu_id);
+            match item.get("choices") {
+                None | Some(Json::Null) => {}
+                Some(Json::Object(choices)) => {
+                    for (modifier, option) in choices {
+                        let option = option
+                            .as_str()
+                            .ok_or_else(|| ApiError::invalid(&field("choices"), "strings"))?;
+                        order_item = order_item.with(modifier, option);
+                    }
+                }
+                Some(_) => return Err(ApiError::invalid(&field("choices"), "an object")),
+            }
+            match item.get("seat") {
+                None | Some(Json::Null) => {}
+                Some(seat) => {
+                    let seat = seat
+                        .as_i64()
+                        .and_then(|s| u32::try_from(s).ok())
+                        .filter(|s| *s > 0)
+                        .ok_or_else(|| ApiError::invalid(&field("seat"), "a positive integer"))?;
+                    order_item = order_item.for_seat(seat);
+                }
+            }
+            let menu_item = self
+                .service
+                .menu
+                .get(menu_id)
+                .ok_or_else(|| MenuError::UnknownItem(String::from(menu_id)))?;
+            if !menu_item.is_available(now) {
+                return Err(ApiError::new(
+                    409,
+                    "unavailable",
+                    format!("{} can't be ordered right now", menu_item.name),
+                ));
+            }
+            self.service.menu.quote(menu_id, &order_item.choices())?;
+            // The item may be on, but not with what was chosen
+            let inventory = self.service.kitchen.inventory();
+            if let Err(e @ InventoryError::Shortage { .. }) =
+                inventory.check(std::slice::from_ref(&order_item), now.date())
+            {
+                return Err(KitchenError::Inventory(e).into());
+            }
+            order_items.push(order_item);
+        }
+        Ok(order_items)
+    }
+}
+
+fn parse_id(text: &str, what: &str) -> Result<u32, ApiError> {
+    text.parse().map_err(|_| {
+        ApiError::new(
+            400,
+            "bad_id",
+            format!("'{}' is not a {} number", text, what),
+        )
+    })
+}
+
+fn parse_rate(text: &str, field: &str) -> Result<Rate, ApiError> {
+    Rate::parse(text).ok_or_else(|| ApiError::invalid(field, "a percentage"))
+}
+
+fn required_str<'a>(body: &'a Json, field: &str) -> Result<&'a str, ApiError> {
+    optional_str(body, field)?.ok_or_else(|| ApiError::invalid(field, "a string"))
+}
+
+fn optional_str<'a>(body: &'a Json, field: &str) -> Result<Option<&'a str>, ApiError> {
+    match body.get(field) {
+        None | Some(Json::Null) => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| ApiError::invalid(field, "a string")),
+    }
+}
+
+fn required_u32(body: &Json, field: &str) -> Result<u32, ApiError> {
+    optional_u32(body, field)?.ok_or_else(|| ApiError::invalid(field, "a whole number"))
+}
+
+fn optional_u32(body: &Json, field: &str) -> Result<Option<u32>, ApiError> {
+    match body.get(field) {
+        None | Some(Json::Null) => Ok(None),
+        Some(value) => value
+            .as_i64()
+            .and_then(|n| u32::try_from(n).ok())
+            .map(Some)
+            .ok_or_else(|| ApiError::invalid(field, "a whole number")),
+    }
+}
+
+fn party_size(body: &Json) -> Result<u32, ApiError> {
+    Some(required_u32(body, "size")?)
+        .filter(|size| (1..=MAX_PARTY).contains(size))
+        .ok_or_else(|| ApiError::invalid("size", &format!("from 1 to {}", MAX_PARTY)))
+}
+
+// Seconds since the epoch, no further off than BOOKING_HORIZON; whether it's
+// too soon is up to the reservation book
+fn booking_time(body: &Json, now: Timestamp) -> Result<Timestamp, ApiError> {
+    body.get("time")
+        .and_then(Json::as_u64)
+        .map(Timestamp::from_secs)
+        .filter(|time| *time <= now + BOOKING_HORIZON)
+        .ok_or_else(|| ApiError::invalid("time", "a time in seconds, at most a year ahead"))
+}
+
+// "guests": [{"seat"?, "allergens"?: [...], "diets"?: [...]}]
+fn guests(body: &Json) -> Result<Vec<GuestProfile>, ApiError> {
+    let expected = || ApiError::invalid("guests", "a list of guest profiles");
+    match body.get("guests") {
+        None | Some(Json::Null) => Ok(Vec::new()),
+        Some(guests) => guests
+            .as_array()
+            .ok_or_else(expected)?
+            .iter()
+            .map(|g| GuestProfile::from_json(g).ok_or_else(expected))
+            .collect(),
+    }
+}
+
+fn payments(body: &Json) -> Result<Vec<Payment>, ApiError> {
+    let list = body
+        .get("payments")
+        .and_then(Json::as_array)
+        .filter(|p| !p.is_empty())
+        .ok_or_else(|| ApiError::invalid("payments", "a non-empty array"))?;
+    if list.len() > MAX_PAYMENTS {
+        return Err(ApiError::invalid(
+            "payments",
+            &format!("at most {} payments", MAX_PAYMENTS),
+        ));
+    }
+    list.iter()
+        .enumerate()
+        .map(|(i, payment)| {
+            let method = payment
+                .get("method")
+                .and_then(Json::as_str)
+                .and_then(PaymentMethod::parse)
+                .ok_or_else(|| {
+                    ApiError::invalid(
+                        &format!("payments[{}].method", i),
+                        "\"cash\", \"card\" or \"gift card\"",
+                    )
+                })?;
+            let amount = payment
+                .get("amount_cents")
+                .and_then(Json::as_i64)
+                .filter(|a| *a > 0 && *a <= MAX_PAYMENT)
+                .ok_or_else(|| {
+                    ApiError::invalid(
+                        &format!("payments[{}].amount_cents", i),
+                        &format!("positive and at most {}", MAX_PAYMENT),
+                    )
+                })?;
+            Ok(Payment {
+                method,
+                amount: Cents(amount),
+            })
+        })
+        .collect()
+}
+
+fn party_json(party: &Party) -> Json {
+    Json::object()
+        .with("name", party.name.as_str())
+        .with("size", party.size)
+        .with("contact", party.contact.clone())
+        .with("arrived", party.arrived.as_secs())
+        .with("reservation", party.reservation)
+}
+
+fn seating_json(seating: &Seating) -> Json {
+    Json::object()
+        .with("party", seating.party.0)
+        .with("tables", seating.tables.clone())
+        .with("server", seating.server.clone())
+        .with("waited_minutes", seating.waited.as_secs() / 60)
+}
+
+// The server is whoever the rotation gave the party to, or else the section's
+fn table_json(service: &Service, table: &Table) -> Json {
+    let server = match table.state() {
+        TableState::Seated { party, .. } => service
+            .staff
+            .server_for_party(*party)
+            .map(|s| s.name.as_str()),
+        _ => None,
+    };
+    let json = Json::object()
+        .with("number", table.number)
+        .with("capacity", table.capacity)
+        .with("section", table.section.as_str())
+        .with(
+            "server",
+            server.or_else(|| service.floor.server_for(table.number)),
+        )
+        .with("neighbours", table.neighbours().to_vec());
+    match table.state() {
+        TableState::Free => json.with("state", "free"),
+        TableState::Dirty => json.with("state", "dirty"),
+        TableState::Seated { party, since } => json
+            .with("state", "seated")
+            .with("party", party.0)
+            .with("since", since.as_secs()),
+        TableState::Reserved {
+            reservation,
+            name,
+            at,
+        } => json
+            .with("state", "reserved")
+            .with("reservation", reservation.0)
+            .with("name", name.as_str())
+            .with("at", at.as_secs()),
+    }
+}
+
+fn staff_json(roster: &Roster, staff: &Staff, now: Timestamp) -> Json {
+    Json::object()
+        .with("id", staff.id.0)
+        .with("name", staff.name.as_str())
+        .with("role", staff.role.name())
+        .with("on_the_clock", roster.is_clocked_in(staff.id))
+        .with(
+            "worked_minutes",
+            roster.worked(staff.id, now).as_secs() / 60,
+        )
+        .with("tables", roster.tables_served(staff.id) as u32)
+}
+
+fn order_json(order: &Order) -> Json {
+    let items: Vec<Json> = order
+        .items
+        .iter()
+        .map(|item| {
+            let choices = item
+                .choices
+                .iter()
+                .fold(Json::object(), |json, (m, o)| json.with(m, o.as_str()));
+            Json::object()
+                .with("menu_id", item.menu_id.as_str())
+                .with("choices", choices)
+                .with("seat", item.seat)
+        })
+        .collect();
+    let history: Vec<Json> = order
+        .history()
+        .iter()
+        .map(|(status, at)| {
+            Json::object()
+                .with("status", status.to_string())
+                .with("at", at.as_secs())
+        })
+        .collect();
+    Json::object()
+        .with("id", order.id.0)
+        .with("table", order.table)
+        .with("status", order.status().to_string())
+        .with("items", items)
+        .with("history", history)
+        .with("paid", order.status() == OrderStatus::Paid)
+        .with("guests", guests_json(order.guests()))
+}
+
+fn conflict_json(conflict: &Conflict) -> Json {
+    let reasons: Vec<Json> = conflict
+        .reasons
+        .iter()
+        .map(|r| Json::from(r.to_string()))
+        .collect();
+    let substitutes: Vec<Json> = conflict
+        .substitutes
+        .iter()
+        .map(|s| match s {
+            Substitute::Option { modifier, option } => Json::object()
+                .with("modifier", modifier.as_str())
+                .with("option", option.as_str()),
+            Substitute::Without { modifier } => Json::object()
+                .with("modifier", modifier.as_str())
+                .with("option", Json::Null),
+            Substitute::Item { menu_id, name } => Json::object()
+                .with("menu_id", menu_id.as_str())
+                .with("name", name.as_str()),
+        })
+        .collect();
+    Json::object()
+        .with("item", conflict.item as u32)
+        .with("menu_id", conflict.menu_id.as_str())
+        .with("seat", conflict.seat)
+        .with("reasons", reasons)
+        .with("substitutes", substitutes)
+}
+
+fn ticket_json(ticket: &Ticket) -> Json {
+    let items: Vec<Json> = ticket
+        .items
+        .iter()
+        .map(|i| Json::from(i.menu_id.as_str()))
+        .collect();
+    Json::object()
+        .with("id", ticket.id.0)
+        .with("order", ticket.order.0)
+        .with("table", ticket.table)
+        .with("station", ticket.station.to_string())
+        .with("course", ticket.course.to_string())
+        .with("items", items)
+        .with("fire_at", ticket.fire_at.as_secs())
+        .with("due", ticket.due().as_secs())
+        .with("started", ticket.started.map(|s| s.as_secs()))
+        .with("done", ticket.done.map(|d| d.as_secs()))
+        .with("remake", ticket.remake.as_ref().map(|r| r.reason.as_str()))
+}
+
+fn receipt_json(receipt: &Receipt) -> Json {
+    let lines: Vec<Json> = receipt
+        .lines
+        .iter()
+        .map(|line| {
+            Json::object()
+                .with("description", line.description.as_str())
+                .with("seat", line.seat)
+                .with("amount_cents", line.amount.0)
+        })
+        .collect();
+    let discounts: Vec<Json> = receipt
+        .discounts()
+        .into_iter()
+        .map(|(name, amount)| {
+            Json::object()
+                .with("name", name)
+                .with("amount_cents", amount.0)
+        })
+        .collect();
+    let taxes: Vec<Json> = receipt
+        .taxes
+        .iter()
+        .map(|(name, amount)| {
+            Json::object()
+                .with("name", name.as_str())
+                .with("amount_cents", amount.0)
+        })
+        .collect();
+    Json::object()
+        .with("lines", lines)
+        .with("subtotal_cents", receipt.subtotal().0)
+        .with("discounts", discounts)
+        .with("service_charge_cents", receipt.service_charge_total().0)
+        .with("taxes", taxes)
+        .with("tip_cents", receipt.tip.0)
+        .with("total_cents", receipt.total().0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_of_house::inventory::Inventory;
+    use crate::clock::ManualClock;
+    use std::path::PathBuf;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("api-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn at(hours: u64, mins: u64) -> Timestamp {
+        Timestamp::from_secs(hours * 3600 + mins * 60)
+    }
+
+    fn restaurant(clock: &ManualClock) -> Restaurant<&ManualClock> {
+        Restaurant::new(FloorPlan::house(), Menu::house(), Kitchen::new(), clock)
+            .with_roster(Roster::house())
+    }
+
+    // The status and the parsed body
+    fn call(
+        restaurant: &mut Restaurant<&ManualClock>,
+        method: Method,
+        target: &str,
+        body: &str,
+    ) -> (u16, Json) {
+        let response = restaurant.handle(&Request::new(method, target).with_body(body));
+        (response.status, Json::parse(&response.body).unwrap())
+    }
+
+    #[test]
+    fn replaying_the_journal_gives_the_live_state() {
+        let dir = scratch("replay");
+        let clock = ManualClock::new(at(17, 0));
+        let mut live = restaurant(&clock);
+        live.open_journal(&dir).unwrap();
+
+        assert_eq!(
+            call(&mut live, Method::Post, "/staff/1/clock-in", "").0,
+            200
+        );
+        let (status, _) = call(
+            &mut live,
+            Method::Post,
+            "/waitlist",
+            r#"{"name": "Ada", "size": 2}"#,
+        );
+        assert_eq!(status, 201);
+        let (status, seating) = call(&mut live, Method::Post, "/waitlist/seat", "");
+        assert_eq!(status, 200);
+        let table = seating.get("tables").and_then(Json::as_array).unwrap()[0]
+            .as_u32()
+            .unwrap();
+        let order = format!(
+            r#"{{"table": {}, "items": [{{"menu_id": "burger", "choices": {{"side": "Fries"}}}}]}}"#,
+            table
+        );
+        assert_eq!(call(&mut live, Method::Post, "/orders", &order).0, 201);
+        assert_eq!(call(&mut live, Method::Post, "/orders/1/cook", "").0, 200);
+        live.snapshot().unwrap(); // <- the rest is replayed on top of this
+
+        let booking = |name: &str, size: u32, time: Timestamp| {
+            format!(
+                r#"{{"name": "{}", "size": {}, "time": {}}}"#,
+                name,
+                size,
+                time.as_secs()
+            )
+        };
+        let (status, brian) = call(
+            &mut live,
+            Method::Post,
+            "/reservations",
+            &booking("Brian", 4, at(19, 0)),
+        );
+        assert_eq!(status, 201);
+        assert_eq!(brian.get("id").and_then(Json::as_u32), Some(1));
+        let moved = format!(r#"{{"time": {}, "size": 4}}"#, at(19, 30).as_secs());
+        assert_eq!(
+            call(&mut live, Method::Put, "/reservations/1", &moved).0,
+            200
+        );
+        assert_eq!(
+            call(
+                &mut live,
+                Method::Post,
+                "/reservations",
+                &booking("Cleo", 4, at(17, 30))
+            )
+            .0,
+            201
+        );
+        assert_eq!(
+            call(
+                &mut live,
+                Method::Post,
+                "/reservations",
+                &booking("Dev", 4, at(17, 10))
+            )
+            .0,
+            201
+        );
+
+        clock.set(at(17, 20));
+        let (status, held) = call(&mut live, Method::Post, "/reservations/hold", "");
+        assert_eq!(status, 200);
+        assert_eq!(
+            held.get("held").and_then(Json::as_array).map(<[Json]>::len),
+            Some(2)
+        );
+        assert_eq!(
+            call(&mut live, Method::Post, "/reservations/2/arrive", "").0,
+            200
+        );
+        clock.set(at(17, 30));
+        let (_, missed) = call(&mut live, Method::Post, "/reservations/hold", "");
+        assert_eq!(
+            missed
+                .get("no_shows")
+                .and_then(Json::as_array)
+                .map(<[Json]>::len),
+            Some(1)
+        );
+
+        let mut recovered = restaurant(&clock);
+        recovered.open_journal(&dir).unwrap();
+        assert_eq!(recovered.service.snapshot(), live.service.snapshot());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_change_the_journal_cant_take_is_undone() {
+        let dir = scratch("undo");
+        let clock = ManualClock::new(at(17, 0));
+        let mut restaurant = restaurant(&clock);
+        restaurant.open_journal(&dir).unwrap();
+        assert_eq!(
+            call(&mut restaurant, Method::Post, "/staff/1/clock-in", "").0,
+            200
+        );
+        let before = restaurant.service.snapshot();
+
+        restaurant.journal.as_mut().unwrap().break_log().unwrap();
+        let (status, error) = call(
+            &mut restaurant,
+            Method::Post,
+            "/waitlist",
+            r#"{"name": "Ada", "size": 2}"#,
+        );
+        assert_eq!(status, 500);
+        assert!(error.get("error").is_some());
+        assert_eq!(restaurant.service.snapshot(), before);
+        assert!(restaurant.service.waitlist.queue().is_empty());
+
+        let booking = format!(
+            r#"{{"name": "Brian", "size": 2, "time": {}}}"#,
+            at(19, 0).as_secs()
+        );
+        assert_eq!(
+            call(&mut restaurant, Method::Post, "/reservations", &booking).0,
+            500
+        );
+        assert!(restaurant.service.reservations.upcoming().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn code(response: &Json) -> Option<&str> {
+        response.get("error")?.get("code")?.as_str()
+    }
+
+    #[test]
+    fn an_order_goes_from_the_waitlist_to_paid() {
+        let clock = ManualClock::new(at(18, 0));
+        let mut restaurant = restaurant(&clock);
+        assert_eq!(call(&mut restaurant, Method::Get, "/menu", "").0, 200);
+        let (status, party) = call(
+            &mut restaurant,
+            Method::Post,
+            "/waitlist",
+            r#"{"name": "Ada", "size": 2}"#,
+        );
+        assert_eq!(status, 201);
+        assert_eq!(party.get("size").and_then(Json::as_u32), Some(2));
+        let (status, seating) = call(&mut restaurant, Method::Post, "/waitlist/seat", "");
+        assert_eq!(status, 200);
+        let table = seating.get("tables").and_then(Json::as_array).unwrap()[0]
+            .as_u32()
+            .unwrap();
+
+        let order = format!(
+            r#"{{"table": {}, "items": [{{"menu_id": "soup"}}, {{"menu_id": "lemonade"}}]}}"#,
+            table
+        );
+        let (status, placed) = call(&mut restaurant, Method::Post, "/orders", &order);
+        assert_eq!(status, 201, "{}", placed);
+        let id = placed.get("id").and_then(Json::as_u32).unwrap();
+        let (status, cooking) = call(
+            &mut restaurant,
+            Method::Post,
+            &format!("/orders/{}/cook", id),
+            "",
+        );
+        assert_eq!(status, 200);
+        for ticket in cooking.get("tickets").and_then(Json::as_array).unwrap() {
+            let ticket = ticket.get("id").and_then(Json::as_u32).unwrap();
+            let start = format!("/tickets/{}/start", ticket);
+            let (status, started) = call(&mut restaurant, Method::Post, &start, "");
+            assert_eq!(status, 200);
+            assert!(started.get("started").and_then(Json::as_u64).is_some());
+            let (status, error) = call(&mut restaurant, Method::Post, &start, "");
+            assert_eq!((status, code(&error)), (409, Some("ticket_state")));
+            let bump = format!("/tickets/{}/bump", ticket);
+            assert_eq!(call(&mut restaurant, Method::Post, &bump, "").0, 200);
+        }
+        let serve = format!("/orders/{}/serve", id);
+        assert_eq!(call(&mut restaurant, Method::Post, &serve, "").0, 200);
+
+        let (status, receipt) = call(
+            &mut restaurant,
+            Method::Get,
+            &format!("/orders/{}/receipt", id),
+            "",
+        );
+        assert_eq!(status, 200);
+        let total = receipt.get("total_cents").and_then(Json::as_i64).unwrap();
+        let pay = |amount: i64| {
+            format!(
+                r#"{{"payments": [{{"method": "card", "amount_cents": {}}}]}}"#,
+                amount
+            )
+        };
+        let payments = format!("/orders/{}/payments", id);
+        let (status, error) = call(&mut restaurant, Method::Post, &payments, &pay(total - 1));
+        assert_eq!((status, code(&error)), (422, Some("underpaid")));
+        let (status, paid) = call(&mut restaurant, Method::Post, &payments, &pay(total));
+        assert_eq!(status, 200, "{}", paid);
+        assert_eq!(paid.get("paid"), Some(&Json::Bool(true)));
+        assert_eq!(paid.get("change_cents").and_then(Json::as_i64), Some(0));
+        let (status, error) = call(&mut restaurant, Method::Post, &payments, &pay(total));
+        assert_eq!((status, code(&error)), (409, Some("illegal_transition")));
+    }
+
+    #[test]
+    fn bad_requests_get_a_4xx_and_change_nothing() {
+        let clock = ManualClock::new(at(18, 0));
+        let mut restaurant = restaurant(&clock);
+        let far_off = format!(r#"{{"name": "Ada", "size": 2, "time": {}}}"#, 1u64 << 53);
+        let cases: [(Method, &str, &str, u16, &str); 14] = [
+            (Method::Get, "/nowhere", "", 404, "no_route"),
+            (Method::Put, "/menu", "", 404, "no_route"),
+            (Method::Get, "/menu/soup%2Fextra", "", 404, "unknown_item"),
+            (Method::Get, "/orders/seven", "", 400, "bad_id"),
+            (Method::Get, "/orders/7", "", 404, "unknown_order"),
+            (Method::Post, "/orders", "{", 400, "invalid_json"),
+            (
+                Method::Post,
+                "/orders",
+                r#"{"table": 99, "items": []}"#,
+                404,
+                "unknown_table",
+            ),
+            (
+                Method::Post,
+                "/orders",
+                r#"{"table": 1, "items": [{"menu_id": "nope"}]}"#,
+                422,
+                "unknown_item",
+            ),
+            (
+                Method::Post,
+                "/waitlist",
+                r#"{"name": "Ada", "size": 0}"#,
+                422,
+                "invalid_field",
+            ),
+            (
+                Method::Post,
+                "/waitlist",
+                r#"{"name": "Ada", "size": 4294967295}"#,
+                422,
+                "invalid_field",
+            ),
+            (
+                Method::Post,
+                "/reservations",
+                &far_off,
+                422,
+                "invalid_field",
+            ),
+            (Method::Post, "/tables/99/clean", "", 404, "unknown_table"),
+            (Method::Post, "/staff/99/clock-in", "", 404, "unknown_staff"),
+            (Method::Post, "/staff/1/clock-out", "", 409, "clock_state"),
+        ];
+        let before = restaurant.service.snapshot();
+        for (method, target, body, status, expected) in cases {
+            let (got, error) = call(&mut restaurant, method, target, body);
+            assert_eq!((got, code(&error)), (status, Some(expected)), "{}", target);
+        }
+        assert_eq!(restaurant.service.snapshot(), before);
+
+        let (_, error) = call(&mut restaurant, Method::Get, "/menu/soup%2Fextra", "");
+        let message = error.get("error").and_then(|e| e.get("message"));
+        assert!(message
+            .and_then(Json::as_str)
+            .unwrap()
+            .contains("soup/extra"));
+    }
+
+    #[test]
+    fn an_option_that_cant_be_made_cant_be_ordered() {
+        let clock = ManualClock::new(at(18, 0));
+        let mut inventory = Inventory::house();
+        inventory
+            .use_items(&vec![OrderItem::new("fries"); 100], at(18, 0))
+            .unwrap(); // <- no potatoes left
+        let kitchen = Kitchen::new().with_inventory(inventory);
+        let mut restaurant = Restaurant::new(FloorPlan::house(), Menu::house(), kitchen, &clock);
+        let order = |side: &str| {
+            format!(
+                r#"{{"table": 1, "items": [{{"menu_id": "burger", "choices": {{"side": "{}"}}}}]}}"#,
+                side
+            )
+        };
+        let (status, error) = call(&mut restaurant, Method::Post, "/orders", &order("Fries"));
+        assert_eq!((status, code(&error)), (409, Some("out_of_stock")));
+        assert_eq!(
+            call(&mut restaurant, Method::Post, "/orders", &order("Salad")).0,
+            201
+        );
+    }
+
+    #[test]
+    fn reservation_errors_map_to_statuses() {
+        let clock = ManualClock::new(at(17, 0));
+        let mut restaurant = restaurant(&clock);
+        let past = format!(
+            r#"{{"name": "Ada", "size": 2, "time": {}}}"#,
+            at(16, 0).as_secs()
+        );
+        let (status, error) = call(&mut restaurant, Method::Post, "/reservations", &past);
+        assert_eq!(status, 422);
+        assert_eq!(
+            error
+                .get("error")
+                .and_then(|e| e.get("code"))
+                .and_then(Json::as_str),
+            Some("invalid_booking")
+        );
+        assert_eq!(
+            call(&mut restaurant, Method::Delete, "/reservations/9", "").0,
+            404
+        );
+        assert_eq!(
+            call(
+                &mut restaurant,
+                Method::Post,
+                "/reservations",
+                r#"{"name": "Ada", "size": 2}"#
+            )
+            .0,
+            422
+        );
+    }
+}
@@ -0,0 +1,179 @@
+// The kitchen side of the restaurant. Cooks only see orders through the order
+// book, the same one the servers use, so both sides agree on where food is.
+
+pub mod inventory;
+pub mod kitchen;
+pub mod seasonal;
+
+use crate::clock::{Date, Timestamp};
+use crate::dietary::DishProfile;
+use crate::menu::{Menu, MenuError};
+use crate::money::Cents;
+use crate::order::{OrderBook, OrderError, OrderId, OrderItem, OrderStatus};
+use inventory::{Inventory, Quantity, Unit};
+use kitchen::{Kitchen, KitchenError, TicketId};
+use seasonal::{Season, SeasonalMenu};
+
+pub enum Appetizer {
+    Soup,
+    Salad,
+}
+impl Appetizer {
+    // Where to find this appetizer in the menu
+    pub fn menu_id(&self) -> &'static str {
+        match self {
+            Appetizer::Soup => "soup",
+            Appetizer::Salad => "salad",
+        }
+    }
+
+    pub fn order_item(&self) -> OrderItem {
+        OrderItem::new(self.menu_id())
+    }
+
+    // What goes into it, from the kitchen's recipe
+    pub fn profile(&self, inventory: &Inventory, date: Date) -> Option<DishProfile> {
+        inventory.dish_profile(&self.order_item(), date)
+    }
+}
+pub struct Breakfast {
+    pub toast: String,
+    seasonal_fruit: String, // <- private field
+}
+impl Breakfast {
+    // Whatever is in season on that date, from the house seasonal menu
+    pub fn for_date(date: Date, toast: &str) -> Breakfast {
+        Breakfast::for_date_with(&SeasonalMenu::default(), date, toast)
+    }
+
+    pub fn for_date_with(seasons: &SeasonalMenu, date: Date, toast: &str) -> Breakfast {
+        Breakfast::with_fruit(seasons.fruit_for(date, |_| true), toast)
+    }
+
+    // Whatever is in season and still in the walk-in
+    pub fn from_stock(inventory: &Inventory, date: Date, toast: &str) -> Breakfast {
+        let portion = Quantity::new(120.0, Unit::Gram);
+        Breakfast::with_fruit(inventory.seasonal_fruit(date, portion), toast)
+    }
+
+    pub fn for_season(season: Season, toast: &str) -> Breakfast {
+        let fruit = SeasonalMenu::default().fruit(season).first().cloned();
+        Breakfast::with_fruit(fruit.as_deref(), toast)
+    }
+
+    pub fn spring(toast: &str) -> Breakfast {
+        Breakfast::for_season(Season::Spring, toast)
+    }
+    pub fn summer(toast: &str) -> Breakfast {
+        Breakfast::for_season(Season::Summer, toast)
+    }
+    pub fn winter(toast: &str) -> Breakfast {
+        Breakfast::for_season(Season::Winter, toast)
+    }
+    pub fn autumn(toast: &str) -> Breakfast {
+        Breakfast::for_season(Season::Autumn, toast)
+    }
+
+    fn with_fruit(fruit: Option<&str>, toast: &str) -> Breakfast {
+        Breakfast {
+            toast: String::from(toast),
+            seasonal_fruit: String::from(fruit.unwrap_or("fruit of the day")),
+        }
+    }
+
+    // Read-only, the kitchen decides the fruit
+    pub fn seasonal_fruit(&self) -> &str {
+        &self.seasonal_fruit
+    }
+
+    pub const MENU_ID: &'static str = "breakfast";
+
+    // What the menu charges for this breakfast with this toast
+    pub fn price(&self, menu: &Menu) -> Result<Cents, MenuError> {
+        menu.quote(Breakfast::MENU_ID, &[("toast", &self.toast)])
+    }
+
+    pub fn order_item(&self) -> OrderItem {
+        OrderItem::new(Breakfast::MENU_ID).with("toast", &self.toast)
+    }
+
+    // What goes into it with this toast, from the kitchen's recipe
+    pub fn profile(&self, inventory: &Inventory, date: Date) -> Option<DishProfile> {
+        inventory.dish_profile(&self.order_item(), date)
+    }
+}
+// The guest sent something back (`menu_ids`, or everything if that's empty): the
+// kitchen makes it again, ahead of everything else
+pub fn fix_incorrect_order(
+    orders: &mut OrderBook,
+    kitchen: &mut Kitchen,
+    id: OrderId,
+    menu_ids: &[&str],
+    reason: &str,
+    now: Timestamp,
+) -> Result<Vec<TicketId>, KitchenError> {
+    let status = orders.status(id).ok_or(OrderError::UnknownOrder(id))?;
+    if !status.can_become(OrderStatus::Remade) {
+        return Err(KitchenError::Order(OrderError::IllegalTransition {
+            order: id,
+            from: status,
+            to: OrderStatus::Remade,
+        }));
+    }
+    // The remade dishes need their ingredients again
+    let order = orders.get(id).ok_or(OrderError::UnknownOrder(id))?;
+    let items: Vec<OrderItem> = order
+        .items
+        .iter()
+        .filter(|i| menu_ids.is_empty() || menu_ids.contains(&i.menu_id.as_str()))
+        .cloned()
+        .collect();
+    let mut stock = kitchen.inventory().clone();
+    stock.use_items(&items, now)?;
+    let tickets = kitchen.remake(id, menu_ids, reason, now)?;
+    *kitchen.inventory_mut() = stock;
+    orders.send_back(id, now)?;
+    orders.start_cooking(id, now)?;
+    Ok(tickets)
+}
+
+// Fire an order that was just placed: it becomes tickets on the stations, its
+// ingredients come out of stock, and anything that can't be made any more is 86'd
+pub fn cook_order(
+    orders: &mut OrderBook,
+    kitchen: &mut Kitchen,
+    menu: &mut Menu,
+    id: OrderId,
+    now: Timestamp,
+) -> Result<Vec<TicketId>, KitchenError> {
+    let order = orders.get(id).ok_or(OrderError::UnknownOrder(id))?;
+    if order.status() != OrderStatus::Placed {
+        return Err(KitchenError::Order(OrderError::IllegalTransition {
+            order: id,
+            from: order.status(),
+            to: OrderStatus::Cooking,
+        }));
+    }
+    let mut stock = kitchen.inventory().clone();
+    stock.deplete(order, now)?;
+    let tickets = kitchen.fire(order, menu, now)?;
+    *kitchen.inventory_mut() = stock; // <- only once the tickets are really on the line
+    kitchen.inventory_mut().update_menu(menu, now);
+    orders.start_cooking(id, now)?;
+    Ok(tickets)
+}
+
+// A station finished a ticket; once every ticket for the order is done the
+// order goes up to the pass
+pub fn bump_ticket(
+    orders: &mut OrderBook,
+    kitchen: &mut Kitchen,
+    ticket: TicketId,
+    now: Timestamp,
+) -> Result<Option<OrderId>, KitchenError> {
+    let Some(order) = kitchen.bump(ticket, now)? else {
+        return Ok(None);
+    };
+    super::deliver_order(orders, order, now)?; // <- super keyword to access parent module, same as Python
+    Ok(Some(order))
+}
@@ -0,0 +1,880 @@
+// What's in the walk-in, and what each dish takes out of it.
+// Every ingredient is counted in one stock unit (grams, millilitres or pieces);
+// recipes can be written in any unit that converts to it. Cooking an order takes
+// its ingredients out, warns when something drops below par, and "86"s (marks
+// sold out) any menu item that can no longer be made, putting it back once the
+// ingredient is restocked. Ingredients also say which allergens they carry, so
+// what's in a dish can be read off its recipe.
+
+use super::seasonal::SeasonalMenu;
+use crate::clock::{Date, Timestamp};
+use crate::dietary::DishProfile;
+use crate::json::Json;
+use crate::menu::{Allergen, Menu};
+use crate::order::{Order, OrderItem};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Mass,
+    Volume,
+    Count,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    Gram,
+    Kilogram,
+    Ounce,
+    Pound,
+    Millilitre,
+    Litre,
+    Teaspoon,
+    Tablespoon,
+    Cup,
+    Each,
+    Dozen,
+}
+
+impl Unit {
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Unit::Gram | Unit::Kilogram | Unit::Ounce | Unit::Pound => Dimension::Mass,
+            Unit::Millilitre | Unit::Litre | Unit::Teaspoon | Unit::Tablespoon | Unit::Cup => {
+                Dimension::Volume
+            }
+            Unit::Each | Unit::Dozen => Dimension::Count,
+        }
+    }
+
+    // How many grams, millilitres or pieces one of this unit is
+    pub fn base_factor(&self) -> f64 {
+        match self {
+            Unit::Gram | Unit::Millilitre | Unit::Each => 1.0,
+            Unit::Kilogram | Unit::Litre => 1000.0,
+            Unit::Ounce => 28.349_523_125,
+            Unit::Pound => 453.592_37,
+            Unit::Teaspoon => 4.928_921_593_75,
+            Unit::Tablespoon => 14.786_764_781_25,
+            Unit::Cup => 236.588_236_5,
+            Unit::Dozen => 12.0,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Unit::Gram => "g",
+            Unit::Kilogram => "kg",
+            Unit::Ounce => "oz",
+            Unit::Pound => "lb",
+            Unit::Millilitre => "ml",
+            Unit::Litre => "l",
+            Unit::Teaspoon => "tsp",
+            Unit::Tablespoon => "tbsp",
+            Unit::Cup => "cup",
+            Unit::Each => "each",
+            Unit::Dozen => "dozen",
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantity {
+    pub amount: f64,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    pub fn new(amount: f64, unit: Unit) -> Quantity {
+        Quantity { amount, unit }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", (self.amount * 100.0).round() / 100.0, self.unit)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ingredient {
+    pub name: String,
+    pub unit: Unit,              // <- what stock is counted in
+    pub on_hand: f64,            // <- in `unit`
+    pub par: f64,                // <- warn when stock drops below this
+    per_piece: Option<Quantity>, // <- what one piece weighs or holds, to cross dimensions
+    pub allergens: Vec<Allergen>,
+    pub meat: bool,
+}
+
+impl Ingredient {
+    pub fn new(name: &str, unit: Unit, on_hand: f64, par: f64) -> Ingredient {
+        Ingredient {
+            name: String::from(name),
+            unit,
+            on_hand,
+            par,
+            per_piece: None,
+            allergens: Vec::new(),
+            meat: false,
+        }
+    }
+
+    pub fn contains(mut self, allergens: &[Allergen]) -> Ingredient {
+        self.allergens.extend_from_slice(allergens);
+        self
+    }
+
+    pub fn meat(mut self) -> Ingredient {
+        self.meat = true;
+        self
+    }
+
+    // One piece (a loaf, a lemon) is this much, so recipes can ask for it either
+    // way: bread counted in slices but bought by weight, for instance
+    pub fn piece_is(mut self, quantity: Quantity) -> Ingredient {
+        self.per_piece = Some(quantity);
+        self
+    }
+
+    // A quantity in this ingredient's stock unit
+    pub fn convert(&self, quantity: Quantity) -> Option<f64> {
+        let from = quantity.unit.dimension();
+        let to = self.unit.dimension();
+        let base = quantity.amount * quantity.unit.base_factor();
+        let base = if from == to {
+            base
+        } else {
+            // Go through pieces: Count <-> whatever a piece is measured in
+            let piece = self.per_piece?;
+            let piece_base = piece.amount * piece.unit.base_factor();
+            match (from, to) {
+                (Dimension::Count, d) if d == piece.unit.dimension() => base * piece_base,
+                (d, Dimension::Count) if d == piece.unit.dimension() => base / piece_base,
+                _ => return None,
+            }
+        };
+        Some(base / self.unit.base_factor())
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.on_hand < self.par
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Component {
+    Ingredient(String),
+    SeasonalFruit, // <- whichever fruit is in season (and in stock) on the day
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecipeLine {
+    pub component: Component,
+    pub quantity: Quantity,
+}
+
+// What one portion of a menu item uses, plus extra for particular choices
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recipe {
+    pub lines: Vec<RecipeLine>,
+    options: HashMap<(String, String), Vec<RecipeLine>>, // <- (modifier, option), lowercase
+}
+
+impl Recipe {
+    pub fn new() -> Recipe {
+        Recipe::default()
+    }
+
+    pub fn uses(mut self, ingredient: &str, amount: f64, unit: Unit) -> Recipe {
+        self.lines.push(RecipeLine {
+            component: Component::Ingredient(String::from(ingredient)),
+            quantity: Quantity::new(amount, unit),
+        });
+        self
+    }
+
+    pub fn seasonal_fruit(mut self, amount: f64, unit: Unit) -> Recipe {
+        self.lines.push(RecipeLine {
+            component: Component::SeasonalFruit,
+            quantity: Quantity::new(amount, unit),
+        });
+        self
+    }
+
+    // Only used when the guest picks this option, like rye bread for rye toast
+    pub fn option_uses(
+        mut self,
+        modifier: &str,
+        option: &str,
+        ingredient: &str,
+        amount: f64,
+        unit: Unit,
+    ) -> Recipe {
+        self.options
+            .entry((modifier.to_lowercase(), option.to_lowercase()))
+            .or_default()
+            .push(RecipeLine {
+                component: Component::Ingredient(String::from(ingredient)),
+                quantity: Quantity::new(amount, unit),
+            });
+        self
+    }
+
+    fn lines_for<'a>(&'a self, item: &'a OrderItem) -> impl Iterator<Item = &'a RecipeLine> {
+        let chosen = item.choices.iter().flat_map(|(modifier, option)| {
+            self.options
+                .get(&(modifier.to_lowercase(), option.to_lowercase()))
+                .into_iter()
+                .flatten()
+        });
+        self.lines.iter().chain(chosen)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InventoryError {
+    UnknownIngredient(String),
+    IncompatibleUnit {
+        ingredient: String,
+        unit: Unit,
+    },
+    Shortage {
+        ingredient: String,
+        needed: f64,
+        on_hand: f64,
+    },
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InventoryError::UnknownIngredient(name) => write!(f, "no '{}' in inventory", name),
+            InventoryError::IncompatibleUnit { ingredient, unit } => {
+                write!(f, "'{}' can't be measured in {}", ingredient, unit)
+            }
+            InventoryError::Shortage {
+                ingredient,
+                needed,
+                on_hand,
+            } => write!(
+                f,
+                "not enough {}: need {:.2}, have {:.2}",
+                ingredient, needed, on_hand
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InventoryError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StockAlert {
+    Low {
+        at: Timestamp,
+        ingredient: String,
+        on_hand: Quantity,
+    },
+    SoldOut {
+        at: Timestamp,
+        menu_id: String,
+    },
+    BackOn {
+        at: Timestamp,
+        menu_id: String,
+    },
+}
+
+impl fmt::Display for StockAlert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StockAlert::Low {
+                at,
+                ingredient,
+                on_hand,
+            } => write!(f, "{} low on {}: {} left", at, ingredient, on_hand),
+            StockAlert::SoldOut { at, menu_id } => write!(f, "{} 86 {}", at, menu_id),
+            StockAlert::BackOn { at, menu_id } => write!(f, "{} {} is back on", at, menu_id),
+        }
+    }
+}
+
+// What cooking an order did to the stock
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Depletion {
+    pub used: BTreeMap<String, f64>, // <- in each ingredient's stock unit
+    pub now_low: Vec<String>,        // <- ingredients that just went below par
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Inventory {
+    ingredients: BTreeMap<String, Ingredient>,
+    recipes: HashMap<String, Recipe>, // <- by menu id; items without one use nothing
+    pub seasons: SeasonalMenu,
+    eighty_sixed: BTreeSet<String>, // <- menu items we took off, so we can put them back
+    alerts: Vec<StockAlert>,
+}
+
+impl Inventory {
+    pub fn new() -> Inventory {
+        Inventory::default()
+    }
+
+    // Stock and recipes for the house menu
+    pub fn house() -> Inventory {
+        let mut inventory = Inventory::new();
+        for ingredient in [
+            Ingredient::new("rye bread", Unit::Gram, 4000.0, 1000.0)
+                .piece_is(Quantity::new(40.0, Unit::Gram)) // <- a slice
+                .contains(&[Allergen::Gluten]),
+            Ingredient::new("wheat bread", Unit::Gram, 4000.0, 1000.0)
+                .piece_is(Quantity::new(40.0, Unit::Gram))
+                .contains(&[Allergen::Gluten]),
+            Ingredient::new("sourdough", Unit::Gram, 3000.0, 800.0)
+                .piece_is(Quantity::new(50.0, Unit::Gram))
+                .contains(&[Allergen::Gluten]),
+            Ingredient::new("gluten-free bread", Unit::Each, 20.0, 6.0),
+            Ingredient::new("eggs", Unit::Each, 180.0, 36.0).contains(&[Allergen::Eggs]),
+            Ingredient::new("butter", Unit::Gram, 2000.0, 500.0).contains(&[Allergen::Dairy]),
+            Ingredient::new("strawberries", Unit::Gram, 3000.0, 500.0),
+            Ingredient::new("peaches", Unit::Gram, 3000.0, 500.0),
+            Ingredient::new("persimmons", Unit::Gram, 3000.0, 500.0),
+            Ingredient::new("oranges", Unit::Each, 40.0, 10.0)
+                .piece_is(Quantity::new(130.0, Unit::Gram)),
+            Ingredient::new("soup base", Unit::Millilitre, 8000.0, 2000.0).meat(), // <- chicken stock
+            Ingredient::new("cream", Unit::Millilitre, 2000.0, 500.0).contains(&[Allergen::Dairy]),
+            Ingredient::new("lettuce", Unit::Gram, 3000.0, 600.0),
+            Ingredient::new("ranch", Unit::Millilitre, 1000.0, 250.0)
+                .contains(&[Allergen::Dairy, Allergen::Eggs]),
+            Ingredient::new("vinaigrette", Unit::Millilitre, 1000.0, 250.0),
+            Ingredient::new("sesame ginger", Unit::Millilitre, 1000.0, 250.0)
+                .contains(&[Allergen::Sesame, Allergen::Soy]),
+            Ingredient::new("chicken", Unit::Gram, 4000.0, 1000.0).meat(),
+            Ingredient::new("salmon", Unit::Gram, 3000.0, 800.0).contains(&[Allergen::Fish]),
+            Ingredient::new("beef patties", Unit::Each, 60.0, 15.0).meat(),
+            Ingredient::new("buns", Unit::Each, 60.0, 15.0).contains(&[Allergen::Gluten]),
+            Ingredient::new("potatoes", Unit::Kilogram, 25.0, 5.0),
+            Ingredient::new("lemons", Unit::Each, 50.0, 12.0)
+                .piece_is(Quantity::new(45.0, Unit::Millilitre)), // <- juice per lemon
+        ] {
+            inventory.add_ingredient(ingredient);
+        }
+        inventory.set_recipe(
+            "breakfast",
+            Recipe::new()
+                .uses("eggs", 2.0, Unit::Each)
+                .uses("butter", 15.0, Unit::Gram)
+                .seasonal_fruit(120.0, Unit::Gram)
+                .option_uses("toast", "Rye", "rye bread", 2.0, Unit::Each)
+                .option_uses("toast", "Wheat", "wheat bread", 2.0, Unit::Each)
+                .option_uses("toast", "Sourdough", "sourdough", 2.0, Unit::Each)
+                .option_uses("toast", "Gluten-Free", "gluten-free bread", 2.0, Unit::Each),
+        );
+        inventory.set_recipe(
+            "soup",
+            Recipe::new()
+                .uses("soup base", 1.5, Unit::Cup)
+                .uses("cream", 2.0, Unit::Tablespoon),
+        );
+        inventory.set_recipe(
+            "salad",
+            Recipe::new()
+                .uses("lettuce", 150.0, Unit::Gram)
+                .option_uses("dressing", "Ranch", "ranch", 3.0, Unit::Tablespoon)
+                .option_uses(
+                    "dressing",
+                    "Vinaigrette",
+                    "vinaigrette",
+                    3.0,
+                    Unit::Tablespoon,
+                )
+                .option_uses(
+                    "dressing",
+                    "Sesame Ginger",
+                    "sesame ginger",
+                    3.0,
+                    Unit::Tablespoon,
+                )
+                .option_uses("protein", "Chicken", "chicken", 5.0, Unit::Ounce)
+                .option_uses("protein", "Salmon", "salmon", 5.0, Unit::Ounce),
+        );
+        inventory.set_recipe(
+            "burger",
+            Recipe::new()
+                .uses("beef patties", 1.0, Unit::Each)
+                .uses("buns", 1.0, Unit::Each)
+                .option_uses("side", "Fries", "potatoes", 250.0, Unit::Gram)
+                .option_uses("side", "Salad", "lettuce", 80.0, Unit::Gram),
+        );
+        inventory.set_recipe("fries", Recipe::new().uses("potatoes", 250.0, Unit::Gram));
+        inventory.set_recipe("lemonade", Recipe::new().uses("lemons", 2.0, Unit::Each));
+        inventory
+    }
+
+    pub fn add_ingredient(&mut self, ingredient: Ingredient) {
+        self.ingredients.insert(ingredient.name.clone(), ingredient);
+    }
+
+    pub fn ingredient(&self, name: &str) -> Option<&Ingredient> {
+        self.ingredients.get(name)
+    }
+
+    pub fn ingredients(&self) -> impl Iterator<Item = &Ingredient> {
+        self.ingredients.values()
+    }
+
+    pub fn set_recipe(&mut self, menu_id: &str, recipe: Recipe) {
+        self.recipes.insert(String::from(menu_id), recipe);
+    }
+
+    pub fn recipe(&self, menu_id: &str) -> Option<&Recipe> {
+        self.recipes.get(menu_id)
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.ingredients.get(name).is_some_and(|i| i.on_hand > 0.0)
+    }
+
+    pub fn restock(&mut self, name: &str, quantity: Quantity) -> Result<f64, InventoryError> {
+        let ingredient = self
+            .ingredients
+            .get_mut(name)
+            .ok_or_else(|| InventoryError::UnknownIngredient(String::from(name)))?;
+        let amount =
+            ingredient
+                .convert(quantity)
+                .ok_or_else(|| InventoryError::IncompatibleUnit {
+                    ingredient: String::from(name),
+                    unit: quantity.unit,
+                })?;
+        ingredient.on_hand += amount;
+        Ok(ingredient.on_hand)
+    }
+
+    pub fn low_stock(&self) -> Vec<&Ingredient> {
+        self.ingredients.values().filter(|i| i.is_low()).collect()
+    }
+
+    // The fruit breakfast gets today: in season, and enough of it for a portion
+    pub fn seasonal_fruit(&self, date: Date, quantity: Quantity) -> Option<&str> {
+        self.seasons.fruit_for(date, |fruit| {
+            self.ingredients.get(fruit).is_some_and(|i| {
+                i.convert(quantity)
+                    .is_some_and(|needed| i.on_hand >= needed)
+            })
+        })
+    }
+
+    // What the recipe for this item, with the guest's choices, puts on the
+    // plate; None for items without a recipe. Ingredients the walk-in doesn't
+    // know are skipped here, and cooking will refuse them anyway.
+    pub fn dish_profile(&self, item: &OrderItem, date: Date) -> Option<DishProfile> {
+        let recipe = self.recipes.get(&item.menu_id)?;
+        let mut dish = DishProfile {
+            from_recipe: true,
+            ..DishProfile::default()
+        };
+        for line in recipe.lines_for(item) {
+            let name = match &line.component {
+                Component::Ingredient(name) => Some(name.as_str()),
+                Component::SeasonalFruit => self.seasonal_fruit(date, line.quantity),
+            };
+            if let Some(ingredient) = name.and_then(|n| self.ingredients.get(n)) {
+                dish.allergens.extend(ingredient.allergens.iter().copied());
+                dish.meat |= ingredient.meat;
+            }
+        }
+        Some(dish)
+    }
+
+    // Everything the order needs, in stock units
+    pub fn requirements(
+        &self,
+        items: &[OrderItem],
+        date: Date,
+    ) -> Result<BTreeMap<String, f64>, InventoryError> {
+        let mut needed: BTreeMap<String, f64> = BTreeMap::new();
+        for item in items {
+            let Some(recipe) = self.recipes.get(&item.menu_id) else {
+                continue;
+            };
+            for line in recipe.lines_for(item) {
+                let name = match &line.component {
+                    Component::Ingredient(name) => name.as_str(),
+                    Component::SeasonalFruit => {
+                        // Fall back to the first fruit of the season, which will
+                        // then be reported as short
+                        match self.seasonal_fruit(date, line.quantity) {
+                            Some(fruit) => fruit,
+                            None => self
+                                .seasons
+                                .fruit(self.seasons.calendar.season(date))
+                                .first()
+                                .map(|f| f.as_str())
+                                .unwrap_or("seasonal fruit"),
+                        }
+                    }
+                };
+                let ingredient = self
+                    .ingredients
+                    .get(name)
+                    .ok_or_else(|| InventoryError::UnknownIngredient(String::from(name)))?;
+                let amount = ingredient.convert(line.quantity).ok_or_else(|| {
+                    InventoryError::IncompatibleUnit {
+                        ingredient: String::from(name),
+                        unit: line.quantity.unit,
+                    }
+                })?;
+                *needed.entry(String::from(name)).or_insert(0.0) += amount;
+            }
+        }
+        Ok(needed)
+    }
+
+    // Take an order's ingredients out of stock. Nothing is taken unless
+    // everything is there.
+    pub fn deplete(&mut self, order: &Order, now: Timestamp) -> Result<Depletion, InventoryError> {
+        self.use_items(&order.items, now)
+    }
+
+    pub fn use_items(
+        &mut self,
+        items: &[OrderItem],
+        now: Timestamp,
+    ) -> Result<Depletion, InventoryError> {
+        let needed = self.check(items, now.date())?;
+        let mut depletion = Depletion::default();
+        for (name, amount) in needed {
+            if let Some(ingredient) = self.ingredients.get_mut(&name) {
+                let was_low = ingredient.is_low();
+                ingredient.on_hand = (ingredient.on_hand - amount).max(0.0);
+                if !was_low && ingredient.is_low() {
+                    depletion.now_low.push(name.clone());
+                    self.alerts.push(StockAlert::Low {
+                        at: now,
+                        ingredient: name.clone(),
+                        on_hand: Quantity::new(ingredient.on_hand, ingredient.unit),
+                    });
+                }
+            }
+            depletion.used.insert(name, amount);
+        }
+        Ok(depletion)
+    }
+
+    // What the items need, if it's all on hand; otherwise the first shortage
+    pub fn check(
+        &self,
+        items: &[OrderItem],
+        date: Date,
+    ) -> Result<BTreeMap<String, f64>, InventoryError> {
+        let needed = self.requirements(items, date)?;
+        for (name, amount) in &needed {
+            let ingredient = &self.ingredients[name];
+            if ingredient.on_hand + 1e-9 < *amount {
+                return Err(InventoryError::Shortage {
+                    ingredient: name.clone(),
+                    needed: *amount,
+                    on_hand: ingredient.on_hand,
+                });
+            }
+        }
+        Ok(needed)
+    }
+
+    // Could one more portion be made, with these choices?
+    pub fn can_make(&self, item: &OrderItem, date: Date) -> bool {
+        self.check(std::slice::from_ref(item), date).is_ok()
+    }
+
+    // 86 the menu items that can't be made any more, and bring back the ones we
+    // 86'd earlier that can. Items someone else marked sold out are left alone.
+    // Returns the items that changed and whether they are now sold out.
+    pub fn update_menu(&mut self, menu: &mut Menu, now: Timestamp) -> Vec<(String, bool)> {
+        let date = now.date();
+        let mut changes = Vec::new();
+        let mut ids: Vec<String> = self.recipes.keys().cloned().collect();
+        ids.sort();
+        for id in ids {
+            let Some(item) = menu.get(&id) else {
+                continue;
+            };
+            // The item as it comes, and some option for every choice that has
+            // to be made
+            let plain = OrderItem::new(&id);
+            let can_make = self.can_make(&plain, date)
+                && item.modifiers.iter().filter(|m| m.required).all(|m| {
+                    m.options.iter().any(|o| {
+                        let chosen = plain.clone().with(&m.name, &o.name);
+                        self.can_make(&chosen, date)
+                    })
+                });
+            if !can_make && !item.sold_out {
+                let _ = menu.set_sold_out(&id, true);
+                self.eighty_sixed.insert(id.clone());
+                self.alerts.push(StockAlert::SoldOut {
+                    at: now,
+                    menu_id: id.clone(),
+                });
+                changes.push((id.clone(), true));
+            } else if can_make && item.sold_out && self.eighty_sixed.remove(&id) {
+                let _ = menu.set_sold_out(&id, false);
+                self.alerts.push(StockAlert::BackOn {
+                    at: now,
+                    menu_id: id.clone(),
+                });
+                changes.push((id.clone(), false));
+            }
+        }
+        changes
+    }
+
+    // What's on hand and what we 86'd; the ingredients and recipes themselves
+    // are set up at opening
+    pub fn state_json(&self) -> Json {
+        let on_hand = self
+            .ingredients
+            .values()
+            .fold(Json::object(), |json, i| json.with(&i.name, i.on_hand));
+        let eighty_sixed: Vec<Json> = self
+            .eighty_sixed
+            .iter()
+            .map(|id| Json::from(id.as_str()))
+            .collect();
+        Json::object()
+            .with("on_hand", on_hand)
+            .with("eighty_sixed", eighty_sixed)
+    }
+
+    pub fn restore_state(&mut self, json: &Json) -> Option<()> {
+        let mut on_hand = Vec::new();
+        for (name, amount) in json.get("on_hand")?.as_object()? {
+            on_hand.push((name, amount.as_f64()?));
+        }
+        let eighty_sixed = json
+            .get("eighty_sixed")?
+            .as_array()?
+            .iter()
+            .map(|id| id.as_str().map(String::from))
+            .collect::<Option<BTreeSet<_>>>()?;
+        for (name, amount) in on_hand {
+            if let Some(ingredient) = self.ingredients.get_mut(name) {
+                ingredient.on_hand = amount;
+            }
+        }
+        self.eighty_sixed = eighty_sixed;
+        Some(())
+    }
+
+    pub fn alerts(&self) -> &[StockAlert] {
+        &self.alerts
+    }
+
+    // Hand the alerts over (to print on the kitchen display, say) and start afresh
+    pub fn take_alerts(&mut self) -> Vec<StockAlert> {
+        std::mem::take(&mut self.alerts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(month: u32, day: u32) -> Timestamp {
+        let date = Date::new(2024, month, day).unwrap();
+        Timestamp::from_secs(date.days_since_epoch() as u64 * 86_400 + 9 * 3600)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn quantities_convert_to_the_stock_unit() {
+        let inventory = Inventory::house();
+        let soup_base = inventory.ingredient("soup base").unwrap();
+        assert!(close(
+            soup_base.convert(Quantity::new(1.0, Unit::Cup)).unwrap(),
+            236.588_236_5
+        ));
+        assert_eq!(soup_base.convert(Quantity::new(1.0, Unit::Gram)), None);
+        let potatoes = inventory.ingredient("potatoes").unwrap();
+        assert!(close(
+            potatoes.convert(Quantity::new(250.0, Unit::Gram)).unwrap(),
+            0.25
+        ));
+        // Through pieces, both ways
+        let rye = inventory.ingredient("rye bread").unwrap();
+        assert!(close(
+            rye.convert(Quantity::new(2.0, Unit::Each)).unwrap(),
+            80.0
+        ));
+        let lemons = inventory.ingredient("lemons").unwrap();
+        assert!(close(
+            lemons
+                .convert(Quantity::new(90.0, Unit::Millilitre))
+                .unwrap(),
+            2.0
+        ));
+        assert_eq!(lemons.convert(Quantity::new(1.0, Unit::Gram)), None);
+    }
+
+    #[test]
+    fn choices_and_the_season_decide_what_a_dish_uses() {
+        let inventory = Inventory::house();
+        let breakfast = OrderItem::new("breakfast").with("toast", "rye");
+        let summer = inventory
+            .requirements(std::slice::from_ref(&breakfast), on(7, 1).date())
+            .unwrap();
+        assert!(close(summer["rye bread"], 80.0));
+        assert!(close(summer["peaches"], 120.0));
+        assert!(!summer.contains_key("sourdough"));
+        let winter = inventory
+            .requirements(&[breakfast], on(1, 10).date())
+            .unwrap();
+        assert!(close(winter["oranges"], 120.0 / 130.0));
+        assert!(inventory
+            .requirements(&[OrderItem::new("coffee")], on(7, 1).date())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn nothing_is_used_unless_everything_is_there() {
+        let mut inventory = Inventory::new();
+        inventory.add_ingredient(Ingredient::new("patties", Unit::Each, 3.0, 2.0));
+        inventory.add_ingredient(Ingredient::new("buns", Unit::Each, 1.0, 0.0));
+        inventory.set_recipe(
+            "burger",
+            Recipe::new()
+                .uses("patties", 1.0, Unit::Each)
+                .uses("buns", 1.0, Unit::Each),
+        );
+        let two = [OrderItem::new("burger"), OrderItem::new("burger")];
+        assert!(matches!(
+            inventory.use_items(&two, on(7, 1)),
+            Err(InventoryError::Shortage { ref ingredient, .. }) if ingredient == "buns"
+        ));
+        assert_eq!(inventory.ingredient("patties").unwrap().on_hand, 3.0);
+
+        let used = inventory.use_items(&two[..1], on(7, 1)).unwrap();
+        assert_eq!(used.used["patties"], 1.0);
+        assert!(used.now_low.is_empty());
+        inventory
+            .restock("buns", Quantity::new(1.0, Unit::Dozen))
+            .unwrap();
+        let used = inventory.use_items(&two[..1], on(7, 1)).unwrap();
+        assert_eq!(used.now_low, ["patties"]);
+        assert_eq!(inventory.low_stock().len(), 1);
+        assert_eq!(inventory.take_alerts().len(), 1);
+        assert!(inventory.alerts().is_empty());
+        assert!(matches!(
+            inventory.restock("buns", Quantity::new(1.0, Unit::Litre)),
+            Err(InventoryError::IncompatibleUnit { .. })
+        ));
+        assert!(inventory
+            .restock("pickles", Quantity::new(1.0, Unit::Each))
+            .is_err());
+    }
+
+    #[test]
+    fn items_are_86d_and_brought_back() {
+        let mut inventory = Inventory::house();
+        let mut menu = Menu::house();
+        let now = on(7, 1);
+        menu.set_sold_out("burger", true).unwrap(); // <- someone else's call
+        assert!(inventory.update_menu(&mut menu, now).is_empty());
+
+        let potatoes = inventory.ingredient("potatoes").unwrap().on_hand;
+        let fries = vec![OrderItem::new("fries"); (potatoes / 0.25) as usize];
+        inventory.use_items(&fries, now).unwrap();
+        assert_eq!(
+            inventory.update_menu(&mut menu, now),
+            [(String::from("fries"), true)]
+        );
+        assert!(menu.get("fries").unwrap().sold_out);
+        assert!(inventory.update_menu(&mut menu, now).is_empty());
+
+        inventory
+            .restock("potatoes", Quantity::new(10.0, Unit::Pound))
+            .unwrap();
+        assert_eq!(
+            inventory.update_menu(&mut menu, now),
+            [(String::from("fries"), false)]
+        );
+        assert!(!menu.get("fries").unwrap().sold_out);
+        assert!(menu.get("burger").unwrap().sold_out);
+    }
+
+    #[test]
+    fn items_are_86d_when_no_choice_can_be_made() {
+        let mut inventory = Inventory::house();
+        let mut menu = Menu::house();
+        let mut burger = menu.get("burger").unwrap().clone();
+        burger.modifiers[0]
+            .options
+            .retain(|o| o.name != "Onion Rings"); // <- no recipe for those
+        menu.add(burger);
+        let now = on(7, 1);
+        inventory
+            .use_items(&vec![OrderItem::new("fries"); 100], now)
+            .unwrap(); // <- no potatoes left
+        let with_fries = OrderItem::new("burger").with("side", "Fries");
+        assert!(!inventory.can_make(&with_fries, now.date()));
+        assert!(inventory.can_make(&OrderItem::new("burger").with("side", "Salad"), now.date()));
+        assert_eq!(
+            inventory.update_menu(&mut menu, now),
+            [(String::from("fries"), true)] // <- the burger still comes with salad
+        );
+
+        let salad = OrderItem::new("salad");
+        while inventory.can_make(&salad, now.date()) {
+            inventory
+                .use_items(std::slice::from_ref(&salad), now)
+                .unwrap();
+        }
+        let side = OrderItem::new("burger").with("side", "Salad");
+        while inventory.can_make(&side, now.date()) {
+            inventory
+                .use_items(std::slice::from_ref(&side), now)
+                .unwrap();
+        }
+        assert_eq!(
+            inventory.update_menu(&mut menu, now),
+            [
+                (String::from("burger"), true),
+                (String::from("salad"), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn stock_round_trips_through_json() {
+        let mut inventory = Inventory::house();
+        let mut menu = Menu::house();
+        inventory
+            .use_items(&[OrderItem::new("soup")], on(7, 1))
+            .unwrap();
+        inventory
+            .use_items(&vec![OrderItem::new("fries"); 100], on(7, 1))
+            .unwrap(); // <- all 25kg of potatoes
+        assert_eq!(
+            inventory.update_menu(&mut menu, on(7, 1)),
+            [(String::from("fries"), true)]
+        );
+        let mut restored = Inventory::house();
+        restored.restore_state(&inventory.state_json()).unwrap();
+        assert_eq!(restored.state_json(), inventory.state_json());
+        assert_eq!(
+            restored.ingredient("soup base"),
+            inventory.ingredient("soup base")
+        );
+    }
+}
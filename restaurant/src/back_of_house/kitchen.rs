@@ -0,0 +1,784 @@
+// The kitchen display: orders are broken into tickets, one per station and
+// course, and each station works through its own queue. Remakes jump the queue;
+// otherwise tickets go out in the order they were fired, earlier courses first.
+// Every ticket knows how long it should take, so late ones can be flagged, and
+// how long its items actually took, so those estimates can be checked.
+
+use super::inventory::{Inventory, InventoryError};
+use crate::clock::{minutes, Timestamp};
+use crate::json::Json;
+use crate::menu::{Category, Menu, MenuError};
+use crate::order::{Order, OrderError, OrderId};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Station {
+    Grill,
+    Fryer,
+    Saute,
+    Cold, // <- salads, desserts and drinks
+}
+
+impl Station {
+    pub const ALL: [Station; 4] = [
+        Station::Grill,
+        Station::Fryer,
+        Station::Saute,
+        Station::Cold,
+    ];
+
+    pub fn parse(name: &str) -> Option<Station> {
+        Station::ALL.into_iter().find(|s| s.to_string() == name)
+    }
+}
+
+impl fmt::Display for Station {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Station::Grill => "grill",
+            Station::Fryer => "fryer",
+            Station::Saute => "saute",
+            Station::Cold => "cold",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Course {
+    Drinks,
+    Starter,
+    Main,
+    Dessert,
+}
+
+impl Course {
+    pub const ALL: [Course; 4] = [
+        Course::Drinks,
+        Course::Starter,
+        Course::Main,
+        Course::Dessert,
+    ];
+
+    pub fn parse(name: &str) -> Option<Course> {
+        Course::ALL.into_iter().find(|c| c.to_string() == name)
+    }
+
+    pub fn for_category(category: Category) -> Course {
+        match category {
+            Category::Drink => Course::Drinks,
+            Category::Appetizer => Course::Starter,
+            Category::Breakfast | Category::Main | Category::Side => Course::Main,
+            Category::Dessert => Course::Dessert,
+        }
+    }
+}
+
+impl fmt::Display for Course {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Course::Drinks => "drinks",
+            Course::Starter => "starter",
+            Course::Main => "main",
+            Course::Dessert => "dessert",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TicketId(pub u32);
+
+impl fmt::Display for TicketId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ticket #{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TicketItem {
+    pub menu_id: String,
+    pub name: String,
+    pub choices: Vec<(String, String)>,
+}
+
+// Why a ticket is being made again, and whose ticket it replaces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remake {
+    pub original: TicketId,
+    pub reason: String,
+    pub station: Station, // <- the station the mistake is put down to
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ticket {
+    pub id: TicketId,
+    pub order: OrderId,
+    pub table: u32,
+    pub station: Station,
+    pub course: Course,
+    pub items: Vec<TicketItem>,
+    pub fire_at: Timestamp, // <- when the station should start on it
+    pub expected: Duration, // <- how long it should take once started
+    pub remake: Option<Remake>,
+    pub started: Option<Timestamp>,
+    pub done: Option<Timestamp>,
+}
+
+impl Ticket {
+    pub fn due(&self) -> Timestamp {
+        self.started.unwrap_or(self.fire_at).max(self.fire_at) + self.expected
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done.is_some()
+    }
+
+    pub fn is_late(&self, now: Timestamp, grace: Duration) -> bool {
+        !self.is_done() && now > self.due() + grace
+    }
+}
+
+impl Ticket {
+    pub fn to_json(&self) -> Json {
+        let items: Vec<Json> = self
+            .items
+            .iter()
+            .map(|i| {
+                let choices: Vec<Json> = i
+                    .choices
+                    .iter()
+                    .map(|(m, o)| Json::from(vec![m.as_str(), o.as_str()]))
+                    .collect();
+                Json::object()
+                    .with("menu_id", i.menu_id.as_str())
+                    .with("name", i.name.as_str())
+                    .with("choices", choices)
+            })
+            .collect();
+        let remake = self.remake.as_ref().map(|r| {
+            Json::object()
+                .with("original", r.original.0)
+                .with("reason", r.reason.as_str())
+                .with("station", r.station.to_string())
+        });
+        Json::object()
+            .with("id", self.id.0)
+            .with("order", self.order.0)
+            .with("table", self.table)
+            .with("station", self.station.to_string())
+            .with("course", self.course.to_string())
+            .with("items", items)
+            .with("fire_at", self.fire_at.as_secs())
+            .with("expected", self.expected.as_secs())
+            .with("remake", remake)
+            .with("started", self.started.map(|t| t.as_secs()))
+            .with("done", self.done.map(|t| t.as_secs()))
+    }
+
+    pub fn from_json(json: &Json) -> Option<Ticket> {
+        let time = |key: &str| match json.get(key)? {
+            Json::Null => Some(None),
+            at => Some(Some(Timestamp::from_secs(at.as_u64()?))),
+        };
+        let mut items = Vec::new();
+        for item in json.get("items")?.as_array()? {
+            let choices = item
+                .get("choices")?
+                .as_array()?
+                .iter()
+                .map(|c| match c.as_array()? {
+                    [m, o] => Some((String::from(m.as_str()?), String::from(o.as_str()?))),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+            items.push(TicketItem {
+                menu_id: String::from(item.get("menu_id")?.as_str()?),
+                name: String::from(item.get("name")?.as_str()?),
+                choices,
+            });
+        }
+        let remake = match json.get("remake")? {
+            Json::Null => None,
+            remake => Some(Remake {
+                original: TicketId(remake.get("original")?.as_u32()?),
+                reason: String::from(remake.get("reason")?.as_str()?),
+                station: Station::parse(remake.get("station")?.as_str()?)?,
+            }),
+        };
+        Some(Ticket {
+            id: TicketId(json.get("id")?.as_u32()?),
+            order: OrderId(json.get("order")?.as_u32()?),
+            table: json.get("table")?.as_u32()?,
+            station: Station::parse(json.get("station")?.as_str()?)?,
+            course: Course::parse(json.get("course")?.as_str()?)?,
+            items,
+            fire_at: time("fire_at")??,
+            expected: Duration::from_secs(json.get("expected")?.as_u64()?),
+            remake,
+            started: time("started")?,
+            done: time("done")?,
+        })
+    }
+}
+
+impl fmt::Display for Ticket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} table {} {} [{}] fire {}",
+            self.id, self.table, self.station, self.course, self.fire_at
+        )?;
+        if self.remake.is_some() {
+            write!(f, " REMAKE")?;
+        }
+        for item in &self.items {
+            write!(f, "\n  {}", item.name)?;
+            for (_, option) in &item.choices {
+                write!(f, ", {}", option)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum KitchenError {
+    UnknownTicket(TicketId),
+    AlreadyStarted(TicketId),
+    AlreadyDone(TicketId),
+    NothingToRemake(OrderId),
+    Order(OrderError),
+    Menu(MenuError),
+    Inventory(InventoryError),
+}
+
+impl fmt::Display for KitchenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KitchenError::UnknownTicket(id) => write!(f, "there is no {}", id),
+            KitchenError::AlreadyStarted(id) => write!(f, "{} was already started", id),
+            KitchenError::AlreadyDone(id) => write!(f, "{} is already done", id),
+            KitchenError::NothingToRemake(id) => {
+                write!(f, "{} has no finished tickets to remake", id)
+            }
+            KitchenError::Order(e) => write!(f, "{}", e),
+            KitchenError::Menu(e) => write!(f, "{}", e),
+            KitchenError::Inventory(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for KitchenError {}
+
+impl From<OrderError> for KitchenError {
+    fn from(e: OrderError) -> Self {
+        KitchenError::Order(e)
+    }
+}
+
+impl From<MenuError> for KitchenError {
+    fn from(e: MenuError) -> Self {
+        KitchenError::Menu(e)
+    }
+}
+
+impl From<InventoryError> for KitchenError {
+    fn from(e: InventoryError) -> Self {
+        KitchenError::Inventory(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Kitchen {
+    routes: HashMap<String, Station>, // <- per menu item, overriding the category
+    category_routes: HashMap<Category, Station>,
+    cook_times: HashMap<String, Duration>,
+    category_cook_times: HashMap<Category, Duration>,
+    pub course_gap: Duration, // <- how long after one course the next is fired
+    pub grace: Duration,      // <- how far past due before a ticket counts as late
+    tickets: Vec<Ticket>,
+    actual: HashMap<String, Vec<Duration>>, // <- measured cook times per menu item
+    next_id: u32,
+    inventory: Inventory,
+}
+
+impl Default for Kitchen {
+    fn default() -> Self {
+        Kitchen {
+            routes: HashMap::new(),
+            category_routes: HashMap::from([
+                (Category::Appetizer, Station::Cold),
+                (Category::Breakfast, Station::Grill),
+                (Category::Main, Station::Grill),
+                (Category::Side, Station::Fryer),
+                (Category::Dessert, Station::Cold),
+                (Category::Drink, Station::Cold),
+            ]),
+            cook_times: HashMap::new(),
+            category_cook_times: HashMap::from([
+                (Category::Appetizer, minutes(8)),
+                (Category::Breakfast, minutes(10)),
+                (Category::Main, minutes(15)),
+                (Category::Side, minutes(6)),
+                (Category::Dessert, minutes(5)),
+                (Category::Drink, minutes(2)),
+            ]),
+            course_gap: minutes(10),
+            grace: minutes(2),
+            tickets: Vec::new(),
+            actual: HashMap::new(),
+            next_id: 0,
+            inventory: Inventory::new(),
+        }
+    }
+}
+
+impl Kitchen {
+    // The house kitchen, with the items that don't follow their category routed
+    pub fn new() -> Kitchen {
+        Kitchen::default()
+            .route("soup", Station::Saute)
+            .route("crab-cakes", Station::Saute)
+            .route("fries", Station::Fryer)
+    }
+
+    pub fn route(mut self, menu_id: &str, station: Station) -> Kitchen {
+        self.routes.insert(String::from(menu_id), station);
+        self
+    }
+
+    pub fn route_category(mut self, category: Category, station: Station) -> Kitchen {
+        self.category_routes.insert(category, station);
+        self
+    }
+
+    pub fn cook_time(mut self, menu_id: &str, time: Duration) -> Kitchen {
+        self.cook_times.insert(String::from(menu_id), time);
+        self
+    }
+
+    // Stock the kitchen; cooking will then use up ingredients
+    pub fn with_inventory(mut self, inventory: Inventory) -> Kitchen {
+        self.inventory = inventory;
+        self
+    }
+
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
+    pub fn inventory_mut(&mut self) -> &mut Inventory {
+        &mut self.inventory
+    }
+
+    pub fn station_for(&self, menu_id: &str, category: Category) -> Station {
+        self.routes
+            .get(menu_id)
+            .or_else(|| self.category_routes.get(&category))
+            .copied()
+            .unwrap_or(Station::Grill)
+    }
+
+    pub fn expected_cook_time(&self, menu_id: &str, category: Category) -> Duration {
+        self.cook_times
+            .get(menu_id)
+            .or_else(|| self.category_cook_times.get(&category))
+            .copied()
+            .unwrap_or(Duration::from_secs(10 * 60))
+    }
+
+    // Break an order into tickets, one per station and course. The first course
+    // on the order fires now and each later one `course_gap` after the last.
+    pub fn fire(
+        &mut self,
+        order: &Order,
+        menu: &Menu,
+        now: Timestamp,
+    ) -> Result<Vec<TicketId>, MenuError> {
+        let mut groups: BTreeMap<(Course, Station), (Vec<TicketItem>, Duration)> = BTreeMap::new();
+        for item in &order.items {
+            let menu_item = menu
+                .get(&item.menu_id)
+                .ok_or_else(|| MenuError::UnknownItem(item.menu_id.clone()))?;
+            let course = Course::for_category(menu_item.category);
+            let station = self.station_for(&menu_item.id, menu_item.category);
+            let time = self.expected_cook_time(&menu_item.id, menu_item.category);
+            let group = groups.entry((course, station)).or_default();
+            group.0.push(TicketItem {
+                menu_id: menu_item.id.clone(),
+                name: menu_item.name.clone(),
+                choices: item.choices.clone(),
+            });
+            group.1 = group.1.max(time); // <- a ticket is done when its slowest item is
+        }
+        // Drinks go out with the first course of food
+        let mut courses: Vec<Course> = groups.keys().map(|(course, _)| *course).collect();
+        courses.retain(|c| *c != Course::Drinks);
+        courses.dedup();
+        let mut ids = Vec::new();
+        for ((course, station), (items, expected)) in groups {
+            let gaps = courses.iter().position(|c| *c == course).unwrap_or(0);
+            self.next_id += 1;
+            let id = TicketId(self.next_id);
+            self.tickets.push(Ticket {
+                id,
+                order: order.id,
+                table: order.table,
+                station,
+                course,
+                items,
+                fire_at: now + self.course_gap * gaps as u32,
+                expected,
+                remake: None,
+                started: None,
+                done: None,
+            });
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    // Make items from an order's finished tickets again (all of them if
+    // `menu_ids` is empty). The remakes go to the front of their station's
+    // queue and are put down to the station that made the original, so they
+    // show up in that station's numbers.
+    pub fn remake(
+        &mut self,
+        order: OrderId,
+        menu_ids: &[&str],
+        reason: &str,
+        now: Timestamp,
+    ) -> Result<Vec<TicketId>, KitchenError> {
+        let mut originals: Vec<Ticket> = self
+            .tickets
+            .iter()
+            .filter(|t| t.order == order && t.is_done())
+            .cloned()
+            .collect();
+        // Only the latest version of each item: anything already remade is
+        // taken off its original, one for one, so the rest of that ticket (or
+        // the other soup on it) can still go back
+        for remade in &self.tickets {
+            let original = remade
+                .remake
+                .as_ref()
+                .and_then(|m| originals.iter_mut().find(|t| t.id == m.original));
+            if let Some(original) = original {
+                for item in &remade.items {
+                    if let Some(k) = original.items.iter().position(|i| i == item) {
+                        original.items.remove(k);
+                    }
+                }
+            }
+        }
+        if originals.is_empty() {
+            return Err(KitchenError::NothingToRemake(order));
+        }
+        let mut ids = Vec::new();
+        for mut original in originals {
+            original
+                .items
+                .retain(|i| menu_ids.is_empty() || menu_ids.contains(&i.menu_id.as_str()));
+            if original.items.is_empty() {
+                continue;
+            }
+            let remake = Remake {
+                original: original.id,
+                reason: String::from(reason),
+                station: original.station,
+            };
+            self.next_id += 1;
+            let id = TicketId(self.next_id);
+            self.tickets.push(Ticket {
+                id,
+                fire_at: now,
+                remake: Some(remake),
+                started: None,
+                done: None,
+                ..original
+            });
+            ids.push(id);
+        }
+        if ids.is_empty() {
+            return Err(KitchenError::NothingToRemake(order));
+        }
+        Ok(ids)
+    }
+
+    pub fn ticket(&self, id: TicketId) -> Option<&Ticket> {
+        self.tickets.iter().find(|t| t.id == id)
+    }
+
+    pub fn tickets(&self) -> &[Ticket] {
+        &self.tickets
+    }
+
+    pub fn tickets_for(&self, order: OrderId) -> Vec<&Ticket> {
+        self.tickets.iter().filter(|t| t.order == order).collect()
+    }
+
+    // What a station should work on, in order: remakes, then whatever was fired
+    // first, then earlier courses
+    pub fn queue(&self, station: Station) -> Vec<&Ticket> {
+        let mut queue: Vec<&Ticket> = self
+            .tickets
+            .iter()
+            .filter(|t| t.station == station && !t.is_done())
+            .collect();
+        queue.sort_by_key(|t| (t.remake.is_none(), t.fire_at, t.course, t.id));
+        queue
+    }
+
+    pub fn start(&mut self, id: TicketId, now: Timestamp) -> Result<(), KitchenError> {
+        let ticket = self.ticket_mut(id)?;
+        if ticket.started.is_some() {
+            return Err(KitchenError::AlreadyStarted(id));
+        }
+        ticket.started = Some(now);
+        Ok(())
+    }
+
+    // The station is done with a ticket. Returns the order if that was its last
+    // open ticket, meaning the whole order can go up to the pass. Only tickets
+    // that were started count towards the measured cook times.
+    pub fn bump(&mut self, id: TicketId, now: Timestamp) -> Result<Option<OrderId>, KitchenError> {
+        let ticket = self.ticket_mut(id)?;
+        if ticket.is_done() {
+            return Err(KitchenError::AlreadyDone(id));
+        }
+        ticket.done = Some(now);
+        let order = ticket.order;
+        if let Some(started) = ticket.started {
+            let took = now.since(started);
+            let items: Vec<String> = ticket.items.iter().map(|i| i.menu_id.clone()).collect();
+            for menu_id in items {
+                self.actual.entry(menu_id).or_default().push(took);
+            }
+        }
+        let finished = self
+            .tickets
+            .iter()
+            .filter(|t| t.order == order)
+            .all(Ticket::is_done);
+        Ok(finished.then_some(order))
+    }
+
+    pub fn late(&self, now: Timestamp) -> Vec<&Ticket> {
+        self.tickets
+            .iter()
+            .filter(|t| t.is_late(now, self.grace))
+            .collect()
+    }
+
+    // How long an item has actually been taking
+    pub fn average_cook_time(&self, menu_id: &str) -> Option<Duration> {
+        let times = self.actual.get(menu_id)?;
+        if times.is_empty() {
+            return None;
+        }
+        Some(times.iter().sum::<Duration>() / times.len() as u32)
+    }
+
+    // Remakes per station they were put down to
+    pub fn remakes_by_station(&self) -> BTreeMap<Station, usize> {
+        let mut counts = BTreeMap::new();
+        for remake in self.tickets.iter().filter_map(|t| t.remake.as_ref()) {
+            *counts.entry(remake.station).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn remakes_by_reason(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for remake in self.tickets.iter().filter_map(|t| t.remake.as_ref()) {
+            *counts.entry(remake.reason.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    // What changes during service: the tickets, measured cook times and stock.
+    // Routes and cook times are set up at opening.
+    pub fn state_json(&self) -> Json {
+        let tickets: Vec<Json> = self.tickets.iter().map(Ticket::to_json).collect();
+        let actual = self
+            .actual
+            .iter()
+            .fold(Json::object(), |json, (id, times)| {
+                let times: Vec<Json> = times.iter().map(|t| Json::from(t.as_secs())).collect();
+                json.with(id, times)
+            });
+        Json::object()
+            .with("tickets", tickets)
+            .with("actual", actual)
+            .with("next_id", self.next_id)
+            .with("inventory", self.inventory.state_json())
+    }
+
+    pub fn restore_state(&mut self, json: &Json) -> Option<()> {
+        let tickets = json
+            .get("tickets")?
+            .as_array()?
+            .iter()
+            .map(Ticket::from_json)
+            .collect::<Option<Vec<_>>>()?;
+        let mut actual = HashMap::new();
+        for (id, times) in json.get("actual")?.as_object()? {
+            let times = times
+                .as_array()?
+                .iter()
+                .map(|t| t.as_u64().map(Duration::from_secs))
+                .collect::<Option<Vec<_>>>()?;
+            actual.insert(id.clone(), times);
+        }
+        let next_id = json.get("next_id")?.as_u32()?;
+        self.inventory.restore_state(json.get("inventory")?)?;
+        self.tickets = tickets;
+        self.actual = actual;
+        self.next_id = next_id;
+        Some(())
+    }
+
+    fn ticket_mut(&mut self, id: TicketId) -> Result<&mut Ticket, KitchenError> {
+        self.tickets
+            .iter_mut()
+            .find(|t| t.id == id)
+            .ok_or(KitchenError::UnknownTicket(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{OrderBook, OrderItem};
+
+    fn at(minute: u64) -> Timestamp {
+        Timestamp::from_secs(12 * 3600) + minutes(minute)
+    }
+
+    fn fired(kitchen: &mut Kitchen, items: &[&str]) -> (OrderId, Vec<TicketId>) {
+        let mut orders = OrderBook::new();
+        let items = items.iter().map(|id| OrderItem::new(id)).collect();
+        let id = orders.place(7, items, at(0)).unwrap();
+        let tickets = kitchen
+            .fire(orders.get(id).unwrap(), &Menu::house(), at(0))
+            .unwrap();
+        (id, tickets)
+    }
+
+    fn menu_ids(ticket: &Ticket) -> Vec<&str> {
+        ticket.items.iter().map(|i| i.menu_id.as_str()).collect()
+    }
+
+    #[test]
+    fn orders_split_by_station_and_course() {
+        let mut kitchen = Kitchen::new();
+        let (order, tickets) = fired(&mut kitchen, &["soup", "salad", "burger", "coffee"]);
+        let tickets: Vec<&Ticket> = tickets
+            .iter()
+            .map(|t| kitchen.ticket(*t).unwrap())
+            .collect();
+        let summary: Vec<(Station, Course, Vec<&str>)> = tickets
+            .iter()
+            .map(|t| (t.station, t.course, menu_ids(t)))
+            .collect();
+        assert_eq!(tickets.len(), 4);
+        assert!(summary.contains(&(Station::Saute, Course::Starter, vec!["soup"])));
+        assert!(summary.contains(&(Station::Grill, Course::Main, vec!["burger"])));
+        for ticket in &tickets {
+            let expected = match ticket.course {
+                Course::Main => at(0) + kitchen.course_gap,
+                _ => at(0),
+            };
+            assert_eq!(ticket.fire_at, expected, "{:?}", ticket.course);
+            assert_eq!(ticket.order, order);
+        }
+    }
+
+    #[test]
+    fn the_last_bump_sends_the_order_up() {
+        let mut kitchen = Kitchen::new();
+        let (order, tickets) = fired(&mut kitchen, &["soup", "fries"]);
+        kitchen.start(tickets[0], at(1)).unwrap();
+        assert!(matches!(
+            kitchen.start(tickets[0], at(2)),
+            Err(KitchenError::AlreadyStarted(_))
+        ));
+        assert_eq!(kitchen.bump(tickets[0], at(9)).unwrap(), None);
+        assert_eq!(kitchen.bump(tickets[1], at(10)).unwrap(), Some(order));
+        assert!(matches!(
+            kitchen.bump(tickets[1], at(11)),
+            Err(KitchenError::AlreadyDone(_))
+        ));
+        assert_eq!(kitchen.average_cook_time("soup"), Some(minutes(8)));
+        assert_eq!(kitchen.average_cook_time("fries"), None); // <- never started
+        assert!(matches!(
+            kitchen.bump(TicketId(99), at(12)),
+            Err(KitchenError::UnknownTicket(_))
+        ));
+    }
+
+    #[test]
+    fn remakes_jump_the_queue_and_count_against_the_station() {
+        let mut kitchen = Kitchen::new();
+        let (first, tickets) = fired(&mut kitchen, &["soup"]);
+        assert!(matches!(
+            kitchen.remake(first, &[], "cold", at(1)),
+            Err(KitchenError::NothingToRemake(_))
+        ));
+        kitchen.bump(tickets[0], at(8)).unwrap();
+        fired(&mut kitchen, &["crab-cakes"]);
+        let remade = kitchen.remake(first, &["soup"], "cold", at(9)).unwrap();
+        let queue = kitchen.queue(Station::Saute);
+        assert_eq!(queue[0].id, remade[0]);
+        assert_eq!(queue[0].remake.as_ref().unwrap().original, tickets[0]);
+        assert_eq!(kitchen.remakes_by_station()[&Station::Saute], 1);
+        assert_eq!(kitchen.remakes_by_reason()["cold"], 1);
+        assert!(kitchen.remake(first, &["fries"], "cold", at(9)).is_err());
+    }
+
+    #[test]
+    fn each_item_on_a_ticket_can_be_remade_separately() {
+        let mut kitchen = Kitchen::new().route("salad", Station::Saute);
+        let (order, tickets) = fired(&mut kitchen, &["soup", "salad", "soup"]);
+        assert_eq!(tickets.len(), 1);
+        kitchen.bump(tickets[0], at(8)).unwrap();
+
+        let soups = kitchen.remake(order, &["soup"], "cold", at(10)).unwrap();
+        assert_eq!(
+            menu_ids(kitchen.ticket(soups[0]).unwrap()),
+            ["soup", "soup"]
+        );
+        // The salad was not remade, so it can still go back
+        let salad = kitchen.remake(order, &["salad"], "wilted", at(11)).unwrap();
+        assert_eq!(menu_ids(kitchen.ticket(salad[0]).unwrap()), ["salad"]);
+        // The soups are being remade and the salad is too: nothing is left
+        assert!(kitchen.remake(order, &[], "again", at(12)).is_err());
+
+        kitchen.bump(soups[0], at(18)).unwrap();
+        kitchen.bump(salad[0], at(19)).unwrap();
+        let everything = kitchen.remake(order, &[], "again", at(20)).unwrap();
+        let mut items: Vec<&str> = everything
+            .iter()
+            .flat_map(|t| menu_ids(kitchen.ticket(*t).unwrap()))
+            .collect();
+        items.sort();
+        assert_eq!(items, ["salad", "soup", "soup"]);
+    }
+
+    #[test]
+    fn state_round_trips_through_json() {
+        let mut kitchen = Kitchen::new();
+        let (order, tickets) = fired(&mut kitchen, &["soup", "burger"]);
+        kitchen.start(tickets[0], at(0)).unwrap();
+        kitchen.bump(tickets[0], at(8)).unwrap();
+        kitchen.remake(order, &[], "cold", at(9)).unwrap();
+        let mut restored = Kitchen::new();
+        restored.restore_state(&kitchen.state_json()).unwrap();
+        assert_eq!(restored.tickets(), kitchen.tickets());
+        assert_eq!(restored.average_cook_time("soup"), Some(minutes(8)));
+        assert_eq!(restored.state_json(), kitchen.state_json());
+    }
+}
@@ -0,0 +1,230 @@
+// Which season it is, and what is worth cooking with in it.
+// Seasons start on fixed dates (meteorological seasons by default: the first of
+// March, June, September and December) and the southern hemisphere is six
+// months out from the northern one, so the same table of produce works for both.
+
+use crate::clock::Date;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    pub const ALL: [Season; 4] = [
+        Season::Spring,
+        Season::Summer,
+        Season::Autumn,
+        Season::Winter,
+    ];
+
+    // The season six months away
+    pub fn opposite(self) -> Season {
+        match self {
+            Season::Spring => Season::Autumn,
+            Season::Summer => Season::Winter,
+            Season::Autumn => Season::Spring,
+            Season::Winter => Season::Summer,
+        }
+    }
+}
+
+impl fmt::Display for Season {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Season::Spring => "spring",
+            Season::Summer => "summer",
+            Season::Autumn => "autumn",
+            Season::Winter => "winter",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Hemisphere {
+    #[default]
+    Northern,
+    Southern,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeasonCalendar {
+    pub hemisphere: Hemisphere,
+    starts: [(u32, u32); 4], // <- (month, day) each northern season starts, spring first
+}
+
+impl Default for SeasonCalendar {
+    fn default() -> Self {
+        SeasonCalendar::meteorological(Hemisphere::Northern)
+    }
+}
+
+impl SeasonCalendar {
+    pub fn meteorological(hemisphere: Hemisphere) -> SeasonCalendar {
+        SeasonCalendar {
+            hemisphere,
+            starts: [(3, 1), (6, 1), (9, 1), (12, 1)],
+        }
+    }
+
+    // Seasons turning on the equinoxes and solstices (close enough to the 21st)
+    pub fn astronomical(hemisphere: Hemisphere) -> SeasonCalendar {
+        SeasonCalendar {
+            hemisphere,
+            starts: [(3, 21), (6, 21), (9, 23), (12, 21)],
+        }
+    }
+
+    // Move the day a (northern) season starts on; None if there's no such day
+    // in a leap year
+    pub fn starting(mut self, season: Season, month: u32, day: u32) -> Option<SeasonCalendar> {
+        Date::new(2024, month, day)?;
+        let index = Season::ALL.iter().position(|s| *s == season)?;
+        self.starts[index] = (month, day);
+        Some(self)
+    }
+
+    pub fn season(&self, date: Date) -> Season {
+        let today = (date.month, date.day);
+        // The latest start on or before today; before the first start of the
+        // year it is still whichever season started last the year before
+        let northern = Season::ALL
+            .iter()
+            .zip(self.starts)
+            .max_by_key(|(_, start)| (*start <= today, *start)) // <- started this year first
+            .map_or(Season::Winter, |(season, _)| *season);
+        match self.hemisphere {
+            Hemisphere::Northern => northern,
+            Hemisphere::Southern => northern.opposite(),
+        }
+    }
+}
+
+// What produce is good in each season, best first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeasonalMenu {
+    pub calendar: SeasonCalendar,
+    fruit: BTreeMap<Season, Vec<String>>,
+}
+
+impl Default for SeasonalMenu {
+    fn default() -> Self {
+        SeasonalMenu::new(SeasonCalendar::default())
+            .with_fruit(Season::Spring, &["strawberries", "rhubarb", "apricots"])
+            .with_fruit(Season::Summer, &["peaches", "cherries", "blueberries"])
+            .with_fruit(Season::Autumn, &["persimmons", "apples", "pears"])
+            .with_fruit(Season::Winter, &["oranges", "grapefruit", "kiwis"])
+    }
+}
+
+impl SeasonalMenu {
+    pub fn new(calendar: SeasonCalendar) -> SeasonalMenu {
+        SeasonalMenu {
+            calendar,
+            fruit: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_fruit(mut self, season: Season, fruit: &[&str]) -> SeasonalMenu {
+        self.fruit
+            .insert(season, fruit.iter().map(|f| String::from(*f)).collect());
+        self
+    }
+
+    pub fn fruit(&self, season: Season) -> &[String] {
+        self.fruit.get(&season).map_or(&[], |f| f.as_slice())
+    }
+
+    // The best fruit for the date, skipping anything `in_stock` says we're out of
+    pub fn fruit_for(&self, date: Date, in_stock: impl Fn(&str) -> bool) -> Option<&str> {
+        self.fruit(self.calendar.season(date))
+            .iter()
+            .map(|f| f.as_str())
+            .find(|f| in_stock(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> Date {
+        Date::new(2024, month, day).unwrap()
+    }
+
+    #[test]
+    fn seasons_turn_on_their_start_dates() {
+        let north = SeasonCalendar::default();
+        assert_eq!(north.season(date(1, 15)), Season::Winter);
+        assert_eq!(north.season(date(2, 29)), Season::Winter);
+        assert_eq!(north.season(date(3, 1)), Season::Spring);
+        assert_eq!(north.season(date(8, 31)), Season::Summer);
+        assert_eq!(north.season(date(11, 30)), Season::Autumn);
+        assert_eq!(north.season(date(12, 1)), Season::Winter);
+
+        let astronomical = SeasonCalendar::astronomical(Hemisphere::Northern);
+        assert_eq!(astronomical.season(date(3, 20)), Season::Winter);
+        assert_eq!(astronomical.season(date(3, 21)), Season::Spring);
+        assert_eq!(astronomical.season(date(9, 22)), Season::Summer);
+    }
+
+    #[test]
+    fn the_south_is_six_months_out() {
+        let south = SeasonCalendar::meteorological(Hemisphere::Southern);
+        let north = SeasonCalendar::default();
+        for month in 1..=12 {
+            assert_eq!(
+                south.season(date(month, 10)),
+                north.season(date(month, 10)).opposite()
+            );
+        }
+        assert_eq!(south.season(date(7, 4)), Season::Winter);
+    }
+
+    #[test]
+    fn start_dates_can_be_moved() {
+        let calendar = SeasonCalendar::default()
+            .starting(Season::Spring, 4, 15)
+            .unwrap();
+        assert_eq!(calendar.season(date(4, 14)), Season::Winter);
+        assert_eq!(calendar.season(date(4, 15)), Season::Spring);
+
+        // Winter moved into the new year: before it starts, it's still autumn
+        let late_winter = SeasonCalendar::default()
+            .starting(Season::Winter, 1, 10)
+            .unwrap();
+        assert_eq!(late_winter.season(date(1, 5)), Season::Autumn);
+        assert_eq!(late_winter.season(date(1, 10)), Season::Winter);
+        assert_eq!(late_winter.season(date(12, 20)), Season::Autumn);
+
+        assert!(SeasonCalendar::default()
+            .starting(Season::Spring, 2, 30)
+            .is_none());
+        assert!(SeasonCalendar::default()
+            .starting(Season::Spring, 13, 1)
+            .is_none());
+        assert!(SeasonCalendar::default()
+            .starting(Season::Spring, 2, 29)
+            .is_some());
+    }
+
+    #[test]
+    fn fruit_skips_what_is_out_of_stock() {
+        let menu = SeasonalMenu::default();
+        assert_eq!(menu.fruit_for(date(7, 1), |_| true), Some("peaches"));
+        assert_eq!(
+            menu.fruit_for(date(7, 1), |f| f != "peaches"),
+            Some("cherries")
+        );
+        assert_eq!(menu.fruit_for(date(7, 1), |_| false), None);
+        let bare = SeasonalMenu::new(SeasonCalendar::default());
+        assert!(bare.fruit(Season::Summer).is_empty());
+        assert_eq!(bare.fruit_for(date(7, 1), |_| true), None);
+    }
+}
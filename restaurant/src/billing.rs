@@ -0,0 +1,908 @@
+// Turning orders into a check: line items priced from the menu, discounts,
+// service charge, tax and tip, all in whole cents. A receipt always balances:
+// subtotal - discounts + service charge + tax + tip is exactly the total, and
+// when a check is split the parts add back up to the whole to the cent.
+
+use crate::menu::{Category, Menu, MenuError};
+use crate::money::{allocate, allocate_with, Cents, Rate};
+use crate::order::{Order, OrderError, OrderId};
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PaymentMethod {
+    Cash,
+    Card,
+    GiftCard,
+}
+
+impl PaymentMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PaymentMethod::Cash => "cash",
+            PaymentMethod::Card => "card",
+            PaymentMethod::GiftCard => "gift card",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<PaymentMethod> {
+        [
+            PaymentMethod::Cash,
+            PaymentMethod::Card,
+            PaymentMethod::GiftCard,
+        ]
+        .into_iter()
+        .find(|m| m.name() == name)
+    }
+}
+
+impl fmt::Display for PaymentMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Payment {
+    pub method: PaymentMethod,
+    pub amount: Cents,
+}
+
+impl Payment {
+    pub fn cash(amount: Cents) -> Payment {
+        Payment {
+            method: PaymentMethod::Cash,
+            amount,
+        }
+    }
+
+    pub fn card(amount: Cents) -> Payment {
+        Payment {
+            method: PaymentMethod::Card,
+            amount,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscountKind {
+    Percent(Rate),
+    Amount(Cents), // <- never takes more off than the lines it applies to cost
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscountScope {
+    Check,
+    Item(String), // <- a menu id
+    Category(Category),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discount {
+    pub name: String,
+    pub kind: DiscountKind,
+    pub scope: DiscountScope,
+}
+
+impl Discount {
+    pub fn percent(name: &str, rate: Rate, scope: DiscountScope) -> Discount {
+        Discount {
+            name: String::from(name),
+            kind: DiscountKind::Percent(rate),
+            scope,
+        }
+    }
+
+    pub fn amount(name: &str, amount: Cents, scope: DiscountScope) -> Discount {
+        Discount {
+            name: String::from(name),
+            kind: DiscountKind::Amount(amount),
+            scope,
+        }
+    }
+
+    fn applies_to(&self, line: &CheckLine) -> bool {
+        match &self.scope {
+            DiscountScope::Check => true,
+            DiscountScope::Item(id) => line.menu_id == *id,
+            DiscountScope::Category(category) => line.category == *category,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tip {
+    #[default]
+    None,
+    Percent(Rate), // <- of the discounted subtotal, before tax
+    Amount(Cents),
+}
+
+// Added automatically for big parties
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceCharge {
+    pub name: String,
+    pub rate: Rate,
+    pub min_guests: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BillingConfig {
+    pub taxes: Vec<(String, Rate)>,
+    pub service_charge: Option<ServiceCharge>,
+    pub tax_service_charge: bool, // <- some places tax the service charge, some don't
+}
+
+impl Default for BillingConfig {
+    fn default() -> Self {
+        BillingConfig {
+            taxes: vec![(
+                String::from("Sales tax"),
+                Rate::from_thousandths_of_percent(8875),
+            )],
+            service_charge: Some(ServiceCharge {
+                name: String::from("Service charge"),
+                rate: Rate::percent(18),
+                min_guests: 6,
+            }),
+            tax_service_charge: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckLine {
+    pub order: Option<OrderId>, // <- None for lines added by hand
+    pub menu_id: String,
+    pub name: String,
+    pub category: Category,
+    pub seat: Option<u32>,
+    pub base: Cents,
+    pub modifiers: Vec<(String, Cents)>, // <- the options chosen and what each adds
+}
+
+impl CheckLine {
+    pub fn amount(&self) -> Cents {
+        self.base
+            + self
+                .modifiers
+                .iter()
+                .map(|(_, price)| *price)
+                .sum::<Cents>()
+    }
+}
+
+#[derive(Debug)]
+pub enum BillingError {
+    Menu(MenuError),
+    Order(OrderError),
+    NoSeats, // <- splitting by seat, but nothing has a seat
+    SplitMismatch { lines: usize, given: usize }, // <- one part number per line is needed
+    Underpaid { total: Cents, paid: Cents },
+    Overpaid { total: Cents, paid: Cents }, // <- only cash can be given back as change
+    Negative { what: String, amount: Cents }, // <- a discount, tip or payment below zero
+    WrongOrder(OrderId),                    // <- paying an order with a receipt for something else
+    PartialReceipt(OrderId), // <- one part of a split check, or a check for other orders too
+    TooLarge,                // <- more money than can be counted
+}
+
+impl fmt::Display for BillingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BillingError::Menu(e) => write!(f, "{}", e),
+            BillingError::Order(e) => write!(f, "{}", e),
+            BillingError::NoSeats => write!(f, "nothing on the check has a seat number"),
+            BillingError::SplitMismatch { lines, given } => write!(
+                f,
+                "the check has {} lines but {} were assigned",
+                lines, given
+            ),
+            BillingError::Underpaid { total, paid } => {
+                write!(f, "the check is {} but only {} was paid", total, paid)
+            }
+            BillingError::Overpaid { total, paid } => write!(
+                f,
+                "the check is {} and {} can't be paid without enough cash to give change",
+                total, paid
+            ),
+            BillingError::Negative { what, amount } => {
+                write!(f, "{} can't be negative, but was {}", what, amount)
+            }
+            BillingError::WrongOrder(id) => write!(f, "the receipt isn't for {}", id),
+            BillingError::PartialReceipt(id) => {
+                write!(f, "the receipt isn't the whole check for {} alone", id)
+            }
+            BillingError::TooLarge => write!(f, "the amounts add up to too much to count"),
+        }
+    }
+}
+
+impl std::error::Error for BillingError {}
+
+impl From<MenuError> for BillingError {
+    fn from(e: MenuError) -> Self {
+        BillingError::Menu(e)
+    }
+}
+
+impl From<OrderError> for BillingError {
+    fn from(e: OrderError) -> Self {
+        BillingError::Order(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Check {
+    pub orders: Vec<OrderId>, // <- the orders the lines came from
+    pub lines: Vec<CheckLine>,
+    pub discounts: Vec<Discount>, // <- applied in order, each to what the last left
+    pub tip: Tip,
+    pub guests: u32,
+}
+
+impl Check {
+    pub fn new(guests: u32) -> Check {
+        Check {
+            guests,
+            ..Check::default()
+        }
+    }
+
+    // Price every item on the orders from the menu
+    pub fn from_orders(orders: &[&Order], menu: &Menu, guests: u32) -> Result<Check, BillingError> {
+        let mut check = Check::new(guests);
+        for order in orders {
+            check.orders.push(order.id);
+            for item in &order.items {
+                menu.quote(&item.menu_id, &item.choices())?; // <- checks the choices are real
+                let menu_item = menu
+                    .get(&item.menu_id)
+                    .ok_or_else(|| MenuError::UnknownItem(item.menu_id.clone()))?;
+                let modifiers = item
+                    .choices
+                    .iter()
+                    .filter_map(|(modifier, option)| {
+                        let option = menu_item.modifier(modifier)?.option(option)?;
+                        Some((option.name.clone(), option.price))
+                    })
+                    .collect();
+                check.lines.push(CheckLine {
+                    order: Some(order.id),
+                    menu_id: menu_item.id.clone(),
+                    name: menu_item.name.clone(),
+                    category: menu_item.category,
+                    seat: item.seat,
+                    base: menu_item.price,
+                    modifiers,
+                });
+            }
+        }
+        Ok(check)
+    }
+
+    pub fn discount(mut self, discount: Discount) -> Check {
+        self.discounts.push(discount);
+        self
+    }
+
+    pub fn tip(mut self, tip: Tip) -> Check {
+        self.tip = tip;
+        self
+    }
+
+    pub fn receipt(&self, config: &BillingConfig) -> Result<Receipt, BillingError> {
+        for discount in &self.discounts {
+            if let DiscountKind::Amount(amount) = discount.kind {
+                not_negative(&format!("the '{}' discount", discount.name), amount)?;
+            }
+        }
+        if let Tip::Amount(amount) = self.tip {
+            not_negative("the tip", amount)?;
+        }
+        let amounts: Vec<Cents> = self.lines.iter().map(CheckLine::amount).collect();
+        let mut net = amounts.clone();
+        let mut line_discounts = vec![Vec::with_capacity(self.discounts.len()); self.lines.len()];
+        for discount in &self.discounts {
+            let eligible: Vec<usize> = (0..self.lines.len())
+                .filter(|&i| discount.applies_to(&self.lines[i]))
+                .collect();
+            let base: Cents = eligible.iter().map(|&i| net[i]).sum();
+            let off = match discount.kind {
+                DiscountKind::Percent(rate) => rate.of(base).min(base), // <- over 100% is all of it
+                DiscountKind::Amount(amount) => amount.min(base),
+            };
+            let weights: Vec<i64> = eligible.iter().map(|&i| net[i].0).collect();
+            let mut shares = vec![Cents::ZERO; self.lines.len()];
+            if !eligible.is_empty() {
+                for (&i, share) in eligible.iter().zip(allocate(off, &weights)) {
+                    shares[i] = share;
+                }
+            }
+            for (i, share) in shares.into_iter().enumerate() {
+                net[i] -= share;
+                line_discounts[i].push(share);
+            }
+        }
+        let lines = self
+            .lines
+            .iter()
+            .zip(amounts)
+            .zip(line_discounts)
+            .map(|((line, amount), discounts)| ReceiptLine {
+                order: line.order,
+                menu_id: line.menu_id.clone(),
+                description: line.name.clone(),
+                seat: line.seat,
+                amount,
+                modifiers: line.modifiers.clone(),
+                discounts,
+            })
+            .collect();
+
+        let net_total: Cents = net.iter().sum();
+        let service_charge = config
+            .service_charge
+            .as_ref()
+            .filter(|s| self.guests >= s.min_guests)
+            .map(|s| (format!("{} ({})", s.name, s.rate), s.rate.of(net_total)));
+        let taxable = match &service_charge {
+            Some((_, amount)) if config.tax_service_charge => net_total + *amount,
+            _ => net_total,
+        };
+        let taxes = config
+            .taxes
+            .iter()
+            .map(|(name, rate)| (format!("{} ({})", name, rate), rate.of(taxable)))
+            .collect();
+        let tip = match self.tip {
+            Tip::None => Cents::ZERO,
+            Tip::Percent(rate) => rate.of(net_total),
+            Tip::Amount(amount) => amount,
+        };
+        Ok(Receipt {
+            label: None,
+            orders: self.orders.clone(),
+            lines,
+            discount_names: self.discounts.iter().map(|d| d.name.clone()).collect(),
+            service_charge,
+            tax_service_charge: config.tax_service_charge,
+            split: false,
+            taxes,
+            tip,
+        })
+    }
+}
+
+fn not_negative(what: &str, amount: Cents) -> Result<(), BillingError> {
+    match amount.is_negative() {
+        true => Err(BillingError::Negative {
+            what: String::from(what),
+            amount,
+        }),
+        false => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiptLine {
+    pub order: Option<OrderId>,
+    pub menu_id: String,
+    pub description: String,
+    pub seat: Option<u32>,
+    pub amount: Cents,
+    pub modifiers: Vec<(String, Cents)>,
+    pub discounts: Vec<Cents>, // <- this line's share of each discount on the receipt
+}
+
+pub enum SplitBy {
+    Evenly(u32),
+    Seat,              // <- lines without a seat are shared evenly between the seats
+    Items(Vec<usize>), // <- which part (from 0) each line goes to
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    pub label: Option<String>, // <- "Seat 2" and so on for split checks
+    pub orders: Vec<OrderId>,
+    pub lines: Vec<ReceiptLine>,
+    pub discount_names: Vec<String>,
+    pub service_charge: Option<(String, Cents)>,
+    tax_service_charge: bool,
+    split: bool, // <- one of the parts from `split`
+    pub taxes: Vec<(String, Cents)>,
+    pub tip: Cents,
+}
+
+impl Receipt {
+    pub fn is_split(&self) -> bool {
+        self.split
+    }
+
+    pub fn subtotal(&self) -> Cents {
+        self.lines.iter().map(|l| l.amount).sum()
+    }
+
+    // Each discount and how much it took off
+    pub fn discounts(&self) -> Vec<(&str, Cents)> {
+        self.discount_names
+            .iter()
+            .enumerate()
+            .map(|(k, name)| {
+                let total = self.lines.iter().map(|l| l.discounts[k]).sum();
+                (name.as_str(), total)
+            })
+            .collect()
+    }
+
+    // What each of the order's items was charged, before discounts: the sales
+    // figure the order is remembered by once it is paid
+    pub fn charges(&self, order: OrderId) -> Vec<(String, Cents)> {
+        self.lines
+            .iter()
+            .filter(|l| l.order == Some(order))
+            .map(|l| (l.menu_id.clone(), l.amount))
+            .collect()
+    }
+
+    pub fn discount_total(&self) -> Cents {
+        self.lines.iter().flat_map(|l| l.discounts.iter()).sum()
+    }
+
+    pub fn tax_total(&self) -> Cents {
+        self.taxes.iter().map(|(_, amount)| *amount).sum()
+    }
+
+    pub fn service_charge_total(&self) -> Cents {
+        self.service_charge
+            .as_ref()
+            .map_or(Cents::ZERO, |(_, a)| *a)
+    }
+
+    pub fn total(&self) -> Cents {
+        self.subtotal() - self.discount_total()
+            + self.service_charge_total()
+            + self.tax_total()
+            + self.tip
+    }
+
+    pub fn split(&self, how: &SplitBy) -> Result<Vec<Receipt>, BillingError> {
+        match how {
+            SplitBy::Evenly(parts) => {
+                let parts = (*parts).max(1) as usize;
+                let labels = (1..=parts).map(|i| format!("{} of {}", i, parts)).collect();
+                Ok(self.split_with(labels, true, |_| vec![1; parts]))
+            }
+            SplitBy::Seat => {
+                let seats: BTreeSet<u32> = self.lines.iter().filter_map(|l| l.seat).collect();
+                if seats.is_empty() {
+                    return Err(BillingError::NoSeats);
+                }
+                let seats: Vec<u32> = seats.into_iter().collect();
+                let labels = seats.iter().map(|s| format!("Seat {}", s)).collect();
+                Ok(self.split_with(labels, false, |line| match line.seat {
+                    Some(seat) => seats.iter().map(|&s| (s == seat) as i64).collect(),
+                    None => vec![1; seats.len()],
+                }))
+            }
+            SplitBy::Items(assignment) => {
+                if assignment.len() != self.lines.len() {
+                    return Err(BillingError::SplitMismatch {
+                        lines: self.lines.len(),
+                        given: assignment.len(),
+                    });
+                }
+                let parts = assignment.iter().max().map_or(1, |m| m + 1);
+                let labels = (1..=parts).map(|i| format!("Check {}", i)).collect();
+                let mut next = assignment.iter();
+                Ok(self.split_with(labels, false, |_| {
+                    let part = *next.next().unwrap_or(&0);
+                    (0..parts).map(|p| (p == part) as i64).collect()
+                }))
+            }
+        }
+    }
+
+    // `weights` says how each line is shared between the parts, in line order.
+    // Lines (and their discounts) are shared by those weights; the service
+    // charge, tax and tip follow each part's share of the discounted subtotal,
+    // or are shared equally when `even`.
+    fn split_with(
+        &self,
+        labels: Vec<String>,
+        even: bool,
+        mut weights: impl FnMut(&ReceiptLine) -> Vec<i64>,
+    ) -> Vec<Receipt> {
+        let mut parts: Vec<Receipt> = labels
+            .into_iter()
+            .map(|label| Receipt {
+                label: Some(label),
+                orders: self.orders.clone(),
+                lines: Vec::new(),
+                discount_names: self.discount_names.clone(),
+                service_charge: None,
+                tax_service_charge: self.tax_service_charge,
+                split: true,
+                taxes: Vec::new(),
+                tip: Cents::ZERO,
+            })
+            .collect();
+        // Running totals, so odd cents go to whoever is paying least so far
+        let mut running = vec![0i64; parts.len()];
+        let mut share = |amount: Cents, weights: &[i64], adds: bool| {
+            let preference: Vec<i64> = running.iter().map(|&r| if adds { r } else { -r }).collect();
+            let shares = allocate_with(amount, weights, &preference);
+            for (r, s) in running.iter_mut().zip(&shares) {
+                *r += if adds { s.0 } else { -s.0 };
+            }
+            shares
+        };
+        for line in &self.lines {
+            let w = weights(line);
+            let whole = w.iter().filter(|&&x| x > 0).count() == 1;
+            let amounts = share(line.amount, &w, true);
+            let discounts: Vec<Vec<Cents>> = line
+                .discounts
+                .iter()
+                .map(|d| share(*d, &w, false))
+                .collect();
+            for (p, part) in parts.iter_mut().enumerate() {
+                if w[p] <= 0 {
+                    continue;
+                }
+                part.lines.push(ReceiptLine {
+                    order: line.order,
+                    menu_id: line.menu_id.clone(),
+                    description: if whole {
+                        line.description.clone()
+                    } else {
+                        format!("{} (shared)", line.description)
+                    },
+                    seat: line.seat,
+                    amount: amounts[p],
+                    modifiers: if whole {
+                        line.modifiers.clone()
+                    } else {
+                        Vec::new()
+                    },
+                    discounts: discounts.iter().map(|d| d[p]).collect(),
+                });
+            }
+        }
+
+        let nets: Vec<i64> = parts
+            .iter()
+            .map(|p| match even {
+                true => 1,
+                false => (p.subtotal() - p.discount_total()).0,
+            })
+            .collect();
+        if let Some((name, amount)) = &self.service_charge {
+            for (part, s) in parts.iter_mut().zip(share(*amount, &nets, true)) {
+                part.service_charge = Some((name.clone(), s));
+            }
+        }
+        let taxable: Vec<i64> = parts
+            .iter()
+            .zip(&nets)
+            .map(|(p, net)| match self.tax_service_charge && !even {
+                true => net + p.service_charge_total().0,
+                false => *net,
+            })
+            .collect();
+        for (name, amount) in &self.taxes {
+            for (part, s) in parts.iter_mut().zip(share(*amount, &taxable, true)) {
+                part.taxes.push((name.clone(), s));
+            }
+        }
+        for (part, s) in parts.iter_mut().zip(share(self.tip, &nets, true)) {
+            part.tip = s;
+        }
+        parts
+    }
+}
+
+const WIDTH: usize = 40;
+
+fn row(f: &mut fmt::Formatter, label: &str, amount: Cents) -> fmt::Result {
+    let width = WIDTH.saturating_sub(12);
+    writeln!(
+        f,
+        "{:<width$}{:>12}",
+        label,
+        amount.to_string(),
+        width = width
+    )
+}
+
+impl fmt::Display for Receipt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(label) = &self.label {
+            writeln!(f, "{:^width$}", label, width = WIDTH)?;
+        }
+        for line in &self.lines {
+            let description = match line.seat {
+                Some(seat) => format!("{} (seat {})", line.description, seat),
+                None => line.description.clone(),
+            };
+            let base = line.amount - line.modifiers.iter().map(|(_, p)| *p).sum::<Cents>();
+            row(f, &description, base)?;
+            for (name, price) in &line.modifiers {
+                row(f, &format!("  + {}", name), *price)?;
+            }
+        }
+        writeln!(f, "{}", "-".repeat(WIDTH))?;
+        row(f, "Subtotal", self.subtotal())?;
+        for (name, amount) in self.discounts() {
+            if amount != Cents::ZERO {
+                row(f, name, -amount)?;
+            }
+        }
+        if let Some((name, amount)) = &self.service_charge {
+            row(f, name, *amount)?;
+        }
+        for (name, amount) in &self.taxes {
+            row(f, name, *amount)?;
+        }
+        if self.tip != Cents::ZERO {
+            row(f, "Tip", self.tip)?;
+        }
+        row(f, "Total", self.total())
+    }
+}
+
+// Check the payments cover the receipt; returns the change owed in cash
+pub fn settle(receipt: &Receipt, payments: &[Payment]) -> Result<Cents, BillingError> {
+    for payment in payments {
+        not_negative(&format!("a {} payment", payment.method), payment.amount)?;
+    }
+    let total = receipt.total();
+    let paid = checked_sum(payments.iter())?;
+    if paid < total {
+        return Err(BillingError::Underpaid { total, paid });
+    }
+    let cash = checked_sum(payments.iter().filter(|p| p.method == PaymentMethod::Cash))?;
+    let change = paid - total;
+    if change > cash {
+        return Err(BillingError::Overpaid { total, paid });
+    }
+    Ok(change)
+}
+
+fn checked_sum<'a>(mut payments: impl Iterator<Item = &'a Payment>) -> Result<Cents, BillingError> {
+    payments.try_fold(Cents::ZERO, |sum, p| {
+        sum.checked_add(p.amount).ok_or(BillingError::TooLarge)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(menu_id: &str, category: Category, seat: Option<u32>, base: i64) -> CheckLine {
+        CheckLine {
+            order: None,
+            menu_id: String::from(menu_id),
+            name: String::from(menu_id),
+            category,
+            seat,
+            base: Cents(base),
+            modifiers: Vec::new(),
+        }
+    }
+
+    fn untaxed() -> BillingConfig {
+        BillingConfig {
+            taxes: Vec::new(),
+            service_charge: None,
+            tax_service_charge: false,
+        }
+    }
+
+    // Odd amounts, so every split has cents left over to place
+    fn dinner(guests: u32) -> Check {
+        let mut check = Check::new(guests);
+        check.lines = vec![
+            line("soup", Category::Appetizer, Some(1), 651),
+            line("burger", Category::Main, Some(2), 1601),
+            line("crab-cakes", Category::Main, Some(3), 2399),
+            line("fries", Category::Side, None, 451),
+            line("coffee", Category::Drink, Some(1), 303),
+        ];
+        check.lines[1]
+            .modifiers
+            .push((String::from("Onion Rings"), Cents(201)));
+        check
+            .discount(Discount::percent(
+                "Mains",
+                Rate::percent(15),
+                DiscountScope::Category(Category::Main),
+            ))
+            .discount(Discount::amount(
+                "Voucher",
+                Cents(333),
+                DiscountScope::Check,
+            ))
+            .tip(Tip::Percent(Rate::percent(19)))
+    }
+
+    fn assert_balances(whole: &Receipt, parts: &[Receipt]) {
+        let sum = |f: &dyn Fn(&Receipt) -> Cents| parts.iter().map(f).sum::<Cents>();
+        assert_eq!(sum(&|p| p.total()), whole.total());
+        assert_eq!(sum(&|p| p.subtotal()), whole.subtotal());
+        assert_eq!(sum(&|p| p.discount_total()), whole.discount_total());
+        assert_eq!(
+            sum(&|p| p.service_charge_total()),
+            whole.service_charge_total()
+        );
+        assert_eq!(sum(&|p| p.tax_total()), whole.tax_total());
+        assert_eq!(sum(&|p| p.tip), whole.tip);
+        for (k, (name, amount)) in whole.discounts().into_iter().enumerate() {
+            assert_eq!(sum(&|p| p.discounts()[k].1), amount, "{}", name);
+        }
+    }
+
+    #[test]
+    fn a_receipt_adds_up() {
+        let receipt = dinner(7).receipt(&BillingConfig::default()).unwrap();
+        assert_eq!(receipt.subtotal(), Cents(5606));
+        assert_eq!(
+            receipt.discounts(),
+            [("Mains", Cents(630)), ("Voucher", Cents(333))]
+        );
+        let net = receipt.subtotal() - receipt.discount_total();
+        assert_eq!(receipt.service_charge_total(), Rate::percent(18).of(net));
+        assert_eq!(receipt.tip, Rate::percent(19).of(net));
+        assert_eq!(
+            receipt.total(),
+            net + receipt.service_charge_total() + receipt.tax_total() + receipt.tip
+        );
+        // Under six guests there is no service charge
+        assert_eq!(
+            dinner(2)
+                .receipt(&BillingConfig::default())
+                .unwrap()
+                .service_charge,
+            None
+        );
+    }
+
+    #[test]
+    fn splits_balance_to_the_cent() {
+        for guests in [2, 7] {
+            let receipt = dinner(guests).receipt(&BillingConfig::default()).unwrap();
+            for parts in 1..=7 {
+                assert_balances(&receipt, &receipt.split(&SplitBy::Evenly(parts)).unwrap());
+            }
+            let by_seat = receipt.split(&SplitBy::Seat).unwrap();
+            assert_eq!(by_seat.len(), 3);
+            assert_balances(&receipt, &by_seat);
+            let by_item = receipt.split(&SplitBy::Items(vec![0, 1, 1, 0, 2])).unwrap();
+            assert_eq!(by_item.len(), 3);
+            assert_balances(&receipt, &by_item);
+        }
+    }
+
+    #[test]
+    fn even_splits_differ_by_at_most_a_cent() {
+        let receipt = dinner(7).receipt(&BillingConfig::default()).unwrap();
+        let totals: Vec<i64> = receipt
+            .split(&SplitBy::Evenly(3))
+            .unwrap()
+            .iter()
+            .map(|p| p.total().0)
+            .collect();
+        let spread = totals.iter().max().unwrap() - totals.iter().min().unwrap();
+        assert!(spread <= 1, "{:?}", totals);
+    }
+
+    #[test]
+    fn bad_splits_are_refused() {
+        let mut check = dinner(2);
+        let receipt = check.receipt(&untaxed()).unwrap();
+        assert!(matches!(
+            receipt.split(&SplitBy::Items(vec![0, 1])),
+            Err(BillingError::SplitMismatch { lines: 5, given: 2 })
+        ));
+        for line in &mut check.lines {
+            line.seat = None;
+        }
+        let receipt = check.receipt(&untaxed()).unwrap();
+        assert!(matches!(
+            receipt.split(&SplitBy::Seat),
+            Err(BillingError::NoSeats)
+        ));
+    }
+
+    #[test]
+    fn discounts_never_take_off_more_than_the_lines_cost() {
+        let mut check = Check::new(1);
+        check.lines = vec![
+            line("soup", Category::Appetizer, None, 650),
+            line("coffee", Category::Drink, None, 300),
+        ];
+        let soup = || DiscountScope::Item(String::from("soup"));
+        let receipt = check
+            .clone()
+            .discount(Discount::percent("Double off", Rate::percent(200), soup()))
+            .receipt(&untaxed())
+            .unwrap();
+        assert_eq!(receipt.discount_total(), Cents(650));
+        assert_eq!(receipt.total(), Cents(300));
+        let receipt = check
+            .discount(Discount::amount(
+                "Big voucher",
+                Cents(10_000),
+                DiscountScope::Check,
+            ))
+            .receipt(&untaxed())
+            .unwrap();
+        assert_eq!(receipt.total(), Cents::ZERO);
+    }
+
+    #[test]
+    fn negative_amounts_are_rejected() {
+        let check = dinner(2);
+        let negative = |result: Result<Receipt, BillingError>| {
+            matches!(result, Err(BillingError::Negative { .. }))
+        };
+        assert!(negative(
+            check
+                .clone()
+                .discount(Discount::amount(
+                    "Surcharge",
+                    Cents(-500),
+                    DiscountScope::Check
+                ))
+                .receipt(&untaxed())
+        ));
+        assert!(negative(
+            check
+                .clone()
+                .tip(Tip::Amount(Cents(-1)))
+                .receipt(&untaxed())
+        ));
+        let receipt = check.receipt(&untaxed()).unwrap();
+        let total = receipt.total();
+        assert!(matches!(
+            settle(
+                &receipt,
+                &[
+                    Payment::card(total + Cents(100)),
+                    Payment::cash(Cents(-100))
+                ]
+            ),
+            Err(BillingError::Negative { .. })
+        ));
+    }
+
+    #[test]
+    fn only_cash_gives_change() {
+        let receipt = dinner(2).receipt(&untaxed()).unwrap();
+        let total = receipt.total();
+        assert_eq!(
+            settle(&receipt, &[Payment::card(total)]).unwrap(),
+            Cents::ZERO
+        );
+        assert_eq!(
+            settle(
+                &receipt,
+                &[Payment::card(Cents(1000)), Payment::cash(total)]
+            )
+            .unwrap(),
+            Cents(1000)
+        );
+        assert!(matches!(
+            settle(&receipt, &[Payment::card(total + Cents(1))]),
+            Err(BillingError::Overpaid { .. })
+        ));
+        assert!(matches!(
+            settle(&receipt, &[Payment::cash(total - Cents(1))]),
+            Err(BillingError::Underpaid { .. })
+        ));
+        let huge = vec![Payment::cash(Cents(i64::MAX / 2)); 3];
+        assert!(matches!(
+            settle(&receipt, &huge),
+            Err(BillingError::TooLarge)
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+// Serve the restaurant API over HTTP until killed.
+//
+//     cargo run --bin serve -- [--addr 127.0.0.1:8080] [--menu menu.json] [--data DIR]
+//
+// Use port 0 to have the system pick a free port; the address actually bound is
+// printed on startup. With --data everything is journaled to DIR, and a restart
+// with the same DIR picks the day up where it left off.
+
+use restaurant::api::Restaurant;
+use restaurant::back_of_house::inventory::Inventory;
+use restaurant::back_of_house::kitchen::Kitchen;
+use restaurant::clock::SystemClock;
+use restaurant::front_of_house::floor::FloorPlan;
+use restaurant::front_of_house::staff::Roster;
+use restaurant::http;
+use restaurant::menu::Menu;
+use std::net::TcpListener;
+use std::process;
+
+const USAGE: &str = "usage: serve [--addr HOST:PORT] [--menu PATH] [--data DIR]";
+
+fn main() {
+    let mut addr = String::from("127.0.0.1:8080");
+    let mut menu_path = None;
+    let mut data_dir = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match (flag.as_str(), args.next()) {
+            ("--addr", Some(value)) => addr = value,
+            ("--menu", Some(value)) => menu_path = Some(value),
+            ("--data", Some(value)) => data_dir = Some(value),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    let menu = match menu_path {
+        Some(path) => Menu::load(&path).unwrap_or_else(|e| {
+            eprintln!("can't load {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => Menu::house(),
+    };
+    let listener = TcpListener::bind(&addr).unwrap_or_else(|e| {
+        eprintln!("can't listen on {}: {}", addr, e);
+        process::exit(1);
+    });
+    if let Ok(local) = listener.local_addr() {
+        println!("listening on http://{}", local);
+    }
+
+    let kitchen = Kitchen::new().with_inventory(Inventory::house());
+    let mut restaurant = Restaurant::new(FloorPlan::house(), menu, kitchen, SystemClock)
+        .with_roster(Roster::house());
+    if let Some(dir) = data_dir {
+        if let Err(e) = restaurant.open_journal(&dir) {
+            eprintln!("can't recover from {}: {}", dir, e);
+            process::exit(1);
+        }
+        println!("journaling to {}", dir);
+    }
+    if let Err(e) = http::serve(listener, |request| restaurant.handle(request)) {
+        eprintln!("server stopped: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,114 @@
+// Simulate a night of service and print how it went.
+//
+//     cargo run --bin simulate -- [--seed N] [--rate PARTIES_PER_HOUR] [--hours H]
+//                                 [--patience MINUTES] [--cooks N]
+//
+// Without --rate arrivals follow the default dinner rush.
+
+use restaurant::clock::minutes;
+use restaurant::simulation::{self, Config};
+use std::process;
+
+const USAGE: &str = "usage: simulate [--seed N] [--rate PARTIES_PER_HOUR] [--hours H] \
+                     [--patience MINUTES] [--cooks N]";
+
+fn parse_args(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        if flag == "--help" || flag == "-h" {
+            return Err(String::from(USAGE));
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value\n{}", flag, USAGE))?;
+        let bad = || format!("bad value for {}: {}", flag, value);
+        match flag.as_str() {
+            "--seed" => config.seed = value.parse().map_err(|_| bad())?,
+            "--rate" => {
+                let rate: f64 = value.parse().map_err(|_| bad())?;
+                if !rate.is_finite() || rate <= 0.0 {
+                    return Err(bad());
+                }
+                config = config.flat_rate(rate);
+            }
+            "--hours" => {
+                let hours: u64 = value.parse().map_err(|_| bad())?;
+                config.close = config.open + minutes(hours * 60);
+            }
+            "--patience" => config.patience = minutes(value.parse().map_err(|_| bad())?),
+            "--cooks" => {
+                config.cooks = value.parse().map_err(|_| bad())?;
+                if config.cooks == 0 {
+                    return Err(bad()); // <- nothing would ever be cooked
+                }
+            }
+            _ => return Err(format!("unknown option {}\n{}", flag, USAGE)),
+        }
+    }
+    Ok(config)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match parse_args(&args) {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+    match simulation::run(&config) {
+        Ok(report) => print!("{}", report),
+        Err(e) => {
+            eprintln!("simulation failed: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn options_set_the_config() {
+        let config = parse_args(&args(
+            "--seed 9 --rate 12.5 --hours 3 --patience 20 --cooks 4",
+        ))
+        .unwrap();
+        assert_eq!(config.seed, 9);
+        assert_eq!(config.arrival_rates, [(std::time::Duration::ZERO, 12.5)]);
+        assert_eq!(config.close, config.open + minutes(180));
+        assert_eq!(config.patience, minutes(20));
+        assert_eq!(config.cooks, 4);
+        assert_eq!(parse_args(&[]).unwrap().seed, Config::default().seed);
+    }
+
+    #[test]
+    fn bad_values_are_refused() {
+        for bad in [
+            "--rate inf",
+            "--rate NaN",
+            "--rate 0",
+            "--rate -2",
+            "--cooks 0",
+            "--cooks -1",
+            "--hours x",
+        ] {
+            let message = parse_args(&args(bad)).unwrap_err();
+            assert!(message.starts_with("bad value"), "{}: {}", bad, message);
+        }
+        assert!(parse_args(&args("--seed"))
+            .unwrap_err()
+            .contains("needs a value"));
+        assert!(parse_args(&args("--speed 2"))
+            .unwrap_err()
+            .starts_with("unknown option"));
+        assert_eq!(parse_args(&args("--help")).unwrap_err(), USAGE);
+    }
+}
@@ -0,0 +1,296 @@
+// Points in time for the restaurant, stored as whole seconds since the Unix epoch.
+// Everything that records "when" (arrivals, seatings, orders) uses this so that
+// callers can pass in whatever time they like, which keeps the logic testable.
+
+use std::cell::Cell;
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub fn from_secs(secs: u64) -> Timestamp {
+        Timestamp(secs)
+    }
+
+    pub fn as_secs(&self) -> u64 {
+        self.0
+    }
+
+    pub fn now() -> Timestamp {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Timestamp(since_epoch.as_secs())
+    }
+
+    pub fn time_of_day(&self) -> TimeOfDay {
+        TimeOfDay((self.0 % 86_400) as u32)
+    }
+
+    pub fn date(&self) -> Date {
+        Date::from_days_since_epoch((self.0 / 86_400) as i64)
+    }
+
+    // Time elapsed since `earlier`, zero if `earlier` is actually later
+    pub fn since(&self, earlier: Timestamp) -> Duration {
+        Duration::from_secs(self.0.saturating_sub(earlier.0))
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, rhs: Duration) -> Timestamp {
+        Timestamp(self.0 + rhs.as_secs())
+    }
+}
+
+impl Sub<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn sub(self, rhs: Duration) -> Timestamp {
+        Timestamp(self.0.saturating_sub(rhs.as_secs()))
+    }
+}
+
+// Shown as hours and minutes of the (UTC) day, which is all a host cares about
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.time_of_day())
+    }
+}
+
+// Where "now" comes from. Anything that needs the current time on its own (rather
+// than being handed it) takes a clock, so tests can hold time still or move it on.
+pub trait Clock {
+    fn now(&self) -> Timestamp;
+}
+
+// The real time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        Timestamp::now()
+    }
+}
+
+// A clock that only moves when told to
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock(Cell<Timestamp>);
+
+impl ManualClock {
+    pub fn new(at: Timestamp) -> ManualClock {
+        ManualClock(Cell::new(at))
+    }
+
+    pub fn set(&self, at: Timestamp) {
+        self.0.set(at);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.0.set(self.0.get() + by);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Timestamp {
+        self.0.get()
+    }
+}
+
+// So one clock can be shared, e.g. a test keeping a handle to move it along
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Timestamp {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for std::rc::Rc<C> {
+    fn now(&self) -> Timestamp {
+        (**self).now()
+    }
+}
+
+// A wall-clock time with no date, as seconds since midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct TimeOfDay(u32);
+
+impl TimeOfDay {
+    pub fn new(hours: u32, minutes: u32) -> Option<TimeOfDay> {
+        if hours < 24 && minutes < 60 {
+            Some(TimeOfDay(hours * 3600 + minutes * 60))
+        } else {
+            None
+        }
+    }
+
+    // "07:30" or "7:30"; "24:00" is allowed to mean the end of the day
+    pub fn parse(text: &str) -> Option<TimeOfDay> {
+        let (hours, minutes) = text.trim().split_once(':')?;
+        if minutes.len() != 2 {
+            return None;
+        }
+        let hours: u32 = hours.parse().ok()?;
+        let minutes: u32 = minutes.parse().ok()?;
+        if hours == 24 && minutes == 0 {
+            return Some(TimeOfDay(86_400));
+        }
+        TimeOfDay::new(hours, minutes)
+    }
+
+    pub fn as_secs(&self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 3600, self.0 % 3600 / 60)
+    }
+}
+
+pub fn minutes(n: u64) -> Duration {
+    Duration::from_secs(n * 60)
+}
+
+// A calendar date (proleptic Gregorian, UTC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32, // <- 1 to 12
+    pub day: u32,   // <- 1 to 31
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+}
+
+pub fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+impl Date {
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Date> {
+        if day >= 1 && day <= days_in_month(year, month) {
+            Some(Date { year, month, day })
+        } else {
+            None
+        }
+    }
+
+    // "2024-03-01"
+    pub fn parse(text: &str) -> Option<Date> {
+        let mut parts = text.trim().splitn(3, '-');
+        let year = parts.next()?.parse().ok()?;
+        let month = parts.next()?.parse().ok()?;
+        let day = parts.next()?.parse().ok()?;
+        Date::new(year, month, day)
+    }
+
+    // Days since 1970-01-01, negative before it. This is Howard Hinnant's
+    // days_from_civil, which counts years from March so leap days come last.
+    pub fn days_since_epoch(&self) -> i64 {
+        let year = if self.month <= 2 {
+            self.year as i64 - 1
+        } else {
+            self.year as i64
+        };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year =
+            (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    pub fn from_days_since_epoch(days: i64) -> Date {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u32;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        Date {
+            year: year as i32,
+            month,
+            day,
+        }
+    }
+
+    // Midnight at the start of the day; dates before 1970 clamp to the epoch
+    pub fn start(&self) -> Timestamp {
+        Timestamp(self.days_since_epoch().max(0) as u64 * 86_400)
+    }
+
+    pub fn at(&self, time: TimeOfDay) -> Timestamp {
+        self.start() + Duration::from_secs(time.as_secs() as u64)
+    }
+
+    pub fn add_days(&self, days: i64) -> Date {
+        Date::from_days_since_epoch(self.days_since_epoch() + days)
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        // 1970-01-01 was a Thursday
+        Weekday::ALL[(self.days_since_epoch() + 3).rem_euclid(7) as usize]
+    }
+
+    // 1 for January 1st
+    pub fn day_of_year(&self) -> u32 {
+        let first = Date {
+            year: self.year,
+            month: 1,
+            day: 1,
+        };
+        (self.days_since_epoch() - first.days_since_epoch()) as u32 + 1
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
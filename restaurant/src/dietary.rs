@@ -0,0 +1,630 @@
+// Allergies and diets. What a dish contains is worked out from its recipe (each
+// ingredient in the walk-in says which allergens it carries and whether it is
+// meat) together with what the menu declares for the item and the options the
+// guest picked, so a recipe that's missing an ingredient's tag doesn't hide what
+// the menu already warns about. Guests tell the server what they can't eat;
+// orders are checked against that before they go in, and anything that clashes
+// comes back with something else on the menu they could have instead.
+
+use crate::back_of_house::inventory::Inventory;
+use crate::clock::Timestamp;
+use crate::json::Json;
+use crate::menu::{Allergen, Menu, MenuItem};
+use crate::order::{OrderError, OrderItem};
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Only offer a few alternatives; the server can always read out the menu
+const MAX_SUBSTITUTES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Diet {
+    Vegan,
+    Vegetarian,
+    Pescatarian,
+    GlutenFree,
+    DairyFree,
+    NutFree,
+}
+
+impl Diet {
+    pub const ALL: [Diet; 6] = [
+        Diet::Vegan,
+        Diet::Vegetarian,
+        Diet::Pescatarian,
+        Diet::GlutenFree,
+        Diet::DairyFree,
+        Diet::NutFree,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Diet::Vegan => "vegan",
+            Diet::Vegetarian => "vegetarian",
+            Diet::Pescatarian => "pescatarian",
+            Diet::GlutenFree => "gluten-free",
+            Diet::DairyFree => "dairy-free",
+            Diet::NutFree => "nut-free",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Diet> {
+        Diet::ALL.into_iter().find(|d| d.name() == name)
+    }
+
+    // Allergens standing in for animal products: fish and shellfish are what
+    // vegetarians skip besides meat, dairy and eggs are what vegans skip too
+    pub fn excludes(&self) -> &'static [Allergen] {
+        match self {
+            Diet::Vegan => &[
+                Allergen::Dairy,
+                Allergen::Eggs,
+                Allergen::Fish,
+                Allergen::Shellfish,
+            ],
+            Diet::Vegetarian => &[Allergen::Fish, Allergen::Shellfish],
+            Diet::Pescatarian => &[],
+            Diet::GlutenFree => &[Allergen::Gluten],
+            Diet::DairyFree => &[Allergen::Dairy],
+            Diet::NutFree => &[Allergen::Nuts, Allergen::Peanuts],
+        }
+    }
+
+    pub fn allows_meat(&self) -> bool {
+        !matches!(self, Diet::Vegan | Diet::Vegetarian | Diet::Pescatarian)
+    }
+
+    pub fn allows(&self, dish: &DishProfile) -> bool {
+        (self.allows_meat() || !dish.meat)
+            && self.excludes().iter().all(|a| !dish.allergens.contains(a))
+    }
+}
+
+impl fmt::Display for Diet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// What's in one dish as ordered
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DishProfile {
+    pub allergens: BTreeSet<Allergen>,
+    pub meat: bool,
+    pub from_recipe: bool, // <- false when only the menu's tags were there to go on
+}
+
+impl DishProfile {
+    pub fn merge(&mut self, other: DishProfile) {
+        self.allergens.extend(other.allergens);
+        self.meat |= other.meat;
+        self.from_recipe |= other.from_recipe;
+    }
+
+    // Every diet this dish suits, for tagging the menu
+    pub fn diets(&self) -> Vec<Diet> {
+        Diet::ALL.into_iter().filter(|d| d.allows(self)).collect()
+    }
+
+    pub fn conflicts(&self, guest: &GuestProfile) -> Vec<Reason> {
+        let allergies = guest
+            .allergens
+            .iter()
+            .filter(|a| self.allergens.contains(a))
+            .map(|a| Reason::Allergen(*a));
+        let diets = guest
+            .diets
+            .iter()
+            .filter(|d| !d.allows(self))
+            .map(|d| Reason::Diet(*d));
+        // The menu's tags cover allergens, but only a recipe can rule out meat
+        let unknown = guest
+            .diets
+            .iter()
+            .filter(|d| !self.from_recipe && !d.allows_meat() && d.allows(self))
+            .map(|d| Reason::Unknown(*d));
+        allergies.chain(diets).chain(unknown).collect()
+    }
+}
+
+// What one guest (or, without a seat, everyone at the table) can't eat
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GuestProfile {
+    pub seat: Option<u32>,
+    pub allergens: BTreeSet<Allergen>,
+    pub diets: BTreeSet<Diet>,
+}
+
+impl GuestProfile {
+    pub fn new() -> GuestProfile {
+        GuestProfile::default()
+    }
+
+    pub fn for_seat(mut self, seat: u32) -> GuestProfile {
+        self.seat = Some(seat);
+        self
+    }
+
+    pub fn allergic_to(mut self, allergen: Allergen) -> GuestProfile {
+        self.allergens.insert(allergen);
+        self
+    }
+
+    pub fn follows(mut self, diet: Diet) -> GuestProfile {
+        self.diets.insert(diet);
+        self
+    }
+
+    // Shared plates are for everyone, so they're checked against every guest
+    pub fn applies_to(&self, item: &OrderItem) -> bool {
+        match (self.seat, item.seat) {
+            (Some(seat), Some(item_seat)) => seat == item_seat,
+            _ => true,
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        let allergens: Vec<Json> = self
+            .allergens
+            .iter()
+            .map(|a| Json::from(a.name()))
+            .collect();
+        let diets: Vec<Json> = self.diets.iter().map(|d| Json::from(d.name())).collect();
+        Json::object()
+            .with("seat", self.seat)
+            .with("allergens", allergens)
+            .with("diets", diets)
+    }
+
+    pub fn from_json(json: &Json) -> Option<GuestProfile> {
+        let seat = match json.get("seat") {
+            None | Some(Json::Null) => None,
+            Some(seat) => Some(seat.as_u32()?),
+        };
+        let names = |key: &str| -> Option<Vec<&str>> {
+            match json.get(key) {
+                None => Some(Vec::new()),
+                Some(names) => names.as_array()?.iter().map(Json::as_str).collect(),
+            }
+        };
+        Some(GuestProfile {
+            seat,
+            allergens: names("allergens")?
+                .into_iter()
+                .map(Allergen::parse)
+                .collect::<Option<_>>()?,
+            diets: names("diets")?
+                .into_iter()
+                .map(Diet::parse)
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+impl fmt::Display for GuestProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.seat {
+            Some(seat) => write!(f, "seat {}:", seat)?,
+            None => write!(f, "table:")?,
+        }
+        let allergens: Vec<&str> = self.allergens.iter().map(|a| a.name()).collect();
+        if !allergens.is_empty() {
+            write!(f, " allergic to {};", allergens.join(", "))?;
+        }
+        let diets: Vec<&str> = self.diets.iter().map(|d| d.name()).collect();
+        if !diets.is_empty() {
+            write!(f, " {}", diets.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Allergen(Allergen),
+    Diet(Diet),
+    Unknown(Diet), // <- no recipe to say whether there's meat in it
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Allergen(allergen) => write!(f, "contains {}", allergen),
+            Reason::Diet(diet) => write!(f, "not {}", diet),
+            Reason::Unknown(diet) => write!(f, "no recipe to show it's {}", diet),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Substitute {
+    Option { modifier: String, option: String }, // <- same dish, a different choice
+    Without { modifier: String },                // <- same dish, leave the extra off
+    Item { menu_id: String, name: String },      // <- something else from the same course
+}
+
+impl fmt::Display for Substitute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Substitute::Option { modifier, option } => write!(f, "{}: {}", modifier, option),
+            Substitute::Without { modifier } => write!(f, "no {}", modifier),
+            Substitute::Item { name, .. } => write!(f, "{}", name),
+        }
+    }
+}
+
+// One item one guest shouldn't have
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub item: usize, // <- position in the order
+    pub menu_id: String,
+    pub seat: Option<u32>, // <- whose profile it clashes with
+    pub reasons: Vec<Reason>,
+    pub substitutes: Vec<Substitute>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.menu_id)?;
+        if let Some(seat) = self.seat {
+            write!(f, " (seat {})", seat)?;
+        }
+        let reasons: Vec<String> = self.reasons.iter().map(|r| r.to_string()).collect();
+        write!(f, " {}", reasons.join(", "))?;
+        if !self.substitutes.is_empty() {
+            let substitutes: Vec<String> = self.substitutes.iter().map(|s| s.to_string()).collect();
+            write!(f, "; try {}", substitutes.join(" or "))?;
+        }
+        Ok(())
+    }
+}
+
+// What to do with an order that clashes with a guest profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    Reject,
+    Flag, // <- let it through, but tell the server
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DietaryError {
+    Order(OrderError),
+    Conflicts(Vec<Conflict>),
+}
+
+impl fmt::Display for DietaryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DietaryError::Order(e) => write!(f, "{}", e),
+            DietaryError::Conflicts(conflicts) => {
+                let conflicts: Vec<String> = conflicts.iter().map(|c| c.to_string()).collect();
+                write!(f, "not safe to serve: {}", conflicts.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for DietaryError {}
+
+impl From<OrderError> for DietaryError {
+    fn from(e: OrderError) -> Self {
+        DietaryError::Order(e)
+    }
+}
+
+// Works out what's in dishes from the menu and the walk-in's recipes
+#[derive(Debug, Clone, Copy)]
+pub struct DietChecker<'a> {
+    menu: &'a Menu,
+    inventory: &'a Inventory,
+    at: Timestamp, // <- decides the seasonal fruit, and what can be offered instead
+}
+
+impl<'a> DietChecker<'a> {
+    pub fn new(menu: &'a Menu, inventory: &'a Inventory, at: Timestamp) -> DietChecker<'a> {
+        DietChecker {
+            menu,
+            inventory,
+            at,
+        }
+    }
+
+    pub fn dish(&self, item: &OrderItem) -> DishProfile {
+        let mut dish = DishProfile::default();
+        if let Some(on_menu) = self.menu.get(&item.menu_id) {
+            dish.allergens.extend(on_menu.allergens.iter().copied());
+            for (modifier, option) in item.choices() {
+                let option = on_menu.modifier(modifier).and_then(|m| m.option(option));
+                if let Some(option) = option {
+                    dish.allergens.extend(option.allergens.iter().copied());
+                }
+            }
+        }
+        if let Some(from_recipe) = self.inventory.dish_profile(item, self.at.date()) {
+            dish.merge(from_recipe);
+        }
+        dish
+    }
+
+    // Every item against every guest it's for
+    pub fn check(&self, items: &[OrderItem], guests: &[GuestProfile]) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        for (index, item) in items.iter().enumerate() {
+            let dish = self.dish(item);
+            for guest in guests.iter().filter(|g| g.applies_to(item)) {
+                let reasons = dish.conflicts(guest);
+                if reasons.is_empty() {
+                    continue;
+                }
+                conflicts.push(Conflict {
+                    item: index,
+                    menu_id: item.menu_id.clone(),
+                    seat: guest.seat,
+                    reasons,
+                    substitutes: self.substitutes(item, guest),
+                });
+            }
+        }
+        conflicts
+    }
+
+    // First a different choice on the same dish, then other dishes from the
+    // same part of the menu that the guest could have as they come
+    pub fn substitutes(&self, item: &OrderItem, guest: &GuestProfile) -> Vec<Substitute> {
+        let mut substitutes = Vec::new();
+        let Some(on_menu) = self.menu.get(&item.menu_id) else {
+            return substitutes;
+        };
+        let safe = |candidate: &OrderItem| self.dish(candidate).conflicts(guest).is_empty();
+        for modifier in &on_menu.modifiers {
+            let chosen = item
+                .choices
+                .iter()
+                .find(|(m, _)| m.eq_ignore_ascii_case(&modifier.name));
+            let others = item
+                .choices
+                .iter()
+                .filter(|(m, _)| !m.eq_ignore_ascii_case(&modifier.name));
+            let without = OrderItem {
+                choices: others.cloned().collect(),
+                ..item.clone()
+            };
+            if chosen.is_some() && !modifier.required && safe(&without) {
+                substitutes.push(Substitute::Without {
+                    modifier: modifier.name.clone(),
+                });
+            }
+            for option in &modifier.options {
+                if chosen.is_some_and(|(_, o)| o.eq_ignore_ascii_case(&option.name)) {
+                    continue;
+                }
+                let swapped = without.clone().with(&modifier.name, &option.name);
+                if safe(&swapped) {
+                    substitutes.push(Substitute::Option {
+                        modifier: modifier.name.clone(),
+                        option: option.name.clone(),
+                    });
+                }
+            }
+        }
+        let others = self.menu.items().filter(|other| {
+            other.id != on_menu.id
+                && other.category == on_menu.category
+                && other.is_available(self.at)
+                && self.can_have(other, guest)
+        });
+        for other in others {
+            substitutes.push(Substitute::Item {
+                menu_id: other.id.clone(),
+                name: other.name.clone(),
+            });
+        }
+        substitutes.truncate(MAX_SUBSTITUTES);
+        substitutes
+    }
+
+    // Whether the guest can order this at all: as it comes, with some safe
+    // option for everything that has to be chosen
+    pub fn can_have(&self, item: &MenuItem, guest: &GuestProfile) -> bool {
+        let plain = OrderItem::new(&item.id);
+        if !self.dish(&plain).conflicts(guest).is_empty() {
+            return false;
+        }
+        item.modifiers.iter().filter(|m| m.required).all(|m| {
+            m.options.iter().any(|o| {
+                let chosen = plain.clone().with(&m.name, &o.name);
+                self.dish(&chosen).conflicts(guest).is_empty()
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_of_house::serving;
+    use crate::order::OrderBook;
+
+    fn evening() -> Timestamp {
+        Timestamp::from_secs(18 * 3600)
+    }
+
+    fn allergens(dish: &DishProfile) -> Vec<Allergen> {
+        dish.allergens.iter().copied().collect()
+    }
+
+    #[test]
+    fn dishes_combine_the_menu_and_the_recipe() {
+        let (menu, inventory) = (Menu::house(), Inventory::house());
+        let checker = DietChecker::new(&menu, &inventory, evening());
+
+        // The menu warns about dairy and gluten; only the recipe knows about the stock
+        let soup = checker.dish(&OrderItem::new("soup"));
+        assert_eq!(allergens(&soup), [Allergen::Gluten, Allergen::Dairy]);
+        assert!(soup.meat && soup.from_recipe);
+
+        let salmon = checker.dish(&OrderItem::new("salad").with("protein", "Salmon"));
+        assert_eq!(allergens(&salmon), [Allergen::Fish]);
+        assert!(!salmon.meat);
+        assert_eq!(
+            salmon.diets(),
+            [
+                Diet::Pescatarian,
+                Diet::GlutenFree,
+                Diet::DairyFree,
+                Diet::NutFree
+            ]
+        );
+        assert!(
+            checker
+                .dish(&OrderItem::new("salad").with("protein", "Chicken"))
+                .meat
+        );
+
+        let crab = checker.dish(&OrderItem::new("crab-cakes")); // <- no recipe on file
+        assert!(!crab.from_recipe);
+        assert!(!Diet::Vegetarian.allows(&crab));
+        assert!(Diet::Pescatarian.allows(&crab));
+    }
+
+    #[test]
+    fn each_guest_is_checked_against_their_own_seat_and_shared_plates() {
+        let (menu, inventory) = (Menu::house(), Inventory::house());
+        let checker = DietChecker::new(&menu, &inventory, evening());
+        let guests = [
+            GuestProfile::new().for_seat(1).follows(Diet::Vegetarian),
+            GuestProfile::new().for_seat(2).allergic_to(Allergen::Nuts),
+        ];
+        let items = [
+            OrderItem::new("soup").for_seat(1),
+            OrderItem::new("brownie").for_seat(1),
+            OrderItem::new("coffee").with("milk", "Almond").for_seat(2),
+            OrderItem::new("brownie"), // <- for the table
+        ];
+        let conflicts = checker.check(&items, &guests);
+        let found: Vec<(usize, Option<u32>, &[Reason])> = conflicts
+            .iter()
+            .map(|c| (c.item, c.seat, c.reasons.as_slice()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (0, Some(1), &[Reason::Diet(Diet::Vegetarian)][..]),
+                (1, Some(1), &[Reason::Unknown(Diet::Vegetarian)][..]), // <- no recipe for the brownie
+                (2, Some(2), &[Reason::Allergen(Allergen::Nuts)][..]),
+                (3, Some(1), &[Reason::Unknown(Diet::Vegetarian)][..]),
+                (3, Some(2), &[Reason::Allergen(Allergen::Nuts)][..]),
+            ]
+        );
+        assert_eq!(
+            conflicts[2].substitutes,
+            [
+                Substitute::Without {
+                    modifier: String::from("milk")
+                },
+                Substitute::Option {
+                    modifier: String::from("milk"),
+                    option: String::from("Whole")
+                },
+                Substitute::Option {
+                    modifier: String::from("milk"),
+                    option: String::from("Oat")
+                },
+            ]
+        );
+        assert_eq!(
+            conflicts[2].to_string(),
+            "coffee (seat 2) contains nuts; try no milk or milk: Whole or milk: Oat"
+        );
+    }
+
+    #[test]
+    fn substitutes_are_things_the_guest_can_actually_have() {
+        let (menu, inventory) = (Menu::house(), Inventory::house());
+        let checker = DietChecker::new(&menu, &inventory, evening());
+
+        let dairy_free = GuestProfile::new().follows(Diet::DairyFree);
+        let ranch = OrderItem::new("salad").with("dressing", "Ranch");
+        assert_eq!(
+            checker.substitutes(&ranch, &dairy_free),
+            [
+                Substitute::Option {
+                    modifier: String::from("dressing"),
+                    option: String::from("Vinaigrette")
+                },
+                Substitute::Option {
+                    modifier: String::from("dressing"),
+                    option: String::from("Sesame Ginger")
+                },
+            ]
+        );
+
+        // Every side still comes with beef, and the crab cakes are shellfish
+        let vegetarian = GuestProfile::new().follows(Diet::Vegetarian);
+        let burger = OrderItem::new("burger").with("side", "Fries");
+        assert!(checker.substitutes(&burger, &vegetarian).is_empty());
+        let fries = menu.get("fries").unwrap();
+        assert!(checker.can_have(fries, &vegetarian));
+        assert!(!checker.can_have(menu.get("burger").unwrap(), &vegetarian));
+    }
+
+    #[test]
+    fn orders_that_clash_are_rejected_or_flagged() {
+        let (menu, inventory) = (Menu::house(), Inventory::house());
+        let checker = DietChecker::new(&menu, &inventory, evening());
+        let mut orders = OrderBook::new();
+        let guests = vec![GuestProfile::new().allergic_to(Allergen::Gluten)];
+        let items = vec![OrderItem::new("soup"), OrderItem::new("lemonade")];
+
+        let rejected = serving::take_checked_order(
+            &mut orders,
+            &checker,
+            4,
+            items.clone(),
+            guests.clone(),
+            OnConflict::Reject,
+            evening(),
+        );
+        assert!(matches!(&rejected, Err(DietaryError::Conflicts(c)) if c.len() == 1));
+        assert_eq!(orders.iter().count(), 0);
+
+        let (id, flagged) = serving::take_checked_order(
+            &mut orders,
+            &checker,
+            4,
+            items,
+            guests.clone(),
+            OnConflict::Flag,
+            evening(),
+        )
+        .unwrap();
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].menu_id, "soup");
+        assert_eq!(orders.get(id).unwrap().guests(), guests.as_slice());
+    }
+
+    #[test]
+    fn guest_profiles_survive_a_round_trip() {
+        let guest = GuestProfile::new()
+            .for_seat(3)
+            .allergic_to(Allergen::Sesame)
+            .allergic_to(Allergen::Peanuts)
+            .follows(Diet::Vegan);
+        assert_eq!(
+            GuestProfile::from_json(&guest.to_json()),
+            Some(guest.clone())
+        );
+        assert_eq!(
+            guest.to_string(),
+            "seat 3: allergic to peanuts, sesame; vegan"
+        );
+
+        let table = Json::parse(r#"{"diets": ["gluten-free"]}"#).unwrap();
+        assert_eq!(
+            GuestProfile::from_json(&table),
+            Some(GuestProfile::new().follows(Diet::GlutenFree))
+        );
+        let unknown = Json::parse(r#"{"diets": ["paleo"]}"#).unwrap();
+        assert_eq!(GuestProfile::from_json(&unknown), None);
+    }
+}
@@ -1,9 +1,5 @@
+pub mod floor;
 pub mod hosting;
-
-mod serving {
-    fn take_order() {}
-
-    fn serve_order() {}
-
-    fn take_payment() {}
-}
+pub mod reservations;
+pub mod serving;
+pub mod staff;
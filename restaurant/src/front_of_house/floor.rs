@@ -0,0 +1,597 @@
+// The floor plan: every table in the dining room, which section it is in, which
+// tables can be pushed together, and what state each one is in right now.
+// Hosting asks the floor plan where a party should go; the floor plan doesn't
+// know anything about who is waiting.
+
+use super::hosting::{Party, PartyId};
+use super::reservations::ReservationId;
+use crate::clock::Timestamp;
+use crate::json::Json;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::time::Duration;
+
+// Pushing more than three tables together blocks the aisles
+const MAX_COMBINED: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableState {
+    Free,
+    Seated {
+        party: PartyId,
+        since: Timestamp,
+    },
+    Dirty, // <- the party left but the table hasn't been bussed yet
+    Reserved {
+        reservation: ReservationId,
+        name: String,
+        at: Timestamp,
+    },
+}
+
+impl fmt::Display for TableState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableState::Free => write!(f, "free"),
+            TableState::Seated { party, since } => write!(f, "{} since {}", party, since),
+            TableState::Dirty => write!(f, "dirty"),
+            TableState::Reserved { name, at, .. } => {
+                write!(f, "reserved for {} at {}", name, at)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub number: u32,
+    pub capacity: u32,
+    pub section: String,
+    neighbours: Vec<u32>, // <- tables this one can be pushed together with
+    state: TableState,
+}
+
+impl Table {
+    pub fn new(number: u32, capacity: u32, section: &str) -> Table {
+        Table {
+            number,
+            capacity,
+            section: String::from(section),
+            neighbours: Vec::new(),
+            state: TableState::Free,
+        }
+    }
+
+    pub fn state(&self) -> &TableState {
+        &self.state
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.state == TableState::Free
+    }
+
+    pub fn neighbours(&self) -> &[u32] {
+        &self.neighbours
+    }
+}
+
+// Where a party should sit: one table, or a few pushed together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub tables: Vec<u32>,
+    pub capacity: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FloorError {
+    UnknownTable(u32),
+    DuplicateTable(u32),
+    TableNotFree(u32),
+    TableNotSeated(u32),
+    TableNotDirty(u32),
+    TableNotReserved(u32),
+    NotCombinable(Vec<u32>), // <- the tables aren't next to each other
+    UnknownParty(PartyId),
+}
+
+impl fmt::Display for FloorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FloorError::UnknownTable(n) => write!(f, "there is no table {}", n),
+            FloorError::DuplicateTable(n) => write!(f, "table {} is already on the floor", n),
+            FloorError::TableNotFree(n) => write!(f, "table {} is not free", n),
+            FloorError::TableNotSeated(n) => write!(f, "table {} has nobody seated", n),
+            FloorError::TableNotDirty(n) => write!(f, "table {} doesn't need bussing", n),
+            FloorError::TableNotReserved(n) => write!(f, "table {} is not reserved", n),
+            FloorError::NotCombinable(tables) => {
+                write!(f, "tables {:?} can't be pushed together", tables)
+            }
+            FloorError::UnknownParty(id) => write!(f, "{} is not seated", id),
+        }
+    }
+}
+
+impl std::error::Error for FloorError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct FloorPlan {
+    tables: Vec<Table>,                          // <- kept sorted by table number
+    servers: HashMap<String, String>,            // <- section -> server working it
+    seated: HashMap<PartyId, (Party, Vec<u32>)>, // <- who is sitting where
+}
+
+impl FloorPlan {
+    pub fn new() -> FloorPlan {
+        FloorPlan::default()
+    }
+
+    // Twelve tables: two-tops along the window that push together, four-tops in
+    // the middle, and a six-top and a booth on the patio
+    pub fn house() -> FloorPlan {
+        let mut floor = FloorPlan::new();
+        let tables = [
+            (1, 2, "window"),
+            (2, 2, "window"),
+            (3, 2, "window"),
+            (4, 2, "window"),
+            (5, 4, "middle"),
+            (6, 4, "middle"),
+            (7, 4, "middle"),
+            (8, 4, "middle"),
+            (9, 6, "patio"),
+            (10, 4, "patio"),
+            (11, 2, "patio"),
+            (12, 2, "patio"),
+        ];
+        for (number, capacity, section) in tables {
+            floor
+                .add_table(Table::new(number, capacity, section))
+                .expect("table numbers are unique");
+        }
+        for (a, b) in [(1, 2), (2, 3), (3, 4), (5, 6), (7, 8), (11, 12)] {
+            floor.join(a, b).expect("both tables exist");
+        }
+        floor.assign_server("window", "Alice");
+        floor.assign_server("middle", "Bob");
+        floor.assign_server("patio", "Carol");
+        floor
+    }
+
+    pub fn add_table(&mut self, table: Table) -> Result<(), FloorError> {
+        match self
+            .tables
+            .binary_search_by_key(&table.number, |t| t.number)
+        {
+            Ok(_) => Err(FloorError::DuplicateTable(table.number)),
+            Err(index) => {
+                self.tables.insert(index, table);
+                Ok(())
+            }
+        }
+    }
+
+    // Mark two tables as next to each other, so they can be pushed together
+    pub fn join(&mut self, a: u32, b: u32) -> Result<(), FloorError> {
+        self.index(a)?;
+        self.index(b)?;
+        if a == b {
+            return Err(FloorError::NotCombinable(vec![a, b]));
+        }
+        for (from, to) in [(a, b), (b, a)] {
+            let table = self.table_mut(from)?;
+            if !table.neighbours.contains(&to) {
+                table.neighbours.push(to);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn table(&self, number: u32) -> Option<&Table> {
+        self.index(number).ok().map(|i| &self.tables[i])
+    }
+
+    pub fn tables(&self) -> &[Table] {
+        &self.tables
+    }
+
+    pub fn sections(&self) -> Vec<&str> {
+        let sections: BTreeSet<&str> = self.tables.iter().map(|t| t.section.as_str()).collect();
+        sections.into_iter().collect()
+    }
+
+    pub fn assign_server(&mut self, section: &str, server: &str) {
+        self.servers
+            .insert(String::from(section), String::from(server));
+    }
+
+    pub fn server_for(&self, number: u32) -> Option<&str> {
+        let table = self.table(number)?;
+        self.servers.get(&table.section).map(|s| s.as_str())
+    }
+
+    pub fn tables_for_server(&self, server: &str) -> Vec<u32> {
+        self.tables
+            .iter()
+            .filter(|t| self.servers.get(&t.section).map(|s| s.as_str()) == Some(server))
+            .map(|t| t.number)
+            .collect()
+    }
+
+    pub fn free_tables(&self) -> Vec<u32> {
+        self.tables
+            .iter()
+            .filter(|t| t.is_free())
+            .map(|t| t.number)
+            .collect()
+    }
+
+    // The best free spot for a party: a single table if one fits (the smallest,
+    // so big tables stay open for big parties), otherwise the fewest neighbouring
+    // tables that fit with the fewest empty chairs.
+    pub fn best_table(&self, size: u32) -> Option<Placement> {
+        self.best_table_where(size, |t| t.is_free())
+    }
+
+    // The same choice, but among the tables `usable` allows rather than the
+    // free ones, for planning ahead
+    pub fn best_table_where(
+        &self,
+        size: u32,
+        usable: impl Fn(&Table) -> bool + Copy,
+    ) -> Option<Placement> {
+        self.placements(size, usable)
+            .into_iter()
+            .min_by_key(|p| (p.tables.len(), p.capacity, p.tables.clone()))
+    }
+
+    // The biggest party this table could ever take part in seating, counting
+    // the neighbours it can be pushed together with
+    pub fn reach(&self, number: u32) -> u32 {
+        self.groups(number, |_| true)
+            .iter()
+            .map(|group| self.capacity_of(group))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn seat(
+        &mut self,
+        id: PartyId,
+        party: Party,
+        tables: &[u32],
+        now: Timestamp,
+    ) -> Result<(), FloorError> {
+        for &number in tables {
+            let table = self.table(number).ok_or(FloorError::UnknownTable(number))?;
+            if !table.is_free() {
+                return Err(FloorError::TableNotFree(number));
+            }
+        }
+        if tables.len() > MAX_COMBINED || !self.connected(tables) {
+            return Err(FloorError::NotCombinable(tables.to_vec()));
+        }
+        for &number in tables {
+            self.table_mut(number)?.state = TableState::Seated {
+                party: id,
+                since: now,
+            };
+        }
+        self.seated.insert(id, (party, tables.to_vec()));
+        Ok(())
+    }
+
+    pub fn seated(&self, id: PartyId) -> Option<(&Party, &[u32])> {
+        self.seated
+            .get(&id)
+            .map(|(party, tables)| (party, tables.as_slice()))
+    }
+
+    // The party at this table left: every table they were using goes to dirty.
+    // Returns the party and how long they stayed.
+    pub fn clear(&mut self, number: u32, now: Timestamp) -> Result<(Party, Duration), FloorError> {
+        let table = self.table(number).ok_or(FloorError::UnknownTable(number))?;
+        let TableState::Seated { party: id, since } = table.state else {
+            return Err(FloorError::TableNotSeated(number));
+        };
+        let (party, tables) = self
+            .seated
+            .remove(&id)
+            .ok_or(FloorError::UnknownParty(id))?;
+        for number in tables {
+            self.table_mut(number)?.state = TableState::Dirty;
+        }
+        Ok((party, now.since(since)))
+    }
+
+    // Bussed and reset
+    pub fn mark_clean(&mut self, number: u32) -> Result<(), FloorError> {
+        let table = self.table_mut(number)?;
+        if table.state != TableState::Dirty {
+            return Err(FloorError::TableNotDirty(number));
+        }
+        table.state = TableState::Free;
+        Ok(())
+    }
+
+    pub fn reserve(
+        &mut self,
+        number: u32,
+        reservation: ReservationId,
+        name: &str,
+        at: Timestamp,
+    ) -> Result<(), FloorError> {
+        let table = self.table_mut(number)?;
+        if !table.is_free() {
+            return Err(FloorError::TableNotFree(number));
+        }
+        table.state = TableState::Reserved {
+            reservation,
+            name: String::from(name),
+            at,
+        };
+        Ok(())
+    }
+
+    pub fn release(&mut self, number: u32) -> Result<(), FloorError> {
+        let table = self.table_mut(number)?;
+        if !matches!(table.state, TableState::Reserved { .. }) {
+            return Err(FloorError::TableNotReserved(number));
+        }
+        table.state = TableState::Free;
+        Ok(())
+    }
+
+    // What changes during service: each table's state and who is sitting where.
+    // The tables themselves, their neighbours and servers are set up at opening.
+    pub fn state_json(&self) -> Json {
+        let tables: Vec<Json> = self
+            .tables
+            .iter()
+            .map(|t| {
+                let json = Json::object().with("number", t.number);
+                match &t.state {
+                    TableState::Free => json.with("state", "free"),
+                    TableState::Dirty => json.with("state", "dirty"),
+                    TableState::Seated { party, since } => json
+                        .with("state", "seated")
+                        .with("party", party.0)
+                        .with("since", since.as_secs()),
+                    TableState::Reserved {
+                        reservation,
+                        name,
+                        at,
+                    } => json
+                        .with("state", "reserved")
+                        .with("reservation", reservation.0)
+                        .with("name", name.as_str())
+                        .with("at", at.as_secs()),
+                }
+            })
+            .collect();
+        let mut seated: Vec<(&PartyId, &(Party, Vec<u32>))> = self.seated.iter().collect();
+        seated.sort_by_key(|(id, _)| **id);
+        let seated: Vec<Json> = seated
+            .into_iter()
+            .map(|(id, (party, tables))| {
+                party
+                    .to_json()
+                    .with("id", id.0)
+                    .with("tables", tables.clone())
+            })
+            .collect();
+        Json::object().with("tables", tables).with("seated", seated)
+    }
+
+    // Put back what state_json saved, onto the same tables
+    pub fn restore_state(&mut self, json: &Json) -> Option<()> {
+        let mut states = Vec::new();
+        for table in json.get("tables")?.as_array()? {
+            let at = |key: &str| Some(Timestamp::from_secs(table.get(key)?.as_u64()?));
+            let state = match table.get("state")?.as_str()? {
+                "free" => TableState::Free,
+                "dirty" => TableState::Dirty,
+                "seated" => TableState::Seated {
+                    party: PartyId(table.get("party")?.as_u32()?),
+                    since: at("since")?,
+                },
+                "reserved" => TableState::Reserved {
+                    reservation: ReservationId(table.get("reservation")?.as_u32()?),
+                    name: String::from(table.get("name")?.as_str()?),
+                    at: at("at")?,
+                },
+                _ => return None,
+            };
+            states.push((table.get("number")?.as_u32()?, state));
+        }
+        let mut seated = HashMap::new();
+        for entry in json.get("seated")?.as_array()? {
+            let tables = entry
+                .get("tables")?
+                .as_array()?
+                .iter()
+                .map(Json::as_u32)
+                .collect::<Option<Vec<_>>>()?;
+            let id = PartyId(entry.get("id")?.as_u32()?);
+            seated.insert(id, (Party::from_json(entry)?, tables));
+        }
+        let numbers = states
+            .iter()
+            .map(|(number, _)| number)
+            .chain(seated.values().flat_map(|(_, tables)| tables));
+        if numbers.clone().any(|number| self.table(*number).is_none()) {
+            return None; // <- checked first, so a bad state changes nothing
+        }
+        for (number, state) in states {
+            self.table_mut(number).ok()?.state = state;
+        }
+        self.seated = seated;
+        Some(())
+    }
+
+    fn index(&self, number: u32) -> Result<usize, FloorError> {
+        self.tables
+            .binary_search_by_key(&number, |t| t.number)
+            .map_err(|_| FloorError::UnknownTable(number))
+    }
+
+    fn table_mut(&mut self, number: u32) -> Result<&mut Table, FloorError> {
+        let index = self.index(number)?;
+        Ok(&mut self.tables[index])
+    }
+
+    fn capacity_of(&self, tables: &[u32]) -> u32 {
+        tables
+            .iter()
+            .filter_map(|&n| self.table(n))
+            .map(|t| t.capacity)
+            .sum()
+    }
+
+    // Every table is reachable from the first through neighbours in the set
+    fn connected(&self, tables: &[u32]) -> bool {
+        let Some(&first) = tables.first() else {
+            return false;
+        };
+        let mut reached = vec![first];
+        let mut i = 0;
+        while i < reached.len() {
+            if let Some(table) = self.table(reached[i]) {
+                for n in &table.neighbours {
+                    if tables.contains(n) && !reached.contains(n) {
+                        reached.push(*n);
+                    }
+                }
+            }
+            i += 1;
+        }
+        reached.len() == tables.len()
+    }
+
+    // Every group of up to MAX_COMBINED neighbouring tables containing `start`
+    // whose tables all pass `usable`, as sorted table numbers
+    fn groups(&self, start: u32, usable: impl Fn(&Table) -> bool) -> BTreeSet<Vec<u32>> {
+        let mut found = BTreeSet::new();
+        if !self.table(start).is_some_and(&usable) {
+            return found;
+        }
+        let mut frontier = vec![vec![start]];
+        while let Some(group) = frontier.pop() {
+            if !found.insert(group.clone()) || group.len() == MAX_COMBINED {
+                continue;
+            }
+            for number in &group {
+                let Some(table) = self.table(*number) else {
+                    continue;
+                };
+                for n in &table.neighbours {
+                    if group.contains(n) || !self.table(*n).is_some_and(&usable) {
+                        continue;
+                    }
+                    let mut bigger = group.clone();
+                    bigger.push(*n);
+                    bigger.sort_unstable();
+                    frontier.push(bigger);
+                }
+            }
+        }
+        found
+    }
+
+    fn placements(&self, size: u32, usable: impl Fn(&Table) -> bool + Copy) -> Vec<Placement> {
+        let mut groups = BTreeSet::new();
+        for table in &self.tables {
+            groups.extend(self.groups(table.number, usable));
+        }
+        groups
+            .into_iter()
+            .map(|tables| Placement {
+                capacity: self.capacity_of(&tables),
+                tables,
+            })
+            .filter(|p| p.capacity >= size)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn party(size: u32) -> Party {
+        Party::walk_in("Test", size, Timestamp::from_secs(0))
+    }
+
+    #[test]
+    fn smallest_single_table_wins_over_pushing_tables_together() {
+        let floor = FloorPlan::house();
+        assert_eq!(floor.best_table(2).unwrap().tables, [1]);
+        assert_eq!(floor.best_table(3).unwrap().tables, [5]);
+        assert_eq!(floor.best_table(6).unwrap().tables, [9]);
+        let eight = floor.best_table(8).unwrap();
+        assert_eq!((eight.tables.len(), eight.capacity), (2, 8));
+        assert!(floor.best_table(20).is_none());
+    }
+
+    #[test]
+    fn combined_tables_must_be_neighbours() {
+        let mut floor = FloorPlan::house();
+        let now = Timestamp::from_secs(0);
+        assert_eq!(
+            floor.seat(PartyId(1), party(4), &[1, 3], now),
+            Err(FloorError::NotCombinable(vec![1, 3]))
+        );
+        // All four window tables are in a row, but that's one too many
+        assert_eq!(
+            floor.seat(PartyId(1), party(8), &[1, 2, 3, 4], now),
+            Err(FloorError::NotCombinable(vec![1, 2, 3, 4]))
+        );
+        floor.seat(PartyId(1), party(4), &[1, 2], now).unwrap();
+        assert_eq!(
+            floor.seat(PartyId(2), party(2), &[2], now),
+            Err(FloorError::TableNotFree(2))
+        );
+        assert_eq!(floor.reach(4), 6);
+    }
+
+    #[test]
+    fn tables_go_seated_dirty_free() {
+        let mut floor = FloorPlan::house();
+        let sat = Timestamp::from_secs(100);
+        floor.seat(PartyId(7), party(4), &[5, 6], sat).unwrap();
+        assert_eq!(floor.mark_clean(5), Err(FloorError::TableNotDirty(5)));
+        let (_, stayed) = floor.clear(6, sat + Duration::from_secs(600)).unwrap();
+        assert_eq!(stayed, Duration::from_secs(600));
+        assert_eq!(floor.table(5).unwrap().state(), &TableState::Dirty);
+        floor.mark_clean(5).unwrap();
+        assert!(floor.table(5).unwrap().is_free());
+        assert_eq!(floor.clear(5, sat), Err(FloorError::TableNotSeated(5)));
+    }
+
+    #[test]
+    fn servers_follow_sections_and_state_round_trips() {
+        let mut floor = FloorPlan::house();
+        assert_eq!(floor.server_for(9), Some("Carol"));
+        assert_eq!(floor.tables_for_server("Bob"), [5, 6, 7, 8]);
+        floor
+            .reserve(10, ReservationId(1), "Rustaceans", Timestamp::from_secs(50))
+            .unwrap();
+        floor
+            .seat(PartyId(3), party(2), &[11], Timestamp::from_secs(60))
+            .unwrap();
+        let mut restored = FloorPlan::house();
+        restored.restore_state(&floor.state_json()).unwrap();
+        assert_eq!(restored.state_json(), floor.state_json());
+        assert!(restored.seated(PartyId(3)).is_some());
+
+        // A party seated at a table this floor doesn't have: nothing is restored
+        let saved = floor
+            .state_json()
+            .to_string()
+            .replace("\"tables\":[11]", "\"tables\":[99]");
+        let mut untouched = FloorPlan::house();
+        assert!(untouched
+            .restore_state(&Json::parse(&saved).unwrap())
+            .is_none());
+        assert_eq!(untouched.state_json(), FloorPlan::house().state_json());
+    }
+}
@@ -2,7 +2,528 @@
 // The hosting module is a child of the front_of_house module
 // We can define functions, structs, enums, and other items within this module and, if they are public, we can access them from outside the module
 
+use super::floor::{FloorError, FloorPlan, TableState};
+use super::staff::Roster;
+use crate::clock::Timestamp;
+use crate::json::Json;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+// Used for wait estimates until some tables have actually turned over
+const DEFAULT_TURNOVER: Duration = Duration::from_secs(45 * 60);
+// Only the most recent turnovers count, so estimates follow the pace of the night
+const TURNOVER_HISTORY: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PartyId(pub u32);
+
+impl fmt::Display for PartyId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "party #{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Party {
+    pub name: String,
+    pub size: u32,
+    pub contact: Option<String>, // <- phone number to text when the table is ready
+    pub arrived: Timestamp,
+    pub reservation: bool, // <- parties with a reservation go ahead of walk-ins
+}
+
+impl Party {
+    pub fn walk_in(name: &str, size: u32, arrived: Timestamp) -> Party {
+        Party {
+            name: String::from(name),
+            size,
+            contact: None,
+            arrived,
+            reservation: false,
+        }
+    }
+}
+
+impl Party {
+    pub fn to_json(&self) -> Json {
+        Json::object()
+            .with("name", self.name.as_str())
+            .with("size", self.size)
+            .with("contact", self.contact.clone())
+            .with("arrived", self.arrived.as_secs())
+            .with("reservation", self.reservation)
+    }
+
+    pub fn from_json(json: &Json) -> Option<Party> {
+        let contact = match json.get("contact")? {
+            Json::Null => None,
+            contact => Some(String::from(contact.as_str()?)),
+        };
+        Some(Party {
+            name: String::from(json.get("name")?.as_str()?),
+            size: json.get("size")?.as_u32().filter(|size| *size > 0)?,
+            contact,
+            arrived: Timestamp::from_secs(json.get("arrived")?.as_u64()?),
+            reservation: json.get("reservation")?.as_bool()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Seating {
+    pub party: PartyId,
+    pub tables: Vec<u32>,
+    pub server: Option<String>,
+    pub waited: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SeatingError {
+    NoGuests, // <- a party of zero
+    EmptyWaitlist,
+    UnknownParty(PartyId),
+    NoFreeTable,
+    NoTableFits { party: PartyId, size: u32 }, // <- free tables exist, but all too small
+    Floor(FloorError),
+}
+
+impl fmt::Display for SeatingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SeatingError::NoGuests => write!(f, "a party needs at least one guest"),
+            SeatingError::EmptyWaitlist => write!(f, "nobody is waiting"),
+            SeatingError::UnknownParty(id) => write!(f, "{} is not on the waitlist", id),
+            SeatingError::NoFreeTable => write!(f, "every table is taken"),
+            SeatingError::NoTableFits { party, size } => {
+                write!(f, "no free table can seat {} ({} guests)", party, size)
+            }
+            SeatingError::Floor(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SeatingError {}
+
+impl From<FloorError> for SeatingError {
+    fn from(e: FloorError) -> Self {
+        SeatingError::Floor(e)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Waitlist {
+    waiting: Vec<(PartyId, Party)>,
+    next_id: u32,
+    turnovers: VecDeque<Duration>, // <- how long recent tables were occupied
+    no_shows: Vec<Party>,
+    waits: Vec<(Timestamp, Duration)>, // <- when each party was seated, and how long they waited
+    turns: Vec<(Timestamp, Duration)>, // <- when each table was cleared, and how long it was sat
+}
+
+impl Waitlist {
+    pub fn new() -> Waitlist {
+        Waitlist::default()
+    }
+
+    // Everyone waiting plus the history estimates are made from, for saving
+    // and loading with from_json
+    pub fn to_json(&self) -> Json {
+        let waiting: Vec<Json> = self
+            .waiting
+            .iter()
+            .map(|(id, party)| party.to_json().with("id", id.0))
+            .collect();
+        let turnovers: Vec<Json> = self
+            .turnovers
+            .iter()
+            .map(|t| Json::from(t.as_secs()))
+            .collect();
+        let no_shows: Vec<Json> = self.no_shows.iter().map(Party::to_json).collect();
+        Json::object()
+            .with("waiting", waiting)
+            .with("next_id", self.next_id)
+            .with("turnovers", turnovers)
+            .with("no_shows", no_shows)
+            .with("waits", log_json(&self.waits))
+            .with("turns", log_json(&self.turns))
+    }
+
+    pub fn from_json(json: &Json) -> Option<Waitlist> {
+        let waiting = json
+            .get("waiting")?
+            .as_array()?
+            .iter()
+            .map(|w| Some((PartyId(w.get("id")?.as_u32()?), Party::from_json(w)?)))
+            .collect::<Option<Vec<_>>>()?;
+        let turnovers = json
+            .get("turnovers")?
+            .as_array()?
+            .iter()
+            .map(|t| t.as_u64().map(Duration::from_secs))
+            .collect::<Option<VecDeque<_>>>()?;
+        let no_shows = json
+            .get("no_shows")?
+            .as_array()?
+            .iter()
+            .map(Party::from_json)
+            .collect::<Option<Vec<_>>>()?;
+        Some(Waitlist {
+            waiting,
+            next_id: json.get("next_id")?.as_u32()?,
+            turnovers,
+            no_shows,
+            waits: log_from_json(json.get("waits")?)?,
+            turns: log_from_json(json.get("turns")?)?,
+        })
+    }
+
+    pub fn add(&mut self, party: Party) -> Result<PartyId, SeatingError> {
+        if party.size == 0 {
+            return Err(SeatingError::NoGuests);
+        }
+        self.next_id += 1;
+        let id = PartyId(self.next_id);
+        self.waiting.push((id, party));
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: PartyId) -> Option<Party> {
+        let index = self.waiting.iter().position(|(p, _)| *p == id)?;
+        Some(self.waiting.remove(index).1)
+    }
+
+    // The party was called but never came back to the stand
+    pub fn mark_no_show(&mut self, id: PartyId) -> Result<(), SeatingError> {
+        let party = self.remove(id).ok_or(SeatingError::UnknownParty(id))?;
+        self.no_shows.push(party);
+        Ok(())
+    }
+
+    pub fn no_shows(&self) -> &[Party] {
+        &self.no_shows
+    }
+
+    pub fn get(&self, id: PartyId) -> Option<&Party> {
+        self.waiting
+            .iter()
+            .find(|(p, _)| *p == id)
+            .map(|(_, party)| party)
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+
+    // Reservations first, then everyone else in the order they arrived
+    pub fn queue(&self) -> Vec<(PartyId, &Party)> {
+        let mut queue: Vec<(PartyId, &Party)> = self
+            .waiting
+            .iter()
+            .map(|(id, party)| (*id, party))
+            .collect();
+        queue.sort_by_key(|(id, party)| (!party.reservation, party.arrived, *id));
+        queue
+    }
+
+    // Seat the first party in the queue that one of the free tables can hold
+    pub fn seat_next(
+        &mut self,
+        floor: &mut FloorPlan,
+        now: Timestamp,
+    ) -> Result<Seating, SeatingError> {
+        let queue: Vec<PartyId> = self.queue().iter().map(|(id, _)| *id).collect();
+        let head = *queue.first().ok_or(SeatingError::EmptyWaitlist)?;
+        if floor.free_tables().is_empty() {
+            return Err(SeatingError::NoFreeTable);
+        }
+        for id in queue {
+            match self.seat_party(id, floor, now) {
+                Err(SeatingError::NoTableFits { .. }) => continue,
+                result => return result,
+            }
+        }
+        let size = self.get(head).map_or(0, |p| p.size);
+        Err(SeatingError::NoTableFits { party: head, size })
+    }
+
+    // Seat a particular party wherever the floor plan thinks is best for their size
+    pub fn seat_party(
+        &mut self,
+        id: PartyId,
+        floor: &mut FloorPlan,
+        now: Timestamp,
+    ) -> Result<Seating, SeatingError> {
+        let size = self.get(id).ok_or(SeatingError::UnknownParty(id))?.size;
+        let placement = floor
+            .best_table(size)
+            .ok_or(SeatingError::NoTableFits { party: id, size })?;
+        self.seat_at_tables(id, floor, placement.tables, now)
+    }
+
+    // Seat a party at tables already picked for them
+    pub fn seat_at_tables(
+        &mut self,
+        id: PartyId,
+        floor: &mut FloorPlan,
+        tables: Vec<u32>,
+        now: Timestamp,
+    ) -> Result<Seating, SeatingError> {
+        let party = self.get(id).ok_or(SeatingError::UnknownParty(id))?.clone();
+        let waited = now.since(party.arrived);
+        floor.seat(id, party, &tables, now)?;
+        self.remove(id); // <- only once they're actually sitting down
+        self.waits.push((now, waited));
+        Ok(Seating {
+            party: id,
+            server: tables
+                .first()
+                .and_then(|t| floor.server_for(*t))
+                .map(String::from),
+            tables,
+            waited,
+        })
+    }
+
+    // The party left: their tables need bussing, and remember how long they stayed
+    pub fn clear_table(
+        &mut self,
+        floor: &mut FloorPlan,
+        number: u32,
+        now: Timestamp,
+    ) -> Result<Party, SeatingError> {
+        let (party, stayed) = floor.clear(number, now)?;
+        self.record_turnover(stayed);
+        self.turns.push((now, stayed));
+        Ok(party)
+    }
+
+    // Every wait so far, stamped with when the party sat down
+    pub fn waits(&self) -> &[(Timestamp, Duration)] {
+        &self.waits
+    }
+
+    // Every table turn so far, stamped with when the table was cleared
+    pub fn turns(&self) -> &[(Timestamp, Duration)] {
+        &self.turns
+    }
+
+    pub fn record_turnover(&mut self, duration: Duration) {
+        self.turnovers.push_back(duration);
+        if self.turnovers.len() > TURNOVER_HISTORY {
+            self.turnovers.pop_front();
+        }
+    }
+
+    pub fn average_turnover(&self) -> Duration {
+        if self.turnovers.is_empty() {
+            return DEFAULT_TURNOVER;
+        }
+        self.turnovers.iter().sum::<Duration>() / self.turnovers.len() as u32
+    }
+
+    // Play the queue forward: each table that could seat the party (alone or
+    // pushed together with its neighbours) frees up after the average turnover,
+    // minus how long its current party has already been there, and the parties
+    // ahead take those tables first. Reserved tables aren't counted.
+    pub fn estimate_wait(
+        &self,
+        id: PartyId,
+        floor: &FloorPlan,
+        now: Timestamp,
+    ) -> Option<Duration> {
+        let party = self.get(id)?;
+        let turnover = self.average_turnover();
+        let mut free_at: Vec<(u32, Duration)> = floor
+            .tables()
+            .iter()
+            .map(|t| (floor.reach(t.number), t.state()))
+            .filter(|(reach, _)| *reach >= party.size)
+            .filter_map(|(reach, state)| match state {
+                TableState::Free | TableState::Dirty => Some((reach, Duration::ZERO)),
+                TableState::Seated { since, .. } => {
+                    Some((reach, turnover.saturating_sub(now.since(*since))))
+                }
+                TableState::Reserved { .. } => None,
+            })
+            .collect();
+        if free_at.is_empty() {
+            return None; // <- no table in the house is big enough
+        }
+        for (ahead, other) in self.queue() {
+            // A party ahead only gets in the way if one of these tables fits them
+            let earliest = free_at
+                .iter_mut()
+                .filter(|(capacity, _)| *capacity >= other.size)
+                .min_by_key(|(capacity, at)| (*at, *capacity));
+            let Some((_, at)) = earliest else {
+                continue;
+            };
+            if ahead == id {
+                return Some(*at);
+            }
+            *at += turnover;
+        }
+        None
+    }
+}
+
+fn log_json(log: &[(Timestamp, Duration)]) -> Json {
+    let entries: Vec<Json> = log
+        .iter()
+        .map(|(at, took)| Json::from(vec![at.as_secs(), took.as_secs()]))
+        .collect();
+    Json::from(entries)
+}
+
+fn log_from_json(json: &Json) -> Option<Vec<(Timestamp, Duration)>> {
+    json.as_array()?
+        .iter()
+        .map(|entry| match entry.as_array()? {
+            [at, took] => Some((
+                Timestamp::from_secs(at.as_u64()?),
+                Duration::from_secs(took.as_u64()?),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
 // Even with the module being public, the function is private unless specified
-pub fn add_to_waitlist() {}
+pub fn add_to_waitlist(waitlist: &mut Waitlist, party: Party) -> Result<PartyId, SeatingError> {
+    waitlist.add(party)
+}
+
+// Seat whoever is next and give them to the next server in the rotation. With
+// no server on the clock the table stays with whoever works its section.
+pub fn seat_at_table(
+    waitlist: &mut Waitlist,
+    floor: &mut FloorPlan,
+    roster: &mut Roster,
+    now: Timestamp,
+) -> Result<Seating, SeatingError> {
+    let mut seating = waitlist.seat_next(floor, now)?;
+    assign_server(roster, &mut seating, now);
+    Ok(seating)
+}
+
+pub fn assign_server(roster: &mut Roster, seating: &mut Seating, now: Timestamp) {
+    if let Some(server) = roster.assign_table(seating.party, now) {
+        seating.server = Some(server.name.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::minutes;
+    use crate::front_of_house::floor::Table;
+
+    fn small_floor() -> FloorPlan {
+        let mut floor = FloorPlan::new();
+        floor.add_table(Table::new(1, 2, "window")).unwrap();
+        floor.add_table(Table::new(2, 4, "middle")).unwrap();
+        floor
+    }
+
+    #[test]
+    fn reservations_go_first_then_arrival_order() {
+        let start = Timestamp::from_secs(1_000);
+        let mut waitlist = Waitlist::new();
+        let early = waitlist.add(Party::walk_in("Early", 2, start)).unwrap();
+        let late = waitlist
+            .add(Party::walk_in("Late", 2, start + minutes(5)))
+            .unwrap();
+        let booked = waitlist
+            .add(Party {
+                reservation: true,
+                ..Party::walk_in("Booked", 2, start + minutes(10))
+            })
+            .unwrap();
+        assert_eq!(
+            waitlist.add(Party::walk_in("Nobody", 0, start)),
+            Err(SeatingError::NoGuests)
+        );
+        let order: Vec<PartyId> = waitlist.queue().iter().map(|(id, _)| *id).collect();
+        assert_eq!(order, [booked, early, late]);
+    }
+
+    #[test]
+    fn a_big_party_at_the_head_lets_smaller_ones_sit() {
+        let start = Timestamp::from_secs(1_000);
+        let mut floor = small_floor();
+        let mut waitlist = Waitlist::new();
+        let big = waitlist.add(Party::walk_in("Big", 6, start)).unwrap();
+        let pair = waitlist
+            .add(Party::walk_in("Pair", 2, start + minutes(1)))
+            .unwrap();
+        let seating = waitlist.seat_next(&mut floor, start + minutes(11)).unwrap();
+        assert_eq!(seating.party, pair);
+        assert_eq!(seating.tables, [1]);
+        assert_eq!(seating.waited, minutes(10));
+        assert_eq!(
+            waitlist.seat_next(&mut floor, start + minutes(12)),
+            Err(SeatingError::NoTableFits {
+                party: big,
+                size: 6
+            })
+        );
+        assert_eq!(waitlist.waits(), [(start + minutes(11), minutes(10))]);
+    }
+
+    #[test]
+    fn waits_are_estimated_from_recent_turnovers() {
+        let start = Timestamp::from_secs(1_000);
+        let mut floor = small_floor();
+        let mut waitlist = Waitlist::new();
+        let first = waitlist.add(Party::walk_in("First", 4, start)).unwrap();
+        waitlist.seat_party(first, &mut floor, start).unwrap();
+        waitlist
+            .clear_table(&mut floor, 2, start + minutes(30))
+            .unwrap();
+        floor.mark_clean(2).unwrap();
+        assert_eq!(waitlist.average_turnover(), minutes(30));
+
+        let second = waitlist
+            .add(Party::walk_in("Second", 4, start + minutes(30)))
+            .unwrap();
+        waitlist
+            .seat_party(second, &mut floor, start + minutes(30))
+            .unwrap();
+        let third = waitlist
+            .add(Party::walk_in("Third", 3, start + minutes(40)))
+            .unwrap();
+        assert_eq!(
+            waitlist.estimate_wait(third, &floor, start + minutes(40)),
+            Some(minutes(20))
+        );
+        let huge = waitlist
+            .add(Party::walk_in("Huge", 9, start + minutes(40)))
+            .unwrap();
+        assert_eq!(
+            waitlist.estimate_wait(huge, &floor, start + minutes(40)),
+            None
+        );
+    }
 
-fn seat_at_table() {}
+    #[test]
+    fn no_shows_leave_the_queue_and_state_round_trips() {
+        let start = Timestamp::from_secs(1_000);
+        let mut waitlist = Waitlist::new();
+        let gone = waitlist.add(Party::walk_in("Gone", 2, start)).unwrap();
+        waitlist.add(Party::walk_in("Here", 3, start)).unwrap();
+        waitlist.mark_no_show(gone).unwrap();
+        assert_eq!(waitlist.len(), 1);
+        assert_eq!(waitlist.no_shows()[0].name, "Gone");
+        assert_eq!(
+            waitlist.mark_no_show(gone),
+            Err(SeatingError::UnknownParty(gone))
+        );
+        let restored = Waitlist::from_json(&waitlist.to_json()).unwrap();
+        assert_eq!(restored.to_json(), waitlist.to_json());
+    }
+}
@@ -0,0 +1,663 @@
+// The reservation book: parties booked for a time, each held a table (or a few
+// pushed together) for as long as a party that size usually stays. A booking is
+// only taken if some tables are free of other bookings for the whole of that
+// time, unless the overbooking policy allows a few extra guests on the chance
+// that someone doesn't turn up; those get a table when they arrive.
+// The book reads the time from its clock, so tests can use a ManualClock.
+
+use super::floor::{FloorError, FloorPlan, TableState};
+use super::hosting::{Party, PartyId, Seating, SeatingError, Waitlist};
+use crate::clock::{minutes, Clock, Timestamp};
+use crate::json::Json;
+use std::fmt;
+use std::time::Duration;
+
+// Tables are held on the floor this long before the booking
+const HOLD_AHEAD: Duration = Duration::from_secs(20 * 60);
+// How late a party can be before the booking counts as a no-show
+const GRACE: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ReservationId(pub u32);
+
+impl fmt::Display for ReservationId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "reservation #{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReservationStatus {
+    Booked,
+    Arrived(PartyId), // <- handed over to the waitlist or seated
+    Cancelled,
+    NoShow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reservation {
+    pub id: ReservationId,
+    pub name: String,
+    pub size: u32,
+    pub at: Timestamp,
+    pub contact: Option<String>,
+    pub tables: Vec<u32>, // <- empty when overbooked, a table is found on arrival
+    pub status: ReservationStatus,
+}
+
+impl Reservation {
+    pub fn is_booked(&self) -> bool {
+        self.status == ReservationStatus::Booked
+    }
+
+    pub fn to_json(&self) -> Json {
+        let json = Json::object()
+            .with("id", self.id.0)
+            .with("name", self.name.as_str())
+            .with("size", self.size)
+            .with("at", self.at.as_secs())
+            .with("contact", self.contact.clone())
+            .with("tables", self.tables.clone());
+        match self.status {
+            ReservationStatus::Booked => json.with("status", "booked"),
+            ReservationStatus::Arrived(party) => {
+                json.with("status", "arrived").with("party", party.0)
+            }
+            ReservationStatus::Cancelled => json.with("status", "cancelled"),
+            ReservationStatus::NoShow => json.with("status", "no_show"),
+        }
+    }
+
+    pub fn from_json(json: &Json) -> Option<Reservation> {
+        let status = match json.get("status")?.as_str()? {
+            "booked" => ReservationStatus::Booked,
+            "arrived" => ReservationStatus::Arrived(PartyId(json.get("party")?.as_u32()?)),
+            "cancelled" => ReservationStatus::Cancelled,
+            "no_show" => ReservationStatus::NoShow,
+            _ => return None,
+        };
+        Some(Reservation {
+            id: ReservationId(json.get("id")?.as_u32()?),
+            name: String::from(json.get("name")?.as_str()?),
+            size: json.get("size")?.as_u32().filter(|size| *size > 0)?,
+            at: Timestamp::from_secs(json.get("at")?.as_u64()?),
+            contact: match json.get("contact")? {
+                Json::Null => None,
+                contact => Some(String::from(contact.as_str()?)),
+            },
+            tables: json
+                .get("tables")?
+                .as_array()?
+                .iter()
+                .map(Json::as_u32)
+                .collect::<Option<Vec<_>>>()?,
+            status,
+        })
+    }
+}
+
+impl fmt::Display for Reservation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ({}) at {} {}",
+            self.id,
+            self.name,
+            self.size,
+            self.at.date(),
+            self.at
+        )?;
+        if self.tables.is_empty() {
+            write!(f, ", overbooked")
+        } else {
+            write!(f, ", tables {:?}", self.tables)
+        }
+    }
+}
+
+// How far past the tables we'll book, in guests at any one time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overbooking {
+    #[default]
+    Never,
+    Guests(u32),  // <- up to this many extra guests
+    Percent(u32), // <- of the seats in the house
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReservationError {
+    UnknownReservation(ReservationId),
+    NotBooked(ReservationId), // <- already arrived, cancelled or a no-show
+    NoGuests,                 // <- a booking for zero
+    InThePast(Timestamp),
+    TooLarge(u32), // <- no tables in the house can seat that many together
+    Full { at: Timestamp, size: u32 },
+    Seating(SeatingError),
+}
+
+impl fmt::Display for ReservationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReservationError::UnknownReservation(id) => write!(f, "there is no {}", id),
+            ReservationError::NotBooked(id) => write!(f, "{} is no longer booked", id),
+            ReservationError::NoGuests => write!(f, "a booking needs at least one guest"),
+            ReservationError::InThePast(at) => {
+                write!(f, "{} {} has already gone", at.date(), at)
+            }
+            ReservationError::TooLarge(size) => {
+                write!(f, "no tables can be put together for {} guests", size)
+            }
+            ReservationError::Full { at, size } => {
+                write!(f, "nothing free for {} at {} {}", size, at.date(), at)
+            }
+            ReservationError::Seating(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReservationError {}
+
+impl From<SeatingError> for ReservationError {
+    fn from(e: SeatingError) -> Self {
+        ReservationError::Seating(e)
+    }
+}
+
+impl From<FloorError> for ReservationError {
+    fn from(e: FloorError) -> Self {
+        ReservationError::Seating(SeatingError::Floor(e))
+    }
+}
+
+// What happened when a party with a booking walked in
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arrival {
+    Seated(Seating),
+    Waiting(PartyId), // <- on the waitlist, ahead of the walk-ins
+}
+
+#[derive(Debug, Clone)]
+pub struct ReservationBook<C: Clock> {
+    clock: C,
+    reservations: Vec<Reservation>,
+    next_id: u32,
+    pub overbooking: Overbooking,
+    dining_times: Vec<(u32, Duration)>, // <- (largest party, how long they stay), by size
+}
+
+impl<C: Clock> ReservationBook<C> {
+    pub fn new(clock: C) -> ReservationBook<C> {
+        ReservationBook {
+            clock,
+            reservations: Vec::new(),
+            next_id: 0,
+            overbooking: Overbooking::default(),
+            dining_times: vec![
+                (2, minutes(75)),
+                (4, minutes(90)),
+                (6, minutes(120)),
+                (u32::MAX, minutes(150)),
+            ],
+        }
+    }
+
+    pub fn overbooking(mut self, policy: Overbooking) -> ReservationBook<C> {
+        self.overbooking = policy;
+        self
+    }
+
+    // Parties of up to `size` stay for `time`
+    pub fn dining_time(mut self, size: u32, time: Duration) -> ReservationBook<C> {
+        match self.dining_times.binary_search_by_key(&size, |(s, _)| *s) {
+            Ok(index) => self.dining_times[index].1 = time,
+            Err(index) => self.dining_times.insert(index, (size, time)),
+        }
+        self
+    }
+
+    pub fn expected_stay(&self, size: u32) -> Duration {
+        self.dining_times
+            .iter()
+            .find(|(up_to, _)| size <= *up_to)
+            .map_or(minutes(90), |(_, time)| *time)
+    }
+
+    pub fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn get(&self, id: ReservationId) -> Option<&Reservation> {
+        self.reservations.iter().find(|r| r.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Reservation> {
+        self.reservations.iter()
+    }
+
+    // Bookings still to come (or running late), earliest first
+    pub fn upcoming(&self) -> Vec<&Reservation> {
+        let mut upcoming: Vec<&Reservation> =
+            self.reservations.iter().filter(|r| r.is_booked()).collect();
+        upcoming.sort_by_key(|r| (r.at, r.id));
+        upcoming
+    }
+
+    pub fn book(
+        &mut self,
+        floor: &FloorPlan,
+        name: &str,
+        size: u32,
+        at: Timestamp,
+    ) -> Result<ReservationId, ReservationError> {
+        let tables = self.find_tables(floor, None, size, at)?;
+        self.next_id += 1;
+        let id = ReservationId(self.next_id);
+        self.reservations.push(Reservation {
+            id,
+            name: String::from(name),
+            size,
+            at,
+            contact: None,
+            tables,
+            status: ReservationStatus::Booked,
+        });
+        Ok(id)
+    }
+
+    pub fn set_contact(
+        &mut self,
+        id: ReservationId,
+        contact: &str,
+    ) -> Result<(), ReservationError> {
+        self.booked_mut(id)?.contact = Some(String::from(contact));
+        Ok(())
+    }
+
+    // Move the booking and/or change the party size. The old booking stands if
+    // the new one doesn't fit.
+    pub fn modify(
+        &mut self,
+        floor: &mut FloorPlan,
+        id: ReservationId,
+        at: Timestamp,
+        size: u32,
+    ) -> Result<(), ReservationError> {
+        let old = self.booked_mut(id)?.clone();
+        let tables = self.find_tables(floor, Some(id), size, at)?;
+        Self::release_held(floor, &old);
+        let reservation = self.booked_mut(id)?;
+        reservation.at = at;
+        reservation.size = size;
+        reservation.tables = tables;
+        Ok(())
+    }
+
+    pub fn cancel(
+        &mut self,
+        floor: &mut FloorPlan,
+        id: ReservationId,
+    ) -> Result<(), ReservationError> {
+        let reservation = self.booked_mut(id)?;
+        reservation.status = ReservationStatus::Cancelled;
+        Self::release_held(floor, reservation);
+        Ok(())
+    }
+
+    // Mark the tables for bookings that are coming up soon as reserved, so the
+    // host doesn't give them to walk-ins. Returns the reservations held for.
+    pub fn hold_tables(&mut self, floor: &mut FloorPlan) -> Vec<ReservationId> {
+        let now = self.clock.now();
+        let mut held = Vec::new();
+        for reservation in self.reservations.iter().filter(|r| r.is_booked()) {
+            if reservation.tables.is_empty() || reservation.at > now + HOLD_AHEAD {
+                continue;
+            }
+            let all_free = reservation
+                .tables
+                .iter()
+                .all(|n| floor.table(*n).is_some_and(|t| t.is_free()));
+            if !all_free {
+                continue; // <- already held, or someone is still eating there
+            }
+            for number in &reservation.tables {
+                let _ = floor.reserve(*number, reservation.id, &reservation.name, reservation.at);
+            }
+            held.push(reservation.id);
+        }
+        held
+    }
+
+    // Bookings more than the grace period late are no-shows; their tables go back
+    pub fn mark_no_shows(&mut self, floor: &mut FloorPlan) -> Vec<ReservationId> {
+        let now = self.clock.now();
+        let mut missed = Vec::new();
+        for reservation in self.reservations.iter_mut().filter(|r| r.is_booked()) {
+            if reservation.at + GRACE >= now {
+                continue;
+            }
+            reservation.status = ReservationStatus::NoShow;
+            Self::release_held(floor, reservation);
+            missed.push(reservation.id);
+        }
+        missed
+    }
+
+    // The party is here: seat them at their tables if those are ready, otherwise
+    // anywhere that fits, otherwise they go to the front of the waitlist
+    pub fn arrive(
+        &mut self,
+        floor: &mut FloorPlan,
+        waitlist: &mut Waitlist,
+        id: ReservationId,
+    ) -> Result<Arrival, ReservationError> {
+        let now = self.clock.now();
+        let reservation = self.booked_mut(id)?.clone();
+        let party = Party {
+            name: reservation.name.clone(),
+            size: reservation.size,
+            contact: reservation.contact.clone(),
+            arrived: now,
+            reservation: true,
+        };
+        let party_id = waitlist.add(party.clone())?;
+        self.booked_mut(id)?.status = ReservationStatus::Arrived(party_id);
+
+        Self::release_held(floor, &reservation);
+        let ready = !reservation.tables.is_empty()
+            && reservation
+                .tables
+                .iter()
+                .all(|n| floor.table(*n).is_some_and(|t| t.is_free()));
+        if ready {
+            let seating = waitlist.seat_at_tables(party_id, floor, reservation.tables, now)?;
+            return Ok(Arrival::Seated(seating));
+        }
+        match waitlist.seat_party(party_id, floor, now) {
+            Ok(seating) => Ok(Arrival::Seated(seating)),
+            Err(SeatingError::NoTableFits { .. }) => Ok(Arrival::Waiting(party_id)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // The bookings; the overbooking policy and dining times are set up at opening
+    pub fn state_json(&self) -> Json {
+        let reservations: Vec<Json> = self.reservations.iter().map(Reservation::to_json).collect();
+        Json::object()
+            .with("reservations", reservations)
+            .with("next_id", self.next_id)
+    }
+
+    pub fn restore_state(&mut self, json: &Json) -> Option<()> {
+        let reservations = json
+            .get("reservations")?
+            .as_array()?
+            .iter()
+            .map(Reservation::from_json)
+            .collect::<Option<Vec<_>>>()?;
+        self.next_id = json.get("next_id")?.as_u32()?;
+        self.reservations = reservations;
+        Some(())
+    }
+
+    fn booked_mut(&mut self, id: ReservationId) -> Result<&mut Reservation, ReservationError> {
+        let reservation = self
+            .reservations
+            .iter_mut()
+            .find(|r| r.id == id)
+            .ok_or(ReservationError::UnknownReservation(id))?;
+        if !reservation.is_booked() {
+            return Err(ReservationError::NotBooked(id));
+        }
+        Ok(reservation)
+    }
+
+    // Tables free of every other booking that overlaps [at, at + stay), or no
+    // tables at all if the overbooking policy lets the party in anyway
+    fn find_tables(
+        &self,
+        floor: &FloorPlan,
+        ignoring: Option<ReservationId>,
+        size: u32,
+        at: Timestamp,
+    ) -> Result<Vec<u32>, ReservationError> {
+        if size == 0 {
+            return Err(ReservationError::NoGuests);
+        }
+        if at < self.clock.now() {
+            return Err(ReservationError::InThePast(at));
+        }
+        if floor.best_table_where(size, |_| true).is_none() {
+            return Err(ReservationError::TooLarge(size));
+        }
+        let until = at + self.expected_stay(size);
+        let overlapping: Vec<&Reservation> = self
+            .reservations
+            .iter()
+            .filter(|r| r.is_booked() && Some(r.id) != ignoring)
+            .filter(|r| r.at < until && at < r.at + self.expected_stay(r.size))
+            .collect();
+        let taken: Vec<u32> = overlapping
+            .iter()
+            .flat_map(|r| r.tables.iter().copied())
+            .collect();
+        if let Some(placement) = floor.best_table_where(size, |t| !taken.contains(&t.number)) {
+            return Ok(placement.tables);
+        }
+        let overbooked: u32 = overlapping
+            .iter()
+            .filter(|r| r.tables.is_empty())
+            .map(|r| r.size)
+            .sum();
+        let allowance = match self.overbooking {
+            Overbooking::Never => 0,
+            Overbooking::Guests(guests) => guests,
+            Overbooking::Percent(percent) => {
+                let seats: u32 = floor.tables().iter().map(|t| t.capacity).sum();
+                seats * percent / 100
+            }
+        };
+        if overbooked + size <= allowance {
+            Ok(Vec::new())
+        } else {
+            Err(ReservationError::Full { at, size })
+        }
+    }
+
+    // Give back any of the reservation's tables still being held for it
+    fn release_held(floor: &mut FloorPlan, reservation: &Reservation) {
+        for number in &reservation.tables {
+            let held = matches!(
+                floor.table(*number).map(|t| t.state()),
+                Some(TableState::Reserved { reservation: id, .. }) if *id == reservation.id
+            );
+            if held {
+                let _ = floor.release(*number);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::front_of_house::floor::Table;
+
+    fn at(hours: u64, mins: u64) -> Timestamp {
+        Timestamp::from_secs(hours * 3600) + minutes(mins)
+    }
+
+    // A two-top and a four-top that can be pushed together
+    fn floor() -> FloorPlan {
+        let mut floor = FloorPlan::new();
+        floor.add_table(Table::new(1, 2, "main")).unwrap();
+        floor.add_table(Table::new(2, 4, "main")).unwrap();
+        floor.join(1, 2).unwrap();
+        floor
+    }
+
+    #[test]
+    fn bookings_that_overlap_need_different_tables() {
+        let clock = ManualClock::new(at(12, 0));
+        let mut book = ReservationBook::new(&clock);
+        let floor = floor();
+        let first = book.book(&floor, "Ada", 2, at(19, 0)).unwrap();
+        assert_eq!(book.get(first).unwrap().tables, [1]);
+        let second = book.book(&floor, "Brian", 2, at(19, 30)).unwrap();
+        assert_eq!(book.get(second).unwrap().tables, [2]);
+        assert_eq!(
+            book.book(&floor, "Cleo", 2, at(19, 45)),
+            Err(ReservationError::Full {
+                at: at(19, 45),
+                size: 2
+            })
+        );
+        // Two-tops stay 75 minutes, so the first table is free again at 20:15
+        let third = book.book(&floor, "Cleo", 2, at(20, 15)).unwrap();
+        assert_eq!(book.get(third).unwrap().tables, [1]);
+
+        assert_eq!(
+            book.book(&floor, "Dev", 7, at(21, 0)),
+            Err(ReservationError::TooLarge(7))
+        );
+        assert_eq!(
+            book.book(&floor, "Eve", 2, at(11, 0)),
+            Err(ReservationError::InThePast(at(11, 0)))
+        );
+        assert_eq!(
+            book.book(&floor, "Fay", 0, at(21, 0)),
+            Err(ReservationError::NoGuests)
+        );
+        let names: Vec<&str> = book.upcoming().iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["Ada", "Brian", "Cleo"]);
+    }
+
+    #[test]
+    fn cancelling_and_moving_free_the_old_slot() {
+        let clock = ManualClock::new(at(12, 0));
+        let mut book = ReservationBook::new(&clock);
+        let mut floor = floor();
+        let big = book.book(&floor, "Ada", 6, at(19, 0)).unwrap();
+        assert_eq!(book.get(big).unwrap().tables, [1, 2]);
+        let late = book.book(&floor, "Brian", 2, at(21, 30)).unwrap();
+        // Moving onto Brian's table doesn't fit, so the booking stands as it was
+        assert!(book.modify(&mut floor, big, at(21, 0), 6).is_err());
+        assert_eq!(book.get(big).unwrap().at, at(19, 0));
+        book.modify(&mut floor, big, at(19, 30), 4).unwrap();
+        assert_eq!(book.get(big).unwrap().tables, [2]);
+        assert!(book.book(&floor, "Cleo", 2, at(19, 15)).is_ok());
+
+        book.cancel(&mut floor, late).unwrap();
+        assert_eq!(
+            book.cancel(&mut floor, late),
+            Err(ReservationError::NotBooked(late))
+        );
+        assert!(book.book(&floor, "Dev", 4, at(21, 30)).is_ok());
+        assert_eq!(
+            book.set_contact(ReservationId(99), "555-0100"),
+            Err(ReservationError::UnknownReservation(ReservationId(99)))
+        );
+    }
+
+    #[test]
+    fn overbooking_takes_a_few_extra_guests_without_tables() {
+        let clock = ManualClock::new(at(12, 0));
+        let mut book = ReservationBook::new(&clock).overbooking(Overbooking::Guests(3));
+        let floor = floor();
+        book.book(&floor, "Ada", 6, at(19, 0)).unwrap();
+        let extra = book.book(&floor, "Brian", 2, at(19, 0)).unwrap();
+        assert!(book.get(extra).unwrap().tables.is_empty());
+        assert!(book.book(&floor, "Cleo", 1, at(19, 30)).is_ok());
+        assert!(matches!(
+            book.book(&floor, "Dev", 1, at(19, 30)),
+            Err(ReservationError::Full { .. })
+        ));
+    }
+
+    #[test]
+    fn tables_are_held_ahead_and_released_for_no_shows() {
+        let clock = ManualClock::new(at(18, 0));
+        let mut book = ReservationBook::new(&clock);
+        let mut floor = floor();
+        let id = book.book(&floor, "Ada", 2, at(19, 0)).unwrap();
+        assert!(book.hold_tables(&mut floor).is_empty());
+
+        clock.set(at(18, 40));
+        assert_eq!(book.hold_tables(&mut floor), [id]);
+        assert!(matches!(
+            floor.table(1).unwrap().state(),
+            TableState::Reserved { name, .. } if name == "Ada"
+        ));
+        assert!(book.hold_tables(&mut floor).is_empty()); // <- already held
+
+        clock.set(at(19, 15));
+        assert!(book.mark_no_shows(&mut floor).is_empty());
+        clock.advance(minutes(1));
+        assert_eq!(book.mark_no_shows(&mut floor), [id]);
+        assert_eq!(book.get(id).unwrap().status, ReservationStatus::NoShow);
+        assert!(floor.table(1).unwrap().is_free());
+        assert!(book.upcoming().is_empty());
+    }
+
+    #[test]
+    fn a_hold_belongs_to_one_booking_not_a_name() {
+        let clock = ManualClock::new(at(18, 0));
+        let mut book = ReservationBook::new(&clock);
+        let mut floor = floor();
+        let first = book.book(&floor, "Ada", 2, at(19, 0)).unwrap();
+        let second = book.book(&floor, "Ada", 2, at(19, 0)).unwrap();
+        clock.set(at(18, 45));
+        assert_eq!(book.hold_tables(&mut floor), [first, second]);
+        book.cancel(&mut floor, first).unwrap();
+        assert!(floor.table(1).unwrap().is_free());
+        assert!(matches!(
+            floor.table(2).unwrap().state(),
+            TableState::Reserved { reservation, .. } if *reservation == second
+        ));
+    }
+
+    #[test]
+    fn arrivals_are_seated_at_their_tables_or_wait_up_front() {
+        let clock = ManualClock::new(at(18, 0));
+        let mut book = ReservationBook::new(&clock);
+        let mut floor = floor();
+        let mut waitlist = Waitlist::new();
+        let ada = book.book(&floor, "Ada", 2, at(19, 0)).unwrap();
+        let brian = book.book(&floor, "Brian", 4, at(19, 0)).unwrap();
+        clock.set(at(18, 45));
+        book.hold_tables(&mut floor);
+
+        clock.set(at(18, 55));
+        match book.arrive(&mut floor, &mut waitlist, ada).unwrap() {
+            Arrival::Seated(seating) => assert_eq!(seating.tables, [1]),
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            book.get(ada).unwrap().status,
+            ReservationStatus::Arrived(_)
+        ));
+        assert_eq!(
+            book.arrive(&mut floor, &mut waitlist, ada),
+            Err(ReservationError::NotBooked(ada))
+        );
+
+        // Someone is still at Brian's table, and nothing else fits four
+        floor.release(2).unwrap();
+        let walk_in = Party::walk_in("Walk-in", 3, clock.now());
+        let walk_in = waitlist.add(walk_in).unwrap();
+        waitlist
+            .seat_at_tables(walk_in, &mut floor, vec![2], clock.now())
+            .unwrap();
+        match book.arrive(&mut floor, &mut waitlist, brian).unwrap() {
+            Arrival::Waiting(party) => {
+                assert_eq!(waitlist.queue()[0].0, party);
+                assert!(waitlist.get(party).unwrap().reservation);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
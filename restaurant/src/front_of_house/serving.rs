@@ -0,0 +1,189 @@
+// This is a module file within the front_of_house module and directory
+// Servers take orders from seated tables, run food from the pass and take payment
+
+use crate::billing::{self, BillingError, Payment, Receipt};
+use crate::clock::Timestamp;
+use crate::dietary::{Conflict, DietChecker, DietaryError, GuestProfile, OnConflict};
+use crate::money::Cents;
+use crate::order::{OrderBook, OrderError, OrderId, OrderItem};
+
+pub fn take_order<I>(
+    orders: &mut OrderBook,
+    table: u32,
+    items: impl IntoIterator<Item = I>,
+    now: Timestamp,
+) -> Result<OrderId, OrderError>
+where
+    I: Into<OrderItem>,
+{
+    let items = items.into_iter().map(Into::into).collect();
+    orders.place(table, items, now)
+}
+
+// Take an order for guests who told the server what they can't eat. Anything
+// that clashes either sends the order back with suggestions (Reject) or goes
+// in anyway and comes back as flags to read out to the guest (Flag).
+pub fn take_checked_order(
+    orders: &mut OrderBook,
+    checker: &DietChecker,
+    table: u32,
+    items: Vec<OrderItem>,
+    guests: Vec<GuestProfile>,
+    on_conflict: OnConflict,
+    now: Timestamp,
+) -> Result<(OrderId, Vec<Conflict>), DietaryError> {
+    let conflicts = checker.check(&items, &guests);
+    if on_conflict == OnConflict::Reject && !conflicts.is_empty() {
+        return Err(DietaryError::Conflicts(conflicts));
+    }
+    let id = orders.place(table, items, now)?;
+    orders.note_guests(id, guests)?;
+    Ok((id, conflicts))
+}
+
+// Only food that is up at the pass can go out
+pub fn serve_order(orders: &mut OrderBook, id: OrderId, now: Timestamp) -> Result<(), OrderError> {
+    orders.serve(id, now)
+}
+
+// Settle the order's whole receipt and close the order, keeping what each item
+// was charged. A part of a split check, or a check that also covers other
+// orders, can't close it. Returns the change to hand back.
+pub fn take_payment(
+    orders: &mut OrderBook,
+    id: OrderId,
+    receipt: &Receipt,
+    payments: Vec<Payment>,
+    now: Timestamp,
+) -> Result<Cents, BillingError> {
+    if !receipt.orders.contains(&id) {
+        return Err(BillingError::WrongOrder(id));
+    }
+    if receipt.is_split() || receipt.orders.len() > 1 {
+        return Err(BillingError::PartialReceipt(id));
+    }
+    let change = billing::settle(receipt, &payments)?;
+    orders.pay(id, payments, receipt.charges(id), now)?;
+    Ok(change)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::{BillingConfig, Check, SplitBy};
+    use crate::menu::Menu;
+    use crate::order::OrderStatus;
+
+    fn served(orders: &mut OrderBook, table: u32, items: &[&str]) -> OrderId {
+        let now = Timestamp::from_secs(0);
+        let id = take_order(
+            orders,
+            table,
+            items.iter().copied().map(OrderItem::new),
+            now,
+        )
+        .unwrap();
+        orders.start_cooking(id, now).unwrap();
+        orders.mark_ready(id, now).unwrap();
+        serve_order(orders, id, now).unwrap();
+        id
+    }
+
+    #[test]
+    fn payment_closes_the_order_the_receipt_is_for() {
+        let menu = Menu::house();
+        let mut orders = OrderBook::new();
+        let soup = served(&mut orders, 1, &["soup"]);
+        let fries = served(&mut orders, 2, &["fries", "coffee"]);
+        let receipt = Check::from_orders(&[orders.get(soup).unwrap()], &menu, 1)
+            .unwrap()
+            .receipt(&BillingConfig::default())
+            .unwrap();
+        let total = receipt.total();
+        let now = Timestamp::from_secs(60);
+
+        let wrong = take_payment(
+            &mut orders,
+            fries,
+            &receipt,
+            vec![Payment::card(total)],
+            now,
+        );
+        assert!(matches!(wrong, Err(BillingError::WrongOrder(id)) if id == fries));
+        assert_eq!(orders.status(fries), Some(OrderStatus::Served));
+
+        let short = take_payment(
+            &mut orders,
+            soup,
+            &receipt,
+            vec![Payment::card(Cents(1))],
+            now,
+        );
+        assert!(matches!(short, Err(BillingError::Underpaid { .. })));
+        assert_eq!(orders.status(soup), Some(OrderStatus::Served));
+
+        let change = take_payment(
+            &mut orders,
+            soup,
+            &receipt,
+            vec![Payment::cash(total + Cents(25))],
+            now,
+        );
+        assert_eq!(change.unwrap(), Cents(25));
+        assert_eq!(orders.status(soup), Some(OrderStatus::Paid));
+        assert!(serve_order(&mut orders, soup, now).is_err());
+    }
+
+    #[test]
+    fn part_of_a_check_cant_close_the_order() {
+        let menu = Menu::house();
+        let mut orders = OrderBook::new();
+        let mut items = vec![OrderItem::new("coffee").for_seat(1)];
+        items.push(OrderItem::new("fries").for_seat(2));
+        let id = take_order(&mut orders, 1, items, Timestamp::from_secs(0)).unwrap();
+        orders.start_cooking(id, Timestamp::from_secs(0)).unwrap();
+        orders.mark_ready(id, Timestamp::from_secs(0)).unwrap();
+        serve_order(&mut orders, id, Timestamp::from_secs(0)).unwrap();
+        let other = served(&mut orders, 2, &["soup"]);
+        let now = Timestamp::from_secs(60);
+
+        let receipt = Check::from_orders(&[orders.get(id).unwrap()], &menu, 2)
+            .unwrap()
+            .receipt(&BillingConfig::default())
+            .unwrap();
+        let seat = receipt.split(&SplitBy::Seat).unwrap().remove(0);
+        let paid = take_payment(
+            &mut orders,
+            id,
+            &seat,
+            vec![Payment::card(seat.total())],
+            now,
+        );
+        assert!(matches!(paid, Err(BillingError::PartialReceipt(_))));
+
+        let both = [orders.get(id).unwrap(), orders.get(other).unwrap()];
+        let both = Check::from_orders(&both, &menu, 2)
+            .unwrap()
+            .receipt(&BillingConfig::default())
+            .unwrap();
+        let paid = take_payment(
+            &mut orders,
+            id,
+            &both,
+            vec![Payment::card(both.total())],
+            now,
+        );
+        assert!(matches!(paid, Err(BillingError::PartialReceipt(_))));
+        assert_eq!(orders.status(id), Some(OrderStatus::Served));
+
+        take_payment(
+            &mut orders,
+            id,
+            &receipt,
+            vec![Payment::card(receipt.total())],
+            now,
+        )
+        .unwrap();
+        assert_eq!(orders.get(id).unwrap().charges().len(), 2);
+    }
+}
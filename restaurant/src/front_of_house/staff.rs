@@ -0,0 +1,556 @@
+// Who is working: the people on the books and their roles, the shifts they are
+// scheduled for, and when they actually clocked in and out. Servers on the
+// clock take tables in turn (whoever has gone longest without one is next), and
+// at close the tip pool is shared out by hours worked, weighted by role.
+
+use super::hosting::PartyId;
+use crate::clock::Timestamp;
+use crate::json::Json;
+use crate::money::{allocate, Cents};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Role {
+    Host,
+    Server,
+    Cook,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Host, Role::Server, Role::Cook];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Host => "host",
+            Role::Server => "server",
+            Role::Cook => "cook",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|r| r.name() == name)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StaffId(pub u32);
+
+impl fmt::Display for StaffId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "staff #{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Staff {
+    pub id: StaffId,
+    pub name: String, // <- what the floor plan calls them when assigning sections
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shift {
+    pub staff: StaffId,
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+impl Shift {
+    pub fn covers(&self, at: Timestamp) -> bool {
+        at >= self.start && at < self.end
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeCard {
+    pub staff: StaffId,
+    pub clocked_in: Timestamp,
+    pub clocked_out: Option<Timestamp>, // <- None while still on the clock
+}
+
+impl TimeCard {
+    // Time worked, counting an open card up to `now`
+    pub fn worked(&self, now: Timestamp) -> Duration {
+        self.clocked_out.unwrap_or(now).since(self.clocked_in)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StaffError {
+    UnknownStaff(StaffId),
+    AlreadyClockedIn(StaffId),
+    NotClockedIn(StaffId),
+    EmptyShift { start: Timestamp, end: Timestamp },
+    OverlappingShift(StaffId), // <- already scheduled for some of that time
+}
+
+impl fmt::Display for StaffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StaffError::UnknownStaff(id) => write!(f, "there is no {}", id),
+            StaffError::AlreadyClockedIn(id) => write!(f, "{} is already clocked in", id),
+            StaffError::NotClockedIn(id) => write!(f, "{} is not clocked in", id),
+            StaffError::EmptyShift { start, end } => {
+                write!(f, "a shift from {} to {} is no time at all", start, end)
+            }
+            StaffError::OverlappingShift(id) => {
+                write!(f, "{} is already scheduled for part of that shift", id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StaffError {}
+
+// Points per hour each role earns from the tip pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TipPool {
+    pub points: Vec<(Role, u32)>,
+}
+
+impl TipPool {
+    pub fn points_for(&self, role: Role) -> u32 {
+        self.points
+            .iter()
+            .find(|(r, _)| *r == role)
+            .map_or(0, |(_, points)| *points)
+    }
+}
+
+impl Default for TipPool {
+    fn default() -> Self {
+        TipPool {
+            points: vec![(Role::Server, 10), (Role::Host, 5), (Role::Cook, 3)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TipShare {
+    pub staff: StaffId,
+    pub worked: Duration,
+    pub amount: Cents,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Roster {
+    staff: BTreeMap<StaffId, Staff>,
+    next_id: u32,
+    shifts: Vec<Shift>,
+    cards: Vec<TimeCard>,
+    assignments: Vec<(StaffId, PartyId, Timestamp)>, // <- every table a server was given
+}
+
+impl Roster {
+    pub fn new() -> Roster {
+        Roster::default()
+    }
+
+    // The servers match the sections in FloorPlan::house()
+    pub fn house() -> Roster {
+        let mut roster = Roster::new();
+        for (name, role) in [
+            ("Alice", Role::Server),
+            ("Bob", Role::Server),
+            ("Carol", Role::Server),
+            ("Dana", Role::Host),
+            ("Eli", Role::Cook),
+            ("Fran", Role::Cook),
+        ] {
+            roster.add_staff(name, role);
+        }
+        roster
+    }
+
+    pub fn add_staff(&mut self, name: &str, role: Role) -> StaffId {
+        self.next_id += 1;
+        let id = StaffId(self.next_id);
+        self.staff.insert(
+            id,
+            Staff {
+                id,
+                name: String::from(name),
+                role,
+            },
+        );
+        id
+    }
+
+    pub fn get(&self, id: StaffId) -> Option<&Staff> {
+        self.staff.get(&id)
+    }
+
+    pub fn staff(&self) -> impl Iterator<Item = &Staff> {
+        self.staff.values()
+    }
+
+    pub fn schedule(
+        &mut self,
+        staff: StaffId,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<(), StaffError> {
+        self.get(staff).ok_or(StaffError::UnknownStaff(staff))?;
+        if end <= start {
+            return Err(StaffError::EmptyShift { start, end });
+        }
+        let overlaps = self
+            .shifts
+            .iter()
+            .any(|s| s.staff == staff && s.start < end && start < s.end);
+        if overlaps {
+            return Err(StaffError::OverlappingShift(staff));
+        }
+        self.shifts.push(Shift { staff, start, end });
+        self.shifts.sort_by_key(|s| (s.start, s.staff));
+        Ok(())
+    }
+
+    pub fn shifts(&self) -> &[Shift] {
+        &self.shifts
+    }
+
+    // Scheduled to be working at `now` but not on the clock
+    pub fn missing(&self, now: Timestamp) -> Vec<StaffId> {
+        let mut missing: Vec<StaffId> = self
+            .shifts
+            .iter()
+            .filter(|s| s.covers(now) && !self.is_clocked_in(s.staff))
+            .map(|s| s.staff)
+            .collect();
+        missing.dedup();
+        missing
+    }
+
+    pub fn clock_in(&mut self, staff: StaffId, now: Timestamp) -> Result<(), StaffError> {
+        self.get(staff).ok_or(StaffError::UnknownStaff(staff))?;
+        if self.is_clocked_in(staff) {
+            return Err(StaffError::AlreadyClockedIn(staff));
+        }
+        self.cards.push(TimeCard {
+            staff,
+            clocked_in: now,
+            clocked_out: None,
+        });
+        Ok(())
+    }
+
+    pub fn clock_out(&mut self, staff: StaffId, now: Timestamp) -> Result<(), StaffError> {
+        self.get(staff).ok_or(StaffError::UnknownStaff(staff))?;
+        let card = self
+            .cards
+            .iter_mut()
+            .find(|c| c.staff == staff && c.clocked_out.is_none())
+            .ok_or(StaffError::NotClockedIn(staff))?;
+        card.clocked_out = Some(now.max(card.clocked_in));
+        Ok(())
+    }
+
+    pub fn is_clocked_in(&self, staff: StaffId) -> bool {
+        self.cards
+            .iter()
+            .any(|c| c.staff == staff && c.clocked_out.is_none())
+    }
+
+    pub fn time_cards(&self) -> &[TimeCard] {
+        &self.cards
+    }
+
+    pub fn on_the_clock(&self, role: Role) -> Vec<&Staff> {
+        self.staff
+            .values()
+            .filter(|s| s.role == role && self.is_clocked_in(s.id))
+            .collect()
+    }
+
+    pub fn worked(&self, staff: StaffId, now: Timestamp) -> Duration {
+        self.cards
+            .iter()
+            .filter(|c| c.staff == staff)
+            .map(|c| c.worked(now))
+            .sum()
+    }
+
+    // The server on the clock who has gone longest without a table; someone
+    // who just clocked in and has had none goes first. Ties go by who clocked
+    // in earliest.
+    pub fn next_server(&self) -> Option<&Staff> {
+        self.on_the_clock(Role::Server).into_iter().min_by_key(|s| {
+            let last_table = self
+                .assignments
+                .iter()
+                .rev()
+                .position(|(server, _, _)| *server == s.id)
+                .map(|from_end| self.assignments.len() - from_end);
+            let clocked_in = self
+                .cards
+                .iter()
+                .rev()
+                .find(|c| c.staff == s.id)
+                .map(|c| c.clocked_in);
+            (last_table, clocked_in, s.id)
+        })
+    }
+
+    // Give the party to whoever is next in the rotation
+    pub fn assign_table(&mut self, party: PartyId, now: Timestamp) -> Option<&Staff> {
+        let server = self.next_server()?.id;
+        self.record_assignment(server, party, now);
+        self.get(server)
+    }
+
+    pub fn record_assignment(&mut self, server: StaffId, party: PartyId, now: Timestamp) {
+        self.assignments.push((server, party, now));
+    }
+
+    pub fn server_for_party(&self, party: PartyId) -> Option<&Staff> {
+        let (server, _, _) = self
+            .assignments
+            .iter()
+            .rev()
+            .find(|(_, p, _)| *p == party)?;
+        self.get(*server)
+    }
+
+    pub fn tables_served(&self, server: StaffId) -> usize {
+        self.assignments
+            .iter()
+            .filter(|(s, _, _)| *s == server)
+            .count()
+    }
+
+    // Share `total` out by hours worked times the role's points, in whole
+    // cents that always add up to the total
+    pub fn pool_tips(&self, total: Cents, pool: &TipPool, now: Timestamp) -> Vec<TipShare> {
+        let weighed: Vec<(StaffId, Duration, i64)> = self
+            .staff
+            .values()
+            .map(|s| {
+                let worked = self.worked(s.id, now);
+                let secs = i64::try_from(worked.as_secs()).unwrap_or(i64::MAX);
+                let weight = secs.saturating_mul(pool.points_for(s.role) as i64);
+                (s.id, worked, weight)
+            })
+            .filter(|(_, _, weight)| *weight > 0)
+            .collect();
+        if weighed.is_empty() || total.0 <= 0 {
+            return Vec::new();
+        }
+        let weights: Vec<i64> = weighed.iter().map(|(_, _, w)| *w).collect();
+        weighed
+            .into_iter()
+            .zip(allocate(total, &weights))
+            .map(|((staff, worked, _), amount)| TipShare {
+                staff,
+                worked,
+                amount,
+            })
+            .collect()
+    }
+
+    // Time cards and table assignments, for saving with the rest of service.
+    // Staff and shifts are set up ahead of time, like the floor plan.
+    pub fn state_json(&self) -> Json {
+        let cards: Vec<Json> = self
+            .cards
+            .iter()
+            .map(|c| {
+                Json::object()
+                    .with("staff", c.staff.0)
+                    .with("in", c.clocked_in.as_secs())
+                    .with("out", c.clocked_out.map(|t| t.as_secs()))
+            })
+            .collect();
+        let assignments: Vec<Json> = self
+            .assignments
+            .iter()
+            .map(|(server, party, at)| {
+                Json::object()
+                    .with("server", server.0)
+                    .with("party", party.0)
+                    .with("at", at.as_secs())
+            })
+            .collect();
+        Json::object()
+            .with("cards", cards)
+            .with("assignments", assignments)
+    }
+
+    pub fn restore_state(&mut self, json: &Json) -> Option<()> {
+        let cards = json
+            .get("cards")?
+            .as_array()?
+            .iter()
+            .map(|c| {
+                let clocked_out = match c.get("out")? {
+                    Json::Null => None,
+                    out => Some(Timestamp::from_secs(out.as_u64()?)),
+                };
+                Some(TimeCard {
+                    staff: StaffId(c.get("staff")?.as_u32()?),
+                    clocked_in: Timestamp::from_secs(c.get("in")?.as_u64()?),
+                    clocked_out,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let assignments = json
+            .get("assignments")?
+            .as_array()?
+            .iter()
+            .map(|a| {
+                Some((
+                    StaffId(a.get("server")?.as_u32()?),
+                    PartyId(a.get("party")?.as_u32()?),
+                    Timestamp::from_secs(a.get("at")?.as_u64()?),
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        self.cards = cards;
+        self.assignments = assignments;
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::minutes;
+
+    fn at(hours: u64, mins: u64) -> Timestamp {
+        Timestamp::from_secs(hours * 3600) + minutes(mins)
+    }
+
+    // Alice, Bob and Carol serve; Dana hosts; Eli and Fran cook
+    const ALICE: StaffId = StaffId(1);
+    const BOB: StaffId = StaffId(2);
+    const CAROL: StaffId = StaffId(3);
+    const DANA: StaffId = StaffId(4);
+    const ELI: StaffId = StaffId(5);
+
+    #[test]
+    fn clocking_in_and_out() {
+        let mut roster = Roster::house();
+        assert_eq!(
+            roster.clock_out(ALICE, at(17, 0)),
+            Err(StaffError::NotClockedIn(ALICE))
+        );
+        roster.clock_in(ALICE, at(17, 0)).unwrap();
+        assert_eq!(
+            roster.clock_in(ALICE, at(17, 5)),
+            Err(StaffError::AlreadyClockedIn(ALICE))
+        );
+        assert_eq!(roster.worked(ALICE, at(18, 30)), minutes(90)); // <- still on the clock
+        roster.clock_out(ALICE, at(21, 0)).unwrap();
+        roster.clock_in(ALICE, at(22, 0)).unwrap();
+        roster.clock_out(ALICE, at(21, 0)).unwrap(); // <- a clock running backwards counts nothing
+        assert_eq!(roster.worked(ALICE, at(23, 0)), minutes(240));
+        assert_eq!(roster.time_cards().len(), 2);
+        assert!(!roster.is_clocked_in(ALICE));
+        assert_eq!(
+            roster.clock_in(StaffId(99), at(17, 0)),
+            Err(StaffError::UnknownStaff(StaffId(99)))
+        );
+    }
+
+    #[test]
+    fn shifts_cant_be_empty_or_overlap() {
+        let mut roster = Roster::house();
+        roster.schedule(BOB, at(16, 0), at(22, 0)).unwrap();
+        roster.schedule(DANA, at(17, 0), at(21, 0)).unwrap();
+        assert_eq!(
+            roster.schedule(BOB, at(21, 0), at(23, 0)),
+            Err(StaffError::OverlappingShift(BOB))
+        );
+        roster.schedule(BOB, at(22, 0), at(23, 0)).unwrap(); // <- back to back is fine
+        assert_eq!(
+            roster.schedule(ELI, at(18, 0), at(18, 0)),
+            Err(StaffError::EmptyShift {
+                start: at(18, 0),
+                end: at(18, 0)
+            })
+        );
+        assert_eq!(roster.missing(at(16, 30)), [BOB]);
+        roster.clock_in(BOB, at(16, 30)).unwrap();
+        assert_eq!(roster.missing(at(17, 0)), [DANA]);
+        assert!(roster.missing(at(23, 30)).is_empty());
+    }
+
+    #[test]
+    fn tables_go_round_the_servers_on_the_clock() {
+        let mut roster = Roster::house();
+        assert!(roster.assign_table(PartyId(1), at(17, 0)).is_none());
+        roster.clock_in(BOB, at(16, 0)).unwrap();
+        roster.clock_in(ALICE, at(16, 30)).unwrap();
+        roster.clock_in(DANA, at(16, 0)).unwrap(); // <- hosts don't take tables
+
+        let mut given = Vec::new();
+        for party in 1..=4 {
+            given.push(roster.assign_table(PartyId(party), at(17, 0)).unwrap().id);
+        }
+        assert_eq!(given, [BOB, ALICE, BOB, ALICE]); // <- Bob clocked in first
+                                                     // Carol comes in late and has had no tables, so she is next
+        roster.clock_in(CAROL, at(18, 0)).unwrap();
+        assert_eq!(roster.next_server().map(|s| s.id), Some(CAROL));
+        roster.clock_out(BOB, at(18, 0)).unwrap();
+        assert_eq!(roster.server_for_party(PartyId(3)).map(|s| s.id), Some(BOB));
+        assert_eq!(roster.tables_served(ALICE), 2);
+    }
+
+    #[test]
+    fn tips_are_pooled_by_hours_and_points() {
+        let mut roster = Roster::house();
+        assert!(roster
+            .pool_tips(Cents(1000), &TipPool::default(), at(22, 0))
+            .is_empty());
+        roster.clock_in(ALICE, at(17, 0)).unwrap();
+        roster.clock_out(ALICE, at(21, 0)).unwrap(); // <- 4 hours at 10 points
+        roster.clock_in(ELI, at(17, 0)).unwrap();
+        roster.clock_out(ELI, at(19, 0)).unwrap(); // <- 2 hours at 3 points
+        roster.clock_in(DANA, at(17, 0)).unwrap();
+        roster.clock_out(DANA, at(18, 0)).unwrap(); // <- 1 hour at 5 points
+
+        let shares = roster.pool_tips(Cents(10001), &TipPool::default(), at(22, 0));
+        let amounts: Vec<(StaffId, Cents)> = shares.iter().map(|s| (s.staff, s.amount)).collect();
+        // 40 + 5 + 6 points: the odd cents go to the largest remainders
+        assert_eq!(
+            amounts,
+            [(ALICE, Cents(7844)), (DANA, Cents(980)), (ELI, Cents(1177))]
+        );
+        assert_eq!(shares.iter().map(|s| s.amount).sum::<Cents>(), Cents(10001));
+        assert_eq!(shares[0].worked, minutes(240));
+
+        let servers_only = TipPool {
+            points: vec![(Role::Server, 1)],
+        };
+        let shares = roster.pool_tips(Cents(500), &servers_only, at(22, 0));
+        assert_eq!(shares.len(), 1);
+        assert_eq!(shares[0].amount, Cents(500));
+    }
+
+    #[test]
+    fn state_survives_a_round_trip() {
+        let mut roster = Roster::house();
+        roster.clock_in(ALICE, at(17, 0)).unwrap();
+        roster.clock_in(BOB, at(17, 0)).unwrap();
+        roster.clock_out(BOB, at(18, 0)).unwrap();
+        roster.assign_table(PartyId(7), at(17, 30));
+
+        let mut restored = Roster::house();
+        restored.restore_state(&roster.state_json()).unwrap();
+        assert_eq!(restored.time_cards(), roster.time_cards());
+        assert_eq!(
+            restored.server_for_party(PartyId(7)).map(|s| s.id),
+            Some(ALICE)
+        );
+        assert!(restored.is_clocked_in(ALICE));
+        assert!(Roster::house().restore_state(&Json::object()).is_none());
+    }
+}
@@ -0,0 +1,553 @@
+// Just enough HTTP/1.1 for a JSON API on the local network: read one request
+// per connection, answer it, close. No keep-alive, no chunked bodies, no TLS;
+// anything fancier belongs in a proper web server in front of this one.
+
+use crate::json::Json;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Bigger bodies than this are refused rather than read into memory
+const MAX_BODY: usize = 1 << 20;
+const MAX_HEADERS: usize = 100;
+// The whole request has to arrive within this, however it is trickled in
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+// Connections read at once; past this new ones are turned away straight off
+const MAX_CONNECTIONS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+    Other(String),
+}
+
+impl Method {
+    pub fn parse(text: &str) -> Method {
+        match text {
+            "GET" => Method::Get,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            other => Method::Other(String::from(other)),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Other(name) => name,
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: Method,
+    pub path: String,                 // <- as sent, without the query string
+    pub query: Vec<(String, String)>, // <- decoded, in the order given
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum HttpError {
+    Io(io::Error),
+    BadRequest(String),
+    TooLarge(usize),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpError::Io(e) => write!(f, "{}", e),
+            HttpError::BadRequest(message) => write!(f, "bad request: {}", message),
+            HttpError::TooLarge(size) => {
+                write!(
+                    f,
+                    "a {} byte body is more than the {} allowed",
+                    size, MAX_BODY
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<io::Error> for HttpError {
+    fn from(e: io::Error) -> Self {
+        HttpError::Io(e)
+    }
+}
+
+impl Request {
+    // A request with no headers or body, handy for calling a handler directly
+    pub fn new(method: Method, target: &str) -> Request {
+        let (path, query) = split_target(target);
+        Request {
+            method,
+            path,
+            query,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn with_body(mut self, body: &str) -> Request {
+        self.body = String::from(body);
+        self
+    }
+
+    pub fn read(reader: &mut impl BufRead) -> Result<Request, HttpError> {
+        let line = read_line(reader)?;
+        let mut parts = line.split(' ');
+        let (Some(method), Some(target), Some(version)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(HttpError::BadRequest(format!(
+                "malformed request line '{}'",
+                line
+            )));
+        };
+        if !version.starts_with("HTTP/1.") {
+            return Err(HttpError::BadRequest(format!(
+                "unsupported version {}",
+                version
+            )));
+        }
+        let mut request = Request::new(Method::parse(method), target);
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            if request.headers.len() == MAX_HEADERS {
+                return Err(HttpError::BadRequest(String::from("too many headers")));
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| HttpError::BadRequest(format!("malformed header '{}'", line)))?;
+            request
+                .headers
+                .push((name.trim().to_ascii_lowercase(), String::from(value.trim())));
+        }
+        let length = match request.header("content-length") {
+            Some(value) => value
+                .parse::<usize>()
+                .map_err(|_| HttpError::BadRequest(format!("bad content-length '{}'", value)))?,
+            None => 0,
+        };
+        if length > MAX_BODY {
+            return Err(HttpError::TooLarge(length));
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        request.body = String::from_utf8(body)
+            .map_err(|_| HttpError::BadRequest(String::from("the body is not UTF-8")))?;
+        Ok(request)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn query(&self, key: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // "/orders/3/serve" -> ["orders", "3", "serve"]. Split before decoding, so
+    // an escaped "%2F" stays inside its segment.
+    pub fn segments(&self) -> Vec<String> {
+        self.path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| percent_decode(s, false))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, body: &Json) -> Response {
+        Response {
+            status,
+            body: body.to_string(),
+        }
+    }
+
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(
+            out,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            self.status,
+            reason(self.status),
+            self.body.len(),
+            self.body
+        )?;
+        out.flush()
+    }
+}
+
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+// The same error body the API uses, for requests that never got that far
+pub fn error_response(status: u16, code: &str, message: &str) -> Response {
+    let error = Json::object()
+        .with("status", status as u32)
+        .with("code", code)
+        .with("message", message);
+    Response::json(status, &Json::object().with("error", error))
+}
+
+// Answer connections forever, each on its own thread so a slow client can't
+// hold up the rest. Reading and writing happen in parallel; the handler itself
+// runs behind a lock, one request at a time, so it can keep `&mut` state. A
+// handler that panics answers 500 and the server carries on.
+pub fn serve(
+    listener: TcpListener,
+    handler: impl FnMut(&Request) -> Response + Send,
+) -> io::Result<()> {
+    let handler = Mutex::new(handler);
+    let open = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("accept failed: {}", e);
+                    continue;
+                }
+            };
+            if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                open.fetch_sub(1, Ordering::SeqCst);
+                let busy = error_response(503, "busy", "too many connections, try again");
+                let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+                let _ = busy.write_to(&mut &stream);
+                continue;
+            }
+            let slot = Slot(&open);
+            let handler = &handler;
+            scope.spawn(move || {
+                let _slot = slot;
+                let mut locked = |request: &Request| {
+                    let mut handler = handler.lock().unwrap_or_else(PoisonError::into_inner);
+                    panic::catch_unwind(AssertUnwindSafe(|| handler(request))).unwrap_or_else(
+                        |_| error_response(500, "internal", "the request could not be handled"),
+                    )
+                };
+                if let Err(e) = handle_connection(stream, &mut locked) {
+                    eprintln!("connection failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+// A connection's place under MAX_CONNECTIONS, given back however its thread ends
+struct Slot<'a>(&'a AtomicUsize);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn handle_connection(
+    stream: TcpStream,
+    handler: &mut impl FnMut(&Request) -> Response,
+) -> io::Result<()> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let mut reader = BufReader::new(Deadline::new(stream.try_clone()?, REQUEST_TIMEOUT));
+    let response = match Request::read(&mut reader) {
+        Ok(request) => handler(&request),
+        Err(HttpError::Io(e)) => return Err(e),
+        Err(e @ HttpError::TooLarge(_)) => error_response(413, "too_large", &e.to_string()),
+        Err(e) => error_response(400, "bad_request", &e.to_string()),
+    };
+    let mut stream = stream;
+    response.write_to(&mut stream)
+}
+
+// Reads from a stream until a fixed moment, rather than allowing each read its
+// own timeout: a client sending a byte every few seconds still runs out of time
+struct Deadline {
+    stream: TcpStream,
+    until: Instant,
+}
+
+impl Deadline {
+    fn new(stream: TcpStream, timeout: Duration) -> Deadline {
+        Deadline {
+            stream,
+            until: Instant::now() + timeout,
+        }
+    }
+}
+
+impl Read for Deadline {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let late = || {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the request took too long to arrive",
+            )
+        };
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(late());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        match self.stream.read(buf) {
+            // Unix reports a read that timed out as WouldBlock
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(late()),
+            read => read,
+        }
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String, HttpError> {
+    let mut line = String::new();
+    let read = reader.by_ref().take(8192).read_line(&mut line)?;
+    if read == 0 {
+        return Err(HttpError::BadRequest(String::from(
+            "connection closed mid-request",
+        )));
+    }
+    if !line.ends_with('\n') {
+        return Err(HttpError::BadRequest(String::from("line too long")));
+    }
+    Ok(String::from(line.trim_end_matches(['\r', '\n'])))
+}
+
+fn split_target(target: &str) -> (String, Vec<(String, String)>) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key, true), percent_decode(value, true))
+        })
+        .collect();
+    (String::from(path), query)
+}
+
+// %XX escapes, and '+' for space in query strings; anything malformed is left
+// as it was
+fn percent_decode(text: &str, plus_is_space: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' if plus_is_space => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::time::Instant;
+
+    fn parse(raw: &str) -> Result<Request, HttpError> {
+        Request::read(&mut raw.as_bytes())
+    }
+
+    // A server on a free port whose handler counts the requests it has seen
+    fn start() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut seen = 0u32;
+            serve(listener, move |request| {
+                if request.path == "/panic" {
+                    panic!("the handler fell over");
+                }
+                seen += 1;
+                let body = Json::object()
+                    .with("seen", seen)
+                    .with("path", request.path.as_str())
+                    .with("body", request.body.as_str());
+                Response::json(200, &body)
+            })
+        });
+        addr
+    }
+
+    fn send(addr: SocketAddr, raw: &str) -> (u16, Json) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(raw.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, Json::parse(body).unwrap())
+    }
+
+    #[test]
+    fn requests_are_parsed_and_decoded() {
+        let request = parse(
+            "POST /tables/4%2Fb?name=Ada+Lovelace&x HTTP/1.1\r\n\
+             Content-Length: 7\r\nX-Thing:  yes \r\n\r\n{\"a\":1}",
+        )
+        .unwrap();
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(request.path, "/tables/4%2Fb");
+        assert_eq!(request.segments(), ["tables", "4/b"]);
+        assert_eq!(request.query("name"), Some("Ada Lovelace"));
+        assert_eq!(request.query("x"), Some(""));
+        assert_eq!(request.header("x-thing"), Some("yes"));
+        assert_eq!(request.body, "{\"a\":1}");
+        assert_eq!(
+            Request::new(Method::Get, "/orders/3/serve").segments(),
+            ["orders", "3", "serve"]
+        );
+        assert_eq!(
+            Request::new(Method::Get, "/menu/a+b").segments(),
+            ["menu", "a+b"]
+        );
+        assert_eq!(percent_decode("100%", true), "100%");
+        assert_eq!(percent_decode("%zz%41", true), "%zzA");
+    }
+
+    #[test]
+    fn malformed_requests_are_refused() {
+        for raw in [
+            "GET\r\n\r\n",
+            "GET / SPDY/3\r\n\r\n",
+            "GET / HTTP/1.1\r\nno colon\r\n\r\n",
+            "GET / HTTP/1.1\r\nContent-Length: lots\r\n\r\n",
+            "GET / HTTP/1.1\r\n",
+        ] {
+            assert!(
+                matches!(parse(raw), Err(HttpError::BadRequest(_))),
+                "{:?}",
+                raw
+            );
+        }
+        let huge = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert!(matches!(parse(&huge), Err(HttpError::TooLarge(_))));
+    }
+
+    #[test]
+    fn a_request_round_trips_over_tcp() {
+        let addr = start();
+        let (status, body) = send(addr, "POST /echo HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi");
+        assert_eq!(status, 200);
+        assert_eq!(body.get("path").and_then(Json::as_str), Some("/echo"));
+        assert_eq!(body.get("body").and_then(Json::as_str), Some("hi"));
+        let (status, body) = send(addr, "GET /again HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 200);
+        assert_eq!(body.get("seen").and_then(Json::as_u32), Some(2));
+        let (status, body) = send(addr, "nonsense\r\n\r\n");
+        assert_eq!(status, 400);
+        assert!(body.get("error").is_some());
+    }
+
+    #[test]
+    fn a_panicking_handler_answers_500_and_frees_its_connection() {
+        let addr = start();
+        for _ in 0..MAX_CONNECTIONS + 5 {
+            let (status, body) = send(addr, "GET /panic HTTP/1.1\r\n\r\n");
+            assert_eq!(status, 500);
+            assert!(body.get("error").is_some());
+        }
+        let (status, body) = send(addr, "GET /fine HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 200);
+        assert_eq!(body.get("seen").and_then(Json::as_u32), Some(1));
+    }
+
+    #[test]
+    fn a_request_trickled_in_still_runs_out_of_time() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut client = TcpStream::connect(addr).unwrap();
+            for _ in 0..50 {
+                if client.write_all(b"G").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let started = Instant::now();
+        let mut reader = Deadline::new(stream, Duration::from_millis(200));
+        let mut received = Vec::new();
+        let error = reader.read_to_end(&mut received).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(!received.is_empty()); // <- every read on its own was quick enough
+        assert!(started.elapsed() < Duration::from_millis(600));
+    }
+
+    #[test]
+    fn a_stalled_client_does_not_hold_up_the_others() {
+        let addr = start();
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled.write_all(b"GET /slow HTTP/1.1\r\n").unwrap();
+        let started = Instant::now();
+        let (status, _) = send(addr, "GET /fast HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 200);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
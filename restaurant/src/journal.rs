@@ -0,0 +1,1213 @@
+// Keeping the day's service on disk. Everything that changes the restaurant is
+// written down as an event, one JSON line each, to an append-only log; after a
+// crash (or just a restart) the day is rebuilt by replaying the log through the
+// same hosting, serving and back_of_house code that produced it. Every so often
+// the whole state is also written out as a snapshot, remembering how far into
+// the log it goes, so recovery only has to replay what came after it.
+//
+// The log is never rewritten, so it doubles as the audit trail for the day.
+// Replay assumes the restaurant is set up the same way as when the events were
+// recorded: the same tables, menu and kitchen routes.
+
+use crate::back_of_house::{self, kitchen::Kitchen, kitchen::TicketId};
+use crate::billing::Payment;
+use crate::clock::{ManualClock, Timestamp};
+use crate::dietary::GuestProfile;
+use crate::front_of_house::floor::FloorPlan;
+use crate::front_of_house::hosting::{Party, PartyId, Waitlist};
+use crate::front_of_house::reservations::{Arrival, ReservationBook, ReservationId};
+use crate::front_of_house::serving;
+use crate::front_of_house::staff::{Roster, StaffId};
+use crate::json::Json;
+use crate::menu::Menu;
+use crate::money::Cents;
+use crate::order::{
+    charges_from_json, charges_json, guests_from_json, guests_json, payments_from_json,
+    payments_json, OrderBook, OrderId, OrderItem,
+};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const LOG: &str = "events.jsonl";
+const SNAPSHOT: &str = "snapshot.json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    PartyAdded {
+        id: PartyId,
+        party: Party,
+    },
+    // Gave up waiting
+    PartyLeft {
+        id: PartyId,
+    },
+    NoShow {
+        id: PartyId,
+    },
+    PartySeated {
+        id: PartyId,
+        tables: Vec<u32>,
+        server: Option<StaffId>, // <- from the rotation, if anyone was on the clock
+        at: Timestamp,
+    },
+    TableCleared {
+        table: u32,
+        at: Timestamp,
+    },
+    TableCleaned {
+        table: u32,
+    },
+    OrderPlaced {
+        id: OrderId,
+        table: u32,
+        items: Vec<OrderItem>,
+        guests: Vec<GuestProfile>, // <- already checked when the order was taken
+        at: Timestamp,
+    },
+    OrderCooked {
+        id: OrderId,
+        at: Timestamp,
+    },
+    TicketStarted {
+        ticket: TicketId,
+        at: Timestamp,
+    },
+    TicketBumped {
+        ticket: TicketId,
+        at: Timestamp,
+    },
+    OrderServed {
+        id: OrderId,
+        at: Timestamp,
+    },
+    OrderRemade {
+        id: OrderId,
+        menu_ids: Vec<String>,
+        reason: String,
+        at: Timestamp,
+    },
+    OrderVoided {
+        id: OrderId,
+        at: Timestamp,
+    },
+    PaymentTaken {
+        id: OrderId,
+        payments: Vec<Payment>,
+        charges: Vec<(String, Cents)>,
+        at: Timestamp,
+    },
+    ClockedIn {
+        staff: StaffId,
+        at: Timestamp,
+    },
+    ClockedOut {
+        staff: StaffId,
+        at: Timestamp,
+    },
+    ReservationBooked {
+        id: ReservationId,
+        name: String,
+        size: u32,
+        time: Timestamp, // <- what the booking is for; `at` is when it was taken
+        contact: Option<String>,
+        at: Timestamp,
+    },
+    ReservationChanged {
+        id: ReservationId,
+        time: Timestamp,
+        size: u32,
+        at: Timestamp,
+    },
+    ReservationCancelled {
+        id: ReservationId,
+        at: Timestamp,
+    },
+    // Tables held for bookings coming up, and bookings given up on, in one write
+    ReservationsChecked {
+        held: Vec<ReservationId>,
+        missed: Vec<ReservationId>,
+        at: Timestamp,
+    },
+    ReservationArrived {
+        id: ReservationId,
+        party: PartyId,
+        server: Option<StaffId>,
+        at: Timestamp,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::PartyAdded { .. } => "party_added",
+            Event::PartyLeft { .. } => "party_left",
+            Event::NoShow { .. } => "no_show",
+            Event::PartySeated { .. } => "party_seated",
+            Event::TableCleared { .. } => "table_cleared",
+            Event::TableCleaned { .. } => "table_cleaned",
+            Event::OrderPlaced { .. } => "order_placed",
+            Event::OrderCooked { .. } => "order_cooked",
+            Event::TicketStarted { .. } => "ticket_started",
+            Event::TicketBumped { .. } => "ticket_bumped",
+            Event::OrderServed { .. } => "order_served",
+            Event::OrderRemade { .. } => "order_remade",
+            Event::OrderVoided { .. } => "order_voided",
+            Event::PaymentTaken { .. } => "payment_taken",
+            Event::ClockedIn { .. } => "clocked_in",
+            Event::ClockedOut { .. } => "clocked_out",
+            Event::ReservationBooked { .. } => "reservation_booked",
+            Event::ReservationChanged { .. } => "reservation_changed",
+            Event::ReservationCancelled { .. } => "reservation_cancelled",
+            Event::ReservationsChecked { .. } => "reservations_checked",
+            Event::ReservationArrived { .. } => "reservation_arrived",
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        let json = Json::object().with("type", self.name());
+        match self {
+            Event::PartyAdded { id, party } => json.with("id", id.0).with("party", party.to_json()),
+            Event::PartyLeft { id } | Event::NoShow { id } => json.with("id", id.0),
+            Event::PartySeated {
+                id,
+                tables,
+                server,
+                at,
+            } => json
+                .with("id", id.0)
+                .with("tables", tables.clone())
+                .with("server", server.map(|s| s.0))
+                .with("at", at.as_secs()),
+            Event::TableCleared { table, at } => {
+                json.with("table", *table).with("at", at.as_secs())
+            }
+            Event::TableCleaned { table } => json.with("table", *table),
+            Event::OrderPlaced {
+                id,
+                table,
+                items,
+                guests,
+                at,
+            } => {
+                let items: Vec<Json> = items.iter().map(OrderItem::to_json).collect();
+                json.with("id", id.0)
+                    .with("table", *table)
+                    .with("items", items)
+                    .with("guests", guests_json(guests))
+                    .with("at", at.as_secs())
+            }
+            Event::OrderCooked { id, at }
+            | Event::OrderServed { id, at }
+            | Event::OrderVoided { id, at } => json.with("id", id.0).with("at", at.as_secs()),
+            Event::TicketStarted { ticket, at } | Event::TicketBumped { ticket, at } => {
+                json.with("ticket", ticket.0).with("at", at.as_secs())
+            }
+            Event::OrderRemade {
+                id,
+                menu_ids,
+                reason,
+                at,
+            } => json
+                .with("id", id.0)
+                .with("menu_ids", menu_ids.clone())
+                .with("reason", reason.as_str())
+                .with("at", at.as_secs()),
+            Event::PaymentTaken {
+                id,
+                payments,
+                charges,
+                at,
+            } => json
+                .with("id", id.0)
+                .with("payments", payments_json(payments))
+                .with("charges", charges_json(charges))
+                .with("at", at.as_secs()),
+            Event::ClockedIn { staff, at } | Event::ClockedOut { staff, at } => {
+                json.with("staff", staff.0).with("at", at.as_secs())
+            }
+            Event::ReservationBooked {
+                id,
+                name,
+                size,
+                time,
+                contact,
+                at,
+            } => json
+                .with("id", id.0)
+                .with("name", name.as_str())
+                .with("size", *size)
+                .with("time", time.as_secs())
+                .with("contact", contact.clone())
+                .with("at", at.as_secs()),
+            Event::ReservationChanged { id, time, size, at } => json
+                .with("id", id.0)
+                .with("time", time.as_secs())
+                .with("size", *size)
+                .with("at", at.as_secs()),
+            Event::ReservationCancelled { id, at } => {
+                json.with("id", id.0).with("at", at.as_secs())
+            }
+            Event::ReservationsChecked { held, missed, at } => {
+                let ids = |ids: &[ReservationId]| -> Vec<Json> {
+                    ids.iter().map(|r| Json::from(r.0)).collect()
+                };
+                json.with("held", ids(held))
+                    .with("missed", ids(missed))
+                    .with("at", at.as_secs())
+            }
+            Event::ReservationArrived {
+                id,
+                party,
+                server,
+                at,
+            } => json
+                .with("id", id.0)
+                .with("party", party.0)
+                .with("server", server.map(|s| s.0))
+                .with("at", at.as_secs()),
+        }
+    }
+
+    pub fn from_json(json: &Json) -> Option<Event> {
+        let u32_field = |key: &str| json.get(key)?.as_u32();
+        let at = || Some(Timestamp::from_secs(json.get("at")?.as_u64()?));
+        let order = || u32_field("id").map(OrderId);
+        let party = || u32_field("id").map(PartyId);
+        let reservation = || u32_field("id").map(ReservationId);
+        let reservations = |field: &str| {
+            json.get(field)?
+                .as_array()?
+                .iter()
+                .map(|id| id.as_u32().map(ReservationId))
+                .collect::<Option<Vec<_>>>()
+        };
+        let time = || Some(Timestamp::from_secs(json.get("time")?.as_u64()?));
+        let server = || match json.get("server")? {
+            Json::Null => Some(None),
+            server => Some(Some(StaffId(server.as_u32()?))),
+        };
+        let event = match json.get("type")?.as_str()? {
+            "party_added" => Event::PartyAdded {
+                id: party()?,
+                party: Party::from_json(json.get("party")?)?,
+            },
+            "party_left" => Event::PartyLeft { id: party()? },
+            "no_show" => Event::NoShow { id: party()? },
+            "party_seated" => Event::PartySeated {
+                id: party()?,
+                tables: json
+                    .get("tables")?
+                    .as_array()?
+                    .iter()
+                    .map(Json::as_u32)
+                    .collect::<Option<Vec<_>>>()?,
+                server: server()?,
+                at: at()?,
+            },
+            "table_cleared" => Event::TableCleared {
+                table: u32_field("table")?,
+                at: at()?,
+            },
+            "table_cleaned" => Event::TableCleaned {
+                table: u32_field("table")?,
+            },
+            "order_placed" => Event::OrderPlaced {
+                id: order()?,
+                table: u32_field("table")?,
+                items: json
+                    .get("items")?
+                    .as_array()?
+                    .iter()
+                    .map(OrderItem::from_json)
+                    .collect::<Option<Vec<_>>>()?,
+                guests: match json.get("guests") {
+                    Some(guests) => guests_from_json(guests)?,
+                    None => Vec::new(),
+                },
+                at: at()?,
+            },
+            "order_cooked" => Event::OrderCooked {
+                id: order()?,
+                at: at()?,
+            },
+            "ticket_started" => Event::TicketStarted {
+                ticket: TicketId(u32_field("ticket")?),
+                at: at()?,
+            },
+            "ticket_bumped" => Event::TicketBumped {
+                ticket: TicketId(u32_field("ticket")?),
+                at: at()?,
+            },
+            "order_served" => Event::OrderServed {
+                id: order()?,
+                at: at()?,
+            },
+            "order_remade" => Event::OrderRemade {
+                id: order()?,
+                menu_ids: json
+                    .get("menu_ids")?
+                    .as_array()?
+                    .iter()
+                    .map(|id| id.as_str().map(String::from))
+                    .collect::<Option<Vec<_>>>()?,
+                reason: String::from(json.get("reason")?.as_str()?),
+                at: at()?,
+            },
+            "order_voided" => Event::OrderVoided {
+                id: order()?,
+                at: at()?,
+            },
+            "payment_taken" => Event::PaymentTaken {
+                id: order()?,
+                payments: payments_from_json(json.get("payments")?)?,
+                charges: charges_from_json(json.get("charges")?)?,
+                at: at()?,
+            },
+            "clocked_in" => Event::ClockedIn {
+                staff: StaffId(u32_field("staff")?),
+                at: at()?,
+            },
+            "clocked_out" => Event::ClockedOut {
+                staff: StaffId(u32_field("staff")?),
+                at: at()?,
+            },
+            "reservation_booked" => Event::ReservationBooked {
+                id: reservation()?,
+                name: String::from(json.get("name")?.as_str()?),
+                size: u32_field("size")?,
+                time: time()?,
+                contact: match json.get("contact")? {
+                    Json::Null => None,
+                    contact => Some(String::from(contact.as_str()?)),
+                },
+                at: at()?,
+            },
+            "reservation_changed" => Event::ReservationChanged {
+                id: reservation()?,
+                time: time()?,
+                size: u32_field("size")?,
+                at: at()?,
+            },
+            "reservation_cancelled" => Event::ReservationCancelled {
+                id: reservation()?,
+                at: at()?,
+            },
+            "reservations_checked" => Event::ReservationsChecked {
+                held: reservations("held")?,
+                missed: reservations("missed")?,
+                at: at()?,
+            },
+            "reservation_arrived" => Event::ReservationArrived {
+                id: reservation()?,
+                party: PartyId(u32_field("party")?),
+                server: server()?,
+                at: at()?,
+            },
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+// One line per event, for reading the log back as an audit trail
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::PartyAdded { id, party } => write!(
+                f,
+                "{} {} ({}, {}) joined the waitlist",
+                party.arrived, id, party.name, party.size
+            ),
+            Event::PartyLeft { id } => write!(f, "{} left the waitlist", id),
+            Event::NoShow { id } => write!(f, "{} didn't come back", id),
+            Event::PartySeated {
+                id,
+                tables,
+                server,
+                at,
+            } => {
+                write!(f, "{} {} seated at {:?}", at, id, tables)?;
+                match server {
+                    Some(server) => write!(f, " with {}", server),
+                    None => Ok(()),
+                }
+            }
+            Event::TableCleared { table, at } => write!(f, "{} table {} cleared", at, table),
+            Event::TableCleaned { table } => write!(f, "table {} bussed", table),
+            Event::OrderPlaced {
+                id,
+                table,
+                items,
+                at,
+                ..
+            } => write!(
+                f,
+                "{} {} placed for table {} ({} items)",
+                at,
+                id,
+                table,
+                items.len()
+            ),
+            Event::OrderCooked { id, at } => write!(f, "{} {} fired", at, id),
+            Event::TicketStarted { ticket, at } => write!(f, "{} {} started", at, ticket),
+            Event::TicketBumped { ticket, at } => write!(f, "{} {} bumped", at, ticket),
+            Event::OrderServed { id, at } => write!(f, "{} {} served", at, id),
+            Event::OrderRemade {
+                id,
+                menu_ids,
+                reason,
+                at,
+            } => write!(f, "{} {} sent back ({}): {:?}", at, id, reason, menu_ids),
+            Event::OrderVoided { id, at } => write!(f, "{} {} voided", at, id),
+            Event::PaymentTaken {
+                id, payments, at, ..
+            } => {
+                let paid: Cents = payments.iter().map(|p| p.amount).sum();
+                write!(f, "{} {} paid {}", at, id, paid)
+            }
+            Event::ClockedIn { staff, at } => write!(f, "{} {} clocked in", at, staff),
+            Event::ClockedOut { staff, at } => write!(f, "{} {} clocked out", at, staff),
+            Event::ReservationBooked {
+                id,
+                name,
+                size,
+                time,
+                at,
+                ..
+            } => write!(
+                f,
+                "{} {} booked for {} ({}) at {} {}",
+                at,
+                id,
+                name,
+                size,
+                time.date(),
+                time
+            ),
+            Event::ReservationChanged { id, time, size, at } => write!(
+                f,
+                "{} {} moved to {} {} for {}",
+                at,
+                id,
+                time.date(),
+                time,
+                size
+            ),
+            Event::ReservationCancelled { id, at } => write!(f, "{} {} cancelled", at, id),
+            Event::ReservationsChecked { held, missed, at } => write!(
+                f,
+                "{} tables held for {} bookings, {} didn't turn up",
+                at,
+                held.len(),
+                missed.len()
+            ),
+            Event::ReservationArrived { id, party, at, .. } => {
+                write!(f, "{} {} arrived as {}", at, id, party)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    Corrupt { line: usize, message: String }, // <- a line of the log can't be read
+    Snapshot(String),
+    Replay { seq: u64, message: String }, // <- an event doesn't apply to the rebuilt state
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JournalError::Io(e) => write!(f, "{}", e),
+            JournalError::Corrupt { line, message } => {
+                write!(f, "line {} of the event log: {}", line, message)
+            }
+            JournalError::Snapshot(message) => write!(f, "bad snapshot: {}", message),
+            JournalError::Replay { seq, message } => {
+                write!(f, "event {} doesn't replay: {}", seq, message)
+            }
+        }
+    }
+}
+
+impl Error for JournalError {}
+
+impl From<io::Error> for JournalError {
+    fn from(e: io::Error) -> Self {
+        JournalError::Io(e)
+    }
+}
+
+// Everything that changes during service
+#[derive(Debug)]
+pub struct Service {
+    pub floor: FloorPlan,
+    pub waitlist: Waitlist,
+    pub orders: OrderBook,
+    pub kitchen: Kitchen,
+    pub menu: Menu,
+    pub staff: Roster,
+    pub reservations: ReservationBook<ManualClock>, // <- its clock is set to each change's time
+}
+
+impl Service {
+    pub fn new(floor: FloorPlan, menu: Menu, kitchen: Kitchen) -> Service {
+        Service {
+            floor,
+            waitlist: Waitlist::new(),
+            orders: OrderBook::new(),
+            kitchen,
+            menu,
+            staff: Roster::new(),
+            reservations: ReservationBook::new(ManualClock::default()),
+        }
+    }
+
+    // Do again what the event records. Ids are handed out in order, so an event
+    // whose id doesn't come out the same means the log and state disagree.
+    pub fn apply(&mut self, event: &Event) -> Result<(), Box<dyn Error>> {
+        match event {
+            Event::PartyAdded { id, party } => {
+                let added = self.waitlist.add(party.clone())?;
+                if added != *id {
+                    return Err(format!("expected {} but the waitlist made {}", id, added).into());
+                }
+            }
+            Event::PartyLeft { id } => {
+                self.waitlist
+                    .remove(*id)
+                    .ok_or_else(|| format!("{} is not waiting", id))?;
+            }
+            Event::NoShow { id } => self.waitlist.mark_no_show(*id)?,
+            Event::PartySeated {
+                id,
+                tables,
+                server,
+                at,
+            } => {
+                self.waitlist
+                    .seat_at_tables(*id, &mut self.floor, tables.clone(), *at)?;
+                if let Some(server) = server {
+                    self.staff.record_assignment(*server, *id, *at);
+                }
+            }
+            Event::TableCleared { table, at } => {
+                self.waitlist.clear_table(&mut self.floor, *table, *at)?;
+            }
+            Event::TableCleaned { table } => self.floor.mark_clean(*table)?,
+            Event::OrderPlaced {
+                id,
+                table,
+                items,
+                guests,
+                at,
+            } => {
+                let placed = serving::take_order(&mut self.orders, *table, items.clone(), *at)?;
+                if placed != *id {
+                    return Err(format!("expected {} but the book made {}", id, placed).into());
+                }
+                self.orders.note_guests(placed, guests.clone())?;
+            }
+            Event::OrderCooked { id, at } => {
+                back_of_house::cook_order(
+                    &mut self.orders,
+                    &mut self.kitchen,
+                    &mut self.menu,
+                    *id,
+                    *at,
+                )?;
+            }
+            Event::TicketStarted { ticket, at } => self.kitchen.start(*ticket, *at)?,
+            Event::TicketBumped { ticket, at } => {
+                back_of_house::bump_ticket(&mut self.orders, &mut self.kitchen, *ticket, *at)?;
+            }
+            Event::OrderServed { id, at } => serving::serve_order(&mut self.orders, *id, *at)?,
+            Event::OrderRemade {
+                id,
+                menu_ids,
+                reason,
+                at,
+            } => {
+                let menu_ids: Vec<&str> = menu_ids.iter().map(String::as_str).collect();
+                back_of_house::fix_incorrect_order(
+                    &mut self.orders,
+                    &mut self.kitchen,
+                    *id,
+                    &menu_ids,
+                    reason,
+                    *at,
+                )?;
+            }
+            Event::OrderVoided { id, at } => self.orders.void(*id, *at)?,
+            // The check was settled when it was taken; only the order needs closing
+            Event::PaymentTaken {
+                id,
+                payments,
+                charges,
+                at,
+            } => self
+                .orders
+                .pay(*id, payments.clone(), charges.clone(), *at)?,
+            Event::ClockedIn { staff, at } => self.staff.clock_in(*staff, *at)?,
+            Event::ClockedOut { staff, at } => self.staff.clock_out(*staff, *at)?,
+            Event::ReservationBooked {
+                id,
+                name,
+                size,
+                time,
+                contact,
+                at,
+            } => {
+                self.reservations.clock().set(*at);
+                let booked = self.reservations.book(&self.floor, name, *size, *time)?;
+                if booked != *id {
+                    return Err(format!("expected {} but the book made {}", id, booked).into());
+                }
+                if let Some(contact) = contact {
+                    self.reservations.set_contact(booked, contact)?;
+                }
+            }
+            Event::ReservationChanged { id, time, size, at } => {
+                self.reservations.clock().set(*at);
+                self.reservations
+                    .modify(&mut self.floor, *id, *time, *size)?;
+            }
+            Event::ReservationCancelled { id, at } => {
+                self.reservations.clock().set(*at);
+                self.reservations.cancel(&mut self.floor, *id)?;
+            }
+            Event::ReservationsChecked { held, missed, at } => {
+                self.reservations.clock().set(*at);
+                let now_held = self.reservations.hold_tables(&mut self.floor);
+                if now_held != *held {
+                    return Err(format!("held tables for {:?}, not {:?}", now_held, held).into());
+                }
+                let now_missed = self.reservations.mark_no_shows(&mut self.floor);
+                if now_missed != *missed {
+                    return Err(format!("{:?} missed, not {:?}", now_missed, missed).into());
+                }
+            }
+            Event::ReservationArrived {
+                id,
+                party,
+                server,
+                at,
+            } => {
+                self.reservations.clock().set(*at);
+                let arrived =
+                    match self
+                        .reservations
+                        .arrive(&mut self.floor, &mut self.waitlist, *id)?
+                    {
+                        Arrival::Seated(seating) => seating.party,
+                        Arrival::Waiting(party) => party,
+                    };
+                if arrived != *party {
+                    return Err(
+                        format!("expected {} but the waitlist made {}", party, arrived).into(),
+                    );
+                }
+                if let Some(server) = server {
+                    self.staff.record_assignment(*server, *party, *at);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> Json {
+        let sold_out: Vec<Json> = self
+            .menu
+            .items()
+            .filter(|i| i.sold_out)
+            .map(|i| Json::from(i.id.as_str()))
+            .collect();
+        Json::object()
+            .with("floor", self.floor.state_json())
+            .with("waitlist", self.waitlist.to_json())
+            .with("orders", self.orders.to_json())
+            .with("kitchen", self.kitchen.state_json())
+            .with("staff", self.staff.state_json())
+            .with("reservations", self.reservations.state_json())
+            .with("sold_out", sold_out)
+    }
+
+    pub fn restore(&mut self, json: &Json) -> Result<(), JournalError> {
+        let part = |key: &str| {
+            json.get(key)
+                .ok_or_else(|| JournalError::Snapshot(format!("no '{}' section", key)))
+        };
+        let unreadable = |key: &str| JournalError::Snapshot(format!("'{}' is unreadable", key));
+        // Every part is read into a copy first, so a bad snapshot changes nothing
+        let mut floor = self.floor.clone();
+        floor
+            .restore_state(part("floor")?)
+            .ok_or_else(|| unreadable("floor"))?;
+        let waitlist =
+            Waitlist::from_json(part("waitlist")?).ok_or_else(|| unreadable("waitlist"))?;
+        let orders = OrderBook::from_json(part("orders")?).ok_or_else(|| unreadable("orders"))?;
+        let mut kitchen = self.kitchen.clone();
+        kitchen
+            .restore_state(part("kitchen")?)
+            .ok_or_else(|| unreadable("kitchen"))?;
+        let mut staff = self.staff.clone();
+        staff
+            .restore_state(part("staff")?)
+            .ok_or_else(|| unreadable("staff"))?;
+        let mut reservations = self.reservations.clone();
+        reservations
+            .restore_state(part("reservations")?)
+            .ok_or_else(|| unreadable("reservations"))?;
+        let sold_out: Vec<&str> = part("sold_out")?
+            .as_array()
+            .ok_or_else(|| unreadable("sold_out"))?
+            .iter()
+            .filter_map(Json::as_str)
+            .collect();
+        self.floor = floor;
+        self.waitlist = waitlist;
+        self.orders = orders;
+        self.kitchen = kitchen;
+        self.staff = staff;
+        self.reservations = reservations;
+        let ids: Vec<String> = self.menu.items().map(|i| i.id.clone()).collect();
+        for id in ids {
+            let _ = self.menu.set_sold_out(&id, sold_out.contains(&id.as_str()));
+        }
+        Ok(())
+    }
+}
+
+// The open event log for a data directory
+#[derive(Debug)]
+pub struct Journal {
+    dir: PathBuf,
+    log: File,
+    seq: u64,               // <- of the last event written
+    offset: u64,            // <- bytes of the log up to and including that event
+    base: (Json, u64, u64), // <- the state as of some event, its seq and offset, to rebuild from
+    since_snapshot: u64,
+    pub snapshot_every: Option<u64>, // <- events between automatic snapshots
+}
+
+impl Journal {
+    // Open (or start) the journal in `dir`, bringing `service` up to date: the
+    // latest snapshot is restored, then every event after it is replayed.
+    // A half-written last line, left by a crash mid-write, is cut off.
+    pub fn open(dir: impl AsRef<Path>, service: &mut Service) -> Result<Journal, JournalError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let (mut seq, mut offset) = (0, 0);
+        let snapshot_path = dir.join(SNAPSHOT);
+        if snapshot_path.exists() {
+            let text = fs::read_to_string(&snapshot_path)?;
+            let json = Json::parse(&text).map_err(|e| JournalError::Snapshot(e.to_string()))?;
+            let field = |key: &str| {
+                json.get(key)
+                    .and_then(Json::as_u64)
+                    .ok_or_else(|| JournalError::Snapshot(format!("no '{}'", key)))
+            };
+            seq = field("seq")?;
+            offset = field("offset")?;
+            let state = json
+                .get("state")
+                .ok_or_else(|| JournalError::Snapshot(String::from("no 'state'")))?;
+            service.restore(state)?;
+        }
+
+        let mut log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(dir.join(LOG))?;
+        let length = log.metadata()?.len();
+        if length < offset {
+            return Err(JournalError::Snapshot(String::from(
+                "the snapshot goes further than the event log",
+            )));
+        }
+        log.seek(SeekFrom::Start(offset))?;
+        let (replayed, read) = replay(BufReader::new(&log), seq, service)?;
+        seq += replayed;
+        offset += read;
+        if offset < length {
+            log.set_len(offset)?;
+        }
+        Ok(Journal {
+            dir,
+            log,
+            seq,
+            offset,
+            base: (service.snapshot(), seq, offset),
+            since_snapshot: 0,
+            snapshot_every: Some(500),
+        })
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    // Write the event down and make sure it is on disk before returning; then
+    // take a snapshot if one is due. On an error nothing of the event is left in
+    // the log, and the caller should undo whatever the event describes (`rebuild`).
+    pub fn record(&mut self, event: &Event, service: &Service) -> Result<u64, JournalError> {
+        let line = format!(
+            "{}\n",
+            Json::object()
+                .with("seq", self.seq + 1)
+                .with("event", event.to_json())
+        );
+        let written = self
+            .log
+            .write_all(line.as_bytes())
+            .and_then(|()| self.log.sync_data());
+        if let Err(e) = written {
+            let _ = self.log.set_len(self.offset); // <- cut off any part that made it
+            return Err(e.into());
+        }
+        self.seq += 1;
+        self.offset += line.len() as u64;
+        self.since_snapshot += 1;
+        if self
+            .snapshot_every
+            .is_some_and(|every| self.since_snapshot >= every)
+        {
+            // The event is safely in the log, so a failed snapshot only makes
+            // the next recovery replay further; it is tried again next event
+            let _ = self.snapshot(service);
+        }
+        Ok(self.seq)
+    }
+
+    // Save the whole state as of the last event. Written to a temporary file
+    // and renamed into place, so a crash never leaves half a snapshot.
+    pub fn snapshot(&mut self, service: &Service) -> Result<(), JournalError> {
+        let json = Json::object()
+            .with("seq", self.seq)
+            .with("offset", self.offset)
+            .with("state", service.snapshot());
+        let temporary = self.dir.join(format!("{}.tmp", SNAPSHOT));
+        let mut file = File::create(&temporary)?;
+        file.write_all(json.to_string().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, self.dir.join(SNAPSHOT))?;
+        // The rename itself only survives a crash once the directory is synced
+        File::open(&self.dir)?.sync_all()?;
+        self.since_snapshot = 0;
+        if let Some(state) = json.get("state") {
+            self.base = (state.clone(), self.seq, self.offset);
+        }
+        Ok(())
+    }
+
+    // Put `service` back to the state the log describes, for when a change was
+    // made that the log couldn't take: the last snapshot taken (or the state
+    // at opening) is restored and the events since replayed.
+    pub fn rebuild(&self, service: &mut Service) -> Result<(), JournalError> {
+        let (state, seq, offset) = &self.base;
+        service.restore(state)?;
+        let mut log = File::open(self.dir.join(LOG))?;
+        log.seek(SeekFrom::Start(*offset))?;
+        let reader = BufReader::new(log.take(self.offset - offset));
+        let (replayed, _) = replay(reader, *seq, service)?;
+        if seq + replayed != self.seq {
+            return Err(JournalError::Snapshot(format!(
+                "rebuilt to event {} rather than {}",
+                seq + replayed,
+                self.seq
+            )));
+        }
+        Ok(())
+    }
+
+    // Swap the log for a handle that can't be written to, so the next record fails
+    #[cfg(test)]
+    pub(crate) fn break_log(&mut self) -> io::Result<()> {
+        self.log = File::open(self.dir.join(LOG))?;
+        Ok(())
+    }
+
+    // Every event in the log, for auditing
+    pub fn read(dir: impl AsRef<Path>) -> Result<Vec<(u64, Event)>, JournalError> {
+        let file = File::open(dir.as_ref().join(LOG))?;
+        let mut events = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let (seq, event) = parse_line(&line?).map_err(|message| JournalError::Corrupt {
+                line: number + 1,
+                message,
+            })?;
+            events.push((seq, event));
+        }
+        Ok(events)
+    }
+}
+
+// Apply each whole line of `reader` to `service`, the first being event
+// `seq + 1`; a torn last line is left alone. Gives back how many events were
+// applied and how many bytes they took up.
+fn replay(
+    mut reader: impl BufRead,
+    seq: u64,
+    service: &mut Service,
+) -> Result<(u64, u64), JournalError> {
+    let (mut replayed, mut offset) = (0, 0);
+    let mut line = String::new();
+    let mut number = 0;
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)? as u64;
+        if read == 0 {
+            break;
+        }
+        number += 1;
+        if !line.ends_with('\n') {
+            break; // <- torn write; the caller decides what to do with it
+        }
+        let (event_seq, event) = parse_line(&line).map_err(|message| JournalError::Corrupt {
+            line: number,
+            message,
+        })?;
+        let expected = seq + replayed + 1;
+        if event_seq != expected {
+            return Err(JournalError::Corrupt {
+                line: number,
+                message: format!("expected event {} but found {}", expected, event_seq),
+            });
+        }
+        service.apply(&event).map_err(|e| JournalError::Replay {
+            seq: event_seq,
+            message: e.to_string(),
+        })?;
+        replayed += 1;
+        offset += read;
+    }
+    Ok((replayed, offset))
+}
+
+fn parse_line(line: &str) -> Result<(u64, Event), String> {
+    let json = Json::parse(line.trim_end()).map_err(|e| e.to_string())?;
+    let seq = json
+        .get("seq")
+        .and_then(Json::as_u64)
+        .ok_or("no event number")?;
+    let event = json
+        .get("event")
+        .and_then(Event::from_json)
+        .ok_or("not an event")?;
+    Ok((seq, event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn service() -> Service {
+        let mut service = Service::new(FloorPlan::house(), Menu::house(), Kitchen::new());
+        service.staff = Roster::house();
+        service
+    }
+
+    fn at(hours: u64) -> Timestamp {
+        Timestamp::from_secs(hours * 3600)
+    }
+
+    // Apply and record, the way the API does
+    fn happen(journal: &mut Journal, service: &mut Service, event: Event) {
+        service.apply(&event).unwrap();
+        journal.record(&event, service).unwrap();
+    }
+
+    #[test]
+    fn reopening_replays_to_the_same_state() {
+        let dir = scratch("replay");
+        let mut live = service();
+        let mut journal = Journal::open(&dir, &mut live).unwrap();
+        journal.snapshot_every = Some(3);
+        let events = [
+            Event::ClockedIn {
+                staff: StaffId(1),
+                at: at(17),
+            },
+            Event::PartyAdded {
+                id: PartyId(1),
+                party: Party::walk_in("Ada", 2, at(18)),
+            },
+            Event::ReservationBooked {
+                id: ReservationId(1),
+                name: String::from("Brian"),
+                size: 4,
+                time: at(20),
+                contact: Some(String::from("555-0100")),
+                at: at(18),
+            },
+            Event::ReservationChanged {
+                id: ReservationId(1),
+                time: at(21),
+                size: 6,
+                at: at(18),
+            },
+            Event::ClockedOut {
+                staff: StaffId(1),
+                at: at(22),
+            },
+        ];
+        for event in events {
+            happen(&mut journal, &mut live, event);
+        }
+        assert_eq!(journal.seq(), 5);
+        assert!(dir.join(SNAPSHOT).exists()); // <- so recovery is snapshot plus two events
+
+        let mut recovered = service();
+        let reopened = Journal::open(&dir, &mut recovered).unwrap();
+        assert_eq!(reopened.seq(), 5);
+        assert_eq!(recovered.snapshot(), live.snapshot());
+        assert_eq!(Journal::read(&dir).unwrap().len(), 5);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_torn_last_line_is_cut_off() {
+        let dir = scratch("torn");
+        let mut live = service();
+        let mut journal = Journal::open(&dir, &mut live).unwrap();
+        let clock_in = Event::ClockedIn {
+            staff: StaffId(2),
+            at: at(17),
+        };
+        happen(&mut journal, &mut live, clock_in);
+        let length = fs::metadata(dir.join(LOG)).unwrap().len();
+        drop(journal);
+        let mut log = OpenOptions::new().append(true).open(dir.join(LOG)).unwrap();
+        log.write_all(b"{\"seq\": 2, \"event\": {\"ty").unwrap();
+
+        let mut recovered = service();
+        let mut journal = Journal::open(&dir, &mut recovered).unwrap();
+        assert_eq!(journal.seq(), 1);
+        assert_eq!(fs::metadata(dir.join(LOG)).unwrap().len(), length);
+        assert!(recovered.staff.is_clocked_in(StaffId(2)));
+        // The next event goes where the torn one was
+        let clock_out = Event::ClockedOut {
+            staff: StaffId(2),
+            at: at(18),
+        };
+        happen(&mut journal, &mut recovered, clock_out);
+        assert_eq!(Journal::read(&dir).unwrap().len(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_failed_write_leaves_the_log_as_it_was() {
+        let dir = scratch("failed");
+        let mut live = service();
+        let mut journal = Journal::open(&dir, &mut live).unwrap();
+        let clock_in = Event::ClockedIn {
+            staff: StaffId(1),
+            at: at(17),
+        };
+        happen(&mut journal, &mut live, clock_in);
+        let length = fs::metadata(dir.join(LOG)).unwrap().len();
+
+        journal.break_log().unwrap();
+        let clock_out = Event::ClockedOut {
+            staff: StaffId(1),
+            at: at(18),
+        };
+        assert!(matches!(
+            journal.record(&clock_out, &live),
+            Err(JournalError::Io(_))
+        ));
+        assert_eq!(journal.seq(), 1);
+        assert_eq!(fs::metadata(dir.join(LOG)).unwrap().len(), length);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rebuilding_drops_a_change_the_log_never_took() {
+        let dir = scratch("rebuild");
+        let mut live = service();
+        let mut journal = Journal::open(&dir, &mut live).unwrap();
+        let clock_in = |staff| Event::ClockedIn {
+            staff: StaffId(staff),
+            at: at(17),
+        };
+        happen(&mut journal, &mut live, clock_in(1));
+        journal.snapshot(&live).unwrap();
+        happen(&mut journal, &mut live, clock_in(2));
+        let logged = live.snapshot();
+
+        journal.break_log().unwrap();
+        live.apply(&clock_in(3)).unwrap();
+        assert!(journal.record(&clock_in(3), &live).is_err());
+        journal.rebuild(&mut live).unwrap();
+        assert_eq!(live.snapshot(), logged);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_bad_snapshot_changes_nothing() {
+        let mut live = service();
+        live.apply(&Event::ClockedIn {
+            staff: StaffId(1),
+            at: at(17),
+        })
+        .unwrap();
+        let before = live.snapshot();
+        let bad = service().snapshot().with("reservations", Json::Null); // <- staff reads fine first
+        assert!(live.restore(&bad).is_err());
+        assert_eq!(live.snapshot(), before);
+    }
+
+    #[test]
+    fn reservation_events_survive_a_round_trip() {
+        let events = [
+            Event::ReservationBooked {
+                id: ReservationId(3),
+                name: String::from("Cleo"),
+                size: 2,
+                time: at(19),
+                contact: None,
+                at: at(12),
+            },
+            Event::ReservationCancelled {
+                id: ReservationId(3),
+                at: at(13),
+            },
+            Event::ReservationsChecked {
+                held: vec![ReservationId(1), ReservationId(2)],
+                missed: Vec::new(),
+                at: at(18),
+            },
+            Event::ReservationArrived {
+                id: ReservationId(1),
+                party: PartyId(4),
+                server: Some(StaffId(2)),
+                at: at(19),
+            },
+        ];
+        for event in events {
+            assert_eq!(Event::from_json(&event.to_json()), Some(event));
+        }
+    }
+
+    #[test]
+    fn replay_refuses_a_log_that_disagrees_with_the_state() {
+        let mut service = service();
+        let held = Event::ReservationsChecked {
+            held: vec![ReservationId(1)],
+            missed: Vec::new(),
+            at: at(18),
+        };
+        assert!(service.apply(&held).is_err()); // <- there is nothing to hold a table for
+    }
+}
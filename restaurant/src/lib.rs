@@ -1,61 +1,260 @@
-fn deliver_order() {}
+pub mod api;
+pub mod back_of_house;
+pub mod billing;
+pub mod clock;
+pub mod dietary;
+pub mod front_of_house;
+pub mod http;
+pub mod journal;
+pub mod json;
+pub mod menu;
+pub mod money;
+pub mod order;
+pub mod reporting;
+pub mod simulation;
 
-mod front_of_house;
+use crate::back_of_house::inventory::{Ingredient, Inventory, Unit};
+use crate::back_of_house::kitchen::Kitchen;
+use crate::billing::{BillingConfig, Check, Discount, DiscountScope, Payment, SplitBy, Tip};
+use crate::dietary::{DietChecker, GuestProfile, OnConflict};
+use crate::menu::Allergen;
+use crate::money::{Cents, Rate};
+use crate::order::{OrderBook, OrderError, OrderId, OrderItem};
+use crate::reporting::DailyReport;
 
-mod back_of_house {
-
-    pub enum Appetizer {
-        Soup,
-        Salad,
-    }
-    pub struct Breakfast {
-        pub toast: String,
-        seasonal_fruit: String, // <- private field
-    }
-    impl Breakfast {
-        pub fn summer(toast: &str) -> Breakfast {
-            Breakfast {
-                toast: String::from(toast),
-                seasonal_fruit: String::from("peaches"),
-            }
-        }
-        pub fn winter(toast: &str) -> Breakfast {
-            Breakfast {
-                toast: String::from(toast),
-                seasonal_fruit: String::from("oranges"),
-            }
-        }
-        pub fn autumn(toast: &str) -> Breakfast {
-            Breakfast {
-                toast: String::from(toast),
-                seasonal_fruit: String::from("persimmons"),
-            }
-        }
-    }
-    fn fix_incorrect_order() {
-        cook_order();
-        super::deliver_order(); // <- super keyword to access parent module, same as Python
-    }
-
-    fn cook_order() {}
+// The kitchen puts the order up at the pass for a server to pick up
+pub fn deliver_order(
+    orders: &mut OrderBook,
+    id: OrderId,
+    now: Timestamp,
+) -> Result<(), OrderError> {
+    orders.mark_ready(id, now)
 }
 
 // Using the 'use' keyword to bring a path into scope
-use crate::front_of_house::hosting;
+use crate::clock::{minutes, ManualClock, Timestamp};
+use crate::front_of_house::floor::{FloorPlan, Table};
+use crate::front_of_house::hosting::{self, Party, Waitlist};
+use crate::front_of_house::reservations::{Arrival, Overbooking, ReservationBook};
+use crate::front_of_house::serving;
+use crate::front_of_house::staff::{Role, Roster, TipPool};
 
 pub fn eat_at_restaurant() {
+    let opening = Timestamp::from_secs(17 * 3600);
+    let mut waitlist = Waitlist::new();
+    let mut floor = FloorPlan::new();
+    for table in [
+        Table::new(1, 2, "window"),
+        Table::new(2, 4, "window"),
+        Table::new(3, 2, "patio"),
+    ] {
+        floor.add_table(table).expect("table numbers are unique");
+    }
+    floor.join(1, 2).expect("both tables exist"); // <- 1 and 2 can be pushed together
+    floor.assign_server("window", "Alice");
+    floor.assign_server("patio", "Bob");
+    // Alice is on from five, Bob comes in late
+    let mut roster = Roster::new();
+    let alice = roster.add_staff("Alice", Role::Server);
+    let bob = roster.add_staff("Bob", Role::Server);
+    let dana = roster.add_staff("Dana", Role::Host);
+    for staff in [alice, bob, dana] {
+        roster
+            .schedule(staff, opening, opening + minutes(6 * 60))
+            .expect("no overlapping shifts");
+    }
+    for staff in [alice, dana] {
+        roster.clock_in(staff, opening).expect("on the books");
+    }
+
     // Absolute path
-    crate::front_of_house::hosting::add_to_waitlist();
+    crate::front_of_house::hosting::add_to_waitlist(
+        &mut waitlist,
+        Party::walk_in("Ferris", 2, opening),
+    )
+    .expect("a party of two");
 
     // Relative path
-    hosting::add_to_waitlist();
+    let crabs = hosting::add_to_waitlist(&mut waitlist, Party::walk_in("Crabs", 6, opening))
+        .expect("a party of six");
+
+    match hosting::seat_at_table(&mut waitlist, &mut floor, &mut roster, opening + minutes(5)) {
+        Ok(seating) => println!(
+            "Seated {} at {:?} with {}",
+            seating.party,
+            seating.tables,
+            seating.server.as_deref().unwrap_or("nobody")
+        ),
+        Err(e) => println!("Could not seat anyone: {}", e),
+    }
+    if let Err(e) =
+        hosting::seat_at_table(&mut waitlist, &mut floor, &mut roster, opening + minutes(5))
+    {
+        println!("Could not seat anyone: {}", e);
+    }
+    if let Err(e) = waitlist.mark_no_show(crabs) {
+        println!("{}", e);
+    }
+
+    // Bookings for later; the clock only moves when we say so
+    let clock = ManualClock::new(opening + minutes(5));
+    let mut book = ReservationBook::new(&clock).overbooking(Overbooking::Guests(4));
+    for (name, at) in [("Rustaceans", minutes(30)), ("Gophers", minutes(45))] {
+        match book.book(&floor, name, 4, opening + at) {
+            Ok(id) => println!("{}", book.get(id).expect("just booked")),
+            Err(e) => println!("{}", e),
+        }
+    }
+    clock.advance(minutes(15));
+    book.hold_tables(&mut floor);
+    clock.advance(minutes(12));
+    if let Some(id) = book.upcoming().first().map(|r| r.id) {
+        match book.arrive(&mut floor, &mut waitlist, id) {
+            Ok(Arrival::Seated(seating)) => println!("{} sat down at {:?}", id, seating.tables),
+            Ok(Arrival::Waiting(party)) => println!("{} is waiting as {}", id, party),
+            Err(e) => println!("{}", e),
+        }
+    }
 
     // Order breakfast in the summer with Rye toast
     let mut meal = back_of_house::Breakfast::summer("Rye");
     // Change the toast
     meal.toast = String::from("Wheat");
     println!("I'd like {} toast please", meal.toast);
+    // Or let the calendar pick the fruit
+    let today = back_of_house::Breakfast::for_date(opening.date(), "Sourdough");
+    println!(
+        "On {} breakfast comes with {}",
+        opening.date(),
+        today.seasonal_fruit()
+    );
+    let mut menu = menu::Menu::house();
+    match meal.price(&menu) {
+        Ok(price) => println!("That will be {}", price),
+        Err(e) => println!("{}", e),
+    }
 
     let order1 = back_of_house::Appetizer::Soup;
     let order2 = back_of_house::Appetizer::Salad;
+    let open = menu.available_at(opening);
+    println!(
+        "{} of {} menu items can be ordered at {}",
+        open.len(),
+        menu.items().count(),
+        opening
+    );
+
+    // Table 1 orders, the kitchen cooks it, and the soup comes back cold
+    let mut orders = OrderBook::new();
+    // Only three lemons left in the walk-in
+    let mut stock = Inventory::house();
+    stock.add_ingredient(Ingredient::new("lemons", Unit::Each, 3.0, 2.0));
+    let mut kitchen = Kitchen::new().with_inventory(stock);
+    let mut service = || -> Result<(), Box<dyn std::error::Error>> {
+        let items = [
+            OrderItem::new("soup").for_seat(1),
+            OrderItem::new("salad")
+                .with("dressing", "Ranch")
+                .with("protein", "Salmon")
+                .for_seat(2),
+            OrderItem::new("lemonade"),
+        ];
+        // Seat 2 can't have fish; the server puts it through anyway and warns the kitchen
+        let guests = vec![GuestProfile::new().for_seat(2).allergic_to(Allergen::Fish)];
+        let checker = DietChecker::new(&menu, kitchen.inventory(), opening + minutes(10));
+        let (order, flags) = serving::take_checked_order(
+            &mut orders,
+            &checker,
+            1,
+            items.to_vec(),
+            guests,
+            OnConflict::Flag,
+            opening + minutes(10),
+        )?;
+        for flag in flags {
+            println!("Check with the guest: {}", flag);
+        }
+        let tickets = back_of_house::cook_order(
+            &mut orders,
+            &mut kitchen,
+            &mut menu,
+            order,
+            opening + minutes(11),
+        )?;
+        for ticket in tickets.iter().filter_map(|t| kitchen.ticket(*t)) {
+            println!("{}", ticket);
+        }
+        for alert in kitchen.inventory_mut().take_alerts() {
+            println!("{}", alert);
+        }
+        for ticket in kitchen.late(opening + minutes(16)) {
+            println!("Late: {}", ticket.id);
+        }
+        for ticket in tickets {
+            back_of_house::bump_ticket(&mut orders, &mut kitchen, ticket, opening + minutes(20))?;
+        }
+        serving::serve_order(&mut orders, order, opening + minutes(21))?;
+        let remakes = back_of_house::fix_incorrect_order(
+            &mut orders,
+            &mut kitchen,
+            order,
+            &["soup"],
+            "cold",
+            opening + minutes(25),
+        )?;
+        for ticket in remakes {
+            back_of_house::bump_ticket(&mut orders, &mut kitchen, ticket, opening + minutes(31))?;
+        }
+        serving::serve_order(&mut orders, order, opening + minutes(32))?;
+
+        let placed = orders.get(order).ok_or(OrderError::UnknownOrder(order))?;
+        let receipt = Check::from_orders(&[placed], &menu, 2)?
+            .discount(Discount::percent(
+                "Soup's on us",
+                Rate::percent(100),
+                DiscountScope::Item(String::from("soup")),
+            ))
+            .tip(Tip::Percent(Rate::percent(20)))
+            .receipt(&BillingConfig::default())?;
+        println!("{}", receipt);
+        for part in receipt.split(&SplitBy::Seat)? {
+            println!("{}", part);
+        }
+        let payments = vec![
+            Payment::card(Cents(1500)),
+            Payment::cash(Cents::from_dollars(20)),
+        ];
+        let change = serving::take_payment(
+            &mut orders,
+            order,
+            &receipt,
+            payments,
+            opening + minutes(60),
+        )?;
+        println!("Change: {}", change);
+        // Paid orders are closed for good
+        if let Err(e) = serving::serve_order(&mut orders, order, opening + minutes(61)) {
+            println!("{}", e);
+        }
+        Ok(())
+    };
+    if let Err(e) = service() {
+        println!("{}", e);
+    }
+    for event in orders.events() {
+        println!("{}", event);
+    }
+
+    // Close out the day
+    for staff in roster.missing(opening + minutes(30)) {
+        println!("{} never showed up", staff);
+    }
+    roster.clock_out(alice, opening + minutes(6 * 60)).ok();
+    roster.clock_out(dana, opening + minutes(4 * 60)).ok();
+    let pool = roster.pool_tips(Cents(3_40), &TipPool::default(), opening + minutes(6 * 60));
+    for share in pool {
+        println!("Tips for {}: {}", share.staff, share.amount);
+    }
+    let report = DailyReport::for_day(opening.date(), &orders, &menu, &waitlist, &kitchen);
+    print!("{}", report.to_markdown());
 }
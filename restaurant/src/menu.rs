@@ -0,0 +1,621 @@
+// The menu as data: every item the restaurant sells, what it costs, what can be
+// changed about it, what allergens it carries and when it can be ordered.
+// Menus are loaded from JSON (see menu.json for the house menu), so changing a
+// price doesn't need a new build.
+
+use crate::clock::{TimeOfDay, Timestamp};
+use crate::json::{Json, JsonError};
+use crate::money::Cents;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const HOUSE_MENU: &str = include_str!("../menu.json");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Category {
+    Appetizer,
+    Breakfast,
+    Main,
+    Side,
+    Dessert,
+    Drink,
+}
+
+impl Category {
+    pub const ALL: [Category; 6] = [
+        Category::Appetizer,
+        Category::Breakfast,
+        Category::Main,
+        Category::Side,
+        Category::Dessert,
+        Category::Drink,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Appetizer => "appetizer",
+            Category::Breakfast => "breakfast",
+            Category::Main => "main",
+            Category::Side => "side",
+            Category::Dessert => "dessert",
+            Category::Drink => "drink",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Category> {
+        Category::ALL.into_iter().find(|c| c.name() == name)
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Allergen {
+    Gluten,
+    Dairy,
+    Eggs,
+    Nuts,
+    Peanuts,
+    Soy,
+    Fish,
+    Shellfish,
+    Sesame,
+}
+
+impl Allergen {
+    pub const ALL: [Allergen; 9] = [
+        Allergen::Gluten,
+        Allergen::Dairy,
+        Allergen::Eggs,
+        Allergen::Nuts,
+        Allergen::Peanuts,
+        Allergen::Soy,
+        Allergen::Fish,
+        Allergen::Shellfish,
+        Allergen::Sesame,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Allergen::Gluten => "gluten",
+            Allergen::Dairy => "dairy",
+            Allergen::Eggs => "eggs",
+            Allergen::Nuts => "nuts",
+            Allergen::Peanuts => "peanuts",
+            Allergen::Soy => "soy",
+            Allergen::Fish => "fish",
+            Allergen::Shellfish => "shellfish",
+            Allergen::Sesame => "sesame",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Allergen> {
+        Allergen::ALL.into_iter().find(|a| a.name() == name)
+    }
+}
+
+impl fmt::Display for Allergen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModifierOption {
+    pub name: String,
+    pub price: Cents, // <- added to the item's price
+    pub allergens: Vec<Allergen>,
+}
+
+// Something the guest picks when ordering, like the toast for a breakfast
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modifier {
+    pub name: String,
+    pub required: bool,
+    pub options: Vec<ModifierOption>,
+}
+
+impl Modifier {
+    pub fn option(&self, name: &str) -> Option<&ModifierOption> {
+        self.options
+            .iter()
+            .find(|o| o.name.eq_ignore_ascii_case(name))
+    }
+}
+
+// When an item can be ordered; `until` before `from` means the window runs
+// past midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub from: TimeOfDay,
+    pub until: TimeOfDay,
+}
+
+impl Window {
+    pub fn contains(&self, time: TimeOfDay) -> bool {
+        if self.from <= self.until {
+            self.from <= time && time < self.until
+        } else {
+            time >= self.from || time < self.until
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuItem {
+    pub id: String,
+    pub name: String,
+    pub category: Category,
+    pub price: Cents,
+    pub modifiers: Vec<Modifier>,
+    pub allergens: Vec<Allergen>,
+    pub windows: Vec<Window>, // <- empty means all day
+    pub sold_out: bool,
+}
+
+impl MenuItem {
+    pub fn modifier(&self, name: &str) -> Option<&Modifier> {
+        self.modifiers
+            .iter()
+            .find(|m| m.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn is_available(&self, at: Timestamp) -> bool {
+        let time = at.time_of_day();
+        !self.sold_out && (self.windows.is_empty() || self.windows.iter().any(|w| w.contains(time)))
+    }
+
+    pub fn to_json(&self) -> Json {
+        let modifiers: Vec<Json> = self
+            .modifiers
+            .iter()
+            .map(|m| {
+                let options: Vec<Json> = m
+                    .options
+                    .iter()
+                    .map(|o| {
+                        Json::object()
+                            .with("name", o.name.as_str())
+                            .with("price_cents", o.price.0)
+                            .with("allergens", allergen_names(&o.allergens))
+                    })
+                    .collect();
+                Json::object()
+                    .with("name", m.name.as_str())
+                    .with("required", m.required)
+                    .with("options", options)
+            })
+            .collect();
+        let windows: Vec<Json> = self
+            .windows
+            .iter()
+            .map(|w| {
+                Json::object()
+                    .with("from", w.from.to_string())
+                    .with("until", w.until.to_string())
+            })
+            .collect();
+        Json::object()
+            .with("id", self.id.as_str())
+            .with("name", self.name.as_str())
+            .with("category", self.category.name())
+            .with("price_cents", self.price.0)
+            .with("modifiers", modifiers)
+            .with("allergens", allergen_names(&self.allergens))
+            .with("available", windows)
+            .with("sold_out", self.sold_out)
+    }
+}
+
+fn allergen_names(allergens: &[Allergen]) -> Vec<Json> {
+    allergens.iter().map(|a| Json::from(a.name())).collect()
+}
+
+#[derive(Debug)]
+pub enum MenuError {
+    Json(JsonError),
+    Io(io::Error),
+    // The file parsed but doesn't describe a menu
+    Invalid {
+        item: String,
+        message: String,
+    },
+    UnknownItem(String),
+    UnknownModifier {
+        item: String,
+        modifier: String,
+    },
+    UnknownOption {
+        item: String,
+        modifier: String,
+        option: String,
+    },
+    MissingModifier {
+        item: String,
+        modifier: String,
+    },
+    DuplicateModifier {
+        item: String,
+        modifier: String,
+    },
+}
+
+impl fmt::Display for MenuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MenuError::Json(e) => write!(f, "{}", e),
+            MenuError::Io(e) => write!(f, "{}", e),
+            MenuError::Invalid { item, message } if item.is_empty() => write!(f, "{}", message),
+            MenuError::Invalid { item, message } => write!(f, "menu item '{}': {}", item, message),
+            MenuError::UnknownItem(id) => write!(f, "'{}' is not on the menu", id),
+            MenuError::UnknownModifier { item, modifier } => {
+                write!(f, "'{}' has no '{}' choice", item, modifier)
+            }
+            MenuError::UnknownOption {
+                item,
+                modifier,
+                option,
+            } => write!(f, "'{}' can't come with {} '{}'", item, modifier, option),
+            MenuError::MissingModifier { item, modifier } => {
+                write!(f, "'{}' needs a {} choice", item, modifier)
+            }
+            MenuError::DuplicateModifier { item, modifier } => {
+                write!(f, "'{}' takes only one {} choice", item, modifier)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MenuError {}
+
+impl From<io::Error> for MenuError {
+    fn from(e: io::Error) -> Self {
+        MenuError::Io(e)
+    }
+}
+
+impl From<JsonError> for MenuError {
+    fn from(e: JsonError) -> Self {
+        MenuError::Json(e)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Menu {
+    items: BTreeMap<String, MenuItem>,
+}
+
+impl Menu {
+    pub fn new() -> Menu {
+        Menu::default()
+    }
+
+    // The menu that ships with the crate
+    pub fn house() -> Menu {
+        Menu::from_json(HOUSE_MENU).expect("menu.json is a valid menu")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Menu, MenuError> {
+        Menu::from_json(&fs::read_to_string(path)?)
+    }
+
+    // { "items": [ { "id", "name", "category", "price_cents", "modifiers"?,
+    //   "allergens"?, "available"? }, ... ] }
+    pub fn from_json(text: &str) -> Result<Menu, MenuError> {
+        let json = Json::parse(text)?;
+        let items = json
+            .get("items")
+            .and_then(Json::as_array)
+            .ok_or_else(|| invalid("", "expected an \"items\" array"))?;
+        let mut menu = Menu::new();
+        for item in items {
+            let item = parse_item(item)?;
+            if menu.items.contains_key(&item.id) {
+                return Err(invalid(&item.id, "the id is used twice"));
+            }
+            menu.add(item);
+        }
+        Ok(menu)
+    }
+
+    pub fn to_json(&self) -> Json {
+        let items: Vec<Json> = self.items.values().map(MenuItem::to_json).collect();
+        Json::object().with("items", items)
+    }
+
+    pub fn add(&mut self, item: MenuItem) -> Option<MenuItem> {
+        self.items.insert(item.id.clone(), item)
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<MenuItem> {
+        self.items.remove(id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&MenuItem> {
+        self.items.get(id)
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &MenuItem> {
+        self.items.values()
+    }
+
+    pub fn in_category(&self, category: Category) -> Vec<&MenuItem> {
+        self.items().filter(|i| i.category == category).collect()
+    }
+
+    // What a guest could order right now
+    pub fn available_at(&self, at: Timestamp) -> Vec<&MenuItem> {
+        self.items().filter(|i| i.is_available(at)).collect()
+    }
+
+    // Take an item off (or put it back on) for the rest of service
+    pub fn set_sold_out(&mut self, id: &str, sold_out: bool) -> Result<(), MenuError> {
+        let item = self
+            .items
+            .get_mut(id)
+            .ok_or_else(|| MenuError::UnknownItem(String::from(id)))?;
+        item.sold_out = sold_out;
+        Ok(())
+    }
+
+    // The price of an item with the guest's choices, checking that every choice
+    // exists, none is made twice and every required one was made
+    pub fn quote(&self, id: &str, choices: &[(&str, &str)]) -> Result<Cents, MenuError> {
+        let item = self
+            .get(id)
+            .ok_or_else(|| MenuError::UnknownItem(String::from(id)))?;
+        let mut price = item.price;
+        for (i, (modifier_name, option_name)) in choices.iter().enumerate() {
+            let modifier =
+                item.modifier(modifier_name)
+                    .ok_or_else(|| MenuError::UnknownModifier {
+                        item: item.id.clone(),
+                        modifier: String::from(*modifier_name),
+                    })?;
+            let option = modifier
+                .option(option_name)
+                .ok_or_else(|| MenuError::UnknownOption {
+                    item: item.id.clone(),
+                    modifier: modifier.name.clone(),
+                    option: String::from(*option_name),
+                })?;
+            if choices[..i]
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case(&modifier.name))
+            {
+                return Err(MenuError::DuplicateModifier {
+                    item: item.id.clone(),
+                    modifier: modifier.name.clone(),
+                });
+            }
+            price += option.price;
+        }
+        for modifier in item.modifiers.iter().filter(|m| m.required) {
+            if !choices
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case(&modifier.name))
+            {
+                return Err(MenuError::MissingModifier {
+                    item: item.id.clone(),
+                    modifier: modifier.name.clone(),
+                });
+            }
+        }
+        Ok(price)
+    }
+}
+
+fn invalid(item: &str, message: &str) -> MenuError {
+    MenuError::Invalid {
+        item: String::from(item),
+        message: String::from(message),
+    }
+}
+
+fn string_field<'a>(json: &'a Json, key: &str, item: &str) -> Result<&'a str, MenuError> {
+    json.get(key)
+        .and_then(Json::as_str)
+        .ok_or_else(|| invalid(item, &format!("\"{}\" should be a string", key)))
+}
+
+fn price_field(json: &Json, item: &str) -> Result<Cents, MenuError> {
+    match json.get("price_cents") {
+        None => Ok(Cents::ZERO),
+        Some(price) => price
+            .as_i64()
+            .filter(|&c| c >= 0)
+            .map(Cents)
+            .ok_or_else(|| invalid(item, "\"price_cents\" should be a whole number of cents")),
+    }
+}
+
+fn array_field<'a>(json: &'a Json, key: &str, item: &str) -> Result<&'a [Json], MenuError> {
+    match json.get(key) {
+        None => Ok(&[]),
+        Some(value) => value
+            .as_array()
+            .ok_or_else(|| invalid(item, &format!("\"{}\" should be an array", key))),
+    }
+}
+
+fn parse_allergens(json: &Json, item: &str) -> Result<Vec<Allergen>, MenuError> {
+    array_field(json, "allergens", item)?
+        .iter()
+        .map(|a| {
+            a.as_str()
+                .and_then(Allergen::parse)
+                .ok_or_else(|| invalid(item, &format!("unknown allergen {}", a)))
+        })
+        .collect()
+}
+
+fn parse_item(json: &Json) -> Result<MenuItem, MenuError> {
+    let id = string_field(json, "id", "")?;
+    let category = string_field(json, "category", id)?;
+    let category = Category::parse(category)
+        .ok_or_else(|| invalid(id, &format!("unknown category '{}'", category)))?;
+    if json.get("price_cents").is_none() {
+        return Err(invalid(id, "\"price_cents\" is missing"));
+    }
+    let mut modifiers = Vec::new();
+    for modifier in array_field(json, "modifiers", id)? {
+        let name = string_field(modifier, "name", id)?;
+        let mut options = Vec::new();
+        for option in array_field(modifier, "options", id)? {
+            options.push(ModifierOption {
+                name: String::from(string_field(option, "name", id)?),
+                price: price_field(option, id)?,
+                allergens: parse_allergens(option, id)?,
+            });
+        }
+        if options.is_empty() {
+            return Err(invalid(id, &format!("'{}' has no options", name)));
+        }
+        modifiers.push(Modifier {
+            name: String::from(name),
+            required: modifier
+                .get("required")
+                .and_then(Json::as_bool)
+                .unwrap_or(false),
+            options,
+        });
+    }
+    let mut windows = Vec::new();
+    for window in array_field(json, "available", id)? {
+        let time = |key: &str| {
+            string_field(window, key, id).and_then(|t| {
+                TimeOfDay::parse(t)
+                    .ok_or_else(|| invalid(id, &format!("'{}' is not a time like 07:30", t)))
+            })
+        };
+        windows.push(Window {
+            from: time("from")?,
+            until: time("until")?,
+        });
+    }
+    Ok(MenuItem {
+        id: String::from(id),
+        name: String::from(string_field(json, "name", id)?),
+        category,
+        price: price_field(json, id)?,
+        modifiers,
+        allergens: parse_allergens(json, id)?,
+        windows,
+        sold_out: json
+            .get("sold_out")
+            .and_then(Json::as_bool)
+            .unwrap_or(false),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL: &str = r#"{ "items": [
+        { "id": "toast", "name": "Toast", "category": "breakfast", "price_cents": 300,
+          "allergens": ["gluten"],
+          "available": [ { "from": "07:00", "until": "11:00" } ],
+          "modifiers": [ { "name": "bread", "required": true, "options": [
+              { "name": "White" }, { "name": "Rye", "price_cents": 50 } ] } ] },
+        { "id": "cocoa", "name": "Cocoa", "category": "drink", "price_cents": 250,
+          "available": [ { "from": "22:00", "until": "02:00" } ] }
+    ] }"#;
+
+    fn at(hours: u64, minutes: u64) -> Timestamp {
+        Timestamp::from_secs(hours * 3600 + minutes * 60)
+    }
+
+    #[test]
+    fn quotes_add_modifier_prices_and_check_choices() {
+        let menu = Menu::from_json(SMALL).unwrap();
+        assert_eq!(
+            menu.quote("toast", &[("bread", "white")]).unwrap(),
+            Cents(300)
+        );
+        assert_eq!(
+            menu.quote("toast", &[("Bread", "rye")]).unwrap(),
+            Cents(350)
+        );
+        assert!(matches!(
+            menu.quote("toast", &[]),
+            Err(MenuError::MissingModifier { .. })
+        ));
+        assert!(matches!(
+            menu.quote("toast", &[("bread", "rye"), ("BREAD", "rye")]),
+            Err(MenuError::DuplicateModifier { .. })
+        ));
+        assert!(matches!(
+            menu.quote("toast", &[("bread", "sourdough")]),
+            Err(MenuError::UnknownOption { .. })
+        ));
+        assert!(matches!(
+            menu.quote("toast", &[("jam", "yes")]),
+            Err(MenuError::UnknownModifier { .. })
+        ));
+        assert!(matches!(
+            menu.quote("waffle", &[]),
+            Err(MenuError::UnknownItem(_))
+        ));
+    }
+
+    #[test]
+    fn availability_follows_windows_and_sold_out() {
+        let mut menu = Menu::from_json(SMALL).unwrap();
+        let ids = |items: Vec<&MenuItem>| items.iter().map(|i| i.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(menu.available_at(at(8, 0))), ["toast"]);
+        assert_eq!(ids(menu.available_at(at(11, 0))), Vec::<String>::new());
+        assert_eq!(ids(menu.available_at(at(23, 30))), ["cocoa"]);
+        assert_eq!(ids(menu.available_at(at(1, 59))), ["cocoa"]);
+        menu.set_sold_out("toast", true).unwrap();
+        assert!(menu.available_at(at(8, 0)).is_empty());
+        assert!(menu.set_sold_out("waffle", true).is_err());
+    }
+
+    #[test]
+    fn bad_menus_are_rejected() {
+        let item = |fields: &str| {
+            format!(
+                r#"{{ "items": [ {{ "id": "x", "name": "X", {} }} ] }}"#,
+                fields
+            )
+        };
+        let bad = [
+            item(r#""category": "brunch-ish", "price_cents": 100"#),
+            item(r#""category": "drink""#),
+            item(r#""category": "drink", "price_cents": -1"#),
+            item(r#""category": "drink", "price_cents": 1, "allergens": ["gravel"]"#),
+            item(r#""category": "drink", "price_cents": 1, "modifiers": [ { "name": "ice" } ]"#),
+            item(
+                r#""category": "drink", "price_cents": 1, "available": [ { "from": "7am", "until": "9" } ]"#,
+            ),
+        ];
+        for text in &bad {
+            assert!(
+                matches!(Menu::from_json(text), Err(MenuError::Invalid { .. })),
+                "{}",
+                text
+            );
+        }
+        let twice = r#"{ "items": [
+            { "id": "x", "name": "X", "category": "drink", "price_cents": 1 },
+            { "id": "x", "name": "Y", "category": "drink", "price_cents": 2 } ] }"#;
+        assert!(Menu::from_json(twice).is_err());
+        assert!(matches!(Menu::from_json("{"), Err(MenuError::Json(_))));
+    }
+
+    #[test]
+    fn the_house_menu_round_trips_through_json() {
+        let menu = Menu::house();
+        assert!(menu.items().count() > 0);
+        let again = Menu::from_json(&menu.to_json().to_string()).unwrap();
+        assert_eq!(again, menu);
+    }
+}
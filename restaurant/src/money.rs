@@ -0,0 +1,230 @@
+// Money is always a whole number of cents. Floats can't hold 0.10 exactly, and a
+// check that is off by a cent is a check someone has to fix by hand.
+
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Cents(pub i64);
+
+impl Cents {
+    pub const ZERO: Cents = Cents(0);
+
+    pub fn from_dollars(dollars: i64) -> Cents {
+        Cents(dollars * 100)
+    }
+
+    // "12", "12.5", "12.50" or "$12.50"; at most two decimal places
+    pub fn parse(text: &str) -> Option<Cents> {
+        let text = text.trim();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let text = text.strip_prefix('$').unwrap_or(text);
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if whole.is_empty() || fraction.len() > 2 {
+            return None;
+        }
+        if !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let whole: i64 = whole.parse().ok()?;
+        let fraction: i64 = format!("{:0<2}", fraction).parse().ok()?;
+        let cents = whole.checked_mul(100)?.checked_add(fraction)?;
+        Some(Cents(if negative { -cents } else { cents }))
+    }
+
+    // Scale by numerator / denominator, rounding half a cent away from zero
+    pub fn scale(self, numerator: i64, denominator: i64) -> Cents {
+        Cents(div_round(self.0 * numerator, denominator))
+    }
+
+    pub fn abs(self) -> Cents {
+        Cents(self.0.abs())
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn checked_add(self, rhs: Cents) -> Option<Cents> {
+        self.0.checked_add(rhs.0).map(Cents)
+    }
+}
+
+// Integer division rounding half away from zero
+pub fn div_round(numerator: i64, denominator: i64) -> i64 {
+    let quotient = (numerator.abs() + denominator.abs() / 2) / denominator.abs();
+    quotient * numerator.signum() * denominator.signum()
+}
+
+impl fmt::Display for Cents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let amount = format!("{}${}.{:02}", sign, self.0.abs() / 100, self.0.abs() % 100);
+        f.pad(&amount) // <- so callers can right-align columns with {:>8}
+    }
+}
+
+impl Add for Cents {
+    type Output = Cents;
+
+    fn add(self, rhs: Cents) -> Cents {
+        Cents(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Cents {
+    fn add_assign(&mut self, rhs: Cents) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Cents {
+    type Output = Cents;
+
+    fn sub(self, rhs: Cents) -> Cents {
+        Cents(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Cents {
+    fn sub_assign(&mut self, rhs: Cents) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Neg for Cents {
+    type Output = Cents;
+
+    fn neg(self) -> Cents {
+        Cents(-self.0)
+    }
+}
+
+impl Mul<u32> for Cents {
+    type Output = Cents;
+
+    fn mul(self, rhs: u32) -> Cents {
+        Cents(self.0 * rhs as i64)
+    }
+}
+
+impl Sum for Cents {
+    fn sum<I: Iterator<Item = Cents>>(iter: I) -> Cents {
+        Cents(iter.map(|c| c.0).sum())
+    }
+}
+
+impl<'a> Sum<&'a Cents> for Cents {
+    fn sum<I: Iterator<Item = &'a Cents>>(iter: I) -> Cents {
+        iter.copied().sum()
+    }
+}
+
+// A percentage kept to a thousandth of a percent, so 8.875% tax is exact
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Rate(u32);
+
+impl Rate {
+    pub fn percent(percent: u32) -> Rate {
+        Rate(percent * 1000)
+    }
+
+    pub fn from_thousandths_of_percent(thousandths: u32) -> Rate {
+        Rate(thousandths)
+    }
+
+    pub fn as_thousandths_of_percent(&self) -> u32 {
+        self.0
+    }
+
+    // "18", "18%" or "8.875%"
+    pub fn parse(text: &str) -> Option<Rate> {
+        let text = text.trim();
+        let text = text.strip_suffix('%').unwrap_or(text).trim();
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if whole.is_empty() || fraction.len() > 3 {
+            return None;
+        }
+        if !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let whole: u32 = whole.parse().ok()?;
+        let fraction: u32 = format!("{:0<3}", fraction).parse().ok()?;
+        Some(Rate(whole.checked_mul(1000)?.checked_add(fraction)?))
+    }
+
+    // This rate of an amount, to the nearest cent
+    pub fn of(&self, amount: Cents) -> Cents {
+        amount.scale(self.0 as i64, 100_000)
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fraction = format!("{:03}", self.0 % 1000);
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            write!(f, "{}%", self.0 / 1000)
+        } else {
+            write!(f, "{}.{}%", self.0 / 1000, fraction)
+        }
+    }
+}
+
+// Split an amount in proportion to `weights` so the parts add back up to the
+// amount exactly; the leftover cents go to the largest remainders. With all
+// weights zero the amount is split evenly.
+pub fn allocate(amount: Cents, weights: &[i64]) -> Vec<Cents> {
+    allocate_with(amount, weights, &vec![0; weights.len()])
+}
+
+// Like `allocate`, but when remainders tie the leftover cents go to the parts
+// with the lowest `preference` first, so repeated even splits don't keep
+// handing the odd cent to the same part
+pub fn allocate_with(amount: Cents, weights: &[i64], preference: &[i64]) -> Vec<Cents> {
+    if weights.is_empty() {
+        return Vec::new();
+    }
+    let even = vec![1; weights.len()];
+    let weights = if weights.iter().all(|&w| w <= 0) {
+        &even[..]
+    } else {
+        weights
+    };
+    let total_weight: i128 = weights.iter().map(|&w| w.max(0) as i128).sum();
+    let magnitude = amount.0.unsigned_abs() as i128;
+    let mut parts = Vec::with_capacity(weights.len());
+    let mut remainders = Vec::with_capacity(weights.len());
+    for (i, &w) in weights.iter().enumerate() {
+        let share = magnitude * w.max(0) as i128;
+        parts.push((share / total_weight) as i64);
+        remainders.push((share % total_weight, i));
+    }
+    let leftover = magnitude as i64 - parts.iter().sum::<i64>();
+    remainders.sort_by_key(|&(remainder, i)| {
+        (
+            std::cmp::Reverse(remainder),
+            preference.get(i).copied().unwrap_or(0),
+            i,
+        )
+    });
+    for &(_, i) in remainders.iter().take(leftover as usize) {
+        parts[i] += 1;
+    }
+    parts
+        .into_iter()
+        .map(|p| Cents(p * amount.0.signum()))
+        .collect()
+}
@@ -0,0 +1,653 @@
+// Orders as they move between the dining room and the kitchen.
+// An order only ever moves along these arrows:
+//
+//   Placed -> Cooking -> Ready -> Served -> Paid
+//                ^         |        |
+//                |         v        v
+//                +------ Remade <---+
+//
+// and anything that hasn't been paid for can be Voided. Every move is stamped
+// with the time it happened and written to an event log, so front of house and
+// back of house always agree on where an order is.
+
+use crate::billing::{Payment, PaymentMethod};
+use crate::clock::Timestamp;
+use crate::dietary::GuestProfile;
+use crate::json::Json;
+use crate::money::Cents;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OrderId(pub u32);
+
+impl fmt::Display for OrderId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "order #{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OrderStatus {
+    Placed,
+    Cooking,
+    Ready, // <- waiting at the pass for a server
+    Served,
+    Paid,
+    Voided,
+    Remade, // <- sent back, the kitchen has to make it again
+}
+
+impl OrderStatus {
+    pub fn can_become(self, next: OrderStatus) -> bool {
+        use OrderStatus::*;
+        matches!(
+            (self, next),
+            (Placed, Cooking)
+                | (Cooking, Ready)
+                | (Ready, Served)
+                | (Served, Paid)
+                | (Ready | Served, Remade)
+                | (Remade, Cooking)
+                | (Placed | Cooking | Ready | Served | Remade, Voided)
+        )
+    }
+
+    // Paid and voided orders never change again
+    pub fn is_closed(self) -> bool {
+        matches!(self, OrderStatus::Paid | OrderStatus::Voided)
+    }
+
+    pub const ALL: [OrderStatus; 7] = [
+        OrderStatus::Placed,
+        OrderStatus::Cooking,
+        OrderStatus::Ready,
+        OrderStatus::Served,
+        OrderStatus::Paid,
+        OrderStatus::Voided,
+        OrderStatus::Remade,
+    ];
+
+    pub fn parse(name: &str) -> Option<OrderStatus> {
+        OrderStatus::ALL.into_iter().find(|s| s.to_string() == name)
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OrderStatus::Placed => "placed",
+            OrderStatus::Cooking => "cooking",
+            OrderStatus::Ready => "ready",
+            OrderStatus::Served => "served",
+            OrderStatus::Paid => "paid",
+            OrderStatus::Voided => "voided",
+            OrderStatus::Remade => "remade",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// One thing a guest asked for: a menu item, the choices they made for it, and
+// which seat it goes to so the check can be split later
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderItem {
+    pub menu_id: String,
+    pub choices: Vec<(String, String)>, // <- (modifier, option), like ("toast", "Rye")
+    pub seat: Option<u32>,
+}
+
+impl OrderItem {
+    pub fn new(menu_id: &str) -> OrderItem {
+        OrderItem {
+            menu_id: String::from(menu_id),
+            choices: Vec::new(),
+            seat: None,
+        }
+    }
+
+    pub fn with(mut self, modifier: &str, option: &str) -> OrderItem {
+        self.choices
+            .push((String::from(modifier), String::from(option)));
+        self
+    }
+
+    pub fn for_seat(mut self, seat: u32) -> OrderItem {
+        self.seat = Some(seat);
+        self
+    }
+
+    pub fn choices(&self) -> Vec<(&str, &str)> {
+        self.choices
+            .iter()
+            .map(|(m, o)| (m.as_str(), o.as_str()))
+            .collect()
+    }
+}
+
+impl OrderItem {
+    pub fn to_json(&self) -> Json {
+        let choices: Vec<Json> = self
+            .choices
+            .iter()
+            .map(|(m, o)| Json::from(vec![m.as_str(), o.as_str()]))
+            .collect();
+        Json::object()
+            .with("menu_id", self.menu_id.as_str())
+            .with("choices", choices)
+            .with("seat", self.seat)
+    }
+
+    pub fn from_json(json: &Json) -> Option<OrderItem> {
+        let mut item = OrderItem::new(json.get("menu_id")?.as_str()?);
+        for choice in json.get("choices")?.as_array()? {
+            match choice.as_array()? {
+                [modifier, option] => item = item.with(modifier.as_str()?, option.as_str()?),
+                _ => return None,
+            }
+        }
+        if let Some(seat) = json.get("seat").filter(|s| **s != Json::Null) {
+            item = item.for_seat(seat.as_u32()?);
+        }
+        Some(item)
+    }
+}
+
+impl From<&str> for OrderItem {
+    fn from(menu_id: &str) -> Self {
+        OrderItem::new(menu_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub id: OrderId,
+    pub table: u32,
+    pub items: Vec<OrderItem>,
+    status: OrderStatus,
+    history: Vec<(OrderStatus, Timestamp)>, // <- every status it has had, oldest first
+    payments: Vec<Payment>,
+    charges: Vec<(String, Cents)>, // <- (menu id, amount) for each item, as it was paid for
+    guests: Vec<GuestProfile>,     // <- allergies and diets the server was told about
+}
+
+impl Order {
+    pub fn status(&self) -> OrderStatus {
+        self.status
+    }
+
+    pub fn history(&self) -> &[(OrderStatus, Timestamp)] {
+        &self.history
+    }
+
+    pub fn guests(&self) -> &[GuestProfile] {
+        &self.guests
+    }
+
+    pub fn payments(&self) -> &[Payment] {
+        &self.payments
+    }
+
+    // What the items sold for, before discounts, tax and tip; empty until paid
+    pub fn charges(&self) -> &[(String, Cents)] {
+        &self.charges
+    }
+
+    // When the order last entered this status
+    pub fn time_of(&self, status: OrderStatus) -> Option<Timestamp> {
+        self.history
+            .iter()
+            .rev()
+            .find(|(s, _)| *s == status)
+            .map(|(_, at)| *at)
+    }
+
+    pub fn remakes(&self) -> usize {
+        self.history
+            .iter()
+            .filter(|(s, _)| *s == OrderStatus::Remade)
+            .count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderEvent {
+    pub at: Timestamp,
+    pub order: OrderId,
+    pub from: Option<OrderStatus>, // <- None when the order was just placed
+    pub to: OrderStatus,
+}
+
+impl fmt::Display for OrderEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.from {
+            None => write!(f, "{} {} {}", self.at, self.order, self.to),
+            Some(from) => write!(f, "{} {} {} -> {}", self.at, self.order, from, self.to),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderError {
+    EmptyOrder,
+    UnknownOrder(OrderId),
+    IllegalTransition {
+        order: OrderId,
+        from: OrderStatus,
+        to: OrderStatus,
+    },
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderError::EmptyOrder => write!(f, "an order needs at least one item"),
+            OrderError::UnknownOrder(id) => write!(f, "there is no {}", id),
+            OrderError::IllegalTransition { order, from, to } => {
+                write!(f, "{} is {} and can't become {}", order, from, to)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+#[derive(Debug, Default)]
+pub struct OrderBook {
+    orders: BTreeMap<OrderId, Order>,
+    events: Vec<OrderEvent>,
+    next_id: u32,
+}
+
+impl OrderBook {
+    pub fn new() -> OrderBook {
+        OrderBook::default()
+    }
+
+    pub fn place(
+        &mut self,
+        table: u32,
+        items: Vec<OrderItem>,
+        now: Timestamp,
+    ) -> Result<OrderId, OrderError> {
+        if items.is_empty() {
+            return Err(OrderError::EmptyOrder);
+        }
+        self.next_id += 1;
+        let id = OrderId(self.next_id);
+        self.orders.insert(
+            id,
+            Order {
+                id,
+                table,
+                items,
+                status: OrderStatus::Placed,
+                history: vec![(OrderStatus::Placed, now)],
+                payments: Vec::new(),
+                charges: Vec::new(),
+                guests: Vec::new(),
+            },
+        );
+        self.events.push(OrderEvent {
+            at: now,
+            order: id,
+            from: None,
+            to: OrderStatus::Placed,
+        });
+        Ok(id)
+    }
+
+    pub fn get(&self, id: OrderId) -> Option<&Order> {
+        self.orders.get(&id)
+    }
+
+    pub fn status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.get(id).map(|o| o.status)
+    }
+
+    // Move an order along, refusing anything that isn't one of the arrows above
+    pub fn transition(
+        &mut self,
+        id: OrderId,
+        to: OrderStatus,
+        now: Timestamp,
+    ) -> Result<(), OrderError> {
+        let order = self
+            .orders
+            .get_mut(&id)
+            .ok_or(OrderError::UnknownOrder(id))?;
+        let from = order.status;
+        if !from.can_become(to) {
+            return Err(OrderError::IllegalTransition {
+                order: id,
+                from,
+                to,
+            });
+        }
+        order.status = to;
+        order.history.push((to, now));
+        self.events.push(OrderEvent {
+            at: now,
+            order: id,
+            from: Some(from),
+            to,
+        });
+        Ok(())
+    }
+
+    pub fn start_cooking(&mut self, id: OrderId, now: Timestamp) -> Result<(), OrderError> {
+        self.transition(id, OrderStatus::Cooking, now)
+    }
+
+    pub fn mark_ready(&mut self, id: OrderId, now: Timestamp) -> Result<(), OrderError> {
+        self.transition(id, OrderStatus::Ready, now)
+    }
+
+    pub fn serve(&mut self, id: OrderId, now: Timestamp) -> Result<(), OrderError> {
+        self.transition(id, OrderStatus::Served, now)
+    }
+
+    pub fn pay(
+        &mut self,
+        id: OrderId,
+        payments: Vec<Payment>,
+        charges: Vec<(String, Cents)>,
+        now: Timestamp,
+    ) -> Result<(), OrderError> {
+        self.transition(id, OrderStatus::Paid, now)?;
+        if let Some(order) = self.orders.get_mut(&id) {
+            order.payments = payments;
+            order.charges = charges;
+        }
+        Ok(())
+    }
+
+    // Keep the guests' allergies and diets with the order, for the kitchen
+    pub fn note_guests(
+        &mut self,
+        id: OrderId,
+        guests: Vec<GuestProfile>,
+    ) -> Result<(), OrderError> {
+        let order = self
+            .orders
+            .get_mut(&id)
+            .ok_or(OrderError::UnknownOrder(id))?;
+        order.guests = guests;
+        Ok(())
+    }
+
+    pub fn void(&mut self, id: OrderId, now: Timestamp) -> Result<(), OrderError> {
+        self.transition(id, OrderStatus::Voided, now)
+    }
+
+    pub fn send_back(&mut self, id: OrderId, now: Timestamp) -> Result<(), OrderError> {
+        self.transition(id, OrderStatus::Remade, now)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.orders.values()
+    }
+
+    pub fn with_status(&self, status: OrderStatus) -> Vec<&Order> {
+        self.iter().filter(|o| o.status == status).collect()
+    }
+
+    // Everything still in progress for a table
+    pub fn open_for_table(&self, table: u32) -> Vec<&Order> {
+        self.iter()
+            .filter(|o| o.table == table && !o.status.is_closed())
+            .collect()
+    }
+
+    pub fn events(&self) -> &[OrderEvent] {
+        &self.events
+    }
+
+    // Everything in the book, for saving and loading with from_json
+    pub fn to_json(&self) -> Json {
+        let orders: Vec<Json> = self
+            .orders
+            .values()
+            .map(|order| {
+                let items: Vec<Json> = order.items.iter().map(OrderItem::to_json).collect();
+                Json::object()
+                    .with("id", order.id.0)
+                    .with("table", order.table)
+                    .with("items", items)
+                    .with("status", order.status.to_string())
+                    .with("history", history_json(&order.history))
+                    .with("payments", payments_json(&order.payments))
+                    .with("charges", charges_json(&order.charges))
+                    .with("guests", guests_json(&order.guests))
+            })
+            .collect();
+        let events: Vec<Json> = self
+            .events
+            .iter()
+            .map(|e| {
+                Json::object()
+                    .with("at", e.at.as_secs())
+                    .with("order", e.order.0)
+                    .with("from", e.from.map(|s| s.to_string()))
+                    .with("to", e.to.to_string())
+            })
+            .collect();
+        Json::object()
+            .with("orders", orders)
+            .with("events", events)
+            .with("next_id", self.next_id)
+    }
+
+    pub fn from_json(json: &Json) -> Option<OrderBook> {
+        let mut book = OrderBook {
+            next_id: json.get("next_id")?.as_u32()?,
+            ..OrderBook::default()
+        };
+        for order in json.get("orders")?.as_array()? {
+            let id = OrderId(order.get("id")?.as_u32()?);
+            let items = order
+                .get("items")?
+                .as_array()?
+                .iter()
+                .map(OrderItem::from_json)
+                .collect::<Option<Vec<_>>>()?;
+            let history = order
+                .get("history")?
+                .as_array()?
+                .iter()
+                .map(|h| {
+                    Some((
+                        OrderStatus::parse(h.get("status")?.as_str()?)?,
+                        timestamp(h.get("at")?)?,
+                    ))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            book.orders.insert(
+                id,
+                Order {
+                    id,
+                    table: order.get("table")?.as_u32()?,
+                    items,
+                    status: OrderStatus::parse(order.get("status")?.as_str()?)?,
+                    history,
+                    payments: payments_from_json(order.get("payments")?)?,
+                    charges: charges_from_json(order.get("charges")?)?,
+                    guests: guests_from_json(order.get("guests")?)?,
+                },
+            );
+        }
+        for event in json.get("events")?.as_array()? {
+            let from = match event.get("from")? {
+                Json::Null => None,
+                from => Some(OrderStatus::parse(from.as_str()?)?),
+            };
+            book.events.push(OrderEvent {
+                at: timestamp(event.get("at")?)?,
+                order: OrderId(event.get("order")?.as_u32()?),
+                from,
+                to: OrderStatus::parse(event.get("to")?.as_str()?)?,
+            });
+        }
+        Some(book)
+    }
+}
+
+fn timestamp(json: &Json) -> Option<Timestamp> {
+    json.as_u64().map(Timestamp::from_secs)
+}
+
+fn history_json(history: &[(OrderStatus, Timestamp)]) -> Json {
+    let entries: Vec<Json> = history
+        .iter()
+        .map(|(status, at)| {
+            Json::object()
+                .with("status", status.to_string())
+                .with("at", at.as_secs())
+        })
+        .collect();
+    Json::from(entries)
+}
+
+pub fn guests_json(guests: &[GuestProfile]) -> Json {
+    let guests: Vec<Json> = guests.iter().map(GuestProfile::to_json).collect();
+    Json::from(guests)
+}
+
+pub fn guests_from_json(json: &Json) -> Option<Vec<GuestProfile>> {
+    json.as_array()?
+        .iter()
+        .map(GuestProfile::from_json)
+        .collect()
+}
+
+pub fn payments_json(payments: &[Payment]) -> Json {
+    let payments: Vec<Json> = payments
+        .iter()
+        .map(|p| {
+            Json::object()
+                .with("method", p.method.name())
+                .with("amount_cents", p.amount.0)
+        })
+        .collect();
+    Json::from(payments)
+}
+
+pub fn payments_from_json(json: &Json) -> Option<Vec<Payment>> {
+    json.as_array()?
+        .iter()
+        .map(|p| {
+            Some(Payment {
+                method: PaymentMethod::parse(p.get("method")?.as_str()?)?,
+                amount: Cents(p.get("amount_cents")?.as_i64()?),
+            })
+        })
+        .collect()
+}
+
+pub fn charges_json(charges: &[(String, Cents)]) -> Json {
+    let charges: Vec<Json> = charges
+        .iter()
+        .map(|(menu_id, amount)| {
+            Json::object()
+                .with("menu_id", menu_id.as_str())
+                .with("amount_cents", amount.0)
+        })
+        .collect();
+    Json::from(charges)
+}
+
+pub fn charges_from_json(json: &Json) -> Option<Vec<(String, Cents)>> {
+    json.as_array()?
+        .iter()
+        .map(|c| {
+            Some((
+                String::from(c.get("menu_id")?.as_str()?),
+                Cents(c.get("amount_cents")?.as_i64()?),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::minutes;
+
+    fn lunch() -> Vec<OrderItem> {
+        vec![OrderItem::new("soup").for_seat(1), OrderItem::new("salad")]
+    }
+
+    #[test]
+    fn an_order_walks_through_the_kitchen_and_is_paid() {
+        let start = Timestamp::from_secs(1_000);
+        let mut book = OrderBook::new();
+        let id = book.place(4, lunch(), start).unwrap();
+        book.start_cooking(id, start + minutes(1)).unwrap();
+        book.mark_ready(id, start + minutes(10)).unwrap();
+        book.serve(id, start + minutes(11)).unwrap();
+        book.pay(id, Vec::new(), Vec::new(), start + minutes(40))
+            .unwrap();
+        let order = book.get(id).unwrap();
+        assert_eq!(order.status(), OrderStatus::Paid);
+        assert_eq!(order.history().len(), 5);
+        assert_eq!(order.time_of(OrderStatus::Ready), Some(start + minutes(10)));
+        assert!(book.open_for_table(4).is_empty());
+        assert_eq!(book.events().len(), 5);
+        assert_eq!(book.events()[0].from, None);
+    }
+
+    #[test]
+    fn illegal_moves_are_refused_and_leave_no_trace() {
+        let now = Timestamp::from_secs(0);
+        let mut book = OrderBook::new();
+        assert_eq!(book.place(1, Vec::new(), now), Err(OrderError::EmptyOrder));
+        let id = book.place(1, lunch(), now).unwrap();
+        assert_eq!(
+            book.serve(id, now),
+            Err(OrderError::IllegalTransition {
+                order: id,
+                from: OrderStatus::Placed,
+                to: OrderStatus::Served,
+            })
+        );
+        assert_eq!(book.events().len(), 1);
+        book.void(id, now).unwrap();
+        assert!(book.start_cooking(id, now).is_err());
+        assert_eq!(
+            book.void(OrderId(99), now),
+            Err(OrderError::UnknownOrder(OrderId(99)))
+        );
+    }
+
+    #[test]
+    fn remakes_go_back_through_the_kitchen() {
+        let now = Timestamp::from_secs(0);
+        let mut book = OrderBook::new();
+        let id = book.place(2, lunch(), now).unwrap();
+        book.start_cooking(id, now).unwrap();
+        book.mark_ready(id, now).unwrap();
+        book.serve(id, now).unwrap();
+        book.send_back(id, now).unwrap();
+        assert!(book.serve(id, now).is_err());
+        book.start_cooking(id, now).unwrap();
+        assert_eq!(book.get(id).unwrap().remakes(), 1);
+    }
+
+    #[test]
+    fn the_book_round_trips_through_json() {
+        let now = Timestamp::from_secs(500);
+        let mut book = OrderBook::new();
+        let id = book.place(3, lunch(), now).unwrap();
+        book.start_cooking(id, now + minutes(2)).unwrap();
+        book.place(
+            5,
+            vec![OrderItem::new("lemonade").with("size", "Large")],
+            now,
+        )
+        .unwrap();
+        let restored = OrderBook::from_json(&book.to_json()).unwrap();
+        assert_eq!(restored.to_json(), book.to_json());
+        assert_eq!(restored.get(id), book.get(id));
+        assert_eq!(restored.events(), book.events());
+    }
+}
@@ -0,0 +1,591 @@
+// End-of-day numbers for the manager: what sold, how the floor turned, how long
+// people waited, what the kitchen had to remake and how guests paid. Everything
+// is read back out of the order book, waitlist and kitchen after the fact;
+// nothing here changes them.
+//
+// Sales are what each item was charged when its order was paid (price plus
+// modifiers, before discounts, tax and tip), so a later change to the menu
+// doesn't rewrite them, and only count orders paid for on the day. The payment
+// mix is what guests handed over, so it includes tax, tips and any cash that
+// went back as change.
+
+use crate::back_of_house::kitchen::{Kitchen, Station};
+use crate::billing::PaymentMethod;
+use crate::clock::{Date, Timestamp};
+use crate::front_of_house::hosting::Waitlist;
+use crate::menu::{Category, Menu};
+use crate::money::Cents;
+use crate::order::{OrderBook, OrderStatus};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemSales {
+    pub menu_id: String,
+    pub name: String,
+    pub category: Option<Category>, // <- None once the item is off the menu
+    pub quantity: u32,
+    pub sales: Cents,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemakeCount {
+    pub reason: String,
+    pub station: Station,
+    pub tickets: u32,
+    pub items: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaymentMix {
+    pub method: PaymentMethod,
+    pub payments: u32,
+    pub amount: Cents,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyReport {
+    pub date: Date,
+    pub orders_paid: u32,
+    pub orders_voided: u32,
+    pub items: Vec<ItemSales>, // <- best sellers first
+    pub turns: Vec<Duration>,
+    pub waits: Vec<Duration>, // <- sorted, shortest first
+    pub remakes: Vec<RemakeCount>,
+    pub payments: Vec<PaymentMix>,
+}
+
+impl DailyReport {
+    pub fn for_day(
+        date: Date,
+        orders: &OrderBook,
+        menu: &Menu,
+        waitlist: &Waitlist,
+        kitchen: &Kitchen,
+    ) -> DailyReport {
+        let (from, until) = (date.start(), date.add_days(1).start());
+        let on_the_day = |at: Timestamp| at >= from && at < until;
+
+        let mut report = DailyReport {
+            date,
+            orders_paid: 0,
+            orders_voided: 0,
+            items: Vec::new(),
+            turns: Vec::new(),
+            waits: Vec::new(),
+            remakes: Vec::new(),
+            payments: Vec::new(),
+        };
+        let mut items: BTreeMap<&str, ItemSales> = BTreeMap::new();
+        let mut payments: BTreeMap<&str, PaymentMix> = BTreeMap::new();
+        for order in orders.iter() {
+            if order.time_of(OrderStatus::Voided).is_some_and(on_the_day) {
+                report.orders_voided += 1;
+            }
+            if !order.time_of(OrderStatus::Paid).is_some_and(on_the_day) {
+                continue;
+            }
+            report.orders_paid += 1;
+            for (menu_id, amount) in order.charges() {
+                let on_menu = menu.get(menu_id);
+                let sales = items.entry(menu_id).or_insert_with(|| ItemSales {
+                    menu_id: menu_id.clone(),
+                    name: on_menu.map_or_else(|| menu_id.clone(), |i| i.name.clone()),
+                    category: on_menu.map(|i| i.category),
+                    quantity: 0,
+                    sales: Cents(0),
+                });
+                sales.quantity += 1;
+                sales.sales += *amount;
+            }
+            for payment in order.payments() {
+                let mix = payments.entry(payment.method.name()).or_insert(PaymentMix {
+                    method: payment.method,
+                    payments: 0,
+                    amount: Cents(0),
+                });
+                mix.payments += 1;
+                mix.amount += payment.amount;
+            }
+        }
+        report.items = items.into_values().collect();
+        report
+            .items
+            .sort_by(|a, b| b.sales.cmp(&a.sales).then(b.quantity.cmp(&a.quantity)));
+        report.payments = payments.into_values().collect();
+
+        report.turns = waitlist
+            .turns()
+            .iter()
+            .filter(|(at, _)| on_the_day(*at))
+            .map(|(_, took)| *took)
+            .collect();
+        report.waits = waitlist
+            .waits()
+            .iter()
+            .filter(|(at, _)| on_the_day(*at))
+            .map(|(_, took)| *took)
+            .collect();
+        report.waits.sort();
+
+        let mut remakes: BTreeMap<(&str, String), RemakeCount> = BTreeMap::new();
+        for ticket in kitchen.tickets().iter().filter(|t| on_the_day(t.fire_at)) {
+            let Some(remake) = &ticket.remake else {
+                continue;
+            };
+            let count = remakes
+                .entry((&remake.reason, remake.station.to_string()))
+                .or_insert(RemakeCount {
+                    reason: remake.reason.clone(),
+                    station: remake.station,
+                    tickets: 0,
+                    items: 0,
+                });
+            count.tickets += 1;
+            count.items += ticket.items.len() as u32;
+        }
+        report.remakes = remakes.into_values().collect();
+        report.remakes.sort_by_key(|r| Reverse(r.items));
+        report
+    }
+
+    pub fn total_sales(&self) -> Cents {
+        self.items.iter().map(|i| i.sales).sum()
+    }
+
+    pub fn total_tendered(&self) -> Cents {
+        self.payments.iter().map(|p| p.amount).sum()
+    }
+
+    // Items rolled up by menu category, in menu order
+    pub fn categories(&self) -> Vec<(Option<Category>, u32, Cents)> {
+        let mut totals: BTreeMap<Option<Category>, (u32, Cents)> = BTreeMap::new();
+        for item in &self.items {
+            let total = totals.entry(item.category).or_default();
+            total.0 += item.quantity;
+            total.1 += item.sales;
+        }
+        totals
+            .into_iter()
+            .map(|(category, (quantity, sales))| (category, quantity, sales))
+            .collect()
+    }
+
+    pub fn average_turn(&self) -> Option<Duration> {
+        if self.turns.is_empty() {
+            return None;
+        }
+        Some(self.turns.iter().sum::<Duration>() / self.turns.len() as u32)
+    }
+
+    pub fn wait_percentile(&self, percent: u32) -> Duration {
+        percentile(&self.waits, percent)
+    }
+
+    pub fn remade_items(&self) -> u32 {
+        self.remakes.iter().map(|r| r.items).sum()
+    }
+
+    pub fn summary(&self) -> Table {
+        let mut table = Table::new("Summary", &["Measure", "Value"]);
+        let average_turn = self.average_turn().map_or_else(String::new, mins);
+        for (measure, value) in [
+            ("Date", self.date.to_string()),
+            ("Orders paid", self.orders_paid.to_string()),
+            ("Orders voided", self.orders_voided.to_string()),
+            ("Sales", dollars(self.total_sales())),
+            ("Tendered", dollars(self.total_tendered())),
+            ("Tables turned", self.turns.len().to_string()),
+            ("Average turn (min)", average_turn),
+            (
+                "Parties seated from the waitlist",
+                self.waits.len().to_string(),
+            ),
+            ("Median wait (min)", mins(self.wait_percentile(50))),
+            ("90th percentile wait (min)", mins(self.wait_percentile(90))),
+            ("Longest wait (min)", mins(self.wait_percentile(100))),
+            ("Items remade", self.remade_items().to_string()),
+        ] {
+            table.push(vec![String::from(measure), value]);
+        }
+        table
+    }
+
+    pub fn sales_by_item(&self) -> Table {
+        let mut table = Table::new(
+            "Sales by item",
+            &["Item", "Menu id", "Category", "Quantity", "Sales"],
+        );
+        for item in &self.items {
+            table.push(vec![
+                item.name.clone(),
+                item.menu_id.clone(),
+                category_name(item.category),
+                item.quantity.to_string(),
+                dollars(item.sales),
+            ]);
+        }
+        table
+    }
+
+    pub fn sales_by_category(&self) -> Table {
+        let mut table = Table::new(
+            "Sales by category",
+            &["Category", "Quantity", "Sales", "Share"],
+        );
+        let total = self.total_sales();
+        for (category, quantity, sales) in self.categories() {
+            table.push(vec![
+                category_name(category),
+                quantity.to_string(),
+                dollars(sales),
+                share(sales, total),
+            ]);
+        }
+        table
+    }
+
+    pub fn remakes(&self) -> Table {
+        let mut table = Table::new("Remakes", &["Reason", "Station", "Tickets", "Items"]);
+        for remake in &self.remakes {
+            table.push(vec![
+                remake.reason.clone(),
+                remake.station.to_string(),
+                remake.tickets.to_string(),
+                remake.items.to_string(),
+            ]);
+        }
+        table
+    }
+
+    pub fn payment_mix(&self) -> Table {
+        let mut table = Table::new("Payment mix", &["Method", "Payments", "Amount", "Share"]);
+        let total = self.total_tendered();
+        for mix in &self.payments {
+            table.push(vec![
+                mix.method.to_string(),
+                mix.payments.to_string(),
+                dollars(mix.amount),
+                share(mix.amount, total),
+            ]);
+        }
+        table
+    }
+
+    pub fn tables(&self) -> Vec<Table> {
+        vec![
+            self.summary(),
+            self.sales_by_item(),
+            self.sales_by_category(),
+            self.remakes(),
+            self.payment_mix(),
+        ]
+    }
+
+    // One CSV per table, named for writing out side by side:
+    // ("sales_by_item.csv", "...")
+    pub fn to_csv(&self) -> Vec<(String, String)> {
+        self.tables()
+            .iter()
+            .map(|t| (format!("{}.csv", t.slug()), t.to_csv()))
+            .collect()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# Daily report for {}\n", self.date);
+        for table in self.tables() {
+            out.push('\n');
+            out.push_str(&table.to_markdown());
+        }
+        out
+    }
+}
+
+impl fmt::Display for DailyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_markdown())
+    }
+}
+
+// A titled grid of text cells, the one shape every export is made from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub title: String,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(title: &str, headers: &[&str]) -> Table {
+        Table {
+            title: String::from(title),
+            headers: headers.iter().map(|h| String::from(*h)).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    // "Sales by item" -> "sales_by_item"
+    pub fn slug(&self) -> String {
+        self.title.to_lowercase().replace(' ', "_")
+    }
+
+    // RFC 4180: cells with commas, quotes or line breaks are quoted
+    pub fn to_csv(&self) -> String {
+        let line = |cells: &[String]| {
+            let cells: Vec<String> = cells
+                .iter()
+                .map(|cell| {
+                    if cell.contains([',', '"', '\n', '\r']) {
+                        format!("\"{}\"", cell.replace('"', "\"\""))
+                    } else {
+                        cell.clone()
+                    }
+                })
+                .collect();
+            cells.join(",") + "\r\n"
+        };
+        let mut out = line(&self.headers);
+        for row in &self.rows {
+            out.push_str(&line(row));
+        }
+        out
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let line = |cells: &[String]| {
+            let cells: Vec<String> = cells
+                .iter()
+                .map(|cell| cell.replace('|', "\\|").replace('\n', " "))
+                .collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+        let rule: Vec<String> = self.headers.iter().map(|_| String::from("---")).collect();
+        let mut out = format!("## {}\n\n", self.title);
+        out.push_str(&line(&self.headers));
+        out.push_str(&line(&rule));
+        for row in &self.rows {
+            out.push_str(&line(row));
+        }
+        out
+    }
+}
+
+// Nearest-rank percentile of durations already sorted shortest first
+pub fn percentile(sorted: &[Duration], percent: u32) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (sorted.len() * percent as usize).div_ceil(100);
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn category_name(category: Option<Category>) -> String {
+    category.map_or_else(|| String::from("off menu"), |c| c.to_string())
+}
+
+// Plain numbers travel better into spreadsheets than "$12.50"
+fn dollars(amount: Cents) -> String {
+    let sign = if amount.0 < 0 { "-" } else { "" };
+    format!(
+        "{}{}.{:02}",
+        sign,
+        amount.0.abs() / 100,
+        amount.0.abs() % 100
+    )
+}
+
+fn mins(duration: Duration) -> String {
+    format!("{:.1}", duration.as_secs_f64() / 60.0)
+}
+
+fn share(part: Cents, total: Cents) -> String {
+    if total.0 == 0 {
+        return String::from("0.0%");
+    }
+    format!("{:.1}%", part.0 as f64 * 100.0 / total.0 as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::{BillingConfig, Check, Payment};
+    use crate::clock::minutes;
+    use crate::front_of_house::floor::FloorPlan;
+    use crate::front_of_house::hosting::Party;
+    use crate::front_of_house::serving;
+    use crate::order::{OrderId, OrderItem};
+
+    fn day() -> Date {
+        Date::new(2024, 5, 17).unwrap()
+    }
+
+    fn at(hours: u64) -> Timestamp {
+        day().start() + minutes(hours * 60)
+    }
+
+    fn served(orders: &mut OrderBook, items: Vec<OrderItem>, now: Timestamp) -> OrderId {
+        let id = orders.place(3, items, now).unwrap();
+        orders.start_cooking(id, now).unwrap();
+        orders.mark_ready(id, now).unwrap();
+        orders.serve(id, now).unwrap();
+        id
+    }
+
+    fn paid(orders: &mut OrderBook, menu: &Menu, items: Vec<OrderItem>, now: Timestamp) -> Cents {
+        let id = served(orders, items, now);
+        let receipt = Check::from_orders(&[orders.get(id).unwrap()], menu, 1)
+            .unwrap()
+            .receipt(&BillingConfig::default())
+            .unwrap();
+        let total = receipt.total();
+        serving::take_payment(orders, id, &receipt, vec![Payment::card(total)], now).unwrap();
+        total
+    }
+
+    #[test]
+    fn sales_stay_what_was_charged_after_the_menu_changes() {
+        let mut menu = Menu::house();
+        let mut orders = OrderBook::new();
+        let burger = || OrderItem::new("burger").with("side", "Onion Rings");
+        let tendered = paid(
+            &mut orders,
+            &menu,
+            vec![burger(), OrderItem::new("coffee"), OrderItem::new("coffee")],
+            at(12),
+        );
+        paid(&mut orders, &menu, vec![burger()], at(36)); // <- the next day
+        let voided = orders
+            .place(4, vec![OrderItem::new("soup")], at(13))
+            .unwrap();
+        orders.void(voided, at(13)).unwrap();
+        served(&mut orders, vec![OrderItem::new("brownie")], at(14)); // <- never paid
+
+        // The burger comes off the menu and coffee goes up after service
+        menu.remove("burger");
+        let mut coffee = menu.get("coffee").unwrap().clone();
+        coffee.price = Cents(450);
+        menu.add(coffee);
+
+        let report = DailyReport::for_day(day(), &orders, &menu, &Waitlist::new(), &Kitchen::new());
+        assert_eq!((report.orders_paid, report.orders_voided), (1, 1));
+        assert_eq!(
+            report.items,
+            [
+                ItemSales {
+                    menu_id: String::from("burger"),
+                    name: String::from("burger"),
+                    category: None,
+                    quantity: 1,
+                    sales: Cents(1800),
+                },
+                ItemSales {
+                    menu_id: String::from("coffee"),
+                    name: String::from("Coffee"),
+                    category: Some(Category::Drink),
+                    quantity: 2,
+                    sales: Cents(600),
+                },
+            ]
+        );
+        assert_eq!(report.total_sales(), Cents(2400));
+        assert_eq!(report.total_tendered(), tendered);
+        let categories: Vec<String> = report
+            .sales_by_category()
+            .rows
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect();
+        assert_eq!(categories, ["off menu", "drink"]);
+    }
+
+    #[test]
+    fn remakes_count_items_as_well_as_tickets() {
+        let menu = Menu::house();
+        let mut orders = OrderBook::new();
+        let mut kitchen = Kitchen::new();
+        let items = vec![
+            OrderItem::new("soup"),
+            OrderItem::new("soup"),
+            OrderItem::new("burger").with("side", "Fries"),
+        ];
+        let id = orders.place(3, items, at(12)).unwrap();
+        let tickets = kitchen
+            .fire(orders.get(id).unwrap(), &menu, at(12))
+            .unwrap();
+        for ticket in tickets {
+            kitchen.start(ticket, at(12)).unwrap();
+            kitchen.bump(ticket, at(12)).unwrap();
+        }
+        kitchen.remake(id, &["soup"], "cold", at(12)).unwrap();
+        kitchen.remake(id, &["burger"], "cold", at(12)).unwrap();
+
+        let report = DailyReport::for_day(day(), &orders, &menu, &Waitlist::new(), &kitchen);
+        let cold: Vec<(u32, u32)> = report
+            .remakes
+            .iter()
+            .map(|r| (r.tickets, r.items))
+            .collect();
+        assert_eq!(cold, [(1, 2), (1, 1)]); // <- both soups on one ticket, most items first
+        assert_eq!(report.remade_items(), 3);
+        assert_eq!(
+            report.remakes().headers,
+            ["Reason", "Station", "Tickets", "Items"]
+        );
+    }
+
+    #[test]
+    fn turns_and_waits_come_from_the_day() {
+        let mut floor = FloorPlan::house();
+        let mut waitlist = Waitlist::new();
+        for (arrived, seated, left) in [(12, 12, 13), (13, 14, 15), (36, 37, 38)] {
+            let id = waitlist.add(Party::walk_in("Ada", 2, at(arrived))).unwrap();
+            let seating = waitlist.seat_party(id, &mut floor, at(seated)).unwrap();
+            waitlist
+                .clear_table(&mut floor, seating.tables[0], at(left))
+                .unwrap();
+            floor.mark_clean(seating.tables[0]).unwrap();
+        }
+        let report = DailyReport::for_day(
+            day(),
+            &OrderBook::new(),
+            &Menu::house(),
+            &waitlist,
+            &Kitchen::new(),
+        );
+        assert_eq!(report.turns, [minutes(60), minutes(60)]);
+        assert_eq!(report.waits, [minutes(0), minutes(60)]);
+        assert_eq!(report.average_turn(), Some(minutes(60)));
+        assert_eq!(report.wait_percentile(50), minutes(0));
+        assert_eq!(report.wait_percentile(90), minutes(60));
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let waits: Vec<Duration> = (1..=10).map(minutes).collect();
+        assert_eq!(percentile(&waits, 0), minutes(1));
+        assert_eq!(percentile(&waits, 50), minutes(5));
+        assert_eq!(percentile(&waits, 91), minutes(10));
+        assert_eq!(percentile(&[], 50), Duration::ZERO);
+    }
+
+    #[test]
+    fn exports_quote_and_escape_cells() {
+        let mut table = Table::new("Sales by item", &["Item", "Sales"]);
+        table.push(vec![String::from("Fish, chips"), dollars(Cents(-1205))]);
+        table.push(vec![String::from("The \"Big\" | One"), dollars(Cents(7))]);
+        assert_eq!(table.slug(), "sales_by_item");
+        assert_eq!(
+            table.to_csv(),
+            "Item,Sales\r\n\"Fish, chips\",-12.05\r\n\"The \"\"Big\"\" | One\",0.07\r\n"
+        );
+        assert!(table
+            .to_markdown()
+            .contains("| The \"Big\" \\| One | 0.07 |"));
+    }
+}
@@ -0,0 +1,673 @@
+// A discrete-event simulation of a night of service, for working out how many
+// tables and cooks the room needs. Parties arrive at random (a Poisson process
+// whose rate can change through the evening), wait on the waitlist until a table
+// fits or they give up, order, and the kitchen works the tickets with a fixed
+// number of cooks per station. Everything goes through the same hosting,
+// serving and back_of_house code as a real night; only the clock is simulated.
+// The random numbers come from a seeded generator, so a seed always gives the
+// same night.
+
+use crate::back_of_house::kitchen::{Kitchen, Station, TicketId};
+use crate::back_of_house::{self, bump_ticket};
+use crate::billing::{BillingConfig, Check, Payment, Tip};
+use crate::clock::{minutes, Date, TimeOfDay, Timestamp};
+use crate::front_of_house::floor::FloorPlan;
+use crate::front_of_house::hosting::{Party, PartyId, Waitlist};
+use crate::front_of_house::serving;
+use crate::menu::{Category, Menu, MenuItem};
+use crate::money::{Cents, Rate};
+use crate::order::{OrderId, OrderItem};
+use crate::reporting;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+// SplitMix64: tiny, fast and plenty random enough for arrivals and cook times
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize
+    }
+
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+
+    pub fn between(&mut self, low: f64, high: f64) -> f64 {
+        low + (high - low) * self.next_f64()
+    }
+
+    // Time until the next event of a Poisson process with `rate` per unit time
+    pub fn exponential(&mut self, rate: f64) -> f64 {
+        -(1.0 - self.next_f64()).ln() / rate
+    }
+
+    pub fn weighted<T: Copy>(&mut self, choices: &[(T, u32)]) -> Option<T> {
+        let total: u32 = choices.iter().map(|(_, w)| w).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = self.below(total as usize) as u32;
+        for (choice, weight) in choices {
+            if pick < *weight {
+                return Some(*choice);
+            }
+            pick -= weight;
+        }
+        None
+    }
+
+    // Stretch or shrink a duration by a random factor in [low, high)
+    fn jitter(&mut self, duration: Duration, low: f64, high: f64) -> Duration {
+        Duration::from_secs((duration.as_secs() as f64 * self.between(low, high)).round() as u64)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub seed: u64,
+    pub open: Timestamp,
+    pub close: Timestamp, // <- no new arrivals after this; everyone inside finishes
+    pub arrival_rates: Vec<(Duration, f64)>, // <- (from this long after open, parties per hour)
+    pub party_sizes: Vec<(u32, u32)>, // <- (size, weight)
+    pub patience: Duration, // <- how long a party will wait before walking out
+    pub browsing: Duration, // <- from sitting down to ordering
+    pub meal: Duration,   // <- from food arriving to leaving, for a party of two
+    pub per_guest: Duration, // <- longer for each guest beyond two
+    pub bussing: Duration,
+    pub cooks: u32, // <- per station
+    pub tip: Rate,
+}
+
+impl Default for Config {
+    // A Friday dinner: 17:00 to 22:00 with a rush from six
+    fn default() -> Self {
+        let day = Date::new(2024, 6, 14).expect("a real date");
+        let open = day.at(TimeOfDay::new(17, 0).expect("a real time"));
+        Config {
+            seed: 42,
+            open,
+            close: open + minutes(5 * 60),
+            arrival_rates: vec![
+                (Duration::ZERO, 8.0),
+                (minutes(60), 16.0),
+                (minutes(150), 12.0),
+                (minutes(240), 6.0),
+            ],
+            party_sizes: vec![(1, 10), (2, 40), (3, 15), (4, 20), (5, 5), (6, 7), (8, 3)],
+            patience: minutes(40),
+            browsing: minutes(6),
+            meal: minutes(40),
+            per_guest: minutes(5),
+            bussing: minutes(5),
+            cooks: 2,
+            tip: Rate::percent(18),
+        }
+    }
+}
+
+impl Config {
+    pub fn flat_rate(mut self, parties_per_hour: f64) -> Config {
+        self.arrival_rates = vec![(Duration::ZERO, parties_per_hour)];
+        self
+    }
+
+    pub fn rate_at(&self, at: Timestamp) -> f64 {
+        let since_open = at.since(self.open);
+        self.arrival_rates
+            .iter()
+            .filter(|(from, _)| *from <= since_open)
+            .max_by_key(|(from, _)| *from)
+            .map_or(0.0, |(_, rate)| *rate)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Event {
+    Arrive(u32),
+    GiveUp(PartyId),
+    Order(PartyId),
+    Wake(Station), // <- a ticket's fire time came round
+    Cooked(TicketId),
+    Serve(OrderId),
+    Leave(PartyId),
+    Cleaned(Vec<u32>),
+}
+
+#[derive(Debug, Clone)]
+struct Sitting {
+    tables: Vec<u32>,
+    size: u32,
+    since: Timestamp,
+    order: Option<OrderId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub open: Timestamp,
+    pub close: Timestamp,
+    pub last_out: Timestamp, // <- when the last table was bussed
+    pub parties_arrived: u32,
+    pub guests_arrived: u32,
+    pub parties_seated: u32,
+    pub guests_served: u32,
+    pub walked_out: u32,
+    pub waits: Vec<Duration>, // <- of seated parties, shortest first
+    pub table_busy: BTreeMap<u32, Duration>,
+    pub tickets: u32,
+    pub late_tickets: u32,     // <- counting time spent waiting for a free cook
+    pub ticket_time: Duration, // <- average from firing to done
+    pub revenue: Cents,
+}
+
+impl Report {
+    // The wait `percent` of seated parties waited no longer than
+    pub fn wait_percentile(&self, percent: u32) -> Duration {
+        reporting::percentile(&self.waits, percent)
+    }
+
+    pub fn average_wait(&self) -> Duration {
+        if self.waits.is_empty() {
+            return Duration::ZERO;
+        }
+        self.waits.iter().sum::<Duration>() / self.waits.len() as u32
+    }
+
+    // Share of the night (open until the last table was bussed) the table was in use
+    pub fn utilisation(&self, table: u32) -> f64 {
+        let night = self.last_out.since(self.open).as_secs_f64();
+        match self.table_busy.get(&table) {
+            Some(busy) if night > 0.0 => busy.as_secs_f64() / night,
+            _ => 0.0,
+        }
+    }
+
+    pub fn average_utilisation(&self) -> f64 {
+        if self.table_busy.is_empty() {
+            return 0.0;
+        }
+        let total: f64 = self.table_busy.keys().map(|t| self.utilisation(*t)).sum();
+        total / self.table_busy.len() as f64
+    }
+
+    // Guests served per hour the doors were open
+    pub fn throughput(&self) -> f64 {
+        let hours = self.close.since(self.open).as_secs_f64() / 3600.0;
+        if hours > 0.0 {
+            self.guests_served as f64 / hours
+        } else {
+            0.0
+        }
+    }
+}
+
+fn mins(d: Duration) -> String {
+    format!("{:.1} min", d.as_secs_f64() / 60.0)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Service {} {}-{}, last table out {}",
+            self.open.date(),
+            self.open,
+            self.close,
+            self.last_out
+        )?;
+        writeln!(
+            f,
+            "Arrivals:    {} parties, {} guests",
+            self.parties_arrived, self.guests_arrived
+        )?;
+        writeln!(
+            f,
+            "Seated:      {} parties, {} guests; {} parties walked out",
+            self.parties_seated, self.guests_served, self.walked_out
+        )?;
+        writeln!(
+            f,
+            "Wait:        avg {}, p50 {}, p90 {}, max {}",
+            mins(self.average_wait()),
+            mins(self.wait_percentile(50)),
+            mins(self.wait_percentile(90)),
+            mins(self.wait_percentile(100))
+        )?;
+        writeln!(
+            f,
+            "Throughput:  {:.1} guests per hour open",
+            self.throughput()
+        )?;
+        writeln!(
+            f,
+            "Kitchen:     {} tickets, avg {}, {} late",
+            self.tickets,
+            mins(self.ticket_time),
+            self.late_tickets
+        )?;
+        writeln!(f, "Revenue:     {}", self.revenue)?;
+        writeln!(
+            f,
+            "Utilisation: {:.0}% on average",
+            self.average_utilisation() * 100.0
+        )?;
+        for table in self.table_busy.keys() {
+            writeln!(
+                f,
+                "  table {:>2}  {:>3.0}%",
+                table,
+                self.utilisation(*table) * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The house floor, menu and kitchen
+pub fn run(config: &Config) -> Result<Report, Box<dyn Error>> {
+    Simulation::new(
+        config.clone(),
+        FloorPlan::house(),
+        Menu::house(),
+        Kitchen::new(),
+    )
+    .run()
+}
+
+pub struct Simulation {
+    config: Config,
+    rng: Rng,
+    floor: FloorPlan,
+    menu: Menu,
+    kitchen: Kitchen,
+    waitlist: Waitlist,
+    orders: crate::order::OrderBook,
+    events: BinaryHeap<Reverse<(Timestamp, u64, Event)>>,
+    next_event: u64,
+    seated: HashMap<PartyId, Sitting>,
+    diners: HashMap<OrderId, PartyId>,
+    busy_cooks: HashMap<Station, u32>,
+    report: Report,
+}
+
+impl Simulation {
+    pub fn new(config: Config, floor: FloorPlan, menu: Menu, kitchen: Kitchen) -> Simulation {
+        let report = Report {
+            open: config.open,
+            close: config.close,
+            last_out: config.open,
+            parties_arrived: 0,
+            guests_arrived: 0,
+            parties_seated: 0,
+            guests_served: 0,
+            walked_out: 0,
+            waits: Vec::new(),
+            table_busy: floor
+                .tables()
+                .iter()
+                .map(|t| (t.number, Duration::ZERO))
+                .collect(),
+            tickets: 0,
+            late_tickets: 0,
+            ticket_time: Duration::ZERO,
+            revenue: Cents::ZERO,
+        };
+        Simulation {
+            rng: Rng::new(config.seed),
+            config,
+            floor,
+            menu,
+            kitchen,
+            waitlist: Waitlist::new(),
+            orders: crate::order::OrderBook::new(),
+            events: BinaryHeap::new(),
+            next_event: 0,
+            seated: HashMap::new(),
+            diners: HashMap::new(),
+            busy_cooks: HashMap::new(),
+            report,
+        }
+    }
+
+    pub fn run(mut self) -> Result<Report, Box<dyn Error>> {
+        self.schedule_arrivals();
+        while let Some(Reverse((now, _, event))) = self.events.pop() {
+            self.handle(event, now)?;
+        }
+        self.finish();
+        Ok(self.report)
+    }
+
+    // Thinning: draw arrivals at the busiest rate and keep each one with
+    // probability (rate then / busiest rate), which gives a Poisson process
+    // whose rate follows the schedule
+    fn schedule_arrivals(&mut self) {
+        let peak = self
+            .config
+            .arrival_rates
+            .iter()
+            .map(|(_, rate)| *rate)
+            .fold(0.0, f64::max);
+        if !peak.is_finite() || peak <= 0.0 {
+            return;
+        }
+        let mut at = self.config.open;
+        loop {
+            // At least a second apart, so a huge rate still gets to closing time
+            let gap = self.rng.exponential(peak) * 3600.0;
+            at = at + Duration::from_secs(gap.round().max(1.0) as u64);
+            if at >= self.config.close {
+                break;
+            }
+            if !self.rng.chance(self.config.rate_at(at) / peak) {
+                continue;
+            }
+            let sizes = self.config.party_sizes.clone();
+            let size = self.rng.weighted(&sizes).unwrap_or(2);
+            self.schedule(at, Event::Arrive(size));
+        }
+    }
+
+    fn schedule(&mut self, at: Timestamp, event: Event) {
+        self.next_event += 1;
+        self.events.push(Reverse((at, self.next_event, event)));
+    }
+
+    fn handle(&mut self, event: Event, now: Timestamp) -> Result<(), Box<dyn Error>> {
+        match event {
+            Event::Arrive(size) => {
+                self.report.parties_arrived += 1;
+                self.report.guests_arrived += size;
+                let name = format!("Party {}", self.report.parties_arrived);
+                let id = self.waitlist.add(Party::walk_in(&name, size, now))?;
+                self.schedule(now + self.config.patience, Event::GiveUp(id));
+                self.seat_waiting(now);
+            }
+            Event::GiveUp(id) => {
+                if self.waitlist.remove(id).is_some() {
+                    self.report.walked_out += 1;
+                }
+            }
+            Event::Order(party) => self.order(party, now)?,
+            Event::Wake(station) => self.work(station, now)?,
+            Event::Cooked(ticket) => {
+                let station = self
+                    .kitchen
+                    .ticket(ticket)
+                    .map(|t| t.station)
+                    .ok_or("cooked a ticket the kitchen doesn't know")?;
+                if let Some(order) = bump_ticket(&mut self.orders, &mut self.kitchen, ticket, now)?
+                {
+                    self.schedule(now + minutes(2), Event::Serve(order));
+                }
+                if let Some(busy) = self.busy_cooks.get_mut(&station) {
+                    *busy = busy.saturating_sub(1);
+                }
+                self.work(station, now)?;
+            }
+            Event::Serve(order) => {
+                serving::serve_order(&mut self.orders, order, now)?;
+                let party = self.diners[&order];
+                let size = self.seated[&party].size;
+                let meal = self.config.meal + self.config.per_guest * size.saturating_sub(2);
+                let stay = self.rng.jitter(meal, 0.75, 1.25);
+                self.schedule(now + stay, Event::Leave(party));
+            }
+            Event::Leave(party) => self.leave(party, now)?,
+            Event::Cleaned(tables) => {
+                for table in tables {
+                    self.floor.mark_clean(table)?;
+                }
+                self.report.last_out = self.report.last_out.max(now);
+                self.seat_waiting(now);
+            }
+        }
+        Ok(())
+    }
+
+    // Seat everyone a table has come free for
+    fn seat_waiting(&mut self, now: Timestamp) {
+        while let Ok(seating) = self.waitlist.seat_next(&mut self.floor, now) {
+            let size = self
+                .floor
+                .seated(seating.party)
+                .map_or(0, |(party, _)| party.size);
+            self.report.parties_seated += 1;
+            self.report.guests_served += size;
+            self.report.waits.push(seating.waited);
+            self.seated.insert(
+                seating.party,
+                Sitting {
+                    tables: seating.tables,
+                    size,
+                    since: now,
+                    order: None,
+                },
+            );
+            let browsing = self.rng.jitter(self.config.browsing, 0.5, 1.5);
+            self.schedule(now + browsing, Event::Order(seating.party));
+        }
+    }
+
+    // Everyone picks something to eat; some want a drink, a starter to share
+    // or dessert
+    fn order(&mut self, party: PartyId, now: Timestamp) -> Result<(), Box<dyn Error>> {
+        let sitting = self.seated[&party].clone();
+        let available: Vec<MenuItem> = self.menu.available_at(now).into_iter().cloned().collect();
+        let in_category = |categories: &[Category]| -> Vec<&MenuItem> {
+            available
+                .iter()
+                .filter(|i| categories.contains(&i.category))
+                .collect()
+        };
+        let mains = in_category(&[Category::Main, Category::Breakfast]);
+        let starters = in_category(&[Category::Appetizer]);
+        let drinks = in_category(&[Category::Drink]);
+        let desserts = in_category(&[Category::Dessert]);
+        let mut items = Vec::new();
+        for seat in 1..=sitting.size {
+            for (choices, chance) in [(&mains, 1.0), (&drinks, 0.6), (&desserts, 0.25)] {
+                if !choices.is_empty() && self.rng.chance(chance) {
+                    let item = choices[self.rng.below(choices.len())];
+                    items.push(self.pick_options(item).for_seat(seat));
+                }
+            }
+        }
+        if !starters.is_empty() && self.rng.chance(0.4) {
+            let item = starters[self.rng.below(starters.len())];
+            items.push(self.pick_options(item));
+        }
+        if items.is_empty() {
+            // Nothing to be had: they leave once they've looked at the menu
+            self.schedule(now, Event::Leave(party));
+            return Ok(());
+        }
+        let order = serving::take_order(&mut self.orders, sitting.tables[0], items, now)?;
+        let tickets = back_of_house::cook_order(
+            &mut self.orders,
+            &mut self.kitchen,
+            &mut self.menu,
+            order,
+            now,
+        )?;
+        if let Some(sitting) = self.seated.get_mut(&party) {
+            sitting.order = Some(order);
+        }
+        self.diners.insert(order, party);
+        let mut stations = Vec::new();
+        for ticket in tickets.iter().filter_map(|t| self.kitchen.ticket(*t)) {
+            stations.push((ticket.station, ticket.fire_at));
+        }
+        for (station, fire_at) in stations {
+            self.schedule(fire_at.max(now), Event::Wake(station));
+        }
+        Ok(())
+    }
+
+    // A random option for each choice the guest has to make
+    fn pick_options(&mut self, item: &MenuItem) -> OrderItem {
+        let mut order_item = OrderItem::new(&item.id);
+        for modifier in item.modifiers.iter().filter(|m| m.required) {
+            if modifier.options.is_empty() {
+                continue;
+            }
+            let option = &modifier.options[self.rng.below(modifier.options.len())];
+            order_item = order_item.with(&modifier.name, &option.name);
+        }
+        order_item
+    }
+
+    // Free cooks at the station start on the next tickets that are due to fire
+    fn work(&mut self, station: Station, now: Timestamp) -> Result<(), Box<dyn Error>> {
+        loop {
+            let busy = self.busy_cooks.get(&station).copied().unwrap_or(0);
+            if busy >= self.config.cooks {
+                return Ok(());
+            }
+            let next = self
+                .kitchen
+                .queue(station)
+                .into_iter()
+                .find(|t| t.started.is_none() && t.fire_at <= now)
+                .map(|t| (t.id, t.expected));
+            let Some((ticket, expected)) = next else {
+                return Ok(());
+            };
+            self.kitchen.start(ticket, now)?;
+            *self.busy_cooks.entry(station).or_insert(0) += 1;
+            let took = self.rng.jitter(expected, 0.8, 1.4);
+            self.schedule(now + took, Event::Cooked(ticket));
+        }
+    }
+
+    fn leave(&mut self, party: PartyId, now: Timestamp) -> Result<(), Box<dyn Error>> {
+        let sitting = self
+            .seated
+            .remove(&party)
+            .ok_or("a party left that was never seated")?;
+        if let Some(order) = sitting.order {
+            let placed = self.orders.get(order).ok_or("the order went missing")?;
+            let receipt = Check::from_orders(&[placed], &self.menu, sitting.size)?
+                .tip(Tip::Percent(self.config.tip))
+                .receipt(&BillingConfig::default())?;
+            let total = receipt.total();
+            serving::take_payment(
+                &mut self.orders,
+                order,
+                &receipt,
+                vec![Payment::card(total)],
+                now,
+            )?;
+            self.report.revenue += total;
+        }
+        self.waitlist
+            .clear_table(&mut self.floor, sitting.tables[0], now)?;
+        for table in &sitting.tables {
+            if let Some(busy) = self.report.table_busy.get_mut(table) {
+                *busy += now.since(sitting.since);
+            }
+        }
+        let bussing = self.rng.jitter(self.config.bussing, 0.6, 1.4);
+        self.schedule(now + bussing, Event::Cleaned(sitting.tables));
+        Ok(())
+    }
+
+    fn finish(&mut self) {
+        let done: Vec<_> = self
+            .kitchen
+            .tickets()
+            .iter()
+            .filter_map(|t| t.done.map(|done| (t, done)))
+            .collect();
+        self.report.tickets = done.len() as u32;
+        self.report.late_tickets = done
+            .iter()
+            .filter(|(t, done)| *done > t.fire_at + t.expected + self.kitchen.grace)
+            .count() as u32;
+        if !done.is_empty() {
+            let total: Duration = done.iter().map(|(t, d)| d.since(t.fire_at)).sum();
+            self.report.ticket_time = total / done.len() as u32;
+        }
+        self.report.waits.sort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_generator_is_seeded_and_in_range() {
+        let mut a = Rng::new(7);
+        let mut b = Rng::new(7);
+        for _ in 0..1000 {
+            let x = a.next_f64();
+            assert_eq!(x, b.next_f64());
+            assert!((0.0..1.0).contains(&x));
+        }
+        assert_ne!(Rng::new(7).next_u64(), Rng::new(8).next_u64());
+        let mean: f64 = (0..20_000).map(|_| a.exponential(4.0)).sum::<f64>() / 20_000.0;
+        assert!((mean - 0.25).abs() < 0.01, "{}", mean);
+        assert_eq!(a.weighted(&[(1, 0), (2, 0)]), None);
+        assert_eq!(a.weighted(&[(1, 0), (2, 5)]), Some(2));
+    }
+
+    #[test]
+    fn the_rate_follows_the_schedule() {
+        let config = Config::default();
+        assert_eq!(config.rate_at(config.open), 8.0);
+        assert_eq!(config.rate_at(config.open + minutes(90)), 16.0);
+        assert_eq!(config.rate_at(config.open + minutes(250)), 6.0);
+        let flat = config.flat_rate(3.0);
+        assert_eq!(flat.rate_at(flat.close), 3.0);
+    }
+
+    #[test]
+    fn a_seed_always_gives_the_same_night() {
+        let config = Config::default();
+        let first = run(&config).unwrap();
+        let again = run(&config).unwrap();
+        assert_eq!(first, again);
+        assert!(first.parties_arrived > 0);
+        assert_eq!(
+            first.parties_arrived,
+            first.parties_seated + first.walked_out
+        );
+        assert!(first.last_out >= first.close);
+        let other = run(&Config { seed: 43, ..config }).unwrap();
+        assert_ne!(other, first);
+    }
+
+    #[test]
+    fn extreme_rates_still_finish() {
+        let quiet = run(&Config::default().flat_rate(0.0)).unwrap();
+        assert_eq!(quiet.parties_arrived, 0);
+        let broken = run(&Config::default().flat_rate(f64::INFINITY)).unwrap();
+        assert_eq!(broken.parties_arrived, 0);
+        let mut config = Config::default().flat_rate(1e300);
+        config.close = config.open + minutes(1);
+        // One a second, the first a second after opening
+        assert_eq!(run(&config).unwrap().parties_arrived, 59);
+    }
+}